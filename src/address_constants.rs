@@ -6,10 +6,30 @@ pub const TERMINAL_BUFFER_SIZE: usize =
 pub const TERMINAL_BUFFER_END: Address = TERMINAL_BUFFER_START + TERMINAL_BUFFER_SIZE as Address;
 pub const TERMINAL_CURSOR_POINTER: Address = TERMINAL_BUFFER_END;
 pub const TERMINAL_CURSOR_MODE: Address = TERMINAL_CURSOR_POINTER + Word::SIZE as Address;
-pub const FRAMEBUFFER_SIZE: usize = display::WIDTH * display::HEIGHT * 4; // RGBA
+/// Memory reserved per framebuffer. Smaller resolutions than the default one leave part of it
+/// unused, so that the stack and the entry point don't move.
+pub const MAX_FRAMEBUFFER_SIZE: usize = display::Resolution::DEFAULT.framebuffer_size();
 pub const FIRST_FRAMEBUFFER_START: Address =
     TERMINAL_BUFFER_START + TERMINAL_BUFFER_SIZE as Address + 2 * Word::SIZE as Address /* 2 extra words for Cursor data */;
-pub const SECOND_FRAMEBUFFER_START: Address = FIRST_FRAMEBUFFER_START + FRAMEBUFFER_SIZE as Address;
-pub const STACK_START: Address = SECOND_FRAMEBUFFER_START + FRAMEBUFFER_SIZE as Address;
-pub const STACK_SIZE: usize = 512 * 1024;
+pub const PALETTE_START: Address = FIRST_FRAMEBUFFER_START + 2 * MAX_FRAMEBUFFER_SIZE as Address;
+pub const PALETTE_SIZE: usize = 256 * 4; // RGBA
+pub const TERMINAL_ATTRIBUTES_START: Address = PALETTE_START + PALETTE_SIZE as Address;
+/// One attribute byte per terminal cell.
+pub const TERMINAL_ATTRIBUTES_SIZE: usize = terminal::WIDTH * terminal::HEIGHT;
+/// Number of interrupt sources, the index into the vector table is the interrupt number.
+pub const NUM_INTERRUPTS: usize = 1;
+pub const TIMER_INTERRUPT: usize = 0;
+/// One handler address per interrupt source.
+pub const INTERRUPT_VECTOR_TABLE_START: Address =
+    TERMINAL_ATTRIBUTES_START + TERMINAL_ATTRIBUTES_SIZE as Address;
+pub const INTERRUPT_VECTOR_TABLE_SIZE: usize = NUM_INTERRUPTS * Word::SIZE;
+/// The timer interrupt fires every time this many cycles have passed (0 disables it).
+pub const TIMER_INTERRUPT_INTERVAL: Address =
+    INTERRUPT_VECTOR_TABLE_START + INTERRUPT_VECTOR_TABLE_SIZE as Address;
+pub const STACK_START: Address = TIMER_INTERRUPT_INTERVAL + Word::SIZE as Address;
+/// The palette, the terminal attributes and the interrupt configuration are taken from the
+/// stack memory so that the entry point doesn't move.
+pub const STACK_SIZE: usize =
+    512 * 1024 - PALETTE_SIZE - TERMINAL_ATTRIBUTES_SIZE - INTERRUPT_VECTOR_TABLE_SIZE - Word::SIZE;
+pub const STACK_END: Address = STACK_START + STACK_SIZE as Address;
 pub const ENTRY_POINT: Address = STACK_START + STACK_SIZE as Address;
@@ -0,0 +1,151 @@
+use std::fmt;
+
+use crate::{
+    opcodes::{Opcode, Operand},
+    Register, Word,
+};
+
+/// An error in the assembly source, `line` is 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblerError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssemblerError {}
+
+/// Assembles source code with one instruction per line into opcodes. An instruction consists of
+/// the mnemonic (as returned by `Opcode::get_mnemonic`) followed by the operands in the order
+/// they are listed in the JSON export, separated by commas: registers are written as `r<n>`,
+/// immediates and addresses in hexadecimal (`0x...`). Everything after a `;` is a comment.
+pub fn assemble(source: &str) -> Result<Vec<Opcode>, AssemblerError> {
+    let mut result = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let error = |message: String| AssemblerError {
+            line: index + 1,
+            message,
+        };
+        let line = match line.split_once(';') {
+            Some((code, _comment)) => code,
+            None => line,
+        }
+        .trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (mnemonic, operands) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let operands = operands
+            .split(',')
+            .map(str::trim)
+            .filter(|operand| !operand.is_empty())
+            .map(|operand| {
+                parse_operand(operand).ok_or_else(|| error(format!("invalid operand '{operand}'")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let opcode = Opcode::from_operands(mnemonic, &operands)
+            .map_err(|message| error(format!("{message} '{line}'")))?;
+        result.push(opcode);
+    }
+    Ok(result)
+}
+
+fn parse_operand(operand: &str) -> Option<Operand> {
+    if let Some(register) = operand.strip_prefix('r') {
+        return register
+            .parse::<u8>()
+            .ok()
+            .map(|register| Operand::Register(Register(register)));
+    }
+    let value = operand
+        .strip_prefix("0x")
+        .or_else(|| operand.strip_prefix("0X"))?;
+    Word::from_str_radix(&value.replace('_', ""), 16)
+        .ok()
+        .map(Operand::Value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{address_constants::ENTRY_POINT, disassembler::disassemble};
+
+    #[test]
+    fn assemble_instructions() {
+        let source = "
+            ; load the answer
+            MoveRegisterImmediate r1, 0x2A
+            MoveAddressRegister 0x00000100, r1 ; store it
+
+            AddTargetSourceImmediate r2,r1,0x1
+            HaltAndCatchFire
+        ";
+        let opcodes = assemble(source).unwrap();
+        assert_eq!(
+            opcodes,
+            [
+                Opcode::MoveRegisterImmediate {
+                    register: 1.into(),
+                    immediate: 0x2A,
+                },
+                Opcode::MoveAddressRegister {
+                    register: 1.into(),
+                    target_address: 0x100,
+                },
+                Opcode::AddTargetSourceImmediate {
+                    target: 2.into(),
+                    source: 1.into(),
+                    immediate: 1,
+                },
+                Opcode::HaltAndCatchFire {},
+            ]
+        );
+        assert_eq!(
+            opcodes[0].as_instruction().to_be_bytes(),
+            [0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2A]
+        );
+    }
+
+    #[test]
+    fn round_trip_through_disassembler() {
+        let source = [
+            "MoveRegisterImmediate r1, 0x0000002a",
+            "MoveAddressRegister 0x00000100, r1",
+            "CompareTargetLhsRhs r10, r1, r42",
+            "JumpImmediateIfLessThan r10, 0x00080028",
+            "HaltAndCatchFire",
+        ];
+        let machine_code: Vec<u8> = assemble(&source.join("\n"))
+            .unwrap()
+            .iter()
+            .flat_map(|opcode| opcode.as_instruction().to_be_bytes())
+            .collect();
+        let listing: Vec<_> = disassemble(&machine_code, ENTRY_POINT)
+            .into_iter()
+            .map(|line| line.split_once(": ").unwrap().1.to_string())
+            .collect();
+        assert_eq!(listing, source);
+    }
+
+    #[test]
+    fn errors_report_the_line() {
+        assert_eq!(assemble("NoOp\nFrobnicate r1").unwrap_err().line, 2);
+        assert_eq!(
+            assemble("MoveRegisterImmediate r1").unwrap_err().message,
+            "invalid operands 'MoveRegisterImmediate r1'"
+        );
+        assert_eq!(
+            assemble("MoveRegisterImmediate r1, 42")
+                .unwrap_err()
+                .message,
+            "invalid operand '42'"
+        );
+        assert!(assemble("MoveRegisterImmediate r1, 0x1, 0x2").is_err());
+        assert!(assemble("MoveRegisterImmediate r256, 0x1").is_err());
+    }
+}
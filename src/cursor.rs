@@ -17,4 +17,88 @@ pub struct Cursor {
 
 impl Cursor {
     pub const TOGGLE_INTERVAL: Duration = Duration::from_millis(400);
+
+    /// Updates the visibility according to the given mode. A blinking cursor is toggled whenever
+    /// the toggle interval has elapsed.
+    pub fn update(&mut self, mode: CursorMode, now: Instant) {
+        match mode {
+            CursorMode::Blinking => {
+                if now >= self.time_of_next_toggle {
+                    self.visible = !self.visible;
+                    self.time_of_next_toggle += Self::TOGGLE_INTERVAL;
+                    if self.time_of_next_toggle <= now {
+                        // we fell behind (e.g. because rendering was paused), don't try to catch up
+                        self.time_of_next_toggle = now + Self::TOGGLE_INTERVAL;
+                    }
+                }
+            }
+            CursorMode::Visible => self.visible = true,
+            CursorMode::Invisible => self.visible = false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor_at(start: Instant) -> Cursor {
+        Cursor {
+            visible: true,
+            time_of_next_toggle: start + Cursor::TOGGLE_INTERVAL,
+        }
+    }
+
+    #[test]
+    fn blinking_cursor_toggles_on_interval() {
+        let start = Instant::now();
+        let mut cursor = cursor_at(start);
+        cursor.update(CursorMode::Blinking, start + Cursor::TOGGLE_INTERVAL / 2);
+        assert!(cursor.visible);
+        cursor.update(CursorMode::Blinking, start + Cursor::TOGGLE_INTERVAL);
+        assert!(!cursor.visible);
+        cursor.update(
+            CursorMode::Blinking,
+            start + Cursor::TOGGLE_INTERVAL * 3 / 2,
+        );
+        assert!(!cursor.visible);
+        cursor.update(CursorMode::Blinking, start + Cursor::TOGGLE_INTERVAL * 2);
+        assert!(cursor.visible);
+    }
+
+    #[test]
+    fn blinking_cursor_does_not_catch_up_after_pause() {
+        let start = Instant::now();
+        let mut cursor = cursor_at(start);
+        let later = start + Cursor::TOGGLE_INTERVAL * 10;
+        cursor.update(CursorMode::Blinking, later);
+        assert!(!cursor.visible);
+        cursor.update(CursorMode::Blinking, later);
+        assert!(!cursor.visible);
+        assert_eq!(cursor.time_of_next_toggle, later + Cursor::TOGGLE_INTERVAL);
+    }
+
+    #[test]
+    fn visible_mode_forces_visibility() {
+        let start = Instant::now();
+        let mut cursor = cursor_at(start);
+        cursor.visible = false;
+        for step in 0..4 {
+            cursor.update(CursorMode::Visible, start + Cursor::TOGGLE_INTERVAL * step);
+            assert!(cursor.visible);
+        }
+    }
+
+    #[test]
+    fn invisible_mode_forces_invisibility() {
+        let start = Instant::now();
+        let mut cursor = cursor_at(start);
+        for step in 0..4 {
+            cursor.update(
+                CursorMode::Invisible,
+                start + Cursor::TOGGLE_INTERVAL * step,
+            );
+            assert!(!cursor.visible);
+        }
+    }
 }
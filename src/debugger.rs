@@ -1,17 +1,27 @@
 mod segmented_reader;
+mod symbols;
 mod tcp_protocol;
 
 use std::{
-    collections::{HashSet, VecDeque},
-    thread,
+    collections::{HashMap, HashSet, VecDeque},
+    io, thread,
     time::Duration,
 };
 
 use crossbeam_channel::{bounded, select, tick, Receiver, Sender, TryRecvError};
 use crossbeam_utils::sync::WaitGroup;
 
-use self::tcp_protocol::{PollReturn, TcpHandler};
-use crate::{memory::Memory, opcodes::Opcode, processor::Processor, Address, Register, Word};
+pub use self::symbols::{Symbol, SymbolMap};
+pub use self::tcp_protocol::{DisassembledInstruction, StackFrame};
+use self::tcp_protocol::{Framing, Location, PollReturn, RegisterName, TcpHandler};
+pub use self::tcp_protocol::{DEFAULT_HOST, DEFAULT_PORT};
+use crate::{
+    disassembler,
+    memory::Memory,
+    opcodes::Opcode,
+    processor::{Flag, Processor},
+    Address, Instruction, Register, Size, Word,
+};
 
 const CHANNEL_BOUND: usize = 100;
 const TCP_POLL_INTERVAL: Duration = Duration::from_millis(50);
@@ -21,16 +31,30 @@ struct Debugger {
     breakpoint_sender: Sender<DebugCommand>,
     started: bool,
     start_notifications: Vec<WaitGroup>,
+    /// Set as soon as the first client connected, so that later connections can be told apart.
+    had_client: bool,
+    /// Used to annotate break locations and to resolve breakpoints given by name.
+    symbols: SymbolMap,
 }
 
 pub struct DebugHandle {
     state: BreakpointHandleState,
     breakpoints: HashSet<Address>,
+    /// Watched data addresses and the value last seen there (`None` until first read).
+    watchpoints: HashMap<Address, Option<Word>>,
+    /// Mnemonics of the instructions to break at.
+    opcode_breaks: HashSet<String>,
     sender: Option<Sender<DebugMessage>>,
     receiver: Option<Receiver<DebugCommand>>,
     receive_cache: VecDeque<DebugCommand>,
     should_pause: bool,
-    call_stack: Vec<Address>,
+    call_stack: Vec<StackFrame>,
+    /// Return address and call stack depth at which a running step over stops again.
+    step_over_target: Option<(Address, usize)>,
+    /// Call stack depth below which a running step out stops again.
+    step_out_depth: Option<usize>,
+    /// Number of instructions left to execute before breaking again after a `RunCycles` command.
+    remaining_instructions: Option<u64>,
     did_execute_last_cycle: bool,
 }
 
@@ -38,6 +62,8 @@ pub struct DebugHandle {
 pub enum ShouldExecuteInstruction {
     Yes,
     No,
+    /// The debugger requested to end the emulation, the process exits.
+    Terminate,
 }
 
 #[derive(Debug, PartialEq)]
@@ -47,7 +73,8 @@ enum BreakpointHandleState {
     Breaking,
 }
 
-enum DebugMessage {
+/// Sent from the `DebugHandle` to the debugger front-end.
+pub enum DebugMessage {
     /// Request to stop the debugger thread.
     Stop,
     /// Request to wait for start and drop the sent wait group after starting.
@@ -56,25 +83,81 @@ enum DebugMessage {
     HitBreakpoint(Address),
     /// Notification that the debugger is (still) breaking at the given instruction address.
     Breaking(Address),
+    /// Notification that the value at a watched data address changed and we will start breaking.
+    HitWatchpoint {
+        address: Address,
+        old: Word,
+        new: Word,
+    },
     /// Notification that the debugger started breaking at the given instruction address due to a pause request.
     Pausing(Address),
     /// Notification that a register value changed. Also used to send initial register values of non-zero registers.
     BreakState {
         registers: Vec<Word>,
-        call_stack: Vec<Address>,
+        call_stack: Vec<StackFrame>,
+        /// The values of the special registers (also contained in `registers`).
+        instruction_pointer: Address,
+        stack_pointer: Address,
+        flags: Word,
+        cycle_count: u64,
+        /// Set if the machine has halted (sent once when that happens).
+        is_halted: bool,
     },
+    /// Notification containing all currently set breakpoints (answer to a list request).
+    Breakpoints {
+        addresses: Vec<Address>,
+        watchpoints: Vec<Address>,
+        opcode_breaks: Vec<String>,
+    },
+    /// Notification containing a range of memory starting at the given address (answer to a read request).
+    MemoryContents { address: Address, bytes: Vec<u8> },
+    /// Notification containing decoded instructions (answer to a disassembly request).
+    Disassembly(Vec<DisassembledInstruction>),
+    /// Notification that a request could not be fulfilled.
+    Error(String),
 }
 
-enum DebugCommand {
+/// Sent from the debugger front-end to the `DebugHandle`.
+pub enum DebugCommand {
     SetBreakpoints(Vec<Address>),
     RemoveBreakpoints(Vec<Address>),
+    SetWatchpoints(Vec<Address>),
+    RemoveWatchpoints(Vec<Address>),
+    SetOpcodeBreaks(Vec<String>),
+    RemoveOpcodeBreaks(Vec<String>),
+    /// Request to report all currently set breakpoints.
+    ListBreakpoints,
     /// Continue normal execution i.e. stop breaking.
     Continue,
+    /// Continue normal execution for the given number of instructions.
+    RunCycles(u64),
     /// Execute one instruction while breaking.
     StepOne,
+    /// Like `StepOne`, but runs called subroutines to completion.
+    StepOver,
+    /// Continue until the current subroutine returns. Behaves like `Continue` at the top level.
+    StepOut,
     /// Instructs breakpoint handler to break as soon as possible.
     Pause,
     SetRegister(u8, Word),
+    /// Read a range of memory while breaking. Out-of-bounds parts of the range are omitted.
+    ReadMemory {
+        address: Address,
+        length: usize,
+    },
+    /// Decode instructions while breaking. Stops early at the end of memory.
+    Disassemble {
+        address: Address,
+        count: usize,
+    },
+    /// Write to memory while breaking. Out-of-bounds writes are rejected as a whole.
+    WriteMemory {
+        address: Address,
+        bytes: Vec<u8>,
+    },
+    /// Forget all breakpoints, watchpoints and pending run control requests and wait for the
+    /// next start request (sent when a new client connects after the previous one disconnected).
+    Reset,
     Terminate,
 }
 
@@ -83,22 +166,43 @@ enum ShouldTerminate {
     No,
 }
 
-pub fn start_debugger() -> DebugHandle {
-    let (sender, receiver) = bounded(CHANNEL_BOUND);
-    let (breakpoint_sender, breakpoint_receiver) = bounded(CHANNEL_BOUND);
+/// Starts the TCP debugger listening on the given address in a separate thread and returns the
+/// handle it controls. The execution doesn't start before a client sent the start request.
+/// Fails if the address can't be listened on.
+pub fn start_debugger(host: &str, port: u16, symbols: SymbolMap) -> io::Result<DebugHandle> {
+    let tcp = TcpHandler::start(host, port)?;
+    let (handle, command_sender, message_receiver) = start_in_process_debugger();
+    thread::spawn(move || Debugger::new(message_receiver, command_sender, symbols).run(tcp));
 
-    thread::spawn(move || Debugger::new(receiver, breakpoint_sender).run());
-
-    DebugHandle {
+    Ok(DebugHandle {
         state: BreakpointHandleState::WaitingForStart,
+        ..handle
+    })
+}
+
+/// Returns a debug handle together with the channels to control it, so that a host program can
+/// act as the debugger front-end without going through TCP. Unlike with `start_debugger`, the
+/// execution starts immediately.
+pub fn start_in_process_debugger() -> (DebugHandle, Sender<DebugCommand>, Receiver<DebugMessage>) {
+    let (message_sender, message_receiver) = bounded(CHANNEL_BOUND);
+    let (command_sender, command_receiver) = bounded(CHANNEL_BOUND);
+
+    let handle = DebugHandle {
+        state: BreakpointHandleState::Running,
         breakpoints: HashSet::new(),
-        sender: Some(sender),
-        receiver: Some(breakpoint_receiver),
+        watchpoints: HashMap::new(),
+        opcode_breaks: HashSet::new(),
+        sender: Some(message_sender),
+        receiver: Some(command_receiver),
         receive_cache: VecDeque::new(),
         should_pause: false,
         call_stack: Vec::new(),
+        step_over_target: None,
+        step_out_depth: None,
+        remaining_instructions: None,
         did_execute_last_cycle: true,
-    }
+    };
+    (handle, command_sender, message_receiver)
 }
 
 impl DebugHandle {
@@ -106,11 +210,16 @@ impl DebugHandle {
         Self {
             state: BreakpointHandleState::Running,
             breakpoints: HashSet::with_capacity(0),
+            watchpoints: HashMap::with_capacity(0),
+            opcode_breaks: HashSet::with_capacity(0),
             sender: None,
             receiver: None,
             receive_cache: VecDeque::with_capacity(0),
             should_pause: false,
             call_stack: Vec::with_capacity(0),
+            step_over_target: None,
+            step_out_depth: None,
+            remaining_instructions: None,
             did_execute_last_cycle: true,
         }
     }
@@ -127,6 +236,9 @@ impl DebugHandle {
         use BreakpointHandleState::*;
 
         let instruction_pointer = processor.get_instruction_pointer();
+        // frames whose return address is no longer on the stack have been left (e.g. by popping
+        // the return address manually or by resetting the stack pointer)
+        self.drop_frames_above(processor.get_stack_pointer());
 
         if self.state == WaitingForStart {
             self.wait_for_start();
@@ -135,23 +247,31 @@ impl DebugHandle {
         }
 
         if self.state == Breaking {
+            // keep last-seen values up to date so that stepping does not trigger stale hits later
+            self.update_watchpoints(memory);
             if self.did_execute_last_cycle {
-                self.send_break_state(&processor.registers);
+                self.send_break_state(processor, false);
                 self.send(DebugMessage::Breaking(instruction_pointer));
             }
         } else {
-            self.start_breaking_if_requested(instruction_pointer, processor);
+            self.start_breaking_if_requested(instruction_pointer, processor, memory);
         }
 
         let result;
         if self.state == Breaking {
-            result = self.breaking(processor);
+            result = self.breaking(processor, memory);
+        } else if self.state == WaitingForStart {
+            // reset by the debugger, the next call waits for the start request
+            result = ShouldExecuteInstruction::No
         } else {
             result = ShouldExecuteInstruction::Yes
         }
 
         if let ShouldExecuteInstruction::Yes = result {
-            self.track_call_stack(memory, instruction_pointer);
+            self.track_call_stack(processor, memory, instruction_pointer);
+            if let Some(remaining) = &mut self.remaining_instructions {
+                *remaining -= 1;
+            }
         }
 
         self.did_execute_last_cycle = result == ShouldExecuteInstruction::Yes;
@@ -168,7 +288,12 @@ impl DebugHandle {
         }
     }
 
-    fn start_breaking_if_requested(&mut self, instruction_pointer: Word, processor: &Processor) {
+    fn start_breaking_if_requested(
+        &mut self,
+        instruction_pointer: Word,
+        processor: &Processor,
+        memory: &Memory,
+    ) {
         use BreakpointHandleState::*;
 
         if self.state == Breaking {
@@ -176,54 +301,211 @@ impl DebugHandle {
         }
 
         self.receive_updates_non_blocking();
+        if self.state == WaitingForStart {
+            return;
+        }
 
         let mut should_start_breaking = None;
-        let hit_breakpoint = self.breakpoints.contains(&instruction_pointer);
+        // the dummy handle (no debugger attached) ignores `DebugBreak` instructions
+        let hit_breakpoint = self.breakpoints.contains(&instruction_pointer)
+            || (self.sender.is_some() && is_debug_break(memory, instruction_pointer))
+            || (!self.opcode_breaks.is_empty()
+                && memory
+                    .read_opcode(instruction_pointer)
+                    .is_ok_and(|opcode| self.opcode_breaks.contains(opcode.get_mnemonic())));
+        let hit_watchpoint = self.update_watchpoints(memory);
+        let finished_step_over =
+            self.step_over_target == Some((instruction_pointer, self.call_stack.len()));
+        let finished_run = self.remaining_instructions == Some(0);
+        let finished_step_out = self
+            .step_out_depth
+            .is_some_and(|depth| self.call_stack.len() < depth);
 
         if self.should_pause {
             should_start_breaking = Some(DebugMessage::Pausing(instruction_pointer));
         } else if hit_breakpoint {
             should_start_breaking = Some(DebugMessage::HitBreakpoint(instruction_pointer));
+        } else if hit_watchpoint.is_some() {
+            should_start_breaking = hit_watchpoint;
+        } else if finished_step_over || finished_step_out || finished_run {
+            should_start_breaking = Some(DebugMessage::Breaking(instruction_pointer));
         }
 
         self.should_pause = false;
 
         if let Some(break_message) = should_start_breaking {
             self.state = Breaking;
-            self.receive_cache.clear();
-            self.send_break_state(&processor.registers);
+            self.step_over_target = None;
+            self.step_out_depth = None;
+            self.remaining_instructions = None;
+            self.receive_cache
+                .retain(|command| !is_run_control(command));
+            self.send_break_state(processor, false);
             self.send(break_message);
         }
     }
 
-    fn breaking(&mut self, processor: &mut Processor) -> ShouldExecuteInstruction {
+    /// Reads the current value of every watched address and remembers it. Returns
+    /// a hit notification if any of the values changed since the last check.
+    fn update_watchpoints(&mut self, memory: &Memory) -> Option<DebugMessage> {
+        let mut hit = None;
+        for (&address, last_seen) in self.watchpoints.iter_mut() {
+            let current = match memory.try_read_data(address) {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            match *last_seen {
+                Some(old) if old != current && hit.is_none() => {
+                    hit = Some(DebugMessage::HitWatchpoint {
+                        address,
+                        old,
+                        new: current,
+                    });
+                }
+                _ => {}
+            }
+            *last_seen = Some(current);
+        }
+        hit
+    }
+
+    fn breaking(
+        &mut self,
+        processor: &mut Processor,
+        memory: &mut Memory,
+    ) -> ShouldExecuteInstruction {
         use DebugCommand::*;
 
         self.receive_updates_non_blocking();
 
         if let Some(message) = self.receive_cache.pop_front() {
             match message {
-                Terminate => std::process::exit(0),
+                Terminate => return ShouldExecuteInstruction::Terminate,
                 StepOne => return ShouldExecuteInstruction::Yes,
+                RunCycles(count) => {
+                    // the counter is checked before the next instruction, so don't execute one here
+                    self.remaining_instructions = Some(count);
+                    self.state = BreakpointHandleState::Running;
+                }
+                StepOver => {
+                    let instruction_pointer = processor.get_instruction_pointer();
+                    if is_call(memory, instruction_pointer) {
+                        // the call stack is only updated after this decision, so its current
+                        // length is the depth we return to after the subroutine
+                        self.step_over_target = Some((
+                            instruction_pointer + Instruction::SIZE as Address,
+                            self.call_stack.len(),
+                        ));
+                        self.state = BreakpointHandleState::Running;
+                    }
+                    return ShouldExecuteInstruction::Yes;
+                }
                 Continue => {
                     self.state = BreakpointHandleState::Running;
                     return ShouldExecuteInstruction::Yes;
                 }
+                StepOut => {
+                    if !self.call_stack.is_empty() {
+                        self.step_out_depth = Some(self.call_stack.len());
+                    }
+                    self.state = BreakpointHandleState::Running;
+                    return ShouldExecuteInstruction::Yes;
+                }
                 SetRegister(register, value) => {
-                    processor.registers[Register(register)] = value;
+                    let register = Register(register);
+                    if let Err(message) = check_register_value(register, value, memory) {
+                        self.send(DebugMessage::Error(message));
+                    } else {
+                        processor.registers[register] = value;
+                        if register == Processor::INSTRUCTION_POINTER {
+                            // the front-end still shows the old location
+                            self.send_break_state(processor, false);
+                            self.send(DebugMessage::Breaking(value));
+                        }
+                    }
+                }
+                ReadMemory { address, length } => {
+                    let start = (address as usize).min(memory.size());
+                    let end = start.saturating_add(length).min(memory.size());
+                    self.send(DebugMessage::MemoryContents {
+                        address,
+                        bytes: memory.data()[start..end].to_vec(),
+                    });
+                }
+                Disassemble { address, count } => {
+                    let end = (address as usize)
+                        .saturating_add(count.saturating_mul(Instruction::SIZE))
+                        .min(Address::MAX as usize) as Address;
+                    let instructions = memory
+                        .instructions(address..end)
+                        .flat_map(|(address, opcode)| match opcode {
+                            Ok(opcode) => vec![DisassembledInstruction {
+                                address,
+                                mnemonic: opcode.get_mnemonic().to_string(),
+                                text: disassembler::format_opcode(opcode),
+                            }],
+                            Err(_) => {
+                                let bytes = &memory.data()[address as usize..][..Instruction::SIZE];
+                                disassembler::format_words(bytes, address)
+                                    .into_iter()
+                                    .map(|(address, text)| DisassembledInstruction {
+                                        address,
+                                        mnemonic: ".word".to_string(),
+                                        text,
+                                    })
+                                    .collect()
+                            }
+                        })
+                        .collect();
+                    self.send(DebugMessage::Disassembly(instructions));
+                }
+                WriteMemory { address, bytes } => {
+                    let end = (address as usize).checked_add(bytes.len());
+                    if end.is_some_and(|end| end <= memory.size()) {
+                        // memory writes mark the affected instruction cache slots as dirty
+                        for (current, byte) in (address..).zip(bytes) {
+                            memory.write_byte(current, byte);
+                        }
+                    } else {
+                        self.send(DebugMessage::Error(format!(
+                            "cannot write {} bytes at address {:#010x}: out of bounds",
+                            bytes.len(),
+                            address
+                        )));
+                    }
                 }
-                Pause | SetBreakpoints(_) | RemoveBreakpoints(_) => panic!("BreakpointHandle: Message should never be added to the message cache but handled immediately."),
+                Pause
+                | SetBreakpoints(_)
+                | RemoveBreakpoints(_)
+                | SetWatchpoints(_)
+                | RemoveWatchpoints(_)
+                | SetOpcodeBreaks(_)
+                | RemoveOpcodeBreaks(_)
+                | ListBreakpoints
+                | Reset => panic!("BreakpointHandle: Message should never be added to the message cache but handled immediately."),
             }
         }
 
         ShouldExecuteInstruction::No
     }
 
+    /// Reports the final state after the machine has halted, since no further instruction (and
+    /// therefore no further break) follows.
+    #[inline]
+    pub fn after_halt(&self, processor: &Processor) {
+        self.send_break_state(processor, true);
+    }
+
     #[inline]
-    fn send_break_state<const SIZE: usize>(&self, registers: &crate::processor::Registers<SIZE>) {
+    fn send_break_state(&self, processor: &Processor, is_halted: bool) {
         self.send(DebugMessage::BreakState {
-            registers: registers.contents().to_vec(),
+            registers: processor.registers.contents().to_vec(),
             call_stack: self.call_stack.clone(),
+            instruction_pointer: processor.get_instruction_pointer(),
+            stack_pointer: processor.get_stack_pointer(),
+            flags: processor.registers[Processor::FLAGS],
+            cycle_count: processor.get_cycle_count(),
+            is_halted,
         });
     }
 
@@ -237,15 +519,13 @@ impl DebugHandle {
     }
 
     fn receive_updates_non_blocking(&mut self) {
-        loop {
-            if let Some(ref receiver) = self.receiver {
-                match receiver.try_recv() {
-                    Ok(message) => self.handle_message(message),
-                    Err(TryRecvError::Disconnected) => {
-                        panic!("Cannot receive breakpoint updates after debugger has been stopped.")
-                    }
-                    Err(TryRecvError::Empty) => break,
+        while let Some(ref receiver) = self.receiver {
+            match receiver.try_recv() {
+                Ok(message) => self.handle_message(message),
+                Err(TryRecvError::Disconnected) => {
+                    panic!("Cannot receive breakpoint updates after debugger has been stopped.")
                 }
+                Err(TryRecvError::Empty) => break,
             }
         }
     }
@@ -264,38 +544,172 @@ impl DebugHandle {
                     self.breakpoints.remove(&location);
                 }
             }
+            DebugCommand::SetWatchpoints(addresses) => {
+                for address in addresses {
+                    self.watchpoints.entry(address).or_insert(None);
+                }
+            }
+            DebugCommand::RemoveWatchpoints(addresses) => {
+                for address in addresses {
+                    self.watchpoints.remove(&address);
+                }
+            }
+            DebugCommand::SetOpcodeBreaks(mnemonics) => {
+                self.opcode_breaks.extend(mnemonics);
+            }
+            DebugCommand::RemoveOpcodeBreaks(mnemonics) => {
+                for mnemonic in mnemonics {
+                    self.opcode_breaks.remove(&mnemonic);
+                }
+            }
+            DebugCommand::ListBreakpoints => {
+                let mut addresses: Vec<_> = self.breakpoints.iter().copied().collect();
+                addresses.sort_unstable();
+                let mut watchpoints: Vec<_> = self.watchpoints.keys().copied().collect();
+                watchpoints.sort_unstable();
+                let mut opcode_breaks: Vec<_> = self.opcode_breaks.iter().cloned().collect();
+                opcode_breaks.sort_unstable();
+                self.send(DebugMessage::Breakpoints {
+                    addresses,
+                    watchpoints,
+                    opcode_breaks,
+                });
+            }
+            DebugCommand::Reset => {
+                self.state = BreakpointHandleState::WaitingForStart;
+                self.breakpoints.clear();
+                self.watchpoints.clear();
+                self.opcode_breaks.clear();
+                self.receive_cache.clear();
+                self.should_pause = false;
+                self.step_over_target = None;
+                self.step_out_depth = None;
+                self.remaining_instructions = None;
+            }
             _ => self.receive_cache.push_back(message),
         }
     }
 
-    fn track_call_stack(&mut self, memory: &mut Memory, instruction_pointer: Address) {
-        let opcode = memory.read_opcode(instruction_pointer);
-        match opcode {
-            Ok(Opcode::CallImmediate { .. })
-            | Ok(Opcode::CallRegister { .. })
-            | Ok(Opcode::CallPointer { .. }) => {
-                self.call_stack.push(instruction_pointer);
-            }
-            Ok(Opcode::Return {}) => {
-                self.call_stack.pop();
-            }
-            _ => {}
+    fn track_call_stack(
+        &mut self,
+        processor: &Processor,
+        memory: &Memory,
+        instruction_pointer: Address,
+    ) {
+        let stack_pointer = processor.get_stack_pointer();
+        if is_call(memory, instruction_pointer) {
+            self.call_stack.push(StackFrame {
+                call_site: instruction_pointer,
+                return_address: instruction_pointer + Instruction::SIZE as Address,
+                stack_address: stack_pointer,
+            });
+        } else if let Ok(Opcode::Return {}) = memory.read_opcode(instruction_pointer) {
+            self.drop_frames_above(stack_pointer.saturating_sub(Word::SIZE as Address));
+        }
+    }
+
+    /// Removes all frames whose return address is stored at or above the given stack address.
+    fn drop_frames_above(&mut self, stack_address: Address) {
+        while self
+            .call_stack
+            .last()
+            .is_some_and(|frame| frame.stack_address >= stack_address)
+        {
+            self.call_stack.pop();
         }
     }
 }
 
+/// Run control commands queued while the machine was running refer to a state the front-end hasn't
+/// seen, so they are dropped when breaking. All other commands are answered once breaking.
+fn is_run_control(command: &DebugCommand) -> bool {
+    matches!(
+        command,
+        DebugCommand::Continue
+            | DebugCommand::RunCycles(_)
+            | DebugCommand::StepOne
+            | DebugCommand::StepOver
+            | DebugCommand::StepOut
+    )
+}
+
+fn is_call(memory: &Memory, instruction_pointer: Address) -> bool {
+    matches!(
+        memory.read_opcode(instruction_pointer),
+        Ok(Opcode::CallImmediate { .. })
+            | Ok(Opcode::CallRegister { .. })
+            | Ok(Opcode::CallPointer { .. })
+    )
+}
+
+fn check_mnemonics(mnemonics: &[String]) -> Result<(), String> {
+    let opcodes = Opcode::as_hashmap();
+    match mnemonics
+        .iter()
+        .find(|mnemonic| !opcodes.contains_key(mnemonic.as_str()))
+    {
+        Some(mnemonic) => Err(format!("unknown opcode '{mnemonic}'")),
+        None => Ok(()),
+    }
+}
+
+/// `DebugBreak` instructions act like breakpoints that are baked into the program.
+fn is_debug_break(memory: &Memory, instruction_pointer: Address) -> bool {
+    matches!(
+        memory.read_opcode(instruction_pointer),
+        Ok(Opcode::DebugBreak {})
+    )
+}
+
+/// Rejects values that would make the processor fail: instruction pointers that don't point to
+/// an executable instruction slot and flags with unknown bits.
+fn check_register_value(register: Register, value: Word, memory: &Memory) -> Result<(), String> {
+    if register == Processor::INSTRUCTION_POINTER
+        && (!(value as usize).is_multiple_of(Instruction::SIZE)
+            || value as usize + Instruction::SIZE > memory.size())
+    {
+        return Err(format!("invalid instruction pointer {value:#010x}"));
+    }
+    if register == Processor::FLAGS && Flag::from_bits(value).is_none() {
+        return Err(format!("invalid flags {value:#010x}"));
+    }
+    Ok(())
+}
+
+/// Translates register names (`pc`, `sp`, `flags` or `r<n>`) into register indices.
+fn resolve_register(register: RegisterName) -> Result<u8, String> {
+    let name = match register {
+        RegisterName::Index(index) => return Ok(index),
+        RegisterName::Name(name) => name,
+    };
+    match name.as_str() {
+        "pc" => Ok(Processor::INSTRUCTION_POINTER.0),
+        "sp" => Ok(Processor::STACK_POINTER.0),
+        "flags" => Ok(Processor::FLAGS.0),
+        _ => name
+            .strip_prefix('r')
+            .and_then(|index| index.parse().ok())
+            .ok_or_else(|| format!("unknown register '{name}'")),
+    }
+}
+
 impl Debugger {
-    fn new(receiver: Receiver<DebugMessage>, breakpoint_sender: Sender<DebugCommand>) -> Self {
+    fn new(
+        receiver: Receiver<DebugMessage>,
+        breakpoint_sender: Sender<DebugCommand>,
+        symbols: SymbolMap,
+    ) -> Self {
         Self {
             receiver,
             breakpoint_sender,
             started: false,
             start_notifications: Vec::new(),
+            had_client: false,
+            symbols,
         }
     }
 
-    fn run(mut self) {
-        let mut tcp = TcpHandler::start();
+    fn run(mut self, mut tcp: TcpHandler) {
         let tcp_poll = tick(TCP_POLL_INTERVAL);
 
         loop {
@@ -328,8 +742,15 @@ impl Debugger {
         match result {
             Ok(PollReturn::Nothing | PollReturn::ClientDisconnected) => {}
             Ok(PollReturn::ClientConnected) => {
+                if self.had_client {
+                    // don't let the new client inherit the state of the previous session
+                    self.started = false;
+                    self.send_to_breakpoint_handler(DebugCommand::Reset);
+                }
+                self.had_client = true;
                 let message = &tcp_protocol::Response::Hello {
                     pid: std::process::id(),
+                    supported_framings: vec![Framing::NulTerminated, Framing::LengthPrefixed],
                 };
                 self.handle_tcp_result(tcp.send(message));
             }
@@ -338,7 +759,7 @@ impl Debugger {
                     if let tcp_protocol::Request::Terminate {} = request {
                         should_terminate = ShouldTerminate::Yes;
                     }
-                    self.handle_request(request);
+                    self.handle_request(request, tcp);
                 }
             }
             Err(_) => self.handle_tcp_result(result),
@@ -356,11 +777,21 @@ impl Debugger {
                 }
             }
             DebugMessage::HitBreakpoint(location) => {
-                let message = tcp_protocol::Response::HitBreakpoint { location };
+                let message = tcp_protocol::Response::HitBreakpoint {
+                    location,
+                    symbol: self.symbols.symbol_at(location).cloned(),
+                };
+                self.handle_tcp_result(tcp.send(&message));
+            }
+            DebugMessage::HitWatchpoint { address, old, new } => {
+                let message = tcp_protocol::Response::WatchpointHit { address, old, new };
                 self.handle_tcp_result(tcp.send(&message));
             }
             DebugMessage::Breaking(location) => {
-                let message = tcp_protocol::Response::Breaking { location };
+                let message = tcp_protocol::Response::Breaking {
+                    location,
+                    symbol: self.symbols.symbol_at(location).cloned(),
+                };
                 self.handle_tcp_result(tcp.send(&message));
             }
             DebugMessage::Pausing(location) => {
@@ -370,13 +801,47 @@ impl Debugger {
             DebugMessage::BreakState {
                 registers,
                 call_stack,
+                instruction_pointer,
+                stack_pointer,
+                flags,
+                cycle_count,
+                is_halted,
             } => {
                 let message = tcp_protocol::Response::BreakState {
                     registers,
                     call_stack,
+                    instruction_pointer,
+                    stack_pointer,
+                    flags,
+                    cycle_count,
+                    is_halted,
+                };
+                self.handle_tcp_result(tcp.send(&message));
+            }
+            DebugMessage::Breakpoints {
+                addresses,
+                watchpoints,
+                opcode_breaks,
+            } => {
+                let message = tcp_protocol::Response::Breakpoints {
+                    addresses,
+                    watchpoints,
+                    opcode_breaks,
                 };
                 self.handle_tcp_result(tcp.send(&message));
             }
+            DebugMessage::MemoryContents { address, bytes } => {
+                let message = tcp_protocol::Response::MemoryContents { address, bytes };
+                self.handle_tcp_result(tcp.send(&message));
+            }
+            DebugMessage::Disassembly(instructions) => {
+                let message = tcp_protocol::Response::Disassembly { instructions };
+                self.handle_tcp_result(tcp.send(&message));
+            }
+            DebugMessage::Error(message) => {
+                let message = tcp_protocol::Response::Error { message };
+                self.handle_tcp_result(tcp.send(&message));
+            }
         }
     }
 
@@ -390,7 +855,7 @@ impl Debugger {
         }
     }
 
-    fn handle_request(&mut self, request: tcp_protocol::Request) {
+    fn handle_request(&mut self, request: tcp_protocol::Request, tcp: &mut TcpHandler) {
         match request {
             tcp_protocol::Request::StartExecution { stop_on_entry } => {
                 if stop_on_entry {
@@ -400,19 +865,89 @@ impl Debugger {
                 self.start_notifications.clear(); // ==> notify all
             }
             tcp_protocol::Request::SetBreakpoints { locations } => {
-                self.send_to_breakpoint_handler(DebugCommand::SetBreakpoints(locations))
+                match self.resolve_locations(locations) {
+                    Ok(addresses) => {
+                        self.send_to_breakpoint_handler(DebugCommand::SetBreakpoints(addresses))
+                    }
+                    Err(message) => self.send_error(tcp, message),
+                }
             }
             tcp_protocol::Request::RemoveBreakpoints { locations } => {
-                self.send_to_breakpoint_handler(DebugCommand::RemoveBreakpoints(locations))
+                match self.resolve_locations(locations) {
+                    Ok(addresses) => {
+                        self.send_to_breakpoint_handler(DebugCommand::RemoveBreakpoints(addresses))
+                    }
+                    Err(message) => self.send_error(tcp, message),
+                }
+            }
+            tcp_protocol::Request::SetWatchpoints { addresses } => {
+                match addresses
+                    .iter()
+                    .find(|&&address| !(address as usize).is_multiple_of(Word::SIZE))
+                {
+                    Some(address) => self.send_error(
+                        tcp,
+                        format!(
+                            "watchpoint address {address:#010x} must be divisible by {}",
+                            Word::SIZE
+                        ),
+                    ),
+                    None => {
+                        self.send_to_breakpoint_handler(DebugCommand::SetWatchpoints(addresses))
+                    }
+                }
+            }
+            tcp_protocol::Request::RemoveWatchpoints { addresses } => {
+                self.send_to_breakpoint_handler(DebugCommand::RemoveWatchpoints(addresses))
+            }
+            tcp_protocol::Request::SetOpcodeBreaks { mnemonics } => {
+                match check_mnemonics(&mnemonics) {
+                    Ok(()) => {
+                        self.send_to_breakpoint_handler(DebugCommand::SetOpcodeBreaks(mnemonics))
+                    }
+                    Err(message) => self.send_error(tcp, message),
+                }
+            }
+            tcp_protocol::Request::RemoveOpcodeBreaks { mnemonics } => {
+                self.send_to_breakpoint_handler(DebugCommand::RemoveOpcodeBreaks(mnemonics))
+            }
+            tcp_protocol::Request::ListBreakpoints {} => {
+                self.send_to_breakpoint_handler(DebugCommand::ListBreakpoints)
             }
             tcp_protocol::Request::Continue {} => {
                 self.send_to_breakpoint_handler(DebugCommand::Continue)
             }
+            tcp_protocol::Request::RunCycles { count } => {
+                self.send_to_breakpoint_handler(DebugCommand::RunCycles(count))
+            }
             tcp_protocol::Request::StepOne {} => {
                 self.send_to_breakpoint_handler(DebugCommand::StepOne)
             }
+            tcp_protocol::Request::StepOver {} => {
+                self.send_to_breakpoint_handler(DebugCommand::StepOver)
+            }
+            tcp_protocol::Request::StepOut {} => {
+                self.send_to_breakpoint_handler(DebugCommand::StepOut)
+            }
             tcp_protocol::Request::SetRegister { register, value } => {
-                self.send_to_breakpoint_handler(DebugCommand::SetRegister(register, value))
+                match resolve_register(register) {
+                    Ok(register) => {
+                        self.send_to_breakpoint_handler(DebugCommand::SetRegister(register, value))
+                    }
+                    Err(message) => self.send_error(tcp, message),
+                }
+            }
+            tcp_protocol::Request::ReadMemory { address, length } => {
+                self.send_to_breakpoint_handler(DebugCommand::ReadMemory { address, length })
+            }
+            tcp_protocol::Request::Disassemble { address, count } => {
+                self.send_to_breakpoint_handler(DebugCommand::Disassemble { address, count })
+            }
+            tcp_protocol::Request::WriteMemory { address, bytes } => {
+                self.send_to_breakpoint_handler(DebugCommand::WriteMemory { address, bytes })
+            }
+            tcp_protocol::Request::SetFraming { .. } => {
+                unreachable!("framing changes are handled by the TcpHandler")
             }
             tcp_protocol::Request::Terminate {} => {
                 self.send_to_breakpoint_handler(DebugCommand::Terminate);
@@ -420,6 +955,26 @@ impl Debugger {
         }
     }
 
+    /// Translates symbol names into addresses. Fails (without setting any breakpoint) if a name
+    /// is unknown.
+    fn resolve_locations(&self, locations: Vec<Location>) -> Result<Vec<Address>, String> {
+        locations
+            .into_iter()
+            .map(|location| match location {
+                Location::Address(address) => Ok(address),
+                Location::Symbol(name) => self
+                    .symbols
+                    .address_of(&name)
+                    .ok_or_else(|| format!("unknown symbol '{name}'")),
+            })
+            .collect()
+    }
+
+    fn send_error(&self, tcp: &mut TcpHandler, message: String) {
+        let message = tcp_protocol::Response::Error { message };
+        self.handle_tcp_result(tcp.send(&message));
+    }
+
     fn send_to_breakpoint_handler(&mut self, message: DebugCommand) {
         match self.breakpoint_sender.try_send(message) {
             Ok(_) | Err(crossbeam_channel::TrySendError::Full(_)) => {}
@@ -429,3 +984,888 @@ impl Debugger {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Instant,
+    };
+
+    use super::*;
+
+    fn create_connected_handle() -> (DebugHandle, Sender<DebugCommand>, Receiver<DebugMessage>) {
+        start_in_process_debugger()
+    }
+
+    #[test]
+    fn terminate_while_breaking_is_passed_to_the_machine() {
+        let (mut handle, commands, _messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+
+        commands.send(DebugCommand::Pause).unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::No
+        );
+        commands.send(DebugCommand::Terminate).unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::Terminate
+        );
+    }
+
+    #[test]
+    fn writing_to_watched_address_starts_breaking() {
+        let (mut handle, commands, messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+        let watched_address = 0x1000;
+
+        commands
+            .send(DebugCommand::SetWatchpoints(vec![watched_address]))
+            .unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::Yes
+        );
+        assert!(messages.try_recv().is_err());
+
+        memory.write_data(watched_address, 42);
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::No
+        );
+        assert_eq!(handle.state, BreakpointHandleState::Breaking);
+
+        let hit = messages
+            .try_iter()
+            .find(|message| matches!(message, DebugMessage::HitWatchpoint { .. }));
+        assert!(matches!(
+            hit,
+            Some(DebugMessage::HitWatchpoint {
+                address: 0x1000,
+                old: 0,
+                new: 42
+            })
+        ));
+    }
+
+    #[test]
+    fn read_memory_while_paused_returns_requested_bytes() {
+        let (mut handle, commands, messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+        memory.write_data(0x2000, 0xDEADBEEF);
+
+        commands.send(DebugCommand::Pause).unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::No
+        );
+
+        commands
+            .send(DebugCommand::ReadMemory {
+                address: 0x2001,
+                length: 3,
+            })
+            .unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::No
+        );
+
+        let contents = messages.try_iter().find_map(|message| match message {
+            DebugMessage::MemoryContents { address, bytes } => Some((address, bytes)),
+            _ => None,
+        });
+        assert_eq!(contents, Some((0x2001, vec![0xAD, 0xBE, 0xEF])));
+    }
+
+    #[test]
+    fn queries_queued_while_running_are_answered_after_breaking() {
+        let (mut handle, commands, messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+        memory.write_data(0x2000, 0xDEADBEEF);
+
+        // all of these arrive while the machine is running
+        commands.send(DebugCommand::Continue).unwrap();
+        commands
+            .send(DebugCommand::ReadMemory {
+                address: 0x2000,
+                length: 2,
+            })
+            .unwrap();
+        commands.send(DebugCommand::Pause).unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::No
+        );
+        let contents = messages.try_iter().find_map(|message| match message {
+            DebugMessage::MemoryContents { address, bytes } => Some((address, bytes)),
+            _ => None,
+        });
+        assert_eq!(contents, Some((0x2000, vec![0xDE, 0xAD])));
+
+        // the stale `Continue` has been dropped
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::No
+        );
+        assert_eq!(handle.state, BreakpointHandleState::Breaking);
+    }
+
+    #[test]
+    fn step_over_call_stops_after_returning() {
+        use crate::address_constants::ENTRY_POINT;
+
+        let (mut handle, commands, _messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+        let subroutine = ENTRY_POINT + 0x100;
+        let return_address = ENTRY_POINT + Instruction::SIZE as Address;
+        memory.write_opcode(
+            ENTRY_POINT,
+            Opcode::CallImmediate {
+                immediate: subroutine,
+            },
+        );
+        memory.write_opcode(subroutine, Opcode::Return {});
+
+        commands.send(DebugCommand::Pause).unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::No
+        );
+
+        commands.send(DebugCommand::StepOver).unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::Yes
+        );
+        processor.stack_push(&mut memory, return_address).unwrap();
+
+        // reaching the return address inside the subroutine (e.g. by recursion) must not stop
+        processor.set_instruction_pointer(return_address);
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::Yes
+        );
+
+        processor.set_instruction_pointer(subroutine);
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::Yes
+        );
+
+        processor.set_instruction_pointer(return_address);
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::No
+        );
+        assert_eq!(handle.state, BreakpointHandleState::Breaking);
+    }
+
+    /// Starts the TCP debugger on an OS-assigned port.
+    fn start_tcp_debugger() -> (DebugHandle, u16) {
+        start_tcp_debugger_with_symbols(SymbolMap::default())
+    }
+
+    fn start_tcp_debugger_with_symbols(symbols: SymbolMap) -> (DebugHandle, u16) {
+        let tcp = TcpHandler::start("127.0.0.1", 0).unwrap();
+        let port = tcp.local_port();
+        let (handle, commands, messages) = start_in_process_debugger();
+        thread::spawn(move || Debugger::new(messages, commands, symbols).run(tcp));
+        let handle = DebugHandle {
+            state: BreakpointHandleState::WaitingForStart,
+            ..handle
+        };
+        (handle, port)
+    }
+
+    fn send_request(client: &mut TcpStream, framing: Framing, request: &str) {
+        client
+            .write_all(&framing.frame(request.as_bytes()))
+            .unwrap();
+    }
+
+    fn read_response(client: &mut TcpStream, framing: Framing) -> String {
+        let mut response = Vec::new();
+        match framing {
+            Framing::NulTerminated => {
+                let mut byte = [0];
+                while client.read(&mut byte).unwrap() == 1 && byte[0] != 0 {
+                    response.push(byte[0]);
+                }
+            }
+            Framing::LengthPrefixed => {
+                let mut length = [0; 4];
+                client.read_exact(&mut length).unwrap();
+                response.resize(u32::from_be_bytes(length) as usize, 0);
+                client.read_exact(&mut response).unwrap();
+            }
+        }
+        String::from_utf8(response).unwrap()
+    }
+
+    fn connect_client(port: u16) -> TcpStream {
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let hello = read_response(&mut client, Framing::NulTerminated);
+        assert!(hello.starts_with(r#"{"Hello""#));
+        client
+    }
+
+    #[test]
+    fn starting_on_occupied_port_fails() {
+        let first = TcpHandler::start("127.0.0.1", 0).unwrap();
+        let error = start_debugger("127.0.0.1", first.local_port(), SymbolMap::default())
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("cannot open debug TCP interface on 127.0.0.1:"));
+    }
+
+    #[test]
+    fn handlers_on_port_zero_get_distinct_ports() {
+        let first = TcpHandler::start("127.0.0.1", 0).unwrap();
+        let second = TcpHandler::start("127.0.0.1", 0).unwrap();
+        assert_ne!(first.local_port(), 0);
+        assert_ne!(first.local_port(), second.local_port());
+    }
+
+    const START_REQUEST: &str = r#"{"StartExecution":{"stop_on_entry":false}}"#;
+
+    #[test]
+    fn reconnecting_client_starts_with_clean_state() {
+        let (mut handle, port) = start_tcp_debugger();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+        let mut run_until = |handle: &mut DebugHandle, condition: fn(&DebugHandle) -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !condition(handle) {
+                assert!(Instant::now() < deadline, "timed out");
+                handle.before_instruction_execution(&mut processor, &mut memory);
+                thread::sleep(Duration::from_millis(1));
+            }
+        };
+        let send = |client: &mut TcpStream, request: &str| {
+            send_request(client, Framing::NulTerminated, request)
+        };
+
+        let mut first_client = connect_client(port);
+        send(&mut first_client, START_REQUEST);
+        send(
+            &mut first_client,
+            r#"{"SetBreakpoints":{"locations":[256]}}"#,
+        );
+        send(
+            &mut first_client,
+            r#"{"SetWatchpoints":{"addresses":[512]}}"#,
+        );
+        run_until(&mut handle, |handle| {
+            !handle.breakpoints.is_empty() && !handle.watchpoints.is_empty()
+        });
+        drop(first_client);
+
+        let mut second_client = connect_client(port);
+        send(&mut second_client, START_REQUEST);
+        run_until(&mut handle, |handle| {
+            handle.breakpoints.is_empty() && handle.state == BreakpointHandleState::Running
+        });
+        assert!(handle.watchpoints.is_empty());
+    }
+
+    #[test]
+    fn length_prefixed_framing_is_negotiated() {
+        let (mut handle, port) = start_tcp_debugger();
+        let mut client = connect_client(port);
+
+        send_request(
+            &mut client,
+            Framing::NulTerminated,
+            r#"{"SetFraming":{"framing":"LengthPrefixed"}}"#,
+        );
+        assert_eq!(
+            read_response(&mut client, Framing::LengthPrefixed),
+            r#"{"FramingChanged":{"framing":"LengthPrefixed"}}"#
+        );
+
+        send_request(&mut client, Framing::LengthPrefixed, START_REQUEST);
+        // blocks until the start request has been received
+        assert_eq!(
+            handle.before_instruction_execution(&mut Processor::new(), &mut Memory::new()),
+            ShouldExecuteInstruction::Yes
+        );
+        assert_eq!(handle.state, BreakpointHandleState::Running);
+    }
+
+    #[test]
+    fn break_state_contains_special_registers() {
+        let (mut handle, commands, messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+        processor.registers[Processor::FLAGS] = 0b101;
+        processor.registers[Processor::STACK_POINTER] += 8;
+
+        commands.send(DebugCommand::Pause).unwrap();
+        handle.before_instruction_execution(&mut processor, &mut memory);
+
+        let break_state = messages.try_iter().find_map(|message| match message {
+            DebugMessage::BreakState {
+                registers,
+                instruction_pointer,
+                stack_pointer,
+                flags,
+                ..
+            } => Some((registers, instruction_pointer, stack_pointer, flags)),
+            _ => None,
+        });
+        let (registers, instruction_pointer, stack_pointer, flags) = break_state.unwrap();
+        assert_eq!(
+            instruction_pointer,
+            registers[Processor::INSTRUCTION_POINTER.0 as usize]
+        );
+        assert_eq!(instruction_pointer, processor.get_instruction_pointer());
+        assert_eq!(
+            stack_pointer,
+            registers[Processor::STACK_POINTER.0 as usize]
+        );
+        assert_eq!(stack_pointer, processor.get_stack_pointer());
+        assert_eq!(flags, registers[Processor::FLAGS.0 as usize]);
+        assert_eq!(flags, 0b101);
+    }
+
+    #[test]
+    fn debug_break_instruction_starts_breaking() {
+        use crate::address_constants::ENTRY_POINT;
+
+        let (mut handle, commands, messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+        memory.write_opcode(ENTRY_POINT, Opcode::DebugBreak {});
+
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::No
+        );
+        assert_eq!(handle.state, BreakpointHandleState::Breaking);
+        assert!(messages
+            .try_iter()
+            .any(|message| matches!(message, DebugMessage::HitBreakpoint(ENTRY_POINT))));
+
+        // continuing executes the (no-op) instruction instead of breaking again
+        commands.send(DebugCommand::Continue).unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::Yes
+        );
+        assert_eq!(handle.state, BreakpointHandleState::Running);
+    }
+
+    #[test]
+    fn step_over_non_call_behaves_like_step_one() {
+        let (mut handle, commands, _messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+
+        commands.send(DebugCommand::Pause).unwrap();
+        handle.before_instruction_execution(&mut processor, &mut memory);
+        commands.send(DebugCommand::StepOver).unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::Yes
+        );
+        assert_eq!(handle.state, BreakpointHandleState::Breaking);
+    }
+
+    #[test]
+    fn step_out_stops_after_call_site() {
+        use crate::address_constants::ENTRY_POINT;
+
+        let (mut handle, commands, _messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+        let subroutine = ENTRY_POINT + 0x100;
+        memory.write_opcode(
+            ENTRY_POINT,
+            Opcode::CallImmediate {
+                immediate: subroutine,
+            },
+        );
+        memory.write_opcode(subroutine, Opcode::NoOp {});
+        memory.write_opcode(subroutine + Instruction::SIZE as Address, Opcode::Return {});
+
+        commands.send(DebugCommand::Pause).unwrap();
+        handle.before_instruction_execution(&mut processor, &mut memory);
+        commands.send(DebugCommand::StepOne).unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::Yes
+        );
+        processor
+            .stack_push(&mut memory, ENTRY_POINT + Instruction::SIZE as Address)
+            .unwrap();
+
+        processor.set_instruction_pointer(subroutine);
+        commands.send(DebugCommand::StepOut).unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::Yes
+        );
+
+        processor.set_instruction_pointer(subroutine + Instruction::SIZE as Address);
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::Yes
+        );
+
+        processor.set_instruction_pointer(ENTRY_POINT + Instruction::SIZE as Address);
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::No
+        );
+        assert_eq!(handle.state, BreakpointHandleState::Breaking);
+    }
+
+    #[test]
+    fn step_out_at_top_level_continues() {
+        let (mut handle, commands, _messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+
+        commands.send(DebugCommand::Pause).unwrap();
+        handle.before_instruction_execution(&mut processor, &mut memory);
+        commands.send(DebugCommand::StepOut).unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::Yes
+        );
+        assert_eq!(handle.state, BreakpointHandleState::Running);
+        assert_eq!(handle.step_out_depth, None);
+    }
+
+    /// Disassembles through a paused handle, like the front-end does.
+    fn disassemble(
+        memory: &mut Memory,
+        address: Address,
+        count: usize,
+    ) -> Vec<DisassembledInstruction> {
+        let (mut handle, commands, messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        commands.send(DebugCommand::Pause).unwrap();
+        handle.before_instruction_execution(&mut processor, memory);
+        commands
+            .send(DebugCommand::Disassemble { address, count })
+            .unwrap();
+        handle.before_instruction_execution(&mut processor, memory);
+        messages
+            .try_iter()
+            .find_map(|message| match message {
+                DebugMessage::Disassembly(instructions) => Some(instructions),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn disassemble_decodes_known_sequence() {
+        use crate::address_constants::ENTRY_POINT;
+
+        let mut memory = Memory::new();
+        memory.write_opcode(
+            ENTRY_POINT,
+            Opcode::MoveRegisterImmediate {
+                register: Register(1),
+                immediate: 42,
+            },
+        );
+        memory.write_opcode(
+            ENTRY_POINT + Instruction::SIZE as Address,
+            Opcode::AddTargetLhsRhs {
+                target: Register(2),
+                lhs: Register(1),
+                rhs: Register(1),
+            },
+        );
+        memory.write_data(ENTRY_POINT + 2 * Instruction::SIZE as Address, 0x7000_0000);
+        memory.write_opcode(
+            ENTRY_POINT + 3 * Instruction::SIZE as Address,
+            Opcode::HaltAndCatchFire {},
+        );
+
+        let instructions = disassemble(&mut memory, ENTRY_POINT, 4);
+        let mnemonics: Vec<_> = instructions
+            .iter()
+            .map(|instruction| instruction.mnemonic.as_str())
+            .collect();
+        assert_eq!(
+            mnemonics,
+            [
+                "MoveRegisterImmediate",
+                "AddTargetLhsRhs",
+                ".word",
+                ".word",
+                "HaltAndCatchFire"
+            ]
+        );
+        assert_eq!(instructions[1].text, "AddTargetLhsRhs r2, r1, r1");
+        assert_eq!(instructions[2].text, ".word 0x70000000");
+        assert_eq!(instructions[3].text, ".word 0x00000000");
+        assert_eq!(
+            instructions[3].address,
+            ENTRY_POINT + (2 * Instruction::SIZE + Word::SIZE) as Address
+        );
+        assert_eq!(
+            instructions[4].address,
+            ENTRY_POINT + 3 * Instruction::SIZE as Address
+        );
+    }
+
+    #[test]
+    fn disassemble_stops_at_end_of_memory() {
+        let mut memory = Memory::new();
+        let last_instruction = (memory.size() - Instruction::SIZE) as Address;
+        assert_eq!(disassemble(&mut memory, last_instruction, 3).len(), 1);
+    }
+
+    #[test]
+    fn write_memory_while_paused_patches_code() {
+        use crate::address_constants::ENTRY_POINT;
+
+        let (mut handle, commands, messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+        memory.write_opcode(
+            ENTRY_POINT,
+            Opcode::MoveRegisterImmediate {
+                register: Register(1),
+                immediate: 1,
+            },
+        );
+        memory.clear_dirty_instructions();
+
+        commands.send(DebugCommand::Pause).unwrap();
+        handle.before_instruction_execution(&mut processor, &mut memory);
+
+        // patch the immediate (lower four bytes of the instruction)
+        commands
+            .send(DebugCommand::WriteMemory {
+                address: ENTRY_POINT + 4,
+                bytes: 42u32.to_be_bytes().to_vec(),
+            })
+            .unwrap();
+        commands.send(DebugCommand::Continue).unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::No
+        );
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::Yes
+        );
+
+        assert!(memory.take_instruction_dirty(ENTRY_POINT));
+        assert_eq!(
+            memory.read_opcode(ENTRY_POINT),
+            Ok(Opcode::MoveRegisterImmediate {
+                register: Register(1),
+                immediate: 42,
+            })
+        );
+        assert!(!messages
+            .try_iter()
+            .any(|message| matches!(message, DebugMessage::Error(_))));
+    }
+
+    #[test]
+    fn write_memory_out_of_bounds_is_rejected() {
+        let (mut handle, commands, messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+        let last_byte = (memory.size() - 1) as Address;
+
+        commands.send(DebugCommand::Pause).unwrap();
+        handle.before_instruction_execution(&mut processor, &mut memory);
+        commands
+            .send(DebugCommand::WriteMemory {
+                address: last_byte,
+                bytes: vec![1, 2],
+            })
+            .unwrap();
+        handle.before_instruction_execution(&mut processor, &mut memory);
+
+        assert_eq!(memory.read_byte(last_byte), 0);
+        assert!(messages
+            .try_iter()
+            .any(|message| matches!(message, DebugMessage::Error(_))));
+    }
+
+    #[test]
+    fn run_cycles_breaks_after_given_number_of_instructions() {
+        use crate::address_constants::ENTRY_POINT;
+
+        let (mut handle, commands, _messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+
+        commands.send(DebugCommand::Pause).unwrap();
+        handle.before_instruction_execution(&mut processor, &mut memory);
+        commands.send(DebugCommand::RunCycles(100)).unwrap();
+        handle.before_instruction_execution(&mut processor, &mut memory);
+
+        // memory is zeroed, i.e. filled with single cycle `MoveRegisterImmediate` instructions
+        let mut num_executed = 0;
+        while handle.before_instruction_execution(&mut processor, &mut memory)
+            == ShouldExecuteInstruction::Yes
+        {
+            num_executed += 1;
+            processor.set_instruction_pointer(
+                processor.get_instruction_pointer() + Instruction::SIZE as Address,
+            );
+            assert!(num_executed <= 100);
+        }
+
+        assert_eq!(num_executed, 100);
+        assert_eq!(
+            processor.get_instruction_pointer(),
+            ENTRY_POINT + 100 * Instruction::SIZE as Address
+        );
+        assert_eq!(handle.state, BreakpointHandleState::Breaking);
+    }
+
+    #[test]
+    fn call_stack_tracks_calls_and_returns() {
+        use crate::address_constants::ENTRY_POINT;
+
+        let (mut handle, _commands, _messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+        let calls = [
+            Opcode::CallImmediate {
+                immediate: ENTRY_POINT,
+            },
+            Opcode::CallRegister {
+                register: Register(1),
+            },
+            Opcode::CallPointer {
+                pointer: Register(1),
+            },
+        ];
+        let return_address = ENTRY_POINT + calls.len() as Address * Instruction::SIZE as Address;
+        memory.write_opcode(return_address, Opcode::Return {});
+
+        for (address, opcode) in (ENTRY_POINT..).step_by(Instruction::SIZE).zip(calls) {
+            memory.write_opcode(address, opcode);
+            processor.set_instruction_pointer(address);
+            handle.before_instruction_execution(&mut processor, &mut memory);
+            // the handle doesn't execute the call, so push the return address manually
+            processor
+                .stack_push(&mut memory, address + Instruction::SIZE as Address)
+                .unwrap();
+        }
+        assert_eq!(
+            handle
+                .call_stack
+                .iter()
+                .map(|frame| frame.call_site)
+                .collect::<Vec<_>>(),
+            [
+                ENTRY_POINT,
+                ENTRY_POINT + Instruction::SIZE as Address,
+                ENTRY_POINT + 2 * Instruction::SIZE as Address
+            ]
+        );
+
+        processor.set_instruction_pointer(return_address);
+        handle.before_instruction_execution(&mut processor, &mut memory);
+        assert_eq!(handle.call_stack.len(), 2);
+
+        // leaving a frame without `Return` is noticed as soon as the stack pointer has moved
+        processor.stack_pop(&mut memory).unwrap();
+        processor.stack_pop(&mut memory).unwrap();
+        processor.set_instruction_pointer(return_address + Instruction::SIZE as Address);
+        handle.before_instruction_execution(&mut processor, &mut memory);
+        assert_eq!(handle.call_stack.len(), 1);
+    }
+
+    #[test]
+    fn breakpoints_can_be_set_by_symbol_name() {
+        let symbols = SymbolMap::from_json(
+            r#"{
+                "0x1000": {"name": "main", "file": "main.bs", "line": 1},
+                "4200": {"name": "loop", "file": "main.bs", "line": 7}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(symbols.address_of("loop"), Some(4200));
+        assert_eq!(symbols.symbol_at(0x1008).unwrap().name, "main");
+        assert_eq!(symbols.symbol_at(0xFFF), None);
+
+        let (mut handle, port) = start_tcp_debugger_with_symbols(symbols);
+        let mut client = connect_client(port);
+        send_request(&mut client, Framing::NulTerminated, START_REQUEST);
+        send_request(
+            &mut client,
+            Framing::NulTerminated,
+            r#"{"SetBreakpoints":{"locations":["loop", 256, "unknown"]}}"#,
+        );
+        send_request(
+            &mut client,
+            Framing::NulTerminated,
+            r#"{"SetBreakpoints":{"locations":["loop", 256]}}"#,
+        );
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while handle.breakpoints.is_empty() {
+            assert!(Instant::now() < deadline, "timed out");
+            handle.before_instruction_execution(&mut processor, &mut memory);
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(handle.breakpoints, HashSet::from([4200, 256]));
+        assert_eq!(
+            read_response(&mut client, Framing::NulTerminated),
+            r#"{"Error":{"message":"unknown symbol 'unknown'"}}"#
+        );
+    }
+
+    /// Keeps calling the handle in a separate thread (so that it answers requests) until the
+    /// returned flag gets set.
+    fn spin_handle(mut handle: DebugHandle) -> (Arc<AtomicBool>, thread::JoinHandle<()>) {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_copy = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            let mut processor = Processor::new();
+            let mut memory = Memory::new();
+            while !stop_copy.load(Ordering::Relaxed) {
+                handle.before_instruction_execution(&mut processor, &mut memory);
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+        (stop, thread)
+    }
+
+    #[test]
+    fn list_breakpoints_reports_everything_armed() {
+        let (handle, port) = start_tcp_debugger();
+        let mut client = connect_client(port);
+        send_request(&mut client, Framing::NulTerminated, START_REQUEST);
+        let (stop, thread) = spin_handle(handle);
+        for request in [
+            r#"{"SetBreakpoints":{"locations":[512, 256]}}"#,
+            r#"{"SetOpcodeBreaks":{"mnemonics":["NoOp", "Bogus"]}}"#,
+            r#"{"SetOpcodeBreaks":{"mnemonics":["Return", "NoOp"]}}"#,
+            r#"{"SetWatchpoints":{"addresses":[4098]}}"#,
+            r#"{"SetWatchpoints":{"addresses":[4096]}}"#,
+            r#"{"ListBreakpoints":{}}"#,
+        ] {
+            send_request(&mut client, Framing::NulTerminated, request);
+        }
+        assert_eq!(
+            read_response(&mut client, Framing::NulTerminated),
+            r#"{"Error":{"message":"unknown opcode 'Bogus'"}}"#
+        );
+        assert_eq!(
+            read_response(&mut client, Framing::NulTerminated),
+            r#"{"Error":{"message":"watchpoint address 0x00001002 must be divisible by 4"}}"#
+        );
+        assert_eq!(
+            read_response(&mut client, Framing::NulTerminated),
+            r#"{"Breakpoints":{"addresses":[256,512],"watchpoints":[4096],"opcode_breaks":["NoOp","Return"]}}"#
+        );
+        stop.store(true, Ordering::Relaxed);
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn opcode_breaks_stop_before_matching_instructions() {
+        use crate::address_constants::ENTRY_POINT;
+
+        let (mut handle, commands, messages) = create_connected_handle();
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+        memory.write_opcode(ENTRY_POINT, Opcode::NoOp {});
+        commands
+            .send(DebugCommand::SetOpcodeBreaks(vec!["NoOp".into()]))
+            .unwrap();
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::No
+        );
+        assert!(messages
+            .try_iter()
+            .any(|message| matches!(message, DebugMessage::HitBreakpoint(ENTRY_POINT))));
+    }
+
+    #[test]
+    fn registers_can_be_set_by_name() {
+        assert_eq!(
+            resolve_register(RegisterName::Name("sp".into())),
+            Ok(Processor::STACK_POINTER.0)
+        );
+        assert_eq!(
+            resolve_register(RegisterName::Name("flags".into())),
+            Ok(Processor::FLAGS.0)
+        );
+        assert_eq!(resolve_register(RegisterName::Name("r42".into())), Ok(42));
+        assert_eq!(resolve_register(RegisterName::Index(7)), Ok(7));
+        assert!(resolve_register(RegisterName::Name("r256".into())).is_err());
+        assert!(resolve_register(RegisterName::Name("ip".into())).is_err());
+
+        use crate::address_constants::ENTRY_POINT;
+        let (mut handle, port) = start_tcp_debugger();
+        let mut client = connect_client(port);
+        let mut processor = Processor::new();
+        let mut memory = Memory::new();
+        let target = ENTRY_POINT + 3 * Instruction::SIZE as Address;
+        send_request(
+            &mut client,
+            Framing::NulTerminated,
+            r#"{"StartExecution":{"stop_on_entry":true}}"#,
+        );
+        assert_eq!(
+            handle.before_instruction_execution(&mut processor, &mut memory),
+            ShouldExecuteInstruction::No
+        );
+        send_request(
+            &mut client,
+            Framing::NulTerminated,
+            &format!(
+                r#"{{"SetRegister":{{"register":"pc","value":{}}}}}"#,
+                target + 1
+            ),
+        );
+        send_request(
+            &mut client,
+            Framing::NulTerminated,
+            &format!(r#"{{"SetRegister":{{"register":"pc","value":{target}}}}}"#),
+        );
+        send_request(&mut client, Framing::NulTerminated, r#"{"StepOne":{}}"#);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while handle.before_instruction_execution(&mut processor, &mut memory)
+            == ShouldExecuteInstruction::No
+        {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+        // the stepped instruction is the one at the new address
+        assert_eq!(processor.get_instruction_pointer(), target);
+        let responses: Vec<_> =
+            std::iter::repeat_with(|| read_response(&mut client, Framing::NulTerminated))
+                .take_while(|response| !response.starts_with(r#"{"Breaking""#))
+                .collect();
+        assert!(responses.contains(&format!(
+            r#"{{"Error":{{"message":"invalid instruction pointer {:#010x}"}}}}"#,
+            target + 1
+        )));
+    }
+}
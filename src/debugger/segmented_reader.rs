@@ -5,13 +5,45 @@ use std::{
     ops::Range,
 };
 
+use serde::{Deserialize, Serialize};
+
 const INITIAL_BUFFER_SIZE: usize = 1024;
+const LENGTH_PREFIX_SIZE: usize = 4;
+
+/// How messages are delimited in the byte stream.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Framing {
+    /// Every message is followed by a NUL byte (i.e. messages must not contain NUL bytes).
+    #[default]
+    NulTerminated,
+    /// Every message is preceded by its length as 4 byte big endian number.
+    LengthPrefixed,
+}
+
+impl Framing {
+    /// Returns the message in this framing.
+    pub fn frame(self, message: &[u8]) -> Vec<u8> {
+        let mut result = Vec::with_capacity(message.len() + LENGTH_PREFIX_SIZE);
+        match self {
+            Framing::NulTerminated => {
+                result.extend_from_slice(message);
+                result.push(0);
+            }
+            Framing::LengthPrefixed => {
+                result.extend_from_slice(&(message.len() as u32).to_be_bytes());
+                result.extend_from_slice(message);
+            }
+        }
+        result
+    }
+}
 
 pub struct SegmentedReader {
     buffer: Vec<u8>,
     length: usize,
     next_segment_start: usize,
     buffer_version: u32,
+    framing: Framing,
 }
 
 #[derive(Debug, PartialEq)]
@@ -35,9 +67,15 @@ impl SegmentedReader {
             length: 0,
             buffer_version: 0,
             next_segment_start: 0,
+            framing: Framing::default(),
         }
     }
 
+    /// Changes how the data that is read from now on is split into segments.
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing;
+    }
+
     pub fn read(&mut self, mut from: impl Read) -> Result<Vec<Segment>> {
         self.consume_old_segments();
         if self.length == self.buffer.len() {
@@ -55,6 +93,10 @@ impl SegmentedReader {
         let (search_start, search_end) = (self.length, self.length + length);
         self.length += length;
 
+        if self.framing == Framing::LengthPrefixed {
+            return Ok(self.length_prefixed_segments());
+        }
+
         let endings = self.buffer[search_start..search_end]
             .iter()
             .enumerate()
@@ -73,6 +115,27 @@ impl SegmentedReader {
         Ok(segments)
     }
 
+    fn length_prefixed_segments(&mut self) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        while let Some(prefix) =
+            self.buffer[self.next_segment_start..self.length].get(..LENGTH_PREFIX_SIZE)
+        {
+            let segment_length = u32::from_be_bytes(prefix.try_into().unwrap()) as usize;
+            let start = self.next_segment_start + LENGTH_PREFIX_SIZE;
+            let end = start + segment_length;
+            if end > self.length {
+                // incomplete, the rest is read later
+                break;
+            }
+            segments.push(Segment {
+                range: start..end,
+                buffer_version: self.buffer_version,
+            });
+            self.next_segment_start = end;
+        }
+        segments
+    }
+
     pub fn segment(&self, s: &Segment) -> &[u8] {
         if s.buffer_version != self.buffer_version {
             panic!("Cannot access old segment after new data is read into buffer");
@@ -80,10 +143,12 @@ impl SegmentedReader {
         &self.buffer[s.range.clone()]
     }
 
+    /// Discards all data that has been read and resets the framing.
     pub fn clear(&mut self) {
         self.buffer_version += 1;
         self.length = 0;
         self.next_segment_start = 0;
+        self.framing = Framing::default();
     }
 
     fn consume_old_segments(&mut self) {
@@ -312,4 +377,31 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn length_prefixed_segments_may_contain_nul_bytes() -> Result<()> {
+        let mut reader = SegmentedReader::new();
+        reader.set_framing(Framing::LengthPrefixed);
+        let first = b"a\0b\0";
+        let second = vec![0; 3000];
+        let mut data = Framing::LengthPrefixed.frame(first);
+        data.extend(Framing::LengthPrefixed.frame(&second));
+        data.extend(Framing::LengthPrefixed.frame(b""));
+
+        // the second message only arrives partially with the first read
+        let (start, rest) = data.split_at(INITIAL_BUFFER_SIZE);
+        let segments = reader.read(start)?;
+        assert_eq!(segments.len(), 1);
+        assert_eq!(reader.segment(&segments[0]), first);
+
+        let mut received = Vec::new();
+        let mut rest = rest;
+        while !rest.is_empty() {
+            for segment in reader.read(&mut rest)? {
+                received.push(reader.segment(&segment).to_vec());
+            }
+        }
+        assert_eq!(received, [second, vec![]]);
+        Ok(())
+    }
 }
@@ -0,0 +1,77 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::EmulatorError, Address};
+
+/// Source location of an address, as produced by the assembler or compiler that built the ROM.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    pub file: String,
+    pub line: u32,
+}
+
+/// Maps addresses to symbols. Symbol files are JSON objects whose keys are addresses (in decimal
+/// or hexadecimal `0x...` notation), e.g. `{"0x1000": {"name": "main", "file": "main.bs",
+/// "line": 1}}`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolMap {
+    symbols: BTreeMap<Address, Symbol>,
+}
+
+impl SymbolMap {
+    pub fn from_json(json: &str) -> Result<Self, EmulatorError> {
+        let entries: HashMap<String, Symbol> = serde_json::from_str(json)
+            .map_err(|error| EmulatorError::Decode(format!("invalid symbol file: {error}")))?;
+        let symbols = entries
+            .into_iter()
+            .map(|(address, symbol)| Ok((parse_address(&address)?, symbol)))
+            .collect::<Result<_, EmulatorError>>()?;
+        Ok(Self { symbols })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, EmulatorError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Returns the path of the symbol file that belongs to the given ROM (`<rom>.sym`).
+    pub fn sidecar_path(rom_path: &Path) -> PathBuf {
+        let mut path = rom_path.as_os_str().to_owned();
+        path.push(".sym");
+        path.into()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Returns the symbol at the given address or, if there is none, the closest one before it
+    /// (i.e. the symbol whose code contains the address).
+    pub fn symbol_at(&self, address: Address) -> Option<&Symbol> {
+        self.symbols
+            .range(..=address)
+            .next_back()
+            .map(|(_, symbol)| symbol)
+    }
+
+    /// Returns the address of the symbol with the given name (the lowest one if the name is
+    /// ambiguous).
+    pub fn address_of(&self, name: &str) -> Option<Address> {
+        self.symbols
+            .iter()
+            .find(|(_, symbol)| symbol.name == name)
+            .map(|(&address, _)| address)
+    }
+}
+
+fn parse_address(string: &str) -> Result<Address, EmulatorError> {
+    let result = match string.strip_prefix("0x") {
+        Some(hex) => Address::from_str_radix(hex, 16),
+        None => string.parse(),
+    };
+    result.map_err(|error| EmulatorError::Decode(format!("invalid address '{string}': {error}")))
+}
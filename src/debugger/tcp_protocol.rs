@@ -9,9 +9,14 @@ use serde::{Deserialize, Serialize};
 
 use crate::{Address, Word};
 
-use super::segmented_reader::{self, Segment, SegmentedReader};
+pub use super::segmented_reader::Framing;
+use super::{
+    segmented_reader::{self, Segment, SegmentedReader},
+    symbols::Symbol,
+};
 
-const TCP_INTERFACE_ADDRESS: &str = "127.0.0.1:57017";
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 57017;
 const DEBUGGER_PORT_PREFIX: &str = "Debugger-Port:";
 
 #[derive(Debug, Deserialize)]
@@ -20,46 +25,160 @@ pub enum Request {
         stop_on_entry: bool,
     },
     SetBreakpoints {
-        locations: Vec<Address>,
+        locations: Vec<Location>,
     },
     RemoveBreakpoints {
-        locations: Vec<Address>,
+        locations: Vec<Location>,
+    },
+    /// Break as soon as the value at one of the given data addresses changes. The addresses
+    /// must be divisible by the word size.
+    SetWatchpoints {
+        addresses: Vec<Address>,
+    },
+    RemoveWatchpoints {
+        addresses: Vec<Address>,
+    },
+    /// Break before executing any instruction with one of the given mnemonics.
+    SetOpcodeBreaks {
+        mnemonics: Vec<String>,
     },
+    RemoveOpcodeBreaks {
+        mnemonics: Vec<String>,
+    },
+    /// Report all currently set breakpoints, watchpoints and opcode breaks.
+    ListBreakpoints {},
     /// Continue normal execution i.e. stop breaking.
     Continue {},
+    /// Continue normal execution for the given number of instructions, then break again.
+    RunCycles {
+        count: u64,
+    },
     /// Execute one instruction while breaking.
     StepOne {},
+    /// Like `StepOne`, but runs a called subroutine to completion.
+    StepOver {},
+    /// Continue until the current subroutine returns.
+    StepOut {},
+    /// Only answered while breaking. Writing the instruction pointer reports the new location
+    /// with `Breaking`.
     SetRegister {
-        register: u8,
+        register: RegisterName,
         value: Word,
     },
+    /// Decode `count` instructions starting at `address`. Only answered while breaking.
+    Disassemble {
+        address: Address,
+        count: usize,
+    },
+    /// Read `length` bytes of memory starting at `address`. Only answered while breaking.
+    ReadMemory {
+        address: Address,
+        length: usize,
+    },
+    /// Write the given bytes to memory starting at `address`. Only answered while breaking.
+    WriteMemory {
+        address: Address,
+        bytes: Vec<u8>,
+    },
+    /// Switch both directions to the given framing (one of the `supported_framings` announced
+    /// in `Hello`). Answered with `FramingChanged` in the new framing, which has to be awaited
+    /// before sending further requests.
+    SetFraming {
+        framing: Framing,
+    },
     Terminate {},
 }
 
+/// A breakpoint location, given either as address or as name of a symbol from the symbol file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Location {
+    Address(Address),
+    Symbol(String),
+}
+
+/// A register, given either as index or by name (`"pc"`, `"sp"`, `"flags"` or `"r<n>"`).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum RegisterName {
+    Index(u8),
+    Name(String),
+}
+
 #[derive(Debug, Serialize)]
 pub enum Response {
     Hello {
         pid: u32,
+        supported_framings: Vec<Framing>,
+    },
+    FramingChanged {
+        framing: Framing,
     },
     HitBreakpoint {
         location: Address,
+        symbol: Option<Symbol>,
+    },
+    WatchpointHit {
+        address: Address,
+        old: Word,
+        new: Word,
     },
     Breaking {
         location: Address,
+        symbol: Option<Symbol>,
     },
     Pausing {
         location: Address,
     },
     BreakState {
         registers: Vec<Word>,
-        call_stack: Vec<Address>,
+        call_stack: Vec<StackFrame>,
+        instruction_pointer: Address,
+        stack_pointer: Address,
+        flags: Word,
+        cycle_count: u64,
+        is_halted: bool,
+    },
+    Breakpoints {
+        addresses: Vec<Address>,
+        watchpoints: Vec<Address>,
+        opcode_breaks: Vec<String>,
+    },
+    MemoryContents {
+        address: Address,
+        bytes: Vec<u8>,
+    },
+    Disassembly {
+        instructions: Vec<DisassembledInstruction>,
+    },
+    Error {
+        message: String,
     },
 }
 
+#[derive(Debug, Serialize)]
+pub struct DisassembledInstruction {
+    pub address: Address,
+    pub mnemonic: String,
+    pub text: String,
+}
+
+/// An active function call, innermost last in `BreakState::call_stack`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StackFrame {
+    /// Address of the call instruction.
+    pub call_site: Address,
+    /// Address the call returns to.
+    pub return_address: Address,
+    /// Address on the machine stack where the return address has been pushed to.
+    pub stack_address: Address,
+}
+
 pub struct TcpHandler {
     listener: TcpListener,
     client: Option<TcpStream>,
     reader: SegmentedReader,
+    framing: Framing,
 }
 
 pub enum PollReturn {
@@ -78,22 +197,33 @@ pub enum Error {
 pub type Result<T> = std::result::Result<T, Error>;
 
 impl TcpHandler {
-    pub fn start() -> Self {
-        let listener =
-            TcpListener::bind(TCP_INTERFACE_ADDRESS).expect("Cannot open debug TCP interface.");
-        listener
-            .set_nonblocking(true)
-            .expect("Cannot set tcp listener to non-blocking.");
+    /// Listens on the given address. Port 0 lets the OS choose a free port (the chosen port is
+    /// printed to stdout in both cases).
+    pub fn start(host: &str, port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((host, port))
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .map_err(|error| {
+                io::Error::new(
+                    error.kind(),
+                    format!("cannot open debug TCP interface on {host}:{port}: {error}"),
+                )
+            })?;
 
         if let Ok(address) = listener.local_addr() {
             println!("{}{}", DEBUGGER_PORT_PREFIX, address.port());
         }
 
-        Self {
+        Ok(Self {
             listener,
             client: None,
             reader: SegmentedReader::new(),
-        }
+            framing: Framing::default(),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn local_port(&self) -> u16 {
+        self.listener.local_addr().unwrap().port()
     }
 
     pub fn poll(&mut self) -> Result<PollReturn> {
@@ -125,15 +255,14 @@ impl TcpHandler {
     }
 
     pub fn send(&mut self, message: &Response) -> Result<()> {
-        let mut json = serde_json::to_vec(message).map_err(Error::Serde)?;
-        json.push(0);
-
-        self.write_all(&json[..])
+        let json = serde_json::to_vec(message).map_err(Error::Serde)?;
+        self.write_all(&self.framing.frame(&json))
     }
 
     fn disconnect(&mut self) {
         self.client = None; // Dropping the stream disconnects it, if it is still active.
         self.reader.clear();
+        self.framing = Framing::default();
     }
 
     fn tcp_accept(&mut self) -> Result<PollReturn> {
@@ -153,7 +282,14 @@ impl TcpHandler {
         for segment in segments {
             let slice = self.reader.segment(segment);
             let request: Request = serde_json::from_slice(slice).map_err(Error::Serde)?;
-            requests.push(request);
+            match request {
+                Request::SetFraming { framing } => {
+                    self.reader.set_framing(framing);
+                    self.framing = framing;
+                    self.send(&Response::FramingChanged { framing })?;
+                }
+                request => requests.push(request),
+            }
         }
 
         Ok(PollReturn::ReceivedRequests(requests))
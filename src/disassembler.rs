@@ -0,0 +1,111 @@
+use std::fmt::Write;
+
+use crate::{
+    opcodes::{Opcode, Operand},
+    Address, Instruction, Size, Word,
+};
+
+/// Formats the opcode as its mnemonic followed by the operands, e.g.
+/// `AddTargetSourceImmediate r1, r1, 0x00000001`.
+pub fn format_opcode(opcode: Opcode) -> String {
+    let mut result = opcode.get_mnemonic().to_string();
+    for (i, operand) in opcode.get_operands().into_iter().enumerate() {
+        result.push_str(if i == 0 { " " } else { ", " });
+        match operand {
+            Operand::Register(register) => write!(result, "r{}", register.0),
+            Operand::Value(value) => write!(result, "{:#010x}", value),
+        }
+        .unwrap();
+    }
+    result
+}
+
+/// Disassembles machine code that is located at the given address into an address-prefixed
+/// listing (one line per instruction). Instructions that can't be decoded are emitted as
+/// `.word` directives, so that the listing always covers the whole input.
+pub fn disassemble(machine_code: &[u8], start_address: Address) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut chunks = machine_code.chunks_exact(Instruction::SIZE);
+    for (i, chunk) in chunks.by_ref().enumerate() {
+        let address = start_address + (i * Instruction::SIZE) as Address;
+        let instruction = Instruction::from_be_bytes(chunk.try_into().unwrap());
+        match Opcode::try_from(instruction) {
+            Ok(opcode) => result.push(format!("{:#010x}: {}", address, format_opcode(opcode))),
+            Err(_) => result.extend(format_listing_words(chunk, address)),
+        }
+    }
+    let remainder = chunks.remainder();
+    let remainder_address = start_address + (machine_code.len() - remainder.len()) as Address;
+    result.extend(format_listing_words(remainder, remainder_address));
+    result
+}
+
+fn format_listing_words(bytes: &[u8], start_address: Address) -> impl Iterator<Item = String> {
+    format_words(bytes, start_address)
+        .into_iter()
+        .map(|(address, directive)| format!("{:#010x}: {}", address, directive))
+}
+
+/// Formats bytes that don't contain a valid instruction as one `.word` directive per word (and
+/// a `.byte` directive for an incomplete trailing word), each paired with its address.
+pub fn format_words(bytes: &[u8], start_address: Address) -> Vec<(Address, String)> {
+    bytes
+        .chunks(Word::SIZE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let address = start_address + (i * Word::SIZE) as Address;
+            match <[u8; Word::SIZE]>::try_from(chunk) {
+                Ok(word) => (
+                    address,
+                    format!(".word {:#010x}", Word::from_be_bytes(word)),
+                ),
+                Err(_) => {
+                    let bytes: Vec<_> = chunk.iter().map(|byte| format!("{:#04x}", byte)).collect();
+                    (address, format!(".byte {}", bytes.join(", ")))
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_opcode_lists_operands_in_argument_order() {
+        assert_eq!(
+            format_opcode(Opcode::AddTargetSourceImmediate {
+                target: 1.into(),
+                source: 2.into(),
+                immediate: 42,
+            }),
+            "AddTargetSourceImmediate r1, r2, 0x0000002a"
+        );
+        assert_eq!(
+            format_opcode(Opcode::MoveAddressRegister {
+                register: 3.into(),
+                target_address: 0x100,
+            }),
+            "MoveAddressRegister 0x00000100, r3"
+        );
+        assert_eq!(format_opcode(Opcode::NoOp {}), "NoOp");
+    }
+
+    #[test]
+    fn invalid_instructions_become_words() {
+        let mut machine_code = Opcode::NoOp {}.as_instruction().to_be_bytes().to_vec();
+        machine_code.extend([0x70, 0x00, 0x00, 0x00, 0x12, 0x34, 0x56, 0x78]);
+        machine_code.extend([0xAB, 0xCD, 0xEF, 0x01, 0x02]);
+        assert_eq!(
+            disassemble(&machine_code, 0x10),
+            [
+                "0x00000010: NoOp",
+                "0x00000018: .word 0x70000000",
+                "0x0000001c: .word 0x12345678",
+                "0x00000020: .word 0xabcdef01",
+                "0x00000024: .byte 0x02",
+            ]
+        );
+    }
+}
@@ -7,51 +7,519 @@ use raylib::{
     texture::{RaylibTexture2D, RenderTexture2D},
 };
 
-use crate::{address_constants, memory::Memory, Address};
+use std::str::FromStr;
 
-pub const WIDTH: usize = 480;
-pub const HEIGHT: usize = WIDTH / 4 * 3;
+use int_enum::IntEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    address_constants,
+    memory::{DisplayArea, Memory},
+    Address,
+};
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, IntEnum)]
+pub enum DisplayMode {
+    /// Every pixel is stored as four bytes (red, green, blue, alpha).
+    Rgba = 0,
+    /// Every pixel is stored as one byte indexing into the palette at `PALETTE_START`.
+    Indexed = 1,
+}
+
+impl DisplayMode {
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            DisplayMode::Rgba => 4,
+            DisplayMode::Indexed => 1,
+        }
+    }
+}
+
+/// Rectangular part of a framebuffer (in pixels).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramebufferRegion {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl FramebufferRegion {
+    pub const fn full(resolution: Resolution) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: resolution.width,
+            height: resolution.height,
+        }
+    }
+}
+
+/// Keeps track of which part of the visible framebuffer has to be uploaded to the screen.
+pub struct UploadTracker {
+    needs_full_upload: bool,
+}
+
+impl UploadTracker {
+    pub fn new() -> Self {
+        Self {
+            needs_full_upload: true,
+        }
+    }
+
+    /// Makes the next upload cover the whole framebuffer (e.g. after a swap).
+    pub fn invalidate(&mut self) {
+        self.needs_full_upload = true;
+    }
+
+    /// Returns the region of the framebuffer starting at `framebuffer_start` that changed since
+    /// the last call (`None` if nothing visible changed) and resets the tracked memory writes.
+    /// Regions spanning multiple rows are widened to full rows. Writes to the other framebuffer
+    /// stay tracked until the next full upload (which happens after a swap anyway).
+    pub fn next_region(
+        &mut self,
+        memory: &mut Memory,
+        resolution: Resolution,
+        mode: DisplayMode,
+        framebuffer_start: Address,
+    ) -> Option<FramebufferRegion> {
+        let (visible_area, invisible_area) =
+            match framebuffer_start == address_constants::FIRST_FRAMEBUFFER_START {
+                true => (
+                    DisplayArea::FirstFramebuffer,
+                    DisplayArea::SecondFramebuffer,
+                ),
+                false => (
+                    DisplayArea::SecondFramebuffer,
+                    DisplayArea::FirstFramebuffer,
+                ),
+            };
+        let dirty_range = memory.take_dirty_range(visible_area);
+        let palette_changed = memory.take_dirty_range(DisplayArea::Palette).is_some();
+        if std::mem::take(&mut self.needs_full_upload) {
+            memory.take_dirty_range(invisible_area);
+            return Some(FramebufferRegion::full(resolution));
+        }
+        if mode == DisplayMode::Indexed && palette_changed {
+            return Some(FramebufferRegion::full(resolution));
+        }
+        let (first, last) = dirty_range?;
+
+        let bytes_per_pixel = mode.bytes_per_pixel();
+        let framebuffer_end =
+            framebuffer_start + (resolution.width * resolution.height * bytes_per_pixel) as Address;
+        if last < framebuffer_start || first >= framebuffer_end {
+            return None;
+        }
+        let first_pixel =
+            (first.max(framebuffer_start) - framebuffer_start) as usize / bytes_per_pixel;
+        let last_pixel =
+            (last.min(framebuffer_end - 1) - framebuffer_start) as usize / bytes_per_pixel;
+        let first_row = first_pixel / resolution.width;
+        let last_row = last_pixel / resolution.width;
+        Some(match first_row == last_row {
+            true => FramebufferRegion {
+                x: first_pixel % resolution.width,
+                y: first_row,
+                width: last_pixel - first_pixel + 1,
+                height: 1,
+            },
+            false => FramebufferRegion {
+                x: 0,
+                y: first_row,
+                width: resolution.width,
+                height: last_row - first_row + 1,
+            },
+        })
+    }
+
+    /// Like `next_region`, but for the framebuffer that is visible according to the swap state.
+    /// Returns `None` while a frame is in progress, so that a partially drawn frame is never
+    /// shown.
+    pub fn next_upload(
+        &mut self,
+        memory: &mut Memory,
+        swap_state: &SwapState,
+        resolution: Resolution,
+        mode: DisplayMode,
+    ) -> Option<FramebufferRegion> {
+        if swap_state.frame_in_progress {
+            return None;
+        }
+        let framebuffer_start = match swap_state.first_framebuffer_visible {
+            true => address_constants::FIRST_FRAMEBUFFER_START,
+            false => resolution.second_framebuffer_start(),
+        };
+        self.next_region(memory, resolution, mode, framebuffer_start)
+    }
+}
+
+impl Default for UploadTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Resolution {
+    pub const DEFAULT: Self = Self {
+        width: 480,
+        height: 480 / 4 * 3,
+    };
+
+    pub const fn framebuffer_size(self) -> usize {
+        self.width * self.height * 4 // RGBA
+    }
+
+    /// The second framebuffer directly follows the first one, i.e. its start depends on the resolution.
+    pub const fn second_framebuffer_start(self) -> Address {
+        address_constants::FIRST_FRAMEBUFFER_START + self.framebuffer_size() as Address
+    }
+
+    /// Returns whether a framebuffer of this resolution fits into the memory reserved for it.
+    pub fn is_valid(self) -> bool {
+        self.width > 0
+            && self.height > 0
+            && self
+                .width
+                .checked_mul(self.height)
+                .and_then(|num_pixels| num_pixels.checked_mul(4))
+                .is_some_and(|size| size <= address_constants::MAX_FRAMEBUFFER_SIZE)
+    }
+}
+
+impl FromStr for Resolution {
+    type Err = String;
+
+    /// Parses resolutions of the form `WIDTHxHEIGHT`, e.g. `320x240`.
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let (width, height) = string
+            .split_once('x')
+            .ok_or_else(|| format!("invalid resolution '{string}' (expected e.g. 320x240)"))?;
+        let resolution = Self {
+            width: width
+                .parse()
+                .map_err(|_| format!("invalid width '{width}'"))?,
+            height: height
+                .parse()
+                .map_err(|_| format!("invalid height '{height}'"))?,
+        };
+        match resolution.is_valid() {
+            true => Ok(resolution),
+            false => Err(format!(
+                "resolution '{string}' exceeds the framebuffer memory of {} bytes",
+                address_constants::MAX_FRAMEBUFFER_SIZE
+            )),
+        }
+    }
+}
 
 pub trait Display {
     type Handle;
     type Thread;
 
-    fn swap(&mut self);
-    fn is_first_framebuffer_visible(&self) -> bool;
+    fn swap_state(&self) -> &SwapState;
+    fn swap_state_mut(&mut self) -> &mut SwapState;
+    /// Returns whether there is no actual screen the framebuffers are shown on.
+    fn is_headless(&self) -> bool;
+    fn resolution(&self) -> Resolution;
+    fn mode(&self) -> DisplayMode;
+    fn mode_mut(&mut self) -> &mut DisplayMode;
+
+    /// Called whenever the visible framebuffer has to be uploaded completely on the next render
+    /// (e.g. after a swap or a mode change). Does nothing by default.
+    fn invalidate_upload(&mut self) {}
+
+    /// Swaps the framebuffers. The swap is deferred until the end of the current frame (see
+    /// `begin_frame`) and, with vsync enabled, until the next rendered frame (see `render_tick`).
+    fn swap(&mut self) {
+        if self.swap_state_mut().swap() {
+            self.invalidate_upload();
+        }
+    }
+
+    fn is_first_framebuffer_visible(&self) -> bool {
+        self.swap_state().first_framebuffer_visible
+    }
+
+    /// Replaces the swap state (e.g. when restoring a snapshot). Unlike `swap`, this takes
+    /// effect immediately.
+    fn set_swap_state(&mut self, state: SwapState) {
+        *self.swap_state_mut() = state;
+        self.invalidate_upload();
+    }
+
+    /// Starts a frame. Swaps requested until the matching `end_frame` are coalesced and
+    /// only applied when the frame gets committed.
+    fn begin_frame(&mut self) {
+        self.swap_state_mut().begin_frame();
+    }
+
+    /// Commits the current frame, applying a pending swap (if any).
+    fn end_frame(&mut self) {
+        if self.swap_state_mut().end_frame() {
+            self.invalidate_upload();
+        }
+    }
+
+    fn is_frame_in_progress(&self) -> bool {
+        self.swap_state().frame_in_progress
+    }
+
+    /// Makes swaps wait for the next rendered frame (see `render_tick`).
+    fn set_vsync(&mut self, enabled: bool) {
+        self.swap_state_mut().vsync = enabled;
+    }
+
+    /// Returns whether a swap waits for the next rendered frame.
+    fn is_vsync_swap_pending(&self) -> bool {
+        self.swap_state().vsync_swap_pending
+    }
+
+    /// Called for every rendered frame. Applies a swap that waits for vsync (if any).
+    fn render_tick(&mut self) {
+        if self.swap_state_mut().render_tick() {
+            self.invalidate_upload();
+        }
+    }
+
+    /// Returns the number of swaps that have been applied so far.
+    fn num_swaps(&self) -> u64 {
+        self.swap_state().num_swaps
+    }
+
+    fn set_mode(&mut self, mode: DisplayMode) {
+        *self.mode_mut() = mode;
+        self.invalidate_upload();
+    }
 
     #[cfg(feature = "graphics")]
     fn render(&mut self, memory: &mut Memory, handle: &mut RaylibDrawHandle);
 
+    fn second_framebuffer_address(&self) -> Address {
+        self.resolution().second_framebuffer_start()
+    }
+
     fn invisible_framebuffer_address(&self) -> Address {
         match self.is_first_framebuffer_visible() {
-            true => address_constants::SECOND_FRAMEBUFFER_START,
+            true => self.second_framebuffer_address(),
             false => address_constants::FIRST_FRAMEBUFFER_START,
         }
     }
+
+    fn visible_framebuffer_address(&self) -> Address {
+        match self.is_first_framebuffer_visible() {
+            true => address_constants::FIRST_FRAMEBUFFER_START,
+            false => self.second_framebuffer_address(),
+        }
+    }
+
+    /// Returns a copy of the currently visible framebuffer (RGBA, row by row). In indexed mode,
+    /// the palette indices are resolved.
+    fn capture(&self, memory: &Memory) -> Vec<u8> {
+        self.capture_region(memory, FramebufferRegion::full(self.resolution()))
+    }
+
+    /// Like `capture`, but only copies the given region.
+    fn capture_region(&self, memory: &Memory, region: FramebufferRegion) -> Vec<u8> {
+        read_framebuffer(
+            memory,
+            self.visible_framebuffer_address(),
+            self.resolution(),
+            self.mode(),
+            region,
+        )
+    }
+}
+
+/// Copies a region of the framebuffer starting at `start` (RGBA, row by row). In indexed mode, the
+/// palette indices are resolved.
+fn read_framebuffer(
+    memory: &Memory,
+    start: Address,
+    resolution: Resolution,
+    mode: DisplayMode,
+    region: FramebufferRegion,
+) -> Vec<u8> {
+    let start = start as usize;
+    let palette = &memory.data()[address_constants::PALETTE_START as usize..]
+        [..address_constants::PALETTE_SIZE];
+    let mut result = Vec::with_capacity(region.width * region.height * 4);
+    for row in region.y..region.y + region.height {
+        let first_pixel = row * resolution.width + region.x;
+        match mode {
+            DisplayMode::Rgba => result
+                .extend_from_slice(&memory.data()[start + first_pixel * 4..][..region.width * 4]),
+            DisplayMode::Indexed => result.extend(
+                memory.data()[start + first_pixel..][..region.width]
+                    .iter()
+                    .flat_map(|&index| &palette[index as usize * 4..][..4]),
+            ),
+        }
+    }
+    result
+}
+
+/// The framebuffer swap logic shared by all displays.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwapState {
+    pub first_framebuffer_visible: bool,
+    pub frame_in_progress: bool,
+    /// Set if a swap has been requested during the current frame.
+    pub swap_pending: bool,
+    /// A setting of the emulator rather than machine state, so it's not part of snapshots.
+    #[serde(skip)]
+    pub vsync: bool,
+    /// Set while a swap waits for the next rendered frame (only with vsync enabled).
+    pub vsync_swap_pending: bool,
+    pub num_swaps: u64,
+}
+
+impl SwapState {
+    pub fn new() -> Self {
+        Self {
+            first_framebuffer_visible: true,
+            frame_in_progress: false,
+            swap_pending: false,
+            vsync: false,
+            vsync_swap_pending: false,
+            num_swaps: 0,
+        }
+    }
+
+    /// Returns whether the visible framebuffer changed.
+    pub fn swap(&mut self) -> bool {
+        if self.frame_in_progress {
+            self.swap_pending = true;
+            false
+        } else if self.vsync {
+            self.vsync_swap_pending = true;
+            false
+        } else {
+            self.flip();
+            true
+        }
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.frame_in_progress = true;
+    }
+
+    /// Returns whether the visible framebuffer changed.
+    pub fn end_frame(&mut self) -> bool {
+        self.frame_in_progress = false;
+        std::mem::take(&mut self.swap_pending) && self.swap()
+    }
+
+    /// Returns whether the visible framebuffer changed.
+    pub fn render_tick(&mut self) -> bool {
+        if std::mem::take(&mut self.vsync_swap_pending) {
+            self.flip();
+            return true;
+        }
+        false
+    }
+
+    fn flip(&mut self) {
+        self.first_framebuffer_visible = !self.first_framebuffer_visible;
+        self.num_swaps += 1;
+    }
+}
+
+impl Default for SwapState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub struct MockDisplay {
-    first_framebuffer_visible: bool,
+    swap_state: SwapState,
+    resolution: Resolution,
+    mode: DisplayMode,
+    upload_tracker: UploadTracker,
 }
 
 impl MockDisplay {
-    pub fn new(_: &mut <Self as Display>::Handle, _: &<Self as Display>::Thread) -> Self {
+    pub fn new(
+        _: &mut <Self as Display>::Handle,
+        _: &<Self as Display>::Thread,
+        resolution: Resolution,
+    ) -> Self {
         Self {
-            first_framebuffer_visible: true,
+            swap_state: SwapState::new(),
+            resolution,
+            mode: DisplayMode::Rgba,
+            upload_tracker: UploadTracker::new(),
         }
     }
+
+    /// Returns the region of the visible framebuffer a real display would upload when rendering
+    /// the next frame (see `UploadTracker::next_upload`).
+    pub fn next_upload(&mut self, memory: &mut Memory) -> Option<FramebufferRegion> {
+        self.upload_tracker
+            .next_upload(memory, &self.swap_state, self.resolution, self.mode)
+    }
+
+    /// Returns a copy of the currently invisible framebuffer, i.e. the one a program draws the
+    /// next frame into (or, right after a swap, the previous frame). See `capture`.
+    pub fn capture_invisible(&self, memory: &Memory) -> Vec<u8> {
+        read_framebuffer(
+            memory,
+            self.invisible_framebuffer_address(),
+            self.resolution,
+            self.mode,
+            FramebufferRegion::full(self.resolution),
+        )
+    }
+
+    /// Returns the RGBA color of the pixel at the given position of the visible framebuffer.
+    pub fn pixel_at(&self, memory: &Memory, x: usize, y: usize) -> [u8; 4] {
+        let region = FramebufferRegion {
+            x,
+            y,
+            width: 1,
+            height: 1,
+        };
+        self.capture_region(memory, region).try_into().unwrap()
+    }
 }
 
 impl Display for MockDisplay {
     type Handle = ();
     type Thread = ();
 
-    fn swap(&mut self) {
-        self.first_framebuffer_visible = !self.first_framebuffer_visible
+    fn swap_state(&self) -> &SwapState {
+        &self.swap_state
     }
 
-    fn is_first_framebuffer_visible(&self) -> bool {
-        self.first_framebuffer_visible
+    fn swap_state_mut(&mut self) -> &mut SwapState {
+        &mut self.swap_state
+    }
+
+    fn is_headless(&self) -> bool {
+        true
+    }
+
+    fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    fn mode(&self) -> DisplayMode {
+        self.mode
+    }
+
+    fn mode_mut(&mut self) -> &mut DisplayMode {
+        &mut self.mode
+    }
+
+    fn invalidate_upload(&mut self) {
+        self.upload_tracker.invalidate();
     }
 
     #[cfg(feature = "graphics")]
@@ -61,24 +529,35 @@ impl Display for MockDisplay {
 }
 
 pub struct DisplayImplementation {
-    first_framebuffer_visible: bool,
+    swap_state: SwapState,
+    resolution: Resolution,
+    mode: DisplayMode,
 
     #[cfg(feature = "graphics")]
     texture: RenderTexture2D,
+    #[cfg(feature = "graphics")]
+    upload_tracker: UploadTracker,
 }
 
 #[cfg(feature = "graphics")]
 impl DisplayImplementation {
-    pub fn new(handle: &mut <Self as Display>::Handle, thread: &<Self as Display>::Thread) -> Self {
+    pub fn new(
+        handle: &mut <Self as Display>::Handle,
+        thread: &<Self as Display>::Thread,
+        resolution: Resolution,
+    ) -> Self {
         let mut texture = handle
-            .load_render_texture(thread, WIDTH as u32, HEIGHT as u32)
+            .load_render_texture(thread, resolution.width as u32, resolution.height as u32)
             .unwrap();
         let render_texture: &mut RenderTexture = texture.as_mut();
         render_texture.texture.format =
             raylib::ffi::PixelFormat::PIXELFORMAT_PIXELFORMAT_UNCOMPRESSED_R8G8B8A8 as _;
         Self {
-            first_framebuffer_visible: true,
+            swap_state: SwapState::new(),
+            resolution,
+            mode: DisplayMode::Rgba,
             texture,
+            upload_tracker: UploadTracker::new(),
         }
     }
 }
@@ -95,14 +574,27 @@ impl Display for DisplayImplementation {
             b: 0xFF,
             a: 0xFF,
         };
-        let scale = SCREEN_SIZE.height as f32 / HEIGHT as f32;
-        let framebuffer_start = match self.is_first_framebuffer_visible() {
-            true => address_constants::FIRST_FRAMEBUFFER_START,
-            false => address_constants::SECOND_FRAMEBUFFER_START,
-        } as usize;
-        self.texture.update_texture(
-            &memory.data()[framebuffer_start..][..address_constants::FRAMEBUFFER_SIZE],
-        );
+        let scale = SCREEN_SIZE.height as f32 / self.resolution.height as f32;
+        let region =
+            self.upload_tracker
+                .next_upload(memory, &self.swap_state, self.resolution, self.mode);
+        if let Some(region) = region {
+            let pixels = self.capture_region(memory, region);
+            let rectangle = raylib::ffi::Rectangle {
+                x: region.x as f32,
+                y: region.y as f32,
+                width: region.width as f32,
+                height: region.height as f32,
+            };
+            // `pixels` always contains exactly the RGBA data of `rectangle`
+            if let Err(error) = self
+                .texture
+                .texture_mut()
+                .update_texture_rec(rectangle, &pixels)
+            {
+                eprintln!("Error updating the display texture: {}", error);
+            }
+        }
         handle.draw_texture_ex(
             &self.texture,
             raylib::ffi::Vector2 { x: 0.0, y: 0.0 },
@@ -112,20 +604,46 @@ impl Display for DisplayImplementation {
         );
     }
 
-    fn swap(&mut self) {
-        self.first_framebuffer_visible = !self.first_framebuffer_visible;
+    fn swap_state(&self) -> &SwapState {
+        &self.swap_state
     }
 
-    fn is_first_framebuffer_visible(&self) -> bool {
-        self.first_framebuffer_visible
+    fn swap_state_mut(&mut self) -> &mut SwapState {
+        &mut self.swap_state
+    }
+
+    fn is_headless(&self) -> bool {
+        false
+    }
+
+    fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    fn mode(&self) -> DisplayMode {
+        self.mode
+    }
+
+    fn mode_mut(&mut self) -> &mut DisplayMode {
+        &mut self.mode
+    }
+
+    fn invalidate_upload(&mut self) {
+        self.upload_tracker.invalidate();
     }
 }
 
 #[cfg(not(feature = "graphics"))]
 impl DisplayImplementation {
-    pub fn new(handle: &mut <Self as Display>::Handle, thread: &<Self as Display>::Thread) -> Self {
+    pub fn new(
+        handle: &mut <Self as Display>::Handle,
+        thread: &<Self as Display>::Thread,
+        resolution: Resolution,
+    ) -> Self {
         DisplayImplementation {
-            first_framebuffer_visible: true,
+            swap_state: SwapState::new(),
+            resolution,
+            mode: DisplayMode::Rgba,
         }
     }
 }
@@ -135,11 +653,197 @@ impl Display for DisplayImplementation {
     type Handle = ();
     type Thread = ();
 
-    fn swap(&mut self) {
-        self.first_framebuffer_visible = !self.first_framebuffer_visible;
+    fn swap_state(&self) -> &SwapState {
+        &self.swap_state
     }
 
-    fn is_first_framebuffer_visible(&self) -> bool {
-        self.first_framebuffer_visible
+    fn swap_state_mut(&mut self) -> &mut SwapState {
+        &mut self.swap_state
+    }
+
+    fn is_headless(&self) -> bool {
+        true
+    }
+
+    fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    fn mode(&self) -> DisplayMode {
+        self.mode
+    }
+
+    fn mode_mut(&mut self) -> &mut DisplayMode {
+        &mut self.mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_returns_visible_framebuffer() {
+        let mut memory = Memory::new();
+        let mut display = MockDisplay::new(&mut (), &(), Resolution::DEFAULT);
+        let first = address_constants::FIRST_FRAMEBUFFER_START;
+        let second = display.second_framebuffer_address();
+        memory.write_data(first, 0x11223344);
+        memory.write_data(second, 0xAABBCCDD);
+
+        let image = display.capture(&memory);
+        assert_eq!(image.len(), Resolution::DEFAULT.framebuffer_size());
+        assert_eq!(image[..4], [0x11, 0x22, 0x33, 0x44]);
+
+        display.swap();
+        assert_eq!(display.capture(&memory)[..4], [0xAA, 0xBB, 0xCC, 0xDD]);
+        assert_eq!(
+            display.capture_invisible(&memory)[..4],
+            [0x11, 0x22, 0x33, 0x44]
+        );
+    }
+
+    #[test]
+    fn parse_resolution() {
+        assert_eq!(
+            "320x240".parse(),
+            Ok(Resolution {
+                width: 320,
+                height: 240
+            })
+        );
+        assert!("320".parse::<Resolution>().is_err());
+        assert!("0x240".parse::<Resolution>().is_err());
+        assert!("4096x4096".parse::<Resolution>().is_err());
+    }
+
+    #[test]
+    fn capture_resolves_palette_indices_in_indexed_mode() {
+        let mut memory = Memory::new();
+        let mut display = MockDisplay::new(&mut (), &(), Resolution::DEFAULT);
+        display.set_mode(DisplayMode::Indexed);
+        memory.write_data(address_constants::PALETTE_START + 3 * 4, 0x11223344);
+        memory.write_byte(address_constants::FIRST_FRAMEBUFFER_START + 1, 3);
+
+        let image = display.capture(&memory);
+        assert_eq!(image.len(), Resolution::DEFAULT.framebuffer_size());
+        assert_eq!(image[..8], [0, 0, 0, 0, 0x11, 0x22, 0x33, 0x44]);
+    }
+
+    #[test]
+    fn single_pixel_write_uploads_single_pixel() {
+        let mut memory = Memory::new();
+        let mut tracker = UploadTracker::new();
+        let resolution = Resolution::DEFAULT;
+        let start = address_constants::FIRST_FRAMEBUFFER_START;
+        let mut uploads = Vec::new();
+        let mut render = |memory: &mut Memory, tracker: &mut UploadTracker| {
+            if let Some(region) = tracker.next_region(memory, resolution, DisplayMode::Rgba, start)
+            {
+                uploads.push(region);
+            }
+        };
+
+        render(&mut memory, &mut tracker);
+        render(&mut memory, &mut tracker);
+        memory.write_data(
+            start + ((2 * resolution.width + 5) * 4) as Address,
+            0xFF0000FF,
+        );
+        render(&mut memory, &mut tracker);
+        // writes to the invisible framebuffer don't need an upload
+        memory.write_data(resolution.second_framebuffer_start(), 0xFF0000FF);
+        render(&mut memory, &mut tracker);
+        tracker.invalidate();
+        render(&mut memory, &mut tracker);
+
+        assert_eq!(
+            uploads,
+            [
+                FramebufferRegion::full(resolution),
+                FramebufferRegion {
+                    x: 5,
+                    y: 2,
+                    width: 1,
+                    height: 1
+                },
+                FramebufferRegion::full(resolution),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_writes_to_visible_framebuffer_are_uploaded() {
+        let resolution = Resolution {
+            width: 320,
+            height: 240,
+        };
+        let mut memory = Memory::new();
+        memory.set_framebuffer_size(resolution.framebuffer_size());
+        let mut display = MockDisplay::new(&mut (), &(), resolution);
+        let first = address_constants::FIRST_FRAMEBUFFER_START;
+        let second = resolution.second_framebuffer_start();
+        assert_eq!(
+            display.next_upload(&mut memory),
+            Some(FramebufferRegion::full(resolution))
+        );
+
+        memory.write_data(first + 4, 0xFF0000FF);
+        memory.write_data(
+            second + ((resolution.width * 10) * 4) as Address,
+            0xFF0000FF,
+        );
+        // the palette is irrelevant in RGBA mode
+        memory.write_data(address_constants::PALETTE_START, 0xFF0000FF);
+        assert_eq!(
+            display.next_upload(&mut memory),
+            Some(FramebufferRegion {
+                x: 1,
+                y: 0,
+                width: 1,
+                height: 1
+            })
+        );
+        assert_eq!(display.next_upload(&mut memory), None);
+
+        // nothing is uploaded until the frame gets committed, then the whole frame is uploaded
+        display.begin_frame();
+        memory.write_data(second, 0xFF0000FF);
+        display.swap();
+        assert_eq!(display.next_upload(&mut memory), None);
+        display.end_frame();
+        assert_eq!(
+            display.next_upload(&mut memory),
+            Some(FramebufferRegion::full(resolution))
+        );
+        memory.write_data(first, 0xFF0000FF);
+        assert_eq!(display.next_upload(&mut memory), None);
+    }
+
+    #[test]
+    fn writes_spanning_rows_upload_full_rows() {
+        let mut memory = Memory::new();
+        let mut tracker = UploadTracker::new();
+        let resolution = Resolution::DEFAULT;
+        let start = address_constants::FIRST_FRAMEBUFFER_START;
+        tracker.next_region(&mut memory, resolution, DisplayMode::Indexed, start);
+
+        memory.write_byte(start + (resolution.width + 7) as Address, 1);
+        memory.write_byte(start + (3 * resolution.width) as Address, 1);
+        assert_eq!(
+            tracker.next_region(&mut memory, resolution, DisplayMode::Indexed, start),
+            Some(FramebufferRegion {
+                x: 0,
+                y: 1,
+                width: resolution.width,
+                height: 3
+            })
+        );
+
+        memory.write_byte(address_constants::PALETTE_START, 0xFF);
+        assert_eq!(
+            tracker.next_region(&mut memory, resolution, DisplayMode::Indexed, start),
+            Some(FramebufferRegion::full(resolution))
+        );
     }
 }
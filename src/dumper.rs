@@ -1,14 +1,383 @@
-use std::{fs, io};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use chrono::prelude::*;
 
-pub fn dump(filename_root: &str, data: &[u8]) -> io::Result<()> {
-    fs::create_dir_all("./dumps")?;
+use crate::{
+    memory::Memory,
+    snapshot::{pack_bits, unpack_bits},
+};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+const COMPRESSED_MEMORY_MAGIC: &[u8; 8] = b"BSS2KMEM";
+
+/// The directory `DumpRegisters` and `DumpMemory` write to unless configured otherwise.
+pub const DEFAULT_DIRECTORY: &str = "./dumps";
+
+/// The file format the `DumpRegisters` instruction writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RegisterDumpFormat {
+    /// The raw big-endian values of all registers (`*.bin`).
+    #[default]
+    Binary,
+    /// A `processor::RegisterDump` with named special registers and decoded flags (`*.json`).
+    Json,
+}
+
+impl RegisterDumpFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Binary => "bin",
+            Self::Json => "json",
+        }
+    }
+}
+
+impl FromStr for RegisterDumpFormat {
+    type Err = String;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "binary" => Ok(Self::Binary),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "invalid dump format '{string}' (expected binary or json)"
+            )),
+        }
+    }
+}
+
+/// The file format the `DumpMemory` instruction writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MemoryDumpFormat {
+    /// The raw memory contents (`*.bin`).
+    #[default]
+    Raw,
+    /// The memory contents compressed with `compress_memory` (`*.mem`).
+    Compressed,
+}
+
+impl MemoryDumpFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Raw => "bin",
+            Self::Compressed => "mem",
+        }
+    }
+
+    pub fn encode(self, memory: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Raw => memory.to_vec(),
+            Self::Compressed => compress_memory(memory),
+        }
+    }
+}
+
+impl FromStr for MemoryDumpFormat {
+    type Err = String;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "raw" => Ok(Self::Raw),
+            "compressed" => Ok(Self::Compressed),
+            _ => Err(format!(
+                "invalid dump format '{string}' (expected raw or compressed)"
+            )),
+        }
+    }
+}
+
+/// Compresses memory contents: a magic number, the memory size (as big endian 64 bit number)
+/// and the PackBits compressed data (like the memory of save states, see `MachineSnapshot`).
+pub fn compress_memory(memory: &[u8]) -> Vec<u8> {
+    let mut result = COMPRESSED_MEMORY_MAGIC.to_vec();
+    result.extend_from_slice(&(memory.len() as u64).to_be_bytes());
+    pack_bits(memory, &mut result);
+    result
+}
+
+/// Returns the memory contents of a dump written by `DumpMemory` in any `MemoryDumpFormat`.
+pub fn decode_memory_dump(dump: &[u8]) -> Result<Vec<u8>, String> {
+    let Some(compressed) = dump.strip_prefix(COMPRESSED_MEMORY_MAGIC) else {
+        return Ok(dump.to_vec());
+    };
+    if compressed.len() < 8 {
+        return Err("compressed memory dump is truncated".into());
+    }
+    let (size, data) = compressed.split_at(8);
+    let size = u64::from_be_bytes(size.try_into().unwrap());
+    let size = match usize::try_from(size) {
+        Ok(size) if size <= Memory::MAX_SIZE && Memory::is_valid_size(size) => size,
+        _ => return Err(format!("compressed memory dump has invalid size {size}")),
+    };
+    unpack_bits(data, size).map_err(|_| "compressed memory dump is corrupt".into())
+}
+
+/// Writes the data into the file `<filename_root>_<timestamp>.<extension>` inside the given
+/// directory (which is created if needed) and returns the path of that file.
+pub fn dump(
+    directory: &Path,
+    filename_root: &str,
+    extension: &str,
+    data: &[u8],
+) -> io::Result<PathBuf> {
+    create_directory(directory)?;
     let now: DateTime<Local> = Local::now();
-    let filename = format!(
-        "./dumps/{}_{}.bin",
+    let path = directory.join(format!(
+        "{}_{}.{}",
         filename_root,
-        now.format("%Y-%m-%d_%H-%M-%S%.3f")
-    );
-    fs::write(filename, data)
+        now.format("%Y-%m-%d_%H-%M-%S%.3f"),
+        extension
+    ));
+    fs::write(&path, data)?;
+    Ok(path)
+}
+
+/// Saves the given pixels as PNG file (see `save_png`) with the given name inside the given
+/// directory (which is created if needed) and returns the path of that file. The name must not
+/// contain path separators or `..`, so that programs can't write outside of the directory.
+pub fn save_screenshot(
+    directory: &Path,
+    filename: &str,
+    width: usize,
+    height: usize,
+    rgba: &[u8],
+) -> io::Result<PathBuf> {
+    if filename.is_empty() || filename.contains(['/', '\\']) || filename.contains("..") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid screenshot file name '{filename}'"),
+        ));
+    }
+    create_directory(directory)?;
+    let path = directory.join(filename);
+    save_png(&path, width, height, rgba)?;
+    Ok(path)
+}
+
+fn create_directory(directory: &Path) -> io::Result<()> {
+    fs::create_dir_all(directory).map_err(|error| {
+        io::Error::new(
+            error.kind(),
+            format!(
+                "cannot create dump directory {}: {error}",
+                directory.display()
+            ),
+        )
+    })
+}
+
+/// Writes the given pixels (RGBA, row by row) into an uncompressed PNG file.
+pub fn save_png(
+    path: impl AsRef<Path>,
+    width: usize,
+    height: usize,
+    rgba: &[u8],
+) -> io::Result<()> {
+    fs::write(path, encode_png(width, height, rgba))
+}
+
+fn encode_png(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    debug_assert_eq!(rgba.len(), width * height * 4);
+
+    // every scanline starts with its filter type (0 = none)
+    let mut scanlines = Vec::with_capacity(height * (width * 4 + 1));
+    for row in rgba.chunks_exact(width * 4) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 6, 0, 0, 0]); // 8 bit RGBA, no interlacing
+
+    let mut result = PNG_SIGNATURE.to_vec();
+    write_png_chunk(&mut result, b"IHDR", &header);
+    write_png_chunk(&mut result, b"IDAT", &zlib_uncompressed(&scanlines));
+    write_png_chunk(&mut result, b"IEND", &[]);
+    result
+}
+
+fn write_png_chunk(output: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    output.extend_from_slice(chunk_type);
+    output.extend_from_slice(data);
+    output.extend_from_slice(&crc32(chunk_type.iter().chain(data)).to_be_bytes());
+}
+
+/// Wraps the data into a zlib stream made of uncompressed ("stored") deflate blocks.
+fn zlib_uncompressed(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK_SIZE: usize = u16::MAX as usize;
+
+    debug_assert!(!data.is_empty());
+
+    let mut result = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK_SIZE).peekable();
+    while let Some(block) = blocks.next() {
+        let is_last_block = blocks.peek().is_none();
+        result.push(is_last_block as u8);
+        result.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        result.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        result.extend_from_slice(block);
+    }
+    result.extend_from_slice(&adler32(data).to_be_bytes());
+    result
+}
+
+pub(crate) fn crc32<'a>(data: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % MODULUS;
+        b = (b + a) % MODULUS;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_reference_values() {
+        assert_eq!(crc32(b"IEND".iter()), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    fn temporary_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("backseat_dumper_{}_{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    #[test]
+    fn dump_creates_timestamped_file_in_given_directory() {
+        let directory = temporary_directory("dump").join("nested");
+        let path = dump(&directory, "registers", "bin", b"data").unwrap();
+
+        assert_eq!(path.parent(), Some(directory.as_path()));
+        let filename = path.file_name().unwrap().to_str().unwrap();
+        let timestamp = filename
+            .strip_prefix("registers_")
+            .and_then(|rest| rest.strip_suffix(".bin"))
+            .unwrap();
+        assert!(
+            NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d_%H-%M-%S%.3f").is_ok(),
+            "unexpected timestamp in {filename}"
+        );
+        assert_eq!(fs::read(&path).unwrap(), b"data");
+        fs::remove_dir_all(directory.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn dump_reports_uncreatable_directory() {
+        let file = temporary_directory("not_a_directory");
+        fs::write(&file, b"").unwrap();
+        let error = dump(&file.join("dumps"), "memory", "bin", b"").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("cannot create dump directory"));
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn screenshot_is_saved_inside_given_directory() {
+        let directory = temporary_directory("screenshot");
+        let rgba = [0xFF; 2 * 2 * 4];
+        let path = save_screenshot(&directory, "shot.png", 2, 2, &rgba).unwrap();
+        assert_eq!(path, directory.join("shot.png"));
+        assert_eq!(fs::read(&path).unwrap(), encode_png(2, 2, &rgba));
+
+        for filename in [
+            "",
+            "../shot.png",
+            "nested/shot.png",
+            "nested\\shot.png",
+            "..",
+        ] {
+            let error = save_screenshot(&directory, filename, 2, 2, &rgba).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn compressed_memory_dump_round_trips() {
+        let mut memory = crate::memory::Memory::new();
+        memory.write_data(0x1000, 0xDEAD_BEEF);
+        memory.data_mut()[0x20_0000..0x20_0100].fill(0x42);
+        let last = memory.size() - 1;
+        memory.data_mut()[last] = 7;
+
+        let directory = temporary_directory("compressed");
+        let format = MemoryDumpFormat::Compressed;
+        let path = dump(
+            &directory,
+            "memory",
+            format.extension(),
+            &format.encode(memory.data()),
+        )
+        .unwrap();
+        let dump = fs::read(&path).unwrap();
+        assert!(dump.len() < memory.size() / 50);
+        assert!(decode_memory_dump(&dump).unwrap() == memory.data());
+        assert!(decode_memory_dump(&dump[..dump.len() - 1]).is_err());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn compressed_memory_dump_with_invalid_size_is_rejected() {
+        for size in [u64::MAX, Memory::MAX_SIZE as u64 + 4, 0x1003] {
+            let mut dump = COMPRESSED_MEMORY_MAGIC.to_vec();
+            dump.extend_from_slice(&size.to_be_bytes());
+            dump.extend_from_slice(&[0x81, 0x00]);
+            assert_eq!(
+                decode_memory_dump(&dump),
+                Err(format!("compressed memory dump has invalid size {size}"))
+            );
+        }
+    }
+
+    #[test]
+    fn raw_memory_dump_is_loaded_unchanged() {
+        let memory = [1, 2, 3, 4];
+        assert_eq!(
+            decode_memory_dump(&MemoryDumpFormat::Raw.encode(&memory)).unwrap(),
+            memory
+        );
+    }
+
+    #[test]
+    fn encoded_png_has_expected_layout() {
+        let rgba = [0xFF; 2 * 3 * 4];
+        let png = encode_png(2, 3, &rgba);
+
+        assert_eq!(&png[..8], PNG_SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..20], 2u32.to_be_bytes());
+        assert_eq!(png[20..24], 3u32.to_be_bytes());
+        assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xAE\x42\x60\x82");
+    }
 }
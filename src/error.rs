@@ -0,0 +1,120 @@
+use std::{fmt, io};
+
+use crate::{
+    address_constants::ENTRY_POINT, assembler::AssemblerError, rom::RomError,
+    snapshot::SnapshotError, Address, Instruction, Size, Word,
+};
+
+/// Errors that can occur while loading a ROM, a memory dump or a save state into a machine or
+/// while running one of the other command line actions.
+#[derive(Debug)]
+pub enum EmulatorError {
+    /// The data doesn't fit into the memory at the address it should be loaded to.
+    TooBig {
+        size: usize,
+        available: usize,
+    },
+    /// The size of the data isn't a multiple of `Word::SIZE`.
+    MisalignedSize(usize),
+    /// The address isn't divisible by the required alignment.
+    MisalignedAddress {
+        address: Address,
+        alignment: usize,
+    },
+    /// The entry point doesn't lie within the memory.
+    EntryPointOutOfRange(Address),
+    /// The memory size is invalid (see `Memory::is_valid_size`).
+    InvalidMemorySize(usize),
+    /// The ROM header is invalid (see `rom::split_header`).
+    Rom(RomError),
+    /// The save state can't be decoded.
+    Snapshot(SnapshotError),
+    /// Any other data (e.g. a memory dump) can't be decoded.
+    Decode(String),
+    /// The assembly source is invalid.
+    Assembler(AssemblerError),
+    /// There is no sample program with the given name.
+    UnknownSample(String),
+    /// Validating a ROM found the given number of invalid instructions.
+    InvalidInstructions(usize),
+    /// The font for the display can't be loaded.
+    Font(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::TooBig { size, available } => {
+                write!(
+                    f,
+                    "Buffer size {size} too big ({available} bytes available)"
+                )
+            }
+            EmulatorError::MisalignedSize(size) => {
+                write!(f, "Buffer size {size} must be divisible by {}", Word::SIZE)
+            }
+            EmulatorError::MisalignedAddress { address, alignment } => {
+                write!(
+                    f,
+                    "Address {address:#010x} must be divisible by {alignment}"
+                )
+            }
+            EmulatorError::EntryPointOutOfRange(address) => {
+                write!(f, "Entry point {address:#010x} is outside of the memory")
+            }
+            EmulatorError::InvalidMemorySize(size) => write!(
+                f,
+                "invalid memory size {size} (must be a multiple of {} and bigger than {})",
+                Instruction::SIZE,
+                ENTRY_POINT
+            ),
+            EmulatorError::Rom(error) => write!(f, "{error}"),
+            EmulatorError::Snapshot(error) => write!(f, "{error}"),
+            EmulatorError::Decode(message) => write!(f, "{message}"),
+            EmulatorError::Assembler(error) => write!(f, "{error}"),
+            EmulatorError::UnknownSample(name) => write!(f, "unknown sample program {name}"),
+            EmulatorError::InvalidInstructions(count) => {
+                write!(f, "found {count} invalid instruction(s)")
+            }
+            EmulatorError::Font(message) => write!(f, "cannot load font: {message}"),
+            EmulatorError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmulatorError::Rom(error) => Some(error),
+            EmulatorError::Snapshot(error) => Some(error),
+            EmulatorError::Assembler(error) => Some(error),
+            EmulatorError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for EmulatorError {
+    fn from(error: io::Error) -> Self {
+        EmulatorError::Io(error)
+    }
+}
+
+impl From<RomError> for EmulatorError {
+    fn from(error: RomError) -> Self {
+        EmulatorError::Rom(error)
+    }
+}
+
+impl From<SnapshotError> for EmulatorError {
+    fn from(error: SnapshotError) -> Self {
+        EmulatorError::Snapshot(error)
+    }
+}
+
+impl From<AssemblerError> for EmulatorError {
+    fn from(error: AssemblerError) -> Self {
+        EmulatorError::Assembler(error)
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{
+    memory::{Memory, MemoryError},
+    Address, Byte, Word,
+};
+
+/// `SystemCall` number that writes the zero-terminated string at the address in register 0 to
+/// the host's stdout.
+pub const SYSTEM_CALL_WRITE_STRING: Word = 0;
+/// `SystemCall` number that reads a line from the host's stdin into the buffer at the address
+/// in register 0 with the size (in bytes, including the zero terminator) in register 1. The
+/// line is stored without the line break and zero-terminated (truncated if it doesn't fit).
+/// Register 0 receives the length of the stored line or `Word::MAX` at the end of the input.
+pub const SYSTEM_CALL_READ_LINE: Word = 1;
+
+/// The host's stdin and stdout as seen by the `SystemCall` instruction. The output is flushed
+/// after every written string, so it's never lost when the process exits.
+pub struct HostIo {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+}
+
+impl HostIo {
+    pub fn new(input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
+        Self { input, output }
+    }
+
+    pub fn stdio() -> Self {
+        Self::new(
+            Box::new(io::BufReader::new(io::stdin())),
+            Box::new(io::BufWriter::new(io::stdout())),
+        )
+    }
+
+    /// Reads nothing and discards all output (e.g. for headless runs and tests).
+    pub fn null() -> Self {
+        Self::new(Box::new(io::empty()), Box::new(io::sink()))
+    }
+
+    pub fn write_string(&mut self, memory: &Memory, address: Address) -> Result<(), MemoryError> {
+        let mut string = Vec::new();
+        for address in address.. {
+            match memory.try_read_byte(address)? {
+                0 => break,
+                byte => string.push(byte),
+            }
+        }
+        // the program can't do anything about a closed stdout
+        let _ = self.output.write_all(&string);
+        self.flush();
+        Ok(())
+    }
+
+    pub fn flush(&mut self) {
+        let _ = self.output.flush();
+    }
+
+    /// Reads a line (without the line break) from the input. Returns `None` at the end of the
+    /// input.
+    pub fn read_line(&mut self) -> Option<Vec<u8>> {
+        // make sure a prompt is visible before waiting for the input
+        self.flush();
+        let mut line = Vec::new();
+        match self.input.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => return None,
+            Ok(_) => {}
+        }
+        if line.ends_with(b"\n") {
+            line.pop();
+            if line.ends_with(b"\r") {
+                line.pop();
+            }
+        }
+        Some(line)
+    }
+}
+
+/// Stores the line zero-terminated into the buffer at the given address with the given size
+/// (truncating the line if it doesn't fit) and returns the length of the stored line.
+pub fn store_line(
+    memory: &mut Memory,
+    address: Address,
+    size: Word,
+    mut line: Vec<u8>,
+) -> Result<Word, MemoryError> {
+    let Some(max_length) = (size as usize).checked_sub(1) else {
+        return Ok(0);
+    };
+    line.truncate(max_length);
+    for (&byte, address) in line.iter().chain(&[0 as Byte]).zip(address..) {
+        memory.try_write_byte(address, byte)?;
+    }
+    Ok(line.len() as Word)
+}
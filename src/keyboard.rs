@@ -1,22 +1,284 @@
+use std::collections::VecDeque;
+
+use int_enum::IntEnum;
+use serde::{Deserialize, Serialize};
+
 use crate::Word;
 
+/// The keys a program can query (see `GetKeyState`) and receive events for (see `KeyEvent`).
+/// The values are the keycodes used by the instruction set: the ASCII codes for letters (upper
+/// case), digits, space and punctuation, values from 256 on for all other keys (these match the
+/// GLFW keycodes that raylib uses).
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntEnum)]
+pub enum KeyCode {
+    Space = 32,
+    Apostrophe = 39,
+    Comma = 44,
+    Minus = 45,
+    Period = 46,
+    Slash = 47,
+    Digit0 = 48,
+    Digit1 = 49,
+    Digit2 = 50,
+    Digit3 = 51,
+    Digit4 = 52,
+    Digit5 = 53,
+    Digit6 = 54,
+    Digit7 = 55,
+    Digit8 = 56,
+    Digit9 = 57,
+    Semicolon = 59,
+    Equal = 61,
+    A = 65,
+    B = 66,
+    C = 67,
+    D = 68,
+    E = 69,
+    F = 70,
+    G = 71,
+    H = 72,
+    I = 73,
+    J = 74,
+    K = 75,
+    L = 76,
+    M = 77,
+    N = 78,
+    O = 79,
+    P = 80,
+    Q = 81,
+    R = 82,
+    S = 83,
+    T = 84,
+    U = 85,
+    V = 86,
+    W = 87,
+    X = 88,
+    Y = 89,
+    Z = 90,
+    LeftBracket = 91,
+    Backslash = 92,
+    RightBracket = 93,
+    Grave = 96,
+    Escape = 256,
+    Enter = 257,
+    Tab = 258,
+    Backspace = 259,
+    Insert = 260,
+    Delete = 261,
+    Right = 262,
+    Left = 263,
+    Down = 264,
+    Up = 265,
+    PageUp = 266,
+    PageDown = 267,
+    Home = 268,
+    End = 269,
+    CapsLock = 280,
+    ScrollLock = 281,
+    NumLock = 282,
+    PrintScreen = 283,
+    Pause = 284,
+    F1 = 290,
+    F2 = 291,
+    F3 = 292,
+    F4 = 293,
+    F5 = 294,
+    F6 = 295,
+    F7 = 296,
+    F8 = 297,
+    F9 = 298,
+    F10 = 299,
+    F11 = 300,
+    F12 = 301,
+    Keypad0 = 320,
+    Keypad1 = 321,
+    Keypad2 = 322,
+    Keypad3 = 323,
+    Keypad4 = 324,
+    Keypad5 = 325,
+    Keypad6 = 326,
+    Keypad7 = 327,
+    Keypad8 = 328,
+    Keypad9 = 329,
+    KeypadDecimal = 330,
+    KeypadDivide = 331,
+    KeypadMultiply = 332,
+    KeypadSubtract = 333,
+    KeypadAdd = 334,
+    KeypadEnter = 335,
+    KeypadEqual = 336,
+    LeftShift = 340,
+    LeftControl = 341,
+    LeftAlt = 342,
+    LeftSuper = 343,
+    RightShift = 344,
+    RightControl = 345,
+    RightAlt = 346,
+    RightSuper = 347,
+    Menu = 348,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum KeyState {
     Down,
     Up,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyEvent {
+    pub keycode: Word,
+    pub state: KeyState,
+}
+
+impl KeyEvent {
+    /// Bit that is set in an encoded event if the key has been pressed (and cleared if it has
+    /// been released).
+    pub const PRESSED_FLAG: Word = 1 << 16;
+    pub const KEYCODE_MASK: Word = Self::PRESSED_FLAG - 1;
+
+    /// Encodes the event into a single word: the lower 16 bits contain the keycode, bit 16 is
+    /// set for key presses. Since there is no keycode 0, an encoded event is never 0.
+    pub fn encode(&self) -> Word {
+        let flag = match self.state {
+            KeyState::Down => Self::PRESSED_FLAG,
+            KeyState::Up => 0,
+        };
+        (self.keycode & Self::KEYCODE_MASK) | flag
+    }
+}
+
 pub struct Keyboard {
-    get_keystate_callback: Box<dyn FnMut(Word) -> KeyState>,
+    get_keystate_callback: Box<dyn FnMut(KeyCode) -> KeyState>,
+    events: VecDeque<KeyEvent>,
+    characters: VecDeque<char>,
 }
 
 impl Keyboard {
-    pub fn new(get_keystate_callback: Box<dyn FnMut(Word) -> KeyState>) -> Self {
+    /// Maximum number of events that are buffered. Further events are dropped until the program
+    /// polls the queue.
+    pub const EVENT_QUEUE_CAPACITY: usize = 64;
+    /// Maximum number of typed characters that are buffered.
+    pub const CHARACTER_QUEUE_CAPACITY: usize = 64;
+
+    pub fn new(get_keystate_callback: Box<dyn FnMut(KeyCode) -> KeyState>) -> Self {
         Keyboard {
             get_keystate_callback,
+            events: VecDeque::with_capacity(Self::EVENT_QUEUE_CAPACITY),
+            characters: VecDeque::with_capacity(Self::CHARACTER_QUEUE_CAPACITY),
         }
     }
 
+    /// Returns the state of the key with the given keycode (see `KeyCode`). Unknown keys are never
+    /// held down.
     pub fn get_keystate(&mut self, key: Word) -> KeyState {
-        (self.get_keystate_callback)(key)
+        match KeyCode::try_from(key) {
+            Ok(key) => (self.get_keystate_callback)(key),
+            Err(_) => KeyState::Up,
+        }
+    }
+
+    /// Enqueues an event. Returns `false` if the event was dropped because the queue is full.
+    pub fn push_event(&mut self, event: KeyEvent) -> bool {
+        if self.events.len() >= Self::EVENT_QUEUE_CAPACITY {
+            return false;
+        }
+        self.events.push_back(event);
+        true
+    }
+
+    /// Removes and returns the oldest event.
+    pub fn poll_event(&mut self) -> Option<KeyEvent> {
+        self.events.pop_front()
+    }
+
+    /// Enqueues a typed character. Returns `false` if the character was dropped because the queue
+    /// is full.
+    pub fn push_char(&mut self, character: char) -> bool {
+        if self.characters.len() >= Self::CHARACTER_QUEUE_CAPACITY {
+            return false;
+        }
+        self.characters.push_back(character);
+        true
+    }
+
+    /// Removes and returns the oldest typed character.
+    pub fn poll_char(&mut self) -> Option<char> {
+        self.characters.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_keyboard() -> Keyboard {
+        Keyboard::new(Box::new(|_| KeyState::Up))
+    }
+
+    #[test]
+    fn events_are_polled_in_order() {
+        let mut keyboard = create_keyboard();
+        let events = [
+            KeyEvent {
+                keycode: 65,
+                state: KeyState::Down,
+            },
+            KeyEvent {
+                keycode: 66,
+                state: KeyState::Down,
+            },
+            KeyEvent {
+                keycode: 65,
+                state: KeyState::Up,
+            },
+        ];
+        for event in events {
+            assert!(keyboard.push_event(event));
+        }
+        for event in events {
+            assert_eq!(keyboard.poll_event(), Some(event));
+        }
+        assert_eq!(keyboard.poll_event(), None);
+    }
+
+    #[test]
+    fn full_queue_drops_new_events() {
+        let mut keyboard = create_keyboard();
+        for keycode in 0..Keyboard::EVENT_QUEUE_CAPACITY {
+            assert!(keyboard.push_event(KeyEvent {
+                keycode: keycode as Word + 1,
+                state: KeyState::Down,
+            }));
+        }
+        assert!(!keyboard.push_event(KeyEvent {
+            keycode: 1000,
+            state: KeyState::Down,
+        }));
+        assert_eq!(keyboard.poll_event().map(|event| event.keycode), Some(1));
+    }
+
+    #[test]
+    fn encode_event() {
+        let pressed = KeyEvent {
+            keycode: 32,
+            state: KeyState::Down,
+        };
+        let released = KeyEvent {
+            keycode: 32,
+            state: KeyState::Up,
+        };
+        assert_eq!(pressed.encode(), 32 | KeyEvent::PRESSED_FLAG);
+        assert_eq!(released.encode(), 32);
+    }
+
+    #[test]
+    fn characters_are_polled_in_order() {
+        let mut keyboard = create_keyboard();
+        assert!(keyboard.push_char('h'));
+        assert!(keyboard.push_char('i'));
+        assert_eq!(keyboard.poll_char(), Some('h'));
+        assert_eq!(keyboard.poll_char(), Some('i'));
+        assert_eq!(keyboard.poll_char(), None);
     }
 }
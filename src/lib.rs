@@ -0,0 +1,109 @@
+//! The BackseatSafeSystem2k virtual machine. The `backseat_safe_system_2k` binary is a thin
+//! frontend over this library, which can also be used to embed the emulator elsewhere (e.g. in
+//! test harnesses).
+
+pub mod address_constants;
+pub mod assembler;
+pub mod cursor;
+#[cfg(feature = "debugger")]
+pub mod debugger;
+pub mod disassembler;
+pub mod display;
+pub mod dumper;
+pub mod error;
+pub mod host_io;
+pub mod keyboard;
+pub mod machine;
+pub mod memory;
+pub mod mouse;
+pub mod opcodes;
+pub mod periphery;
+pub mod processor;
+pub mod profiler;
+pub mod replay;
+pub mod rom;
+pub mod samples;
+pub mod snapshot;
+pub mod stepper;
+pub mod terminal;
+pub mod timer;
+pub mod tracer;
+pub mod validator;
+use address_constants::ENTRY_POINT;
+use serde::{Deserialize, Serialize};
+
+pub use error::EmulatorError;
+pub use machine::Machine;
+pub use memory::Memory;
+pub use opcodes::Opcode;
+pub use processor::{ExecutionResult, Processor};
+
+pub struct Size2D {
+    pub width: i32,
+    pub height: i32,
+}
+
+pub const SCREEN_SIZE: Size2D = Size2D {
+    width: 1280,
+    height: 720,
+};
+
+pub const OPCODE_LENGTH: usize = 16;
+
+pub const fn static_assert(condition: bool) {
+    assert!(condition);
+}
+
+pub type Instruction = u64;
+pub type Word = u32;
+pub type Halfword = u16;
+pub type Byte = u8;
+pub type Address = u32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Register(pub u8);
+
+impl From<u8> for Register {
+    fn from(value: u8) -> Self {
+        Self(value)
+    }
+}
+
+const _: () = static_assert(Halfword::SIZE * 2 == Word::SIZE);
+
+pub trait AsHalfwords {
+    fn as_halfwords(&self) -> (Halfword, Halfword);
+}
+
+impl AsHalfwords for Word {
+    fn as_halfwords(&self) -> (Halfword, Halfword) {
+        (
+            (self >> (8 * Halfword::SIZE)) as Halfword,
+            *self as Halfword,
+        )
+    }
+}
+
+pub trait AsWords {
+    fn as_words(&self) -> (Word, Word);
+}
+
+impl AsWords for Instruction {
+    fn as_words(&self) -> (Word, Word) {
+        ((self >> (Word::SIZE * 8)) as Word, *self as Word)
+    }
+}
+
+pub trait Size: Sized {
+    const SIZE: usize = std::mem::size_of::<Self>();
+}
+
+impl Size for Instruction {}
+impl Size for Word {}
+impl Size for Halfword {}
+impl Size for Byte {}
+
+/// Returns the address of the instruction with the given index, counted from the entry point.
+pub const fn instruction_address(index: usize) -> Address {
+    ENTRY_POINT + (index * Instruction::SIZE) as Address
+}
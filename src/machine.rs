@@ -1,21 +1,51 @@
 use std::time::Instant;
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
     address_constants,
-    cursor::{Cursor, CursorMode},
-    display,
+    cursor::CursorMode,
+    display::{self, DisplayMode},
+    error::EmulatorError,
     memory::Memory,
     periphery::PeripheryImplementation,
     processor::{CachedInstruction, ExecutionResult, InstructionCache, Processor},
-    terminal, Address, Instruction, Size,
+    rom,
+    snapshot::MachineSnapshot,
+    terminal,
+    tracer::Tracer,
+    Address, Instruction, Size, Word,
 };
 
 #[cfg(feature = "debugger")]
-use crate::debugger::{DebugHandle, ShouldExecuteInstruction};
+use crate::debugger::{DebugCommand, DebugHandle, DebugMessage, ShouldExecuteInstruction};
+#[cfg(feature = "debugger")]
+use crossbeam_channel::{Receiver, Sender};
 
 #[cfg(feature = "graphics")]
 use raylib::prelude::*;
 
+/// Why the machine has stopped executing instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HaltReason {
+    /// The program executed `HaltAndCatchFire`.
+    CaughtFire,
+    /// An `Assert*` instruction or a `Checkpoint` failed.
+    AssertionFailed,
+    /// An instruction could not be executed (e.g. invalid opcode or memory access).
+    Error,
+    /// The cycle limit (see `Machine::set_max_cycles`) has been reached.
+    CycleLimit,
+}
+
+/// A failed `Assert*` instruction or `Checkpoint` (see `ExecutionResult::AssertionFailed`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssertionFailure {
+    pub expected: Word,
+    pub actual: Word,
+    pub address: Address,
+}
+
 pub struct Machine<Display>
 where
     Display: display::Display,
@@ -23,8 +53,14 @@ where
     pub memory: Memory,
     pub processor: Processor,
     pub periphery: PeripheryImplementation<Display>,
-    is_halted: bool,
+    halt_reason: Option<HaltReason>,
+    assertion_failures: Vec<AssertionFailure>,
+    halt_on_assertion_failure: bool,
+    real_time_sleep: bool,
+    pad_rom: bool,
+    max_cycles: Option<u64>,
     instruction_cache: InstructionCache<PeripheryImplementation<Display>>,
+    tracer: Option<Tracer>,
     #[cfg(feature = "debugger")]
     debug_handle: DebugHandle,
 }
@@ -33,9 +69,21 @@ impl<Display> Machine<Display>
 where
     Display: display::Display + 'static,
 {
-    pub fn new(periphery: PeripheryImplementation<Display>, exit_on_halt: bool) -> Self {
-        const MAX_NUM_INSTRUCTIONS: usize = Memory::SIZE / Instruction::SIZE;
-        let cache: Vec<_> = (0..MAX_NUM_INSTRUCTIONS)
+    pub fn new(periphery: PeripheryImplementation<Display>) -> Self {
+        Self::with_memory(periphery, Memory::new())
+    }
+
+    /// Creates a machine with the given memory size in bytes (see `Memory::with_size`).
+    pub fn with_memory_size(
+        periphery: PeripheryImplementation<Display>,
+        memory_size: usize,
+    ) -> Self {
+        Self::with_memory(periphery, Memory::with_size(memory_size))
+    }
+
+    fn with_memory(periphery: PeripheryImplementation<Display>, mut memory: Memory) -> Self {
+        memory.set_framebuffer_size(periphery.display.resolution().framebuffer_size());
+        let cache: Vec<_> = (0..memory.size() / Instruction::SIZE)
             .map(|_| {
                 Box::new(
                     |_: &mut Processor,
@@ -47,67 +95,183 @@ where
             })
             .collect();
         let instruction_cache = InstructionCache {
-            cache: cache
-                .into_boxed_slice()
-                .try_into()
-                .unwrap_or_else(|_| unreachable!()),
+            executed: vec![false; cache.len()].into_boxed_slice(),
+            cache: cache.into_boxed_slice(),
+            num_distinct: 0,
         };
 
         #[cfg(not(feature = "debugger"))]
         {
             Self {
-                memory: Memory::new(),
-                processor: Processor::new(exit_on_halt),
+                memory,
+                processor: Processor::new(),
                 periphery,
-                is_halted: false,
+                halt_reason: None,
+                assertion_failures: Vec::new(),
+                halt_on_assertion_failure: true,
+                real_time_sleep: false,
+                pad_rom: false,
+                max_cycles: None,
                 instruction_cache,
+                tracer: None,
             }
         }
         #[cfg(feature = "debugger")]
         {
             Self {
-                memory: Memory::new(),
-                processor: Processor::new(exit_on_halt),
+                memory,
+                processor: Processor::new(),
                 periphery,
-                is_halted: false,
+                halt_reason: None,
+                assertion_failures: Vec::new(),
+                halt_on_assertion_failure: true,
+                real_time_sleep: false,
+                pad_rom: false,
+                max_cycles: None,
                 instruction_cache,
+                tracer: None,
                 debug_handle: DebugHandle::dummy(),
             }
         }
     }
 
     pub fn generate_instruction_cache(&mut self) {
-        const MAX_NUM_INSTRUCTIONS: usize = Memory::SIZE / Instruction::SIZE;
-        let cache: Vec<CachedInstruction<PeripheryImplementation<Display>>> = (0
-            ..MAX_NUM_INSTRUCTIONS)
-            .map(|i| {
-                let address = (i * Instruction::SIZE) as Address;
-                match address >= address_constants::ENTRY_POINT {
-                    true => match self.memory.read_opcode(address) {
-                        Ok(opcode) => Processor::generate_cached_instruction(opcode),
-                        Err(_) => Box::new(
-                            |_: &mut Processor,
-                             _: &mut Memory,
-                             _: &mut PeripheryImplementation<Display>| {
-                                ExecutionResult::Error
-                            },
-                        ),
-                    },
-                    false => Box::new(
-                        |_: &mut Processor,
-                         _: &mut Memory,
-                         _: &mut PeripheryImplementation<Display>| {
-                            ExecutionResult::Error
-                        },
-                    ),
-                }
-            })
-            .collect();
+        let cache: Vec<CachedInstruction<PeripheryImplementation<Display>>> =
+            (0..self.memory.size() / Instruction::SIZE)
+                .map(|i| {
+                    Processor::generate_cached_instruction_at(
+                        &self.memory,
+                        (i * Instruction::SIZE) as Address,
+                    )
+                })
+                .collect();
+
+        // regenerating keeps the executed instructions, only the memory size may have changed
+        if self.instruction_cache.executed.len() != cache.len() {
+            self.instruction_cache.executed = vec![false; cache.len()].into_boxed_slice();
+        }
+        self.instruction_cache.cache = cache.into_boxed_slice();
+        self.memory.clear_dirty_instructions();
+    }
+
+    /// Copies the ROM into memory at the entry point and regenerates the instruction cache (see
+    /// `load_rom_at`).
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), EmulatorError> {
+        self.load_rom_at(rom, address_constants::ENTRY_POINT)
+    }
+
+    /// Copies the ROM into memory at the given (word aligned) address and regenerates the
+    /// instruction cache. If the ROM starts with a header (see `rom::RomHeader`), only the body
+    /// is copied and the instruction pointer is set to the declared entry point, otherwise the
+    /// instruction pointer is not changed (see `set_entry_point`). The size of the ROM must be a
+    /// multiple of `Word::SIZE` unless padding is enabled (see `set_pad_rom`).
+    pub fn load_rom_at(&mut self, rom: &[u8], address: Address) -> Result<(), EmulatorError> {
+        let (header, rom) = rom::split_header(rom)?;
+        if address as usize % Word::SIZE != 0 {
+            return Err(EmulatorError::MisalignedAddress {
+                address,
+                alignment: Word::SIZE,
+            });
+        }
+        let size = match self.pad_rom {
+            true => rom.len().next_multiple_of(Word::SIZE),
+            false => rom.len(),
+        };
+        let available = self.memory.size().saturating_sub(address as usize);
+        if available < size {
+            return Err(EmulatorError::TooBig { size, available });
+        }
+        if size % Word::SIZE != 0 {
+            return Err(EmulatorError::MisalignedSize(size));
+        }
+        let target = &mut self.memory.data_mut()[address as usize..][..size];
+        target[..rom.len()].copy_from_slice(rom);
+        target[rom.len()..].fill(0);
+        self.generate_instruction_cache();
+        if let Some(header) = header {
+            self.set_entry_point(header.entry_point)?;
+        }
+        Ok(())
+    }
+
+    /// Sets whether ROMs whose size isn't a multiple of `Word::SIZE` are zero-padded up to the
+    /// next word boundary when loaded instead of being rejected (the default).
+    pub fn set_pad_rom(&mut self, pad: bool) {
+        self.pad_rom = pad;
+    }
+
+    /// Sets the instruction pointer to the given address, which must be aligned to the size of
+    /// an instruction and lie within the memory.
+    pub fn set_entry_point(&mut self, address: Address) -> Result<(), EmulatorError> {
+        if address as usize + Instruction::SIZE > self.memory.size() {
+            return Err(EmulatorError::EntryPointOutOfRange(address));
+        }
+        if address as usize % Instruction::SIZE != 0 {
+            return Err(EmulatorError::MisalignedAddress {
+                address,
+                alignment: Instruction::SIZE,
+            });
+        }
+        self.processor.set_instruction_pointer(address);
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> MachineSnapshot {
+        MachineSnapshot {
+            memory: self.memory.data().to_vec(),
+            processor: self.processor.snapshot(),
+            halt_reason: self.halt_reason,
+            assertion_failures: self.assertion_failures.clone(),
+            swap_state: self.periphery.display.swap_state().clone(),
+            display_mode: self.periphery.display.mode() as Word,
+            cursor_visible: self.periphery.cursor.visible,
+            terminal_writer: self.periphery.terminal_writer.clone(),
+        }
+    }
+
+    /// Replaces the whole memory contents (e.g. with a dump written by `DumpMemory`) and
+    /// regenerates the instruction cache. The memory size changes to the size of the data.
+    pub fn load_memory(&mut self, data: &[u8]) -> Result<(), EmulatorError> {
+        if !Memory::is_valid_size(data.len()) {
+            return Err(EmulatorError::InvalidMemorySize(data.len()));
+        }
+        let mut memory = Memory::with_size(data.len());
+        memory.data_mut().copy_from_slice(data);
+        memory.set_framebuffer_size(self.periphery.display.resolution().framebuffer_size());
+        self.memory = memory;
+        self.generate_instruction_cache();
+        Ok(())
+    }
 
-        self.instruction_cache.cache = cache
-            .into_boxed_slice()
-            .try_into()
-            .unwrap_or_else(|_| unreachable!());
+    /// Restores a snapshot taken with `snapshot`. The instruction cache is regenerated from the
+    /// restored memory contents.
+    pub fn restore(&mut self, snapshot: &MachineSnapshot) -> Result<(), EmulatorError> {
+        if !Memory::is_valid_size(snapshot.memory.len()) {
+            return Err(EmulatorError::InvalidMemorySize(snapshot.memory.len()));
+        }
+        let display_mode = DisplayMode::try_from(snapshot.display_mode).map_err(|_| {
+            EmulatorError::Decode(format!("invalid display mode {}", snapshot.display_mode))
+        })?;
+        self.processor
+            .restore(&snapshot.processor)
+            .map_err(EmulatorError::Decode)?;
+        let mut memory = Memory::with_size(snapshot.memory.len());
+        memory.data_mut().copy_from_slice(&snapshot.memory);
+        memory.set_framebuffer_size(self.periphery.display.resolution().framebuffer_size());
+        self.memory = memory;
+        self.halt_reason = snapshot.halt_reason;
+        self.assertion_failures = snapshot.assertion_failures.clone();
+        let display = &mut self.periphery.display;
+        let vsync = display.swap_state().vsync;
+        display.set_swap_state(display::SwapState {
+            vsync,
+            ..snapshot.swap_state.clone()
+        });
+        display.set_mode(display_mode);
+        self.periphery.cursor.visible = snapshot.cursor_visible;
+        self.periphery.terminal_writer = snapshot.terminal_writer.clone();
+        self.generate_instruction_cache();
+        Ok(())
     }
 
     fn update_cursor(&mut self) {
@@ -116,16 +280,7 @@ where
                 .read_data(address_constants::TERMINAL_CURSOR_MODE),
         );
         if let Ok(cursor_mode) = cursor_mode_flag {
-            match cursor_mode {
-                CursorMode::Blinking => {
-                    if Instant::now() >= self.periphery.cursor.time_of_next_toggle {
-                        self.periphery.cursor.visible = !self.periphery.cursor.visible;
-                        self.periphery.cursor.time_of_next_toggle += Cursor::TOGGLE_INTERVAL;
-                    }
-                }
-                CursorMode::Visible => self.periphery.cursor.visible = true,
-                CursorMode::Invisible => self.periphery.cursor.visible = false,
-            }
+            self.periphery.cursor.update(cursor_mode, Instant::now());
         }
     }
 
@@ -146,37 +301,287 @@ where
     pub fn execute_next_instruction(&mut self) {
         use crate::processor::ExecutionResult::*;
 
+        self.apply_replayed_render_ticks();
+        let remaining_sleep_ms = self.remaining_sleep_ms();
+        if remaining_sleep_ms > 0 {
+            if self.real_time_sleep {
+                return;
+            }
+            self.periphery.timer.skip_monotonic(remaining_sleep_ms);
+            self.processor.wake_up();
+        }
+        if self.is_waiting_for_frame() {
+            return;
+        }
+
         #[cfg(feature = "debugger")]
         {
             let result = self
                 .debug_handle
                 .before_instruction_execution(&mut self.processor, &mut self.memory);
-            if let ShouldExecuteInstruction::No = result {
-                return;
+            match result {
+                ShouldExecuteInstruction::Yes => {}
+                ShouldExecuteInstruction::No => return,
+                ShouldExecuteInstruction::Terminate => {
+                    self.periphery.host_io.flush();
+                    std::process::exit(0);
+                }
             }
         }
 
-        match self.processor.execute_next_instruction(
+        let trace_state = self.tracer.is_some().then(|| {
+            (
+                self.processor.get_instruction_pointer(),
+                *self.processor.registers.contents(),
+            )
+        });
+
+        let result = self.processor.execute_next_instruction(
             &mut self.memory,
             &mut self.periphery,
             &mut self.instruction_cache,
-        ) {
-            Error => panic!(),
-            Normal => {}
+        );
+
+        if let (Some(tracer), Some((instruction_address, registers_before))) =
+            (&mut self.tracer, trace_state)
+        {
+            tracer.trace(
+                instruction_address,
+                self.memory.read_opcode(instruction_address).ok(),
+                &registers_before,
+                self.processor.registers.contents(),
+            );
+        }
+
+        match result {
+            Error => {
+                eprintln!(
+                    "execution error at address {:#010x}",
+                    self.processor.get_instruction_pointer()
+                );
+                self.halt_reason = Some(HaltReason::Error);
+            }
+            AssertionFailed {
+                expected,
+                actual,
+                address,
+            } => {
+                eprintln!(
+                    "assertion failed at address {:#010x}: expected {:#010x}, got {:#010x}",
+                    address, expected, actual
+                );
+                self.assertion_failures.push(AssertionFailure {
+                    expected,
+                    actual,
+                    address,
+                });
+                if self.halt_on_assertion_failure {
+                    self.halt_reason = Some(HaltReason::AssertionFailed);
+                } else {
+                    // the failed assertion has just been decoded from this address
+                    let opcode = self
+                        .memory
+                        .read_opcode(address)
+                        .expect("failed assertion must be a valid opcode");
+                    self.processor.complete_instruction(opcode);
+                }
+            }
+            Normal => {
+                // programs write text directly and advance the cursor pointer themselves
+                if self.memory.take_terminal_cursor_dirty() {
+                    terminal::scroll_if_needed(&mut self.memory);
+                }
+            }
             Halted => {
-                self.is_halted = true;
+                self.halt_reason = Some(HaltReason::CaughtFire);
+            }
+        }
+
+        #[cfg(feature = "debugger")]
+        if self.is_halted() {
+            self.debug_handle.after_halt(&self.processor);
+        }
+    }
+
+    /// Executes up to `count` instructions. Stops early if the machine halts or runs into an
+    /// error and returns the corresponding result, returns `Normal` if all instructions have been
+    /// executed. Reaching the cycle limit (see `set_max_cycles`) counts as an error.
+    pub fn run_cycles(&mut self, count: u64) -> ExecutionResult {
+        for _ in 0..count {
+            self.apply_replayed_render_ticks();
+            if self.is_halted()
+                || (self.real_time_sleep && self.remaining_sleep_ms() > 0)
+                || self.is_waiting_for_frame()
+            {
+                break;
+            }
+            if let Some(max_cycles) = self.max_cycles {
+                if self.processor.get_cycle_count() >= max_cycles {
+                    eprintln!(
+                        "cycle limit of {max_cycles} cycles reached at address {:#010x}",
+                        self.processor.get_instruction_pointer()
+                    );
+                    self.halt_reason = Some(HaltReason::CycleLimit);
+                    #[cfg(feature = "debugger")]
+                    self.debug_handle.after_halt(&self.processor);
+                    break;
+                }
+            }
+            self.execute_next_instruction();
+        }
+        match self.halt_reason {
+            None => ExecutionResult::Normal,
+            Some(HaltReason::CaughtFire) => ExecutionResult::Halted,
+            Some(HaltReason::AssertionFailed) => {
+                // the failure that caused the halt is always the last one
+                let AssertionFailure {
+                    expected,
+                    actual,
+                    address,
+                } = *self.assertion_failures.last().unwrap();
+                ExecutionResult::AssertionFailed {
+                    expected,
+                    actual,
+                    address,
+                }
             }
+            Some(HaltReason::Error | HaltReason::CycleLimit) => ExecutionResult::Error,
         }
     }
 
+    /// Enables (or disables with `None`) tracing of every executed instruction.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
     #[must_use = "Am I a joke to you?"]
     pub fn is_halted(&self) -> bool {
-        self.is_halted
+        self.halt_reason.is_some()
+    }
+
+    /// Returns whether the execution has been stopped because of an error, a failed assertion or
+    /// the cycle limit.
+    #[must_use]
+    pub fn is_faulted(&self) -> bool {
+        matches!(
+            self.halt_reason,
+            Some(HaltReason::Error | HaltReason::AssertionFailed | HaltReason::CycleLimit)
+        )
+    }
+
+    /// Returns all assertion failures so far, in the order they happened.
+    pub fn assertion_failures(&self) -> &[AssertionFailure] {
+        &self.assertion_failures
+    }
+
+    /// Sets whether `SleepMilliseconds` waits for the monotonic clock of the timer (`run_cycles`
+    /// returns early while the processor sleeps). By default, the clock is advanced by the
+    /// requested duration instead, so that headless runs don't wait.
+    pub fn set_real_time_sleep(&mut self, enabled: bool) {
+        self.real_time_sleep = enabled;
+    }
+
+    /// Sets the number of cycles after which `run_cycles` halts the machine with
+    /// `HaltReason::CycleLimit` (unlimited with `None`, the default), so that runaway programs
+    /// terminate.
+    pub fn set_max_cycles(&mut self, max_cycles: Option<u64>) {
+        self.max_cycles = max_cycles;
+    }
+
+    /// Returns for how many more milliseconds the processor sleeps (see `SleepMilliseconds`).
+    pub fn remaining_sleep_ms(&mut self) -> u64 {
+        let Some(sleep_until) = self.processor.sleep_until() else {
+            return 0;
+        };
+        let now = self.periphery.timer.get_monotonic_ms();
+        if now >= sleep_until {
+            self.processor.wake_up();
+            return 0;
+        }
+        sleep_until - now
+    }
+
+    /// Makes `SwapFramebuffers` stall the execution until the swap has been applied with the next
+    /// rendered frame (see `render_tick`) instead of swapping immediately.
+    pub fn set_vsync(&mut self, enabled: bool) {
+        self.periphery.display.set_vsync(enabled);
+    }
+
+    /// Returns whether the execution stalls because a swap waits for the next rendered frame
+    /// (see `set_vsync`).
+    pub fn is_swap_pending(&self) -> bool {
+        self.periphery.display.is_vsync_swap_pending()
+    }
+
+    /// Returns whether the execution stalls until the next frame gets rendered (because of a
+    /// pending swap or a `WaitForVSync`).
+    pub fn is_waiting_for_frame(&self) -> bool {
+        self.is_swap_pending() || self.processor.is_waiting_for_vsync()
+    }
+
+    /// Called by the render path for every rendered frame. Advances the frame count (see
+    /// `PollFrameCount`) and applies a swap that is pending because of vsync, which resumes the
+    /// execution. The frame goes through the input log, i.e. while replaying, the recorded frames
+    /// are applied at their original cycle counts instead.
+    pub fn render_tick(&mut self) {
+        let cycle = self.processor.get_cycle_count();
+        if self.periphery.input_log.render_tick(cycle) {
+            self.apply_render_tick();
+        }
+    }
+
+    fn apply_render_tick(&mut self) {
+        self.processor.advance_frame_count();
+        self.periphery.display.render_tick();
+    }
+
+    fn apply_replayed_render_ticks(&mut self) {
+        while self
+            .periphery
+            .input_log
+            .replayed_render_tick(self.processor.get_cycle_count())
+        {
+            self.apply_render_tick();
+        }
+    }
+
+    /// Sets whether a failed assertion halts the machine (the default). Otherwise, the failure
+    /// is only recorded and the execution continues after the failed instruction.
+    pub fn set_halt_on_assertion_failure(&mut self, halt: bool) {
+        self.halt_on_assertion_failure = halt;
+    }
+
+    /// Returns why the machine has halted (or `None` if it is still running).
+    #[must_use]
+    pub fn halt_reason(&self) -> Option<HaltReason> {
+        self.halt_reason
+    }
+
+    /// Returns the number of distinct instructions compiled into the instruction cache that have
+    /// been executed so far. An instruction overwritten by self-modifying code is compiled again
+    /// and counts as a new one once it is executed.
+    pub fn num_distinct_instructions(&self) -> u64 {
+        self.instruction_cache.num_distinct
+    }
+
+    #[cfg(feature = "debugger")]
+    pub fn start_debugger(
+        &mut self,
+        host: &str,
+        port: u16,
+        symbols: crate::debugger::SymbolMap,
+    ) -> std::io::Result<()> {
+        self.debug_handle = crate::debugger::start_debugger(host, port, symbols)?;
+        Ok(())
     }
 
+    /// Attaches a debugger that is controlled through the returned channels instead of TCP (see
+    /// `debugger::start_in_process_debugger`).
     #[cfg(feature = "debugger")]
-    pub fn start_debugger(&mut self) {
-        self.debug_handle = crate::debugger::start_debugger();
+    pub fn start_in_process_debugger(&mut self) -> (Sender<DebugCommand>, Receiver<DebugMessage>) {
+        let (handle, commands, messages) = crate::debugger::start_in_process_debugger();
+        self.debug_handle = handle;
+        (commands, messages)
     }
 
     #[cfg(feature = "debugger")]
@@ -187,14 +592,18 @@ where
 
 #[cfg(test)]
 mod tests {
-    use std::time::Instant;
+    use std::io;
 
-    use crate::cursor::Cursor;
-    use crate::display::MockDisplay;
-    use crate::keyboard::{KeyState, Keyboard};
+    use crate::display::{Display, DisplayMode, MockDisplay, Resolution};
+    use crate::host_io::{HostIo, SYSTEM_CALL_READ_LINE, SYSTEM_CALL_WRITE_STRING};
+    use crate::keyboard::{KeyCode, KeyEvent, KeyState, Keyboard};
+    use crate::mouse::{self, MockMouse, Mouse};
     use crate::processor::Flag;
+    use crate::replay::{InputLog, Recording};
     use crate::timer::Timer;
-    use crate::{address_constants, Address, Instruction, Size, Word};
+    use crate::{
+        address_constants, instruction_address, Address, AsWords, Instruction, Size, Word,
+    };
     use crate::{
         opcodes::Opcode::{self, *},
         Register,
@@ -275,7 +684,7 @@ mod tests {
     }
 
     fn create_machine_with_opcodes(opcodes: &[Opcode]) -> Machine<MockDisplay> {
-        let mut machine = Machine::new(create_mock_periphery(), false);
+        let mut machine = Machine::new(create_mock_periphery());
         for (&opcode, address) in opcodes
             .iter()
             .zip((address_constants::ENTRY_POINT..).step_by(Instruction::SIZE))
@@ -313,12 +722,8 @@ mod tests {
                 time += 1;
                 old_value
             }),
-            keyboard: Keyboard::new(Box::new(|_| KeyState::Up)),
-            display: MockDisplay::new(&mut (), &()),
-            cursor: Cursor {
-                visible: false,
-                time_of_next_toggle: Instant::now() + Cursor::TOGGLE_INTERVAL,
-            },
+            mouse: Mouse::new(12, 34),
+            ..PeripheryImplementation::headless(Resolution::DEFAULT)
         }
     }
 
@@ -328,10 +733,7 @@ mod tests {
             register: 0.into(),
             immediate: 0
         }],
-        registers_post = [(
-            Processor::INSTRUCTION_POINTER,
-            address_constants::ENTRY_POINT + Instruction::SIZE as u32
-        )],
+        registers_post = [(Processor::INSTRUCTION_POINTER, instruction_address(1))],
     );
 
     create_test!(
@@ -385,7 +787,7 @@ mod tests {
 
     #[test]
     fn move_from_one_register_to_another() {
-        let mut machine = Machine::new(create_mock_periphery(), false);
+        let mut machine = Machine::new(create_mock_periphery());
         let source = 0x5.into();
         let target = 0x0A.into();
         let data = 0xCAFE;
@@ -680,6 +1082,56 @@ mod tests {
         registers_post = [(target, 0xABCD)],
     );
 
+    create_test!(
+        store_register_range_into_memory,
+        setup = {
+            let address = 0x1F0;
+            let pointer = 0x0A.into();
+        },
+        opcodes = &[StoreRegisterRange { pointer, first_register: 0x20.into(), immediate: 3 }],
+        registers_pre = [address => pointer, 0xAA => 0x20, 0xBB => 0x21, 0xCC => 0x22, 0xDD => 0x23],
+        memory_post = [
+            (address, 0xAA),
+            (address + Word::SIZE as Address, 0xBB),
+            (address + 2 * Word::SIZE as Address, 0xCC),
+            (address + 3 * Word::SIZE as Address, 0)
+        ],
+    );
+
+    create_test!(
+        load_register_range_from_memory,
+        setup = {
+            let address = 0x1F0;
+            let pointer = 0x0A.into();
+        },
+        opcodes = &[LoadRegisterRange { pointer, first_register: 0x20.into(), immediate: 3 }],
+        registers_pre = [address => pointer],
+        memory_pre = [
+            0xAA => address,
+            0xBB => address + Word::SIZE as Address,
+            0xCC => address + 2 * Word::SIZE as Address,
+            0xDD => address + 3 * Word::SIZE as Address
+        ],
+        registers_post = [(0x20.into(), 0xAA), (0x21.into(), 0xBB), (0x22.into(), 0xCC), (0x23.into(), 0)],
+    );
+
+    #[test]
+    fn register_range_exceeding_the_register_file_is_an_error() {
+        let mut machine = create_machine_with_opcodes(&[StoreRegisterRange {
+            pointer: 0.into(),
+            first_register: 250.into(),
+            immediate: 10,
+        }]);
+        assert!(matches!(
+            machine.processor.execute_next_instruction(
+                &mut machine.memory,
+                &mut machine.periphery,
+                &mut machine.instruction_cache,
+            ),
+            ExecutionResult::Error
+        ));
+    }
+
     create_test!(
         halt_and_catch_fire_prevents_further_instructions,
         setup = {
@@ -976,6 +1428,162 @@ mod tests {
         flags_post = [(Zero, true), (Carry, true)],
     );
 
+    /// Runs a single instruction with r1 = `lhs`, r2 = `rhs` and the given carry flag and
+    /// returns r3 and the resulting carry and overflow flags.
+    fn run_arithmetic(opcode: Opcode, lhs: Word, rhs: Word, carry: bool) -> (Word, bool, bool) {
+        let mut machine = create_machine_with_opcodes(&[opcode]);
+        machine.processor.registers[Register(1)] = lhs;
+        machine.processor.registers[Register(2)] = rhs;
+        machine.processor.set_flag(Flag::Carry, carry);
+        assert_eq!(machine.run_cycles(1), ExecutionResult::Normal);
+        (
+            machine.processor.registers[Register(3)],
+            machine.processor.get_flag(Flag::Carry),
+            machine.processor.get_flag(Flag::Overflow),
+        )
+    }
+
+    #[test]
+    fn all_subtractions_report_borrow_in_carry_flag() {
+        let registers = SubtractTargetLhsRhs {
+            target: Register(3),
+            lhs: Register(1),
+            rhs: Register(2),
+        };
+        let with_carry = SubtractWithCarryTargetLhsRhs {
+            target: Register(3),
+            lhs: Register(1),
+            rhs: Register(2),
+        };
+        let immediate = |immediate| SubtractTargetSourceImmediate {
+            target: Register(3),
+            source: Register(1),
+            immediate,
+        };
+        for (lhs, rhs, expected) in [(5, 5, (0, false, false)), (5, 6, (Word::MAX, true, false))] {
+            assert_eq!(run_arithmetic(registers, lhs, rhs, false), expected);
+            assert_eq!(run_arithmetic(with_carry, lhs, rhs, false), expected);
+            assert_eq!(run_arithmetic(with_carry, lhs, rhs - 1, true), expected);
+            assert_eq!(run_arithmetic(immediate(rhs), lhs, 0, false), expected);
+        }
+        // the source is read before the target (which is the same register) is written
+        let mut machine = create_machine_with_opcodes(&[SubtractTargetSourceImmediate {
+            target: Register(1),
+            source: Register(1),
+            immediate: 6,
+        }]);
+        machine.processor.registers[Register(1)] = 5;
+        assert_eq!(machine.run_cycles(1), ExecutionResult::Normal);
+        assert!(machine.processor.get_flag(Flag::Carry));
+    }
+
+    #[test]
+    fn subtraction_with_carry_chains_borrow_into_next_word() {
+        // 0x1_0000_0005 - 0x0_0000_0006 = 0x0_FFFF_FFFF
+        let low = SubtractTargetLhsRhs {
+            target: Register(3),
+            lhs: Register(1),
+            rhs: Register(2),
+        };
+        let high = SubtractWithCarryTargetLhsRhs {
+            target: Register(3),
+            lhs: Register(1),
+            rhs: Register(2),
+        };
+        let (low_result, borrow, _) = run_arithmetic(low, 5, 6, false);
+        assert_eq!((low_result, borrow), (Word::MAX, true));
+        assert_eq!(run_arithmetic(high, 1, 0, borrow), (0, false, false));
+        // a borrow from the low word that can't be satisfied propagates
+        assert_eq!(run_arithmetic(high, 0, 0, borrow), (Word::MAX, true, false));
+        assert_eq!(run_arithmetic(high, 0, Word::MAX, true), (0, true, false));
+    }
+
+    #[test]
+    fn signed_overflow_is_reported_separately_from_carry() {
+        let carry_and_overflow = |opcode, lhs, rhs, carry| {
+            let (_, carry, overflow) = run_arithmetic(opcode, lhs, rhs, carry);
+            (carry, overflow)
+        };
+        let add = AddTargetLhsRhs {
+            target: Register(3),
+            lhs: Register(1),
+            rhs: Register(2),
+        };
+        let add_with_carry = AddWithCarryTargetLhsRhs {
+            target: Register(3),
+            lhs: Register(1),
+            rhs: Register(2),
+        };
+        let add_immediate = AddTargetSourceImmediate {
+            target: Register(3),
+            source: Register(1),
+            immediate: 1,
+        };
+        // (carry, overflow)
+        assert_eq!(
+            carry_and_overflow(add, 0x7FFF_FFFF, 1, false),
+            (false, true)
+        );
+        assert_eq!(
+            carry_and_overflow(add, 0xFFFF_FFFF, 1, false),
+            (true, false)
+        );
+        assert_eq!(
+            carry_and_overflow(add, 0x8000_0000, 0x8000_0000, false),
+            (true, true)
+        );
+        assert_eq!(carry_and_overflow(add, 1, 1, false), (false, false));
+        assert_eq!(
+            carry_and_overflow(add_with_carry, 0x7FFF_FFFF, 0, true),
+            (false, true)
+        );
+        assert_eq!(
+            carry_and_overflow(add_with_carry, 0xFFFF_FFFF, 0, true),
+            (true, false)
+        );
+        assert_eq!(
+            carry_and_overflow(add_immediate, 0x7FFF_FFFF, 0, false),
+            (false, true)
+        );
+        assert_eq!(
+            carry_and_overflow(add_immediate, 0xFFFF_FFFF, 0, false),
+            (true, false)
+        );
+
+        let subtract = SubtractTargetLhsRhs {
+            target: Register(3),
+            lhs: Register(1),
+            rhs: Register(2),
+        };
+        let subtract_with_carry = SubtractWithCarryTargetLhsRhs {
+            target: Register(3),
+            lhs: Register(1),
+            rhs: Register(2),
+        };
+        let subtract_immediate = SubtractTargetSourceImmediate {
+            target: Register(3),
+            source: Register(1),
+            immediate: 1,
+        };
+        assert_eq!(
+            carry_and_overflow(subtract, 0x8000_0000, 1, false),
+            (false, true)
+        );
+        assert_eq!(carry_and_overflow(subtract, 0, 1, false), (true, false));
+        assert_eq!(
+            carry_and_overflow(subtract_with_carry, 0x8000_0000, 0, true),
+            (false, true)
+        );
+        assert_eq!(
+            carry_and_overflow(subtract_immediate, 0x8000_0000, 0, false),
+            (false, true)
+        );
+        assert_eq!(
+            carry_and_overflow(subtract_immediate, 0, 0, false),
+            (true, false)
+        );
+    }
+
     create_test!(
         multiply_two_values_without_any_flags_set,
         setup = {
@@ -1068,6 +1676,78 @@ mod tests {
         flags_post = [(Carry, true), (Zero, true)],
     );
 
+    create_test!(
+        wide_multiply_add_without_any_flags_set,
+        setup = {
+            let lhs_register = 0x42.into();
+            let rhs_register = 0x43.into();
+            let addend_register = 0x44.into();
+            let target_high = 0x09.into();
+            let target_low = 0x0A.into();
+            let lhs: Word = 3;
+            let rhs: Word = 4;
+            let addend: Word = 5;
+        },
+        opcodes = &[WideMultiplyAdd {
+            high: target_high,
+            low: target_low,
+            lhs: lhs_register,
+            rhs: rhs_register,
+            addend: addend_register,
+        }],
+        registers_pre = [lhs => lhs_register, rhs => rhs_register, addend => addend_register],
+        registers_post = [(lhs_register, lhs), (rhs_register, rhs), (addend_register, addend), (target_high, 0), (target_low, 17)],
+        flags_post = [(Zero, false), (Carry, false)],
+    );
+
+    create_test!(
+        wide_multiply_add_carries_addend_into_high_part,
+        setup = {
+            let lhs_register = 0x42.into();
+            let rhs_register = 0x43.into();
+            let addend_register = 0x44.into();
+            let target_high = 0x09.into();
+            let target_low = 0x0A.into();
+            let lhs: Word = Word::MAX;
+            let rhs: Word = 1;
+            let addend: Word = 1;
+        },
+        opcodes = &[WideMultiplyAdd {
+            high: target_high,
+            low: target_low,
+            lhs: lhs_register,
+            rhs: rhs_register,
+            addend: addend_register,
+        }],
+        registers_pre = [lhs => lhs_register, rhs => rhs_register, addend => addend_register],
+        registers_post = [(lhs_register, lhs), (rhs_register, rhs), (addend_register, addend), (target_high, 1), (target_low, 0)],
+        flags_post = [(Zero, true), (Carry, true)],
+    );
+
+    create_test!(
+        wide_multiply_add_at_maximum_values,
+        setup = {
+            let lhs_register = 0x42.into();
+            let rhs_register = 0x43.into();
+            let addend_register = 0x44.into();
+            let target_high = 0x09.into();
+            let target_low = 0x0A.into();
+            let lhs: Word = Word::MAX;
+            let rhs: Word = Word::MAX;
+            let addend: Word = Word::MAX;
+        },
+        opcodes = &[WideMultiplyAdd {
+            high: target_high,
+            low: target_low,
+            lhs: lhs_register,
+            rhs: rhs_register,
+            addend: addend_register,
+        }],
+        registers_pre = [lhs => lhs_register, rhs => rhs_register, addend => addend_register],
+        registers_post = [(lhs_register, lhs), (rhs_register, rhs), (addend_register, addend), (target_high, Word::MAX), (target_low, 0)],
+        flags_post = [(Zero, true), (Carry, true)],
+    );
+
     macro_rules! create_divmod_test{
         (
             $test_name:ident,
@@ -1137,13 +1817,14 @@ mod tests {
         zero = true
     );
 
-    macro_rules! create_bitwise_test{
+    macro_rules! create_signed_divmod_test{
         (
             $test_name:ident,
-            $bitwise_instruction:ident,
             $lhs:expr,
             $rhs:expr,
-            $expected:expr,
+            $quotient:expr,
+            $remainder:expr,
+            divide_by_zero = $divide_by_zero:literal,
             zero = $zero:literal
         ) => {
             create_test!(
@@ -1151,19 +1832,107 @@ mod tests {
                 setup = {
                     let lhs_register = 0x42.into();
                     let rhs_register = 0x43.into();
-                    let target_register = 0x0A.into();
-                    let lhs: Word = $lhs;
-                    let rhs = $rhs;
-                    let expected = $expected;
+                    let target_quotient = 0x09.into();
+                    let target_remainder = 0x0A.into();
+                    let lhs = $lhs as i32 as Word;
+                    let rhs = $rhs as i32 as Word;
+                    let expected_quotient = $quotient as i32 as Word;
+                    let expected_remainder = $remainder as i32 as Word;
                 },
-                opcodes = &[$bitwise_instruction {
-                    target: target_register,
+                opcodes = &[SignedDivmodTargetModLhsRhs {
+                    result: target_quotient,
+                    remainder: target_remainder,
                     lhs: lhs_register,
                     rhs: rhs_register,
                 }],
                 registers_pre = [lhs => lhs_register, rhs => rhs_register],
-                registers_post = [(lhs_register, lhs), (rhs_register, rhs), (target_register, expected)],
-                flags_post = [(Zero, $zero)],
+                registers_post = [
+                    (lhs_register, lhs),
+                    (rhs_register, rhs),
+                    (target_quotient, expected_quotient),
+                    (target_remainder, expected_remainder)],
+                flags_post = [(DivideByZero, $divide_by_zero), (Zero, $zero)],
+            );
+        }
+    }
+
+    create_signed_divmod_test!(
+        signed_divmod_negative_dividend,
+        -7,
+        2,
+        -3,
+        -1,
+        divide_by_zero = false,
+        zero = false
+    );
+
+    create_signed_divmod_test!(
+        signed_divmod_negative_divisor,
+        7,
+        -2,
+        -3,
+        1,
+        divide_by_zero = false,
+        zero = false
+    );
+
+    create_signed_divmod_test!(
+        signed_divmod_with_zero_flag_set,
+        -1,
+        2,
+        0,
+        -1,
+        divide_by_zero = false,
+        zero = true
+    );
+
+    create_signed_divmod_test!(
+        signed_divmod_smallest_value_by_minus_one_wraps,
+        i32::MIN,
+        -1,
+        i32::MIN,
+        0,
+        divide_by_zero = false,
+        zero = false
+    );
+
+    create_signed_divmod_test!(
+        signed_divmod_divide_by_zero,
+        -15,
+        0,
+        0,
+        -15,
+        divide_by_zero = true,
+        zero = true
+    );
+
+    macro_rules! create_bitwise_test{
+        (
+            $test_name:ident,
+            $bitwise_instruction:ident,
+            $lhs:expr,
+            $rhs:expr,
+            $expected:expr,
+            zero = $zero:literal
+        ) => {
+            create_test!(
+                $test_name,
+                setup = {
+                    let lhs_register = 0x42.into();
+                    let rhs_register = 0x43.into();
+                    let target_register = 0x0A.into();
+                    let lhs: Word = $lhs;
+                    let rhs = $rhs;
+                    let expected = $expected;
+                },
+                opcodes = &[$bitwise_instruction {
+                    target: target_register,
+                    lhs: lhs_register,
+                    rhs: rhs_register,
+                }],
+                registers_pre = [lhs => lhs_register, rhs => rhs_register],
+                registers_post = [(lhs_register, lhs), (rhs_register, rhs), (target_register, expected)],
+                flags_post = [(Zero, $zero)],
             );
         }
     }
@@ -1222,6 +1991,154 @@ mod tests {
         zero = true
     );
 
+    create_bitwise_test!(
+        rotate_left_with_no_flags_set,
+        RotateLeftTargetLhsRhs,
+        0x1234_5678,
+        8,
+        0x3456_7812,
+        zero = false
+    );
+
+    create_bitwise_test!(
+        rotate_left_by_word_size_is_a_no_op,
+        RotateLeftTargetLhsRhs,
+        0x1234_5678,
+        32,
+        0x1234_5678,
+        zero = false
+    );
+
+    create_bitwise_test!(
+        rotate_left_with_zero_flag_set,
+        RotateLeftTargetLhsRhs,
+        0,
+        5,
+        0,
+        zero = true
+    );
+
+    create_bitwise_test!(
+        rotate_right_with_no_flags_set,
+        RotateRightTargetLhsRhs,
+        0x1234_5678,
+        8,
+        0x7812_3456,
+        zero = false
+    );
+
+    create_bitwise_test!(
+        rotate_right_by_word_size_is_a_no_op,
+        RotateRightTargetLhsRhs,
+        0x1234_5678,
+        32,
+        0x1234_5678,
+        zero = false
+    );
+
+    create_bitwise_test!(
+        rotate_right_amount_is_taken_modulo_word_size,
+        RotateRightTargetLhsRhs,
+        0x1234_5678,
+        40,
+        0x7812_3456,
+        zero = false
+    );
+
+    macro_rules! create_bit_manipulation_test{
+        (
+            $test_name:ident,
+            $bit_instruction:ident,
+            $source:expr,
+            $index:expr,
+            $expected:expr,
+            zero = $zero:literal
+        ) => {
+            create_test!(
+                $test_name,
+                setup = {
+                    let source_register = 0x42.into();
+                    let index_register = 0x43.into();
+                    let target_register = 0x0A.into();
+                    let source: Word = $source;
+                    let index: Word = $index;
+                    let expected: Word = $expected;
+                },
+                opcodes = &[$bit_instruction {
+                    target: target_register,
+                    source: source_register,
+                    index: index_register,
+                }],
+                registers_pre = [source => source_register, index => index_register],
+                registers_post = [(source_register, source), (index_register, index), (target_register, expected)],
+                flags_post = [(Zero, $zero)],
+            );
+        }
+    }
+
+    create_bit_manipulation_test!(
+        bit_test_set_bit,
+        BitTestTargetSourceIndex,
+        0b1000,
+        3,
+        1,
+        zero = false
+    );
+
+    create_bit_manipulation_test!(
+        bit_test_already_clear_bit,
+        BitTestTargetSourceIndex,
+        0xFFFF_FFF7,
+        3,
+        0,
+        zero = true
+    );
+
+    create_bit_manipulation_test!(
+        bit_test_index_wraps_around,
+        BitTestTargetSourceIndex,
+        0b10,
+        33,
+        1,
+        zero = false
+    );
+
+    create_bit_manipulation_test!(
+        bit_set_highest_bit,
+        BitSetTargetSourceIndex,
+        0x0000_0001,
+        31,
+        0x8000_0001,
+        zero = false
+    );
+
+    create_bit_manipulation_test!(
+        bit_set_already_set_bit,
+        BitSetTargetSourceIndex,
+        0x0000_0001,
+        0,
+        0x0000_0001,
+        zero = false
+    );
+
+    create_bit_manipulation_test!(
+        bit_clear_lowest_bit,
+        BitClearTargetSourceIndex,
+        0x8000_0001,
+        0,
+        0x8000_0000,
+        zero = false
+    );
+
+    create_bit_manipulation_test!(
+        bit_clear_last_set_bit,
+        BitClearTargetSourceIndex,
+        0x0000_0001,
+        32,
+        0,
+        zero = true
+    );
+
     create_test!(
         bitwise_not_value_with_no_flags_set,
         setup = {
@@ -1250,6 +2167,189 @@ mod tests {
         flags_post = [(Zero, true)],
     );
 
+    macro_rules! create_unary_operation_test{
+        (
+            $test_name:ident,
+            $instruction:ident,
+            $source:expr,
+            $expected:expr,
+            zero = $zero:literal
+        ) => {
+            create_test!(
+                $test_name,
+                opcodes = &[$instruction {
+                    target: 0x0A.into(),
+                    source: 0x05.into(),
+                }],
+                registers_pre = [$source => Register(0x05)],
+                registers_post = [(0x05.into(), $source), (0x0A.into(), $expected)],
+                flags_post = [(Zero, $zero)],
+            );
+        }
+    }
+
+    create_unary_operation_test!(
+        count_leading_zeros_of_zero,
+        CountLeadingZerosTargetSource,
+        0,
+        32,
+        zero = false
+    );
+
+    create_unary_operation_test!(
+        count_leading_zeros_of_one,
+        CountLeadingZerosTargetSource,
+        1,
+        31,
+        zero = false
+    );
+
+    create_unary_operation_test!(
+        count_leading_zeros_of_highest_bit,
+        CountLeadingZerosTargetSource,
+        0x8000_0000,
+        0,
+        zero = true
+    );
+
+    create_unary_operation_test!(
+        count_leading_zeros_of_all_bits,
+        CountLeadingZerosTargetSource,
+        0xFFFF_FFFF,
+        0,
+        zero = true
+    );
+
+    create_unary_operation_test!(
+        count_trailing_zeros_of_zero,
+        CountTrailingZerosTargetSource,
+        0,
+        32,
+        zero = false
+    );
+
+    create_unary_operation_test!(
+        count_trailing_zeros_of_one,
+        CountTrailingZerosTargetSource,
+        1,
+        0,
+        zero = true
+    );
+
+    create_unary_operation_test!(
+        count_trailing_zeros_of_highest_bit,
+        CountTrailingZerosTargetSource,
+        0x8000_0000,
+        31,
+        zero = false
+    );
+
+    create_unary_operation_test!(
+        count_trailing_zeros_of_all_bits,
+        CountTrailingZerosTargetSource,
+        0xFFFF_FFFF,
+        0,
+        zero = true
+    );
+
+    create_unary_operation_test!(
+        sign_extend_negative_byte,
+        SignExtendByteTargetSource,
+        0x80,
+        0xFFFF_FF80,
+        zero = false
+    );
+
+    create_unary_operation_test!(
+        sign_extend_positive_byte,
+        SignExtendByteTargetSource,
+        0x7F,
+        0x7F,
+        zero = false
+    );
+
+    create_unary_operation_test!(
+        sign_extend_byte_ignores_upper_bits,
+        SignExtendByteTargetSource,
+        0xABCD_EF00,
+        0,
+        zero = true
+    );
+
+    create_unary_operation_test!(
+        sign_extend_negative_halfword,
+        SignExtendHalfwordTargetSource,
+        0x8000,
+        0xFFFF_8000,
+        zero = false
+    );
+
+    create_unary_operation_test!(
+        sign_extend_positive_halfword,
+        SignExtendHalfwordTargetSource,
+        0x7FFF,
+        0x7FFF,
+        zero = false
+    );
+
+    create_unary_operation_test!(
+        negate_positive_value,
+        NegateTargetSource,
+        42,
+        -42i32 as Word,
+        zero = false
+    );
+
+    create_unary_operation_test!(
+        negate_negative_value,
+        NegateTargetSource,
+        -42i32 as Word,
+        42,
+        zero = false
+    );
+
+    create_unary_operation_test!(negate_zero, NegateTargetSource, 0, 0, zero = true);
+
+    create_unary_operation_test!(
+        negate_smallest_value_wraps_around,
+        NegateTargetSource,
+        i32::MIN as Word,
+        i32::MIN as Word,
+        zero = false
+    );
+
+    create_unary_operation_test!(
+        absolute_value_of_positive_value,
+        AbsoluteValueTargetSource,
+        42,
+        42,
+        zero = false
+    );
+
+    create_unary_operation_test!(
+        absolute_value_of_negative_value,
+        AbsoluteValueTargetSource,
+        -42i32 as Word,
+        42,
+        zero = false
+    );
+
+    create_unary_operation_test!(
+        absolute_value_of_zero,
+        AbsoluteValueTargetSource,
+        0,
+        0,
+        zero = true
+    );
+
+    create_unary_operation_test!(
+        absolute_value_of_smallest_value_wraps_around,
+        AbsoluteValueTargetSource,
+        i32::MIN as Word,
+        i32::MIN as Word,
+        zero = false
+    );
+
     macro_rules! create_shift_test{
         (
             $test_name:ident,
@@ -1384,34 +2484,194 @@ mod tests {
         carry = false
     );
 
-    macro_rules! create_add_immediate_test{
-        (
-            $test_name:ident,
-            $immediate:expr,
-            $source_value:expr,
-            $expected_value:expr,
-            zero = $zero:literal,
-            carry = $carry:literal
-        ) => {
-            create_test!(
-                $test_name,
-                opcodes = &[AddTargetSourceImmediate {
-                    target: Register(0xAB),
-                    source: Register(0x07),
-                    immediate: $immediate,
-                }],
-                registers_pre = [$source_value => Register(0x07)],
-                registers_post = [(Register(0x07), $source_value), (Register(0xAB), $expected_value)],
-                flags_post = [(Zero, $zero), (Carry, $carry)],
-            );
-        }
-    }
-
-    create_add_immediate_test!(
-        add_immediate_with_no_flags_set,
-        2,
-        40,
-        42,
+    create_shift_test!(
+        left_shift_by_31_keeps_lowest_bit,
+        LeftShiftTargetLhsRhs,
+        0b1,
+        31,
+        0b1 << 31,
+        zero = false,
+        carry = false
+    );
+
+    create_shift_test!(
+        left_shift_by_31_shifts_out_second_bit,
+        LeftShiftTargetLhsRhs,
+        0b11,
+        31,
+        0b1 << 31,
+        zero = false,
+        carry = true
+    );
+
+    create_shift_test!(
+        left_shift_by_32_shifts_out_everything,
+        LeftShiftTargetLhsRhs,
+        0b1,
+        32,
+        0,
+        zero = true,
+        carry = true
+    );
+
+    create_shift_test!(
+        left_shift_by_33_shifts_out_everything,
+        LeftShiftTargetLhsRhs,
+        0b1,
+        33,
+        0,
+        zero = true,
+        carry = true
+    );
+
+    create_shift_test!(
+        left_shift_zero_by_32,
+        LeftShiftTargetLhsRhs,
+        0,
+        32,
+        0,
+        zero = true,
+        carry = false
+    );
+
+    create_shift_test!(
+        right_shift_by_31_keeps_highest_bit,
+        RightShiftTargetLhsRhs,
+        0b1 << 31,
+        31,
+        0b1,
+        zero = false,
+        carry = false
+    );
+
+    create_shift_test!(
+        right_shift_by_31_shifts_out_second_bit,
+        RightShiftTargetLhsRhs,
+        0b11 << 30,
+        31,
+        0b1,
+        zero = false,
+        carry = true
+    );
+
+    create_shift_test!(
+        right_shift_by_32_shifts_out_everything,
+        RightShiftTargetLhsRhs,
+        0b1 << 31,
+        32,
+        0,
+        zero = true,
+        carry = true
+    );
+
+    create_shift_test!(
+        right_shift_by_33_shifts_out_everything,
+        RightShiftTargetLhsRhs,
+        0b1 << 31,
+        33,
+        0,
+        zero = true,
+        carry = true
+    );
+
+    create_shift_test!(
+        right_shift_zero_by_32,
+        RightShiftTargetLhsRhs,
+        0,
+        32,
+        0,
+        zero = true,
+        carry = false
+    );
+
+    create_shift_test!(
+        arithmetic_right_shift_without_any_flags_set,
+        ArithmeticRightShiftTargetLhsRhs,
+        0b10,
+        1,
+        0b1,
+        zero = false,
+        carry = false
+    );
+
+    create_shift_test!(
+        arithmetic_right_shift_negative_value,
+        ArithmeticRightShiftTargetLhsRhs,
+        0x8000_0000,
+        4,
+        0xF800_0000,
+        zero = false,
+        carry = false
+    );
+
+    create_shift_test!(
+        arithmetic_right_shift_negative_value_with_carry_flag_set,
+        ArithmeticRightShiftTargetLhsRhs,
+        0xFFFF_FFF1,
+        4,
+        0xFFFF_FFFF,
+        zero = false,
+        carry = true
+    );
+
+    create_shift_test!(
+        arithmetic_right_shift_with_carry_and_zero_flags_set,
+        ArithmeticRightShiftTargetLhsRhs,
+        0b1,
+        1,
+        0,
+        zero = true,
+        carry = true
+    );
+
+    create_shift_test!(
+        arithmetic_right_shift_negative_value_way_too_far,
+        ArithmeticRightShiftTargetLhsRhs,
+        0x8000_0000,
+        123,
+        0xFFFF_FFFF,
+        zero = false,
+        carry = true
+    );
+
+    create_shift_test!(
+        arithmetic_right_shift_positive_value_way_too_far,
+        ArithmeticRightShiftTargetLhsRhs,
+        0x7FFF_FFFF,
+        32,
+        0,
+        zero = true,
+        carry = true
+    );
+
+    macro_rules! create_add_immediate_test{
+        (
+            $test_name:ident,
+            $immediate:expr,
+            $source_value:expr,
+            $expected_value:expr,
+            zero = $zero:literal,
+            carry = $carry:literal
+        ) => {
+            create_test!(
+                $test_name,
+                opcodes = &[AddTargetSourceImmediate {
+                    target: Register(0xAB),
+                    source: Register(0x07),
+                    immediate: $immediate,
+                }],
+                registers_pre = [$source_value => Register(0x07)],
+                registers_post = [(Register(0x07), $source_value), (Register(0xAB), $expected_value)],
+                flags_post = [(Zero, $zero), (Carry, $carry)],
+            );
+        }
+    }
+
+    create_add_immediate_test!(
+        add_immediate_with_no_flags_set,
+        2,
+        40,
+        42,
         zero = false,
         carry = false
     );
@@ -1493,6 +2753,92 @@ mod tests {
         carry = true
     );
 
+    macro_rules! create_increment_decrement_test{
+        (
+            $test_name:ident,
+            $instruction:ident,
+            $value:expr,
+            $expected_value:expr,
+            zero = $zero:literal,
+            carry = $carry:literal
+        ) => {
+            create_test!(
+                $test_name,
+                opcodes = &[$instruction {
+                    register: Register(0x07),
+                }],
+                registers_pre = [$value => Register(0x07)],
+                registers_post = [(Register(0x07), $expected_value)],
+                flags_post = [(Zero, $zero), (Carry, $carry)],
+            );
+        }
+    }
+
+    create_increment_decrement_test!(
+        increment_register_with_no_flags_set,
+        IncrementRegister,
+        41,
+        42,
+        zero = false,
+        carry = false
+    );
+
+    create_increment_decrement_test!(
+        increment_register_wraps_around,
+        IncrementRegister,
+        0xFFFF_FFFF,
+        0,
+        zero = true,
+        carry = true
+    );
+
+    create_increment_decrement_test!(
+        decrement_register_with_no_flags_set,
+        DecrementRegister,
+        43,
+        42,
+        zero = false,
+        carry = false
+    );
+
+    create_increment_decrement_test!(
+        decrement_register_to_zero,
+        DecrementRegister,
+        1,
+        0,
+        zero = true,
+        carry = false
+    );
+
+    create_increment_decrement_test!(
+        decrement_register_wraps_around,
+        DecrementRegister,
+        0,
+        0xFFFF_FFFF,
+        zero = false,
+        carry = true
+    );
+
+    create_test!(
+        increment_register_sets_overflow_flag_on_signed_wrap,
+        opcodes = &[IncrementRegister {
+            register: Register(0x07),
+        }],
+        registers_pre = [0x7FFF_FFFF => Register(0x07)],
+        registers_post = [(Register(0x07), 0x8000_0000)],
+        flags_post = [(Zero, false), (Carry, false), (Overflow, true), (Negative, true)],
+    );
+
+    create_test!(
+        decrement_register_sets_overflow_flag_on_signed_wrap,
+        opcodes = &[DecrementRegister {
+            register: Register(0x07),
+        }],
+        registers_pre = [0x8000_0000 => Register(0x07)],
+        registers_post = [(Register(0x07), 0x7FFF_FFFF)],
+        flags_post = [(Zero, false), (Carry, false), (Overflow, true), (Negative, false)],
+    );
+
     macro_rules! create_comparison_test{
         (
             $test_name:ident,
@@ -1539,7 +2885,7 @@ mod tests {
 
     #[test]
     fn push_and_pop_stack_value() {
-        let mut machine = Machine::new(create_mock_periphery(), false);
+        let mut machine = Machine::new(create_mock_periphery());
         let source_register = 0xAB.into();
         let target_register = 0x06.into();
         let data = 42;
@@ -1577,7 +2923,7 @@ mod tests {
 
     #[test]
     fn push_and_pop_immediate() {
-        let machine = Machine::new(create_mock_periphery(), false);
+        let machine = Machine::new(create_mock_periphery());
         let target_register = 0x06.into();
         let data = 42;
         assert_eq!(
@@ -1609,7 +2955,7 @@ mod tests {
     #[test]
     fn push_and_pop_multiple_stack_values() {
         let values = [1, 4, 5, 42, 2, 3];
-        let mut machine = Machine::new(create_mock_periphery(), false);
+        let mut machine = Machine::new(create_mock_periphery());
         for (register, value) in (0..).map(Register).zip(values) {
             machine.processor.registers[register] = value;
             machine = execute_instruction_with_machine(machine, PushRegister { register });
@@ -1636,10 +2982,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn push_onto_full_stack_is_an_error() {
+        let mut machine = create_machine_with_opcodes(&[
+            PushImmediate { immediate: 42 },
+            PushImmediate { immediate: 43 },
+        ]);
+        let stack_end = address_constants::STACK_END;
+        let memory_after_stack = machine.memory.read_data(stack_end);
+        machine
+            .processor
+            .set_stack_pointer(stack_end - Word::SIZE as Address);
+        machine.execute_next_instruction();
+        assert!(!machine.is_faulted());
+        assert_eq!(machine.processor.get_stack_pointer(), stack_end);
+        machine.execute_next_instruction();
+        assert!(machine.is_faulted());
+        assert_eq!(machine.processor.get_stack_pointer(), stack_end);
+        assert_eq!(machine.memory.read_data(stack_end), memory_after_stack);
+    }
+
+    #[test]
+    fn pop_from_empty_stack_is_an_error() {
+        let mut machine = create_machine_with_opcodes(&[PopRegister { register: 0.into() }]);
+        machine.processor.registers[0.into()] = 42;
+        machine.execute_next_instruction();
+        assert!(machine.is_faulted());
+        assert_eq!(machine.processor.registers[0.into()], 42);
+        assert_eq!(
+            machine.processor.get_stack_pointer(),
+            address_constants::STACK_START
+        );
+    }
+
     #[test]
     fn call_and_return() {
-        let mut machine: Machine<MockDisplay> = Machine::new(create_mock_periphery(), false);
-        let call_address = address_constants::ENTRY_POINT + 200 * Instruction::SIZE as Address;
+        let mut machine: Machine<MockDisplay> = Machine::new(create_mock_periphery());
+        let call_address = instruction_address(200);
         machine.memory.write_opcode(
             address_constants::ENTRY_POINT,
             Opcode::CallImmediate {
@@ -1664,7 +3043,7 @@ mod tests {
         machine.execute_next_instruction(); // jump into subroutine
         assert_eq!(
             machine.memory.read_data(address_constants::STACK_START),
-            address_constants::ENTRY_POINT + Instruction::SIZE as Address
+            instruction_address(1)
         );
         assert_eq!(
             machine.processor.registers[Processor::INSTRUCTION_POINTER],
@@ -1681,7 +3060,7 @@ mod tests {
         machine.execute_next_instruction(); // jump back from subroutine
         assert_eq!(
             machine.processor.registers[Processor::INSTRUCTION_POINTER],
-            address_constants::ENTRY_POINT + Instruction::SIZE as Address
+            instruction_address(1)
         );
     }
 
@@ -1695,47 +3074,242 @@ mod tests {
     );
 
     create_test!(
-        jump_to_pointer,
-        setup = {
-            let register = Register(0xAB);
-            let address = address_constants::ENTRY_POINT as Address + 42;
-        },
-        opcodes = &[Opcode::JumpRegister { register }],
-        registers_pre = [address => register],
-        registers_post = [(Processor::INSTRUCTION_POINTER, address)],
+        jump_relative_forwards,
+        opcodes = &[Opcode::JumpRelativeImmediate {
+            immediate: 5 * Instruction::SIZE as Word
+        }],
+        registers_post = [(Processor::INSTRUCTION_POINTER, instruction_address(5))],
     );
 
-    macro_rules! create_jump_tests {
-        (
-            $address_test_name:ident,
-            $pointer_test_name:ident,
-            $jump_address_instruction:ident,
-            $jump_register_instruction:ident,
-            $lhs:literal,
-            $rhs:literal,
-            $should_jump:literal
-        ) => {
-            // create test for "jump address"
-            create_test!(
-                $address_test_name,
-                setup = {
-                    let target_address = address_constants::ENTRY_POINT + 42 * Instruction::SIZE as Address;
-                    let target_register = 0.into();
-                },
-                opcodes = &[
-                    Opcode::CompareTargetLhsRhs {
-                        target: target_register,
-                        lhs: 1.into(),
-                        rhs: 2.into(),
-                    },
-                    Opcode::$jump_address_instruction {
-                        comparison: target_register,
-                        immediate: target_address,
-                    },
-                ],
-                registers_pre = [$lhs => 1, $rhs => 2],
-                registers_post = [(Processor::INSTRUCTION_POINTER, if $should_jump { target_address } else {
-                    address_constants::ENTRY_POINT + 2 * Instruction::SIZE as Address
+    #[test]
+    fn overwritten_instructions_are_recompiled() {
+        let (high, low) = Opcode::AddTargetSourceImmediate {
+            target: 0.into(),
+            source: 0.into(),
+            immediate: 10,
+        }
+        .as_instruction()
+        .as_words();
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::AddTargetSourceImmediate {
+                target: 0.into(),
+                source: 0.into(),
+                immediate: 1,
+            },
+            Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: high,
+            },
+            Opcode::MoveRegisterImmediate {
+                register: 2.into(),
+                immediate: low,
+            },
+            Opcode::MoveAddressRegister {
+                register: 1.into(),
+                target_address: instruction_address(0),
+            },
+            Opcode::MoveAddressRegister {
+                register: 2.into(),
+                target_address: instruction_address(0) + Word::SIZE as Address,
+            },
+            Opcode::JumpImmediate {
+                immediate: instruction_address(0),
+            },
+        ]);
+        for _ in 0..7 {
+            machine.execute_next_instruction();
+        }
+        assert_eq!(machine.processor.registers[0.into()], 11);
+    }
+
+    #[test]
+    fn reading_word_from_odd_address_is_an_error() {
+        let mut machine = create_machine_with_opcodes(&[Opcode::MoveTargetPointer {
+            target: 0.into(),
+            pointer: 1.into(),
+        }]);
+        machine.processor.registers[1.into()] = address_constants::ENTRY_POINT + 1;
+        assert!(matches!(
+            machine.processor.execute_next_instruction(
+                &mut machine.memory,
+                &mut machine.periphery,
+                &mut machine.instruction_cache,
+            ),
+            ExecutionResult::Error
+        ));
+    }
+
+    #[test]
+    fn reading_word_from_last_address_is_an_error() {
+        let mut machine = create_machine_with_opcodes(&[Opcode::MoveTargetPointer {
+            target: 0.into(),
+            pointer: 1.into(),
+        }]);
+        machine.processor.registers[1.into()] = (Memory::DEFAULT_SIZE - 1) as Address;
+        assert!(matches!(
+            machine.processor.execute_next_instruction(
+                &mut machine.memory,
+                &mut machine.periphery,
+                &mut machine.instruction_cache,
+            ),
+            ExecutionResult::Error
+        ));
+    }
+
+    #[test]
+    fn machine_with_custom_memory_size() {
+        const MEMORY_SIZE: usize = 2 * 1024 * 1024;
+        let mut machine = Machine::with_memory_size(create_mock_periphery(), MEMORY_SIZE);
+        let opcodes = [
+            Opcode::MovePointerSource {
+                pointer: 1.into(),
+                source: 0.into(),
+            },
+            Opcode::MovePointerSource {
+                pointer: 2.into(),
+                source: 0.into(),
+            },
+        ];
+        for (i, &opcode) in opcodes.iter().enumerate() {
+            machine.memory.write_opcode(instruction_address(i), opcode);
+        }
+        machine.generate_instruction_cache();
+        machine.processor.registers[0.into()] = 42;
+        machine.processor.registers[1.into()] = instruction_address(opcodes.len());
+        machine.processor.registers[2.into()] = MEMORY_SIZE as Address;
+
+        machine.execute_next_instruction();
+        assert_eq!(machine.memory.size(), MEMORY_SIZE);
+        assert_eq!(
+            machine.memory.read_data(instruction_address(opcodes.len())),
+            42
+        );
+        assert!(!machine.is_faulted());
+        machine.execute_next_instruction();
+        assert!(machine.is_faulted());
+    }
+
+    #[test]
+    fn instruction_fetch_out_of_bounds_is_an_error() {
+        let mut machine = create_machine_with_opcodes(&[]);
+        machine.generate_instruction_cache();
+        machine
+            .processor
+            .set_instruction_pointer((Memory::DEFAULT_SIZE - Word::SIZE) as Address);
+        assert!(matches!(
+            machine.processor.execute_next_instruction(
+                &mut machine.memory,
+                &mut machine.periphery,
+                &mut machine.instruction_cache,
+            ),
+            ExecutionResult::Error
+        ));
+        machine.execute_next_instruction();
+        assert!(machine.is_halted());
+        assert!(machine.is_faulted());
+    }
+
+    #[test]
+    fn misaligned_instruction_fetch_is_an_error() {
+        let mut machine = create_machine_with_opcodes(&[Opcode::NoOp {}, Opcode::NoOp {}]);
+        machine
+            .processor
+            .set_instruction_pointer(address_constants::ENTRY_POINT + 4);
+        assert_eq!(machine.run_cycles(1), ExecutionResult::Error);
+        assert!(machine.is_faulted());
+    }
+
+    #[test]
+    fn jump_relative_to_misaligned_address_is_an_error() {
+        let mut machine = create_machine_with_opcodes(&[Opcode::JumpRelativeImmediate {
+            immediate: Instruction::SIZE as Word + 4,
+        }]);
+        assert_eq!(machine.run_cycles(1), ExecutionResult::Error);
+        assert_eq!(
+            machine.processor.get_instruction_pointer(),
+            address_constants::ENTRY_POINT
+        );
+    }
+
+    #[test]
+    fn jump_relative_backwards_loops() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::AddTargetSourceImmediate {
+                target: 0.into(),
+                source: 0.into(),
+                immediate: 1,
+            },
+            Opcode::JumpRelativeImmediate {
+                immediate: -(Instruction::SIZE as i32) as Word,
+            },
+        ]);
+        for _ in 0..6 {
+            machine.execute_next_instruction();
+        }
+        assert_eq!(machine.processor.registers[0.into()], 3);
+        assert_eq!(
+            machine.processor.get_instruction_pointer(),
+            address_constants::ENTRY_POINT
+        );
+    }
+
+    #[test]
+    fn jump_relative_outside_of_memory_is_an_error() {
+        let mut machine = create_machine_with_opcodes(&[Opcode::JumpRelativeImmediate {
+            immediate: (-(address_constants::ENTRY_POINT as i32) - 1) as Word,
+        }]);
+        assert!(matches!(
+            machine.processor.execute_next_instruction(
+                &mut machine.memory,
+                &mut machine.periphery,
+                &mut machine.instruction_cache,
+            ),
+            ExecutionResult::Error
+        ));
+    }
+
+    create_test!(
+        jump_to_pointer,
+        setup = {
+            let register = Register(0xAB);
+            let address = address_constants::ENTRY_POINT as Address + 42;
+        },
+        opcodes = &[Opcode::JumpRegister { register }],
+        registers_pre = [address => register],
+        registers_post = [(Processor::INSTRUCTION_POINTER, address)],
+    );
+
+    macro_rules! create_jump_tests {
+        (
+            $address_test_name:ident,
+            $pointer_test_name:ident,
+            $jump_address_instruction:ident,
+            $jump_register_instruction:ident,
+            $lhs:literal,
+            $rhs:literal,
+            $should_jump:literal
+        ) => {
+            // create test for "jump address"
+            create_test!(
+                $address_test_name,
+                setup = {
+                    let target_address = instruction_address(42);
+                    let target_register = 0.into();
+                },
+                opcodes = &[
+                    Opcode::CompareTargetLhsRhs {
+                        target: target_register,
+                        lhs: 1.into(),
+                        rhs: 2.into(),
+                    },
+                    Opcode::$jump_address_instruction {
+                        comparison: target_register,
+                        immediate: target_address,
+                    },
+                ],
+                registers_pre = [$lhs => 1, $rhs => 2],
+                registers_post = [(Processor::INSTRUCTION_POINTER, if $should_jump { target_address } else {
+                    instruction_address(2)
                 })],
             );
 
@@ -1743,7 +3317,7 @@ mod tests {
             create_test!(
                 $pointer_test_name,
                 setup = {
-                    let target_address = address_constants::ENTRY_POINT + 42 * Instruction::SIZE as Address;
+                    let target_address = instruction_address(42);
                     let pointer_register = 0xA.into();
                     let comparison_register = 0.into();
                 },
@@ -1760,7 +3334,7 @@ mod tests {
                 ],
                 registers_pre = [$lhs => 1, $rhs => 2, target_address => pointer_register],
                 registers_post = [(Processor::INSTRUCTION_POINTER, if $should_jump { target_address } else {
-                    address_constants::ENTRY_POINT + 2 * Instruction::SIZE as Address
+                    instruction_address(2)
                 })],
             );
         };
@@ -1917,7 +3491,7 @@ mod tests {
             create_test!(
                 $test_name,
                 setup = {
-                    let target_address = address_constants::ENTRY_POINT + 42 * Instruction::SIZE as Address;
+                    let target_address = instruction_address(42);
                     let high_register = 3.into();
                     let target_register = 0.into();
                 },
@@ -1934,7 +3508,7 @@ mod tests {
                 ],
                 registers_pre = [$lhs => 1, $rhs => 2],
                 registers_post = [(Processor::INSTRUCTION_POINTER, if $should_jump { target_address } else {
-                    address_constants::ENTRY_POINT + 2 * Instruction::SIZE as Address
+                    instruction_address(2)
                 })],
             );
         }
@@ -2015,7 +3589,7 @@ mod tests {
             create_test!(
                 $test_name,
                 setup = {
-                    let target_address = address_constants::ENTRY_POINT + 42 * Instruction::SIZE as Address;
+                    let target_address = instruction_address(42);
                     let remainder_register = 3.into();
                     let target_register = 0.into();
                 },
@@ -2032,7 +3606,7 @@ mod tests {
                 ],
                 registers_pre = [$lhs => 1, $rhs => 2],
                 registers_post = [(Processor::INSTRUCTION_POINTER, if $should_jump { target_address } else {
-                    address_constants::ENTRY_POINT + 2 * Instruction::SIZE as Address
+                    instruction_address(2)
                 })],
             );
         };
@@ -2070,17 +3644,112 @@ mod tests {
         false
     );
 
+    macro_rules! create_jump_subtract_test {
+        (
+            $test_name:ident,
+            $jump_instruction:ident,
+            $lhs:expr,
+            $rhs:expr,
+            $should_jump:literal
+        ) => {
+            create_test!(
+                $test_name,
+                setup = {
+                    let target_address = instruction_address(42);
+                    let target_register = 0.into();
+                },
+                opcodes = &[
+                    Opcode::SubtractTargetLhsRhs {
+                        target: target_register,
+                        lhs: 1.into(),
+                        rhs: 2.into(),
+                    },
+                    Opcode::$jump_instruction {
+                        immediate: target_address,
+                    },
+                ],
+                registers_pre = [$lhs => 1, $rhs => 2],
+                registers_post = [(Processor::INSTRUCTION_POINTER, if $should_jump { target_address } else {
+                    instruction_address(2)
+                })],
+            );
+        };
+    }
+
+    create_jump_subtract_test!(
+        jump_to_address_if_negative_flag_set_that_jumps,
+        JumpImmediateIfNegative,
+        2,
+        5,
+        true
+    );
+
+    create_jump_subtract_test!(
+        jump_to_address_if_negative_flag_set_that_does_not_jump,
+        JumpImmediateIfNegative,
+        5,
+        2,
+        false
+    );
+
+    create_jump_subtract_test!(
+        jump_to_address_if_negative_flag_not_set_that_jumps,
+        JumpImmediateIfNotNegative,
+        5,
+        5,
+        true
+    );
+
+    create_jump_subtract_test!(
+        jump_to_address_if_negative_flag_not_set_that_does_not_jump,
+        JumpImmediateIfNotNegative,
+        0,
+        1,
+        false
+    );
+
+    create_test!(
+        subtraction_with_high_bit_result_sets_negative_flag,
+        setup = {
+            let lhs_register = 0x42.into();
+            let rhs_register = 0x43.into();
+            let target_register = 0x0A.into();
+        },
+        opcodes = &[SubtractTargetLhsRhs {
+            target: target_register,
+            lhs: lhs_register,
+            rhs: rhs_register,
+        }],
+        registers_pre = [0x9000_0000 => lhs_register, 0x1000_0000 => rhs_register],
+        registers_post = [(target_register, 0x8000_0000)],
+        flags_post = [(Zero, false), (Carry, false), (Negative, true)],
+    );
+
+    create_test!(
+        logic_result_without_high_bit_clears_negative_flag,
+        setup = {
+            let lhs_register = 0x42.into();
+            let rhs_register = 0x43.into();
+            let target_register = 0x0A.into();
+        },
+        opcodes = &[AndTargetLhsRhs {
+            target: target_register,
+            lhs: lhs_register,
+            rhs: rhs_register,
+        }],
+        registers_pre = [0xF000_000F => lhs_register, 0x7000_00FF => rhs_register],
+        flags_pre = [true => Negative],
+        registers_post = [(target_register, 0x7000_000F)],
+        flags_post = [(Zero, false), (Negative, false)],
+    );
+
     create_test!(
         no_op_does_advance_the_instruction_pointer,
         opcodes = &[NoOp {}],
-        registers_post = [(
-            Processor::INSTRUCTION_POINTER,
-            address_constants::ENTRY_POINT + Instruction::SIZE as Address
-        )],
+        registers_post = [(Processor::INSTRUCTION_POINTER, instruction_address(1))],
     );
 
     #[test]
-    #[cfg(feature = "graphics")]
     fn get_keystate() {
         let keycode_register = 0.into();
         let target_register = 1.into();
@@ -2093,23 +3762,27 @@ mod tests {
                 target: target_register,
                 keycode: keycode_register,
             },
+            Opcode::GetKeyState {
+                target: target_register,
+                keycode: keycode_register,
+            },
         ]);
-        machine.periphery.keyboard = Keyboard::new(Box::new(|keycode| {
-            if raylib::input::key_from_i32(keycode.try_into().expect("keycode out of range"))
-                .expect("invalid keycode")
-                == raylib::consts::KeyboardKey::KEY_A
-            {
-                KeyState::Down
-            } else {
-                KeyState::Up
-            }
+        machine.periphery.keyboard = Keyboard::new(Box::new(|keycode| match keycode {
+            KeyCode::A => KeyState::Down,
+            _ => KeyState::Up,
         }));
-        machine.processor.registers[keycode_register] = raylib::consts::KeyboardKey::KEY_A as Word;
+        machine.processor.registers[keycode_register] = KeyCode::A as Word;
         machine.execute_next_instruction();
         assert_eq!(machine.processor.registers[target_register], 1);
         assert!(!machine.processor.get_flag(Flag::Zero));
 
-        machine.processor.registers[keycode_register] = raylib::consts::KeyboardKey::KEY_B as Word;
+        machine.processor.registers[keycode_register] = KeyCode::B as Word;
+        machine.execute_next_instruction();
+        assert_eq!(machine.processor.registers[target_register], 0);
+        assert!(machine.processor.get_flag(Flag::Zero));
+
+        // unknown keycodes are never reported as held down
+        machine.processor.registers[keycode_register] = 0xFFFF;
         machine.execute_next_instruction();
         assert_eq!(machine.processor.registers[target_register], 0);
         assert!(machine.processor.get_flag(Flag::Zero));
@@ -2130,6 +3803,196 @@ mod tests {
         registers_post = [(0.into(), 0), (1.into(), 1)],
     );
 
+    #[test]
+    fn monotonic_time_never_decreases() {
+        let poll_both = [
+            Opcode::PollTime {
+                high: 0.into(),
+                low: 1.into(),
+            },
+            Opcode::PollMonotonic {
+                high: 2.into(),
+                low: 3.into(),
+            },
+        ];
+        let mut machine = create_machine_with_opcodes(&poll_both.repeat(3));
+        // both the wall clock and (a broken) monotonic source go backwards
+        let mut wall_clock = [1_000, 500, 2_000].into_iter();
+        let mut monotonic_source = [10, 5, 20].into_iter();
+        machine.periphery.timer = Timer::new(move || wall_clock.next().unwrap())
+            .with_monotonic_source(move || monotonic_source.next().unwrap());
+
+        let mut wall_times = Vec::new();
+        let mut monotonic_times = Vec::new();
+        for _ in 0..3 {
+            assert_eq!(machine.run_cycles(2), ExecutionResult::Normal);
+            let registers = &machine.processor.registers;
+            wall_times.push(registers[Register(1)]);
+            monotonic_times.push(registers[Register(3)]);
+            assert_eq!(registers[Register(2)], 0);
+        }
+        assert_eq!(wall_times, [1_000, 500, 2_000]);
+        assert_eq!(monotonic_times, [10, 10, 20]);
+    }
+
+    #[test]
+    fn popping_flags_restores_pushed_flags() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::PushFlags {},
+            Opcode::ClearFlags {},
+            Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: 0,
+            },
+            Opcode::AddTargetSourceImmediate {
+                target: 1.into(),
+                source: 1.into(),
+                immediate: 0,
+            },
+            Opcode::PopFlags {},
+        ]);
+        machine.processor.set_flag(Flag::Carry, true);
+        machine.processor.set_flag(Flag::DivideByZero, true);
+        let flags = machine.processor.registers[Processor::FLAGS];
+
+        assert_eq!(machine.run_cycles(2), ExecutionResult::Normal);
+        assert_eq!(machine.processor.registers[Processor::FLAGS], 0);
+        assert_eq!(machine.run_cycles(2), ExecutionResult::Normal);
+        assert!(machine.processor.get_flag(Flag::Zero));
+        assert!(!machine.processor.get_flag(Flag::Carry));
+        assert_eq!(machine.run_cycles(1), ExecutionResult::Normal);
+        assert_eq!(machine.processor.registers[Processor::FLAGS], flags);
+        assert_eq!(
+            machine.processor.get_stack_pointer(),
+            address_constants::STACK_START
+        );
+    }
+
+    #[test]
+    fn popped_flags_ignore_unknown_bits() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::PushImmediate {
+                immediate: 0xFFFF_FFFF,
+            },
+            Opcode::PopFlags {},
+        ]);
+        assert_eq!(machine.run_cycles(2), ExecutionResult::Normal);
+        assert_eq!(
+            machine.processor.registers[Processor::FLAGS],
+            Flag::all().bits()
+        );
+    }
+
+    fn compare_exchange_machine(memory_value: Word, expected: Word) -> Machine<MockDisplay> {
+        let mut machine = create_machine_with_opcodes(&[Opcode::CompareExchange {
+            pointer: 1.into(),
+            expected: 2.into(),
+            new: 3.into(),
+            result: 4.into(),
+        }]);
+        machine.memory.write_data(0x100, memory_value);
+        machine.processor.registers[Register(1)] = 0x100;
+        machine.processor.registers[Register(2)] = expected;
+        machine.processor.registers[Register(3)] = 99;
+        machine
+    }
+
+    #[test]
+    fn compare_exchange_swaps_matching_value() {
+        let mut machine = compare_exchange_machine(42, 42);
+        assert_eq!(machine.run_cycles(1), ExecutionResult::Normal);
+        assert_eq!(machine.memory.read_data(0x100), 99);
+        assert_eq!(machine.processor.registers[Register(4)], 42);
+        assert!(machine.processor.get_flag(Flag::Zero));
+    }
+
+    #[test]
+    fn compare_exchange_keeps_mismatching_value() {
+        let mut machine = compare_exchange_machine(42, 41);
+        machine.processor.set_flag(Flag::Zero, true);
+        assert_eq!(machine.run_cycles(1), ExecutionResult::Normal);
+        assert_eq!(machine.memory.read_data(0x100), 42);
+        assert_eq!(machine.processor.registers[Register(4)], 42);
+        assert!(!machine.processor.get_flag(Flag::Zero));
+    }
+
+    #[test]
+    fn compare_exchange_out_of_bounds_is_an_error() {
+        let mut machine = compare_exchange_machine(0, 0);
+        machine.processor.registers[Register(1)] = (machine.memory.size() - 2) as Address;
+        assert_eq!(machine.run_cycles(1), ExecutionResult::Error);
+        assert_eq!(machine.processor.registers[Register(4)], 0);
+    }
+
+    fn sleep_opcodes() -> Vec<Opcode> {
+        vec![
+            Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: 100,
+            },
+            Opcode::SleepMilliseconds { source: 1.into() },
+            Opcode::PollMonotonic {
+                high: 2.into(),
+                low: 3.into(),
+            },
+            Opcode::HaltAndCatchFire {},
+        ]
+    }
+
+    #[test]
+    fn real_time_sleep_pauses_until_the_clock_has_advanced() {
+        let mut machine = create_machine_with_opcodes(&sleep_opcodes());
+        let clock = std::rc::Rc::new(std::cell::Cell::new(1_000));
+        let source = clock.clone();
+        machine.periphery.timer = Timer::new(|| 0).with_monotonic_source(move || source.get());
+        machine.set_real_time_sleep(true);
+
+        assert_eq!(machine.run_cycles(2), ExecutionResult::Normal);
+        let cycles_after_sleep = machine.processor.get_cycle_count();
+        assert_eq!(machine.remaining_sleep_ms(), 100);
+
+        clock.set(1_099);
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Normal);
+        machine.execute_next_instruction();
+        assert_eq!(machine.processor.get_cycle_count(), cycles_after_sleep);
+        assert_eq!(machine.remaining_sleep_ms(), 1);
+
+        clock.set(1_100);
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert!(machine.processor.get_cycle_count() > cycles_after_sleep);
+        assert_eq!(machine.processor.registers[Register(3)], 1_100);
+    }
+
+    #[test]
+    fn sleep_advances_the_clock_by_default() {
+        let mut machine = create_machine_with_opcodes(&sleep_opcodes());
+        machine.periphery.timer = Timer::new(|| 0).with_monotonic_source(|| 1_000);
+
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert_eq!(machine.processor.registers[Register(3)], 1_100);
+        assert_eq!(machine.remaining_sleep_ms(), 0);
+    }
+
+    #[test]
+    fn sleep_starts_at_the_replayed_clock() {
+        let mut recording_machine = create_machine_with_opcodes(&sleep_opcodes());
+        recording_machine.periphery.timer = Timer::new(|| 0).with_monotonic_source(|| 1_000);
+        recording_machine.periphery.input_log = InputLog::record(1);
+        assert_eq!(recording_machine.run_cycles(2), ExecutionResult::Normal);
+        let recording = recording_machine
+            .periphery
+            .input_log
+            .recording()
+            .unwrap()
+            .clone();
+
+        let mut replaying_machine = create_machine_with_opcodes(&sleep_opcodes());
+        replaying_machine.periphery.timer = Timer::new(|| 0).with_monotonic_source(|| 5_000);
+        replaying_machine.periphery.input_log = InputLog::replay(recording);
+        assert_eq!(replaying_machine.run_cycles(2), ExecutionResult::Normal);
+        assert_eq!(replaying_machine.processor.sleep_until(), Some(1_100));
+    }
+
     create_test!(
         call_register,
         opcodes = &[
@@ -2159,12 +4022,12 @@ mod tests {
         machine.execute_next_instruction();
         assert_eq!(
             machine.processor.registers[0.into()],
-            address_constants::SECOND_FRAMEBUFFER_START
+            Resolution::DEFAULT.second_framebuffer_start()
         );
         machine.execute_next_instruction();
         assert_eq!(
             machine.processor.registers[0.into()],
-            address_constants::SECOND_FRAMEBUFFER_START
+            Resolution::DEFAULT.second_framebuffer_start()
         );
         machine.execute_next_instruction();
         assert_eq!(
@@ -2173,6 +4036,1779 @@ mod tests {
         );
     }
 
+    #[test]
+    fn visible_and_invisible_framebuffer_addresses_swap() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::VisibleFramebufferAddress { target: 0.into() },
+            Opcode::InvisibleFramebufferAddress { target: 1.into() },
+            Opcode::SwapFramebuffers {},
+            Opcode::VisibleFramebufferAddress { target: 2.into() },
+            Opcode::InvisibleFramebufferAddress { target: 3.into() },
+        ]);
+        for _ in 0..5 {
+            machine.execute_next_instruction();
+        }
+        let first = address_constants::FIRST_FRAMEBUFFER_START;
+        let second = Resolution::DEFAULT.second_framebuffer_start();
+        assert_eq!(machine.processor.registers[0.into()], first);
+        assert_eq!(machine.processor.registers[1.into()], second);
+        assert_eq!(machine.processor.registers[2.into()], second);
+        assert_eq!(machine.processor.registers[3.into()], first);
+    }
+
+    #[test]
+    fn vsync_defers_swap_until_render_tick() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::SwapFramebuffers {},
+            Opcode::VisibleFramebufferAddress { target: 0.into() },
+        ]);
+        machine.set_vsync(true);
+
+        machine.execute_next_instruction();
+        assert!(machine.is_swap_pending());
+        assert!(machine.periphery.display.is_first_framebuffer_visible());
+
+        // the execution stalls until the next frame gets rendered
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Normal);
+        machine.execute_next_instruction();
+        assert_eq!(
+            machine.processor.get_instruction_pointer(),
+            instruction_address(1)
+        );
+        assert_eq!(machine.processor.get_cycle_count(), 1);
+        assert!(machine.periphery.display.is_first_framebuffer_visible());
+
+        machine.render_tick();
+        assert!(!machine.is_swap_pending());
+        assert!(!machine.periphery.display.is_first_framebuffer_visible());
+        machine.execute_next_instruction();
+        assert_eq!(
+            machine.processor.registers[0.into()],
+            Resolution::DEFAULT.second_framebuffer_start()
+        );
+    }
+
+    #[test]
+    fn vsync_applies_swap_of_committed_frame() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::BeginFrame {},
+            Opcode::SwapFramebuffers {},
+            Opcode::EndFrame {},
+            Opcode::VisibleFramebufferAddress { target: 0.into() },
+        ]);
+        machine.set_vsync(true);
+
+        // the swap is deferred until the end of the frame, so the frame can still be drawn
+        machine.execute_next_instruction();
+        machine.execute_next_instruction();
+        assert!(!machine.is_swap_pending());
+        machine.execute_next_instruction();
+        assert!(machine.is_swap_pending());
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Normal);
+        assert_eq!(
+            machine.processor.get_instruction_pointer(),
+            instruction_address(3)
+        );
+        assert!(machine.periphery.display.is_first_framebuffer_visible());
+
+        machine.render_tick();
+        assert!(!machine.periphery.display.is_first_framebuffer_visible());
+        assert_eq!(machine.periphery.display.num_swaps(), 1);
+        machine.execute_next_instruction();
+        assert_eq!(
+            machine.processor.registers[0.into()],
+            Resolution::DEFAULT.second_framebuffer_start()
+        );
+    }
+
+    #[test]
+    fn poll_frame_count_follows_render_ticks() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::PollFrameCount { target: 0.into() },
+            Opcode::PollFrameCount { target: 1.into() },
+        ]);
+        machine.execute_next_instruction();
+        machine.render_tick();
+        machine.render_tick();
+        machine.execute_next_instruction();
+        assert_eq!(machine.processor.registers[0.into()], 0);
+        assert_eq!(machine.processor.registers[1.into()], 2);
+    }
+
+    #[test]
+    fn wait_for_vsync_resumes_after_render_tick() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::WaitForVSync {},
+            Opcode::PollFrameCount { target: 0.into() },
+        ]);
+        machine.execute_next_instruction();
+        assert!(machine.is_waiting_for_frame());
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Normal);
+        assert_eq!(
+            machine.processor.get_instruction_pointer(),
+            instruction_address(1)
+        );
+
+        machine.render_tick();
+        assert!(!machine.is_waiting_for_frame());
+        machine.execute_next_instruction();
+        assert_eq!(machine.processor.registers[0.into()], 1);
+    }
+
+    #[test]
+    fn framebuffer_addresses_follow_configured_resolution() {
+        let resolution = Resolution {
+            width: 320,
+            height: 240,
+        };
+        let mut periphery = create_mock_periphery();
+        periphery.display = MockDisplay::new(&mut (), &(), resolution);
+        let mut machine = Machine::new(periphery);
+        for (&opcode, address) in [
+            Opcode::InvisibleFramebufferAddress { target: 0.into() },
+            Opcode::SwapFramebuffers {},
+            Opcode::InvisibleFramebufferAddress { target: 1.into() },
+        ]
+        .iter()
+        .zip((address_constants::ENTRY_POINT..).step_by(Instruction::SIZE))
+        {
+            machine.memory.write_opcode(address, opcode);
+        }
+        machine.generate_instruction_cache();
+        for _ in 0..3 {
+            machine.execute_next_instruction();
+        }
+
+        assert_eq!(
+            machine.processor.registers[0.into()],
+            address_constants::FIRST_FRAMEBUFFER_START + 320 * 240 * 4
+        );
+        assert_eq!(
+            machine.processor.registers[1.into()],
+            address_constants::FIRST_FRAMEBUFFER_START
+        );
+        assert_eq!(
+            machine.periphery.display.capture(&machine.memory).len(),
+            320 * 240 * 4
+        );
+    }
+
+    #[test]
+    fn restoring_snapshot_reproduces_execution() {
+        let create_machine = || {
+            let mut machine = create_machine_with_opcodes(&[
+                Opcode::Random { target: 0.into() },
+                Opcode::AddTargetLhsRhs {
+                    target: 1.into(),
+                    lhs: 1.into(),
+                    rhs: 0.into(),
+                },
+                Opcode::MoveAddressRegister {
+                    register: 1.into(),
+                    target_address: address_constants::STACK_START,
+                },
+                Opcode::SwapFramebuffers {},
+                Opcode::JumpImmediate {
+                    immediate: instruction_address(0),
+                },
+            ]);
+            machine.processor.seed_rng(42);
+            machine
+        };
+        let mut machine = create_machine();
+        assert_eq!(machine.run_cycles(13), ExecutionResult::Normal);
+        let snapshot = machine.snapshot();
+        assert_eq!(machine.run_cycles(100), ExecutionResult::Normal);
+        let expected = machine.snapshot();
+
+        let mut restored = create_machine();
+        restored
+            .restore(&MachineSnapshot::decode(&snapshot.encode()).unwrap())
+            .unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(restored.run_cycles(100), ExecutionResult::Normal);
+        assert_eq!(restored.snapshot(), expected);
+    }
+
+    #[test]
+    fn restoring_snapshot_with_undefined_flags_fails() {
+        let mut machine = create_machine_with_opcodes(&[]);
+        let mut snapshot = machine.snapshot();
+        snapshot.processor.registers[Processor::FLAGS.0 as usize] = 0xFFFF_FFFF;
+        assert!(machine.restore(&snapshot).is_err());
+    }
+
+    #[test]
+    fn replaying_recorded_session_reproduces_final_state() {
+        let opcodes = [
+            Opcode::PollTime {
+                high: 0.into(),
+                low: 1.into(),
+            },
+            Opcode::MoveRegisterImmediate {
+                register: 2.into(),
+                immediate: 65,
+            },
+            Opcode::GetKeyState {
+                target: 3.into(),
+                keycode: 2.into(),
+            },
+            Opcode::AddTargetLhsRhs {
+                target: 4.into(),
+                lhs: 4.into(),
+                rhs: 1.into(),
+            },
+            Opcode::AddTargetLhsRhs {
+                target: 5.into(),
+                lhs: 5.into(),
+                rhs: 3.into(),
+            },
+            Opcode::Random { target: 6.into() },
+            Opcode::GetKeyEvent { target: 7.into() },
+            Opcode::GetCharInput { target: 8.into() },
+            Opcode::GetMousePosition {
+                x_target: 9.into(),
+                y_target: 10.into(),
+            },
+            Opcode::GetMouseButtons { target: 11.into() },
+            Opcode::MoveRegisterImmediate {
+                register: 0.into(),
+                immediate: 0x0010_0000,
+            },
+            Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: 16,
+            },
+            Opcode::SystemCall {
+                immediate: SYSTEM_CALL_READ_LINE,
+            },
+            Opcode::AddTargetLhsRhs {
+                target: 12.into(),
+                lhs: 12.into(),
+                rhs: 0.into(),
+            },
+            Opcode::JumpImmediate {
+                immediate: instruction_address(0),
+            },
+        ];
+
+        let mut recording_machine = create_machine_with_opcodes(&opcodes);
+        let mut time = 1000;
+        recording_machine.periphery.timer = Timer::new(move || {
+            time += 7;
+            time
+        });
+        let mut num_queries = 0;
+        recording_machine.periphery.keyboard = Keyboard::new(Box::new(move |_| {
+            num_queries += 1;
+            match num_queries % 3 {
+                0 => KeyState::Down,
+                _ => KeyState::Up,
+            }
+        }));
+        for keycode in [65, 66] {
+            recording_machine.periphery.keyboard.push_event(KeyEvent {
+                keycode,
+                state: KeyState::Down,
+            });
+        }
+        recording_machine.periphery.keyboard.push_char('x');
+        MockMouse::new([Mouse::with_buttons(100, 50, mouse::BUTTON_RIGHT)])
+            .step(&mut recording_machine.periphery.mouse);
+        recording_machine.periphery.host_io = HostIo::new(
+            Box::new(io::Cursor::new(b"first line\nsecond\n".to_vec())),
+            Box::new(io::sink()),
+        );
+        let rng_seed = 1234;
+        recording_machine.processor.seed_rng(rng_seed);
+        recording_machine.periphery.input_log = InputLog::record(rng_seed);
+        assert_eq!(recording_machine.run_cycles(50), ExecutionResult::Normal);
+        let recording = recording_machine
+            .periphery
+            .input_log
+            .recording()
+            .unwrap()
+            .clone();
+        assert_eq!(recording.records.len(), 23);
+
+        // the live periphery of this machine returns completely different values
+        let mut replaying_machine = create_machine_with_opcodes(&opcodes);
+        let serialized = serde_json::to_string(&recording).unwrap();
+        let recording: Recording = serde_json::from_str(&serialized).unwrap();
+        replaying_machine.processor.seed_rng(recording.rng_seed);
+        replaying_machine.periphery.input_log = InputLog::replay(recording);
+        assert_eq!(replaying_machine.run_cycles(50), ExecutionResult::Normal);
+
+        assert!(!replaying_machine.periphery.input_log.has_diverged());
+        assert_eq!(replaying_machine.snapshot(), recording_machine.snapshot());
+    }
+
+    #[test]
+    fn replay_applies_render_ticks_at_recorded_cycles() {
+        let opcodes = [
+            Opcode::PollFrameCount { target: 0.into() },
+            Opcode::AddTargetLhsRhs {
+                target: 1.into(),
+                lhs: 1.into(),
+                rhs: 0.into(),
+            },
+            Opcode::WaitForVSync {},
+            Opcode::JumpImmediate {
+                immediate: instruction_address(0),
+            },
+        ];
+
+        let mut recording_machine = create_machine_with_opcodes(&opcodes);
+        recording_machine.processor.seed_rng(1);
+        recording_machine.periphery.input_log = InputLog::record(1);
+        recording_machine.run_cycles(1);
+        recording_machine.render_tick();
+        recording_machine.run_cycles(10);
+        recording_machine.render_tick();
+        recording_machine.render_tick();
+        recording_machine.run_cycles(10);
+        recording_machine.render_tick();
+        recording_machine.run_cycles(10);
+        assert!(recording_machine.is_waiting_for_frame());
+        let recording = recording_machine
+            .periphery
+            .input_log
+            .recording()
+            .unwrap()
+            .clone();
+
+        let mut replaying_machine = create_machine_with_opcodes(&opcodes);
+        replaying_machine.processor.seed_rng(recording.rng_seed);
+        replaying_machine.periphery.input_log = InputLog::replay(recording);
+        // live frames are ignored while replaying
+        replaying_machine.render_tick();
+        replaying_machine.run_cycles(100);
+
+        assert!(!replaying_machine.periphery.input_log.has_diverged());
+        assert_eq!(replaying_machine.snapshot(), recording_machine.snapshot());
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_register_dump_contains_special_registers_and_flags() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: 5,
+            },
+            Opcode::PushRegister { register: 1.into() },
+            Opcode::SubtractTargetSourceImmediate {
+                target: 2.into(),
+                source: 1.into(),
+                immediate: 6,
+            },
+        ]);
+        assert_eq!(machine.run_cycles(3), ExecutionResult::Normal);
+
+        let json = serde_json::to_value(machine.processor.register_dump()).unwrap();
+        assert_eq!(json["instruction_pointer"], instruction_address(3));
+        assert_eq!(
+            json["stack_pointer"],
+            address_constants::STACK_START + Word::SIZE as Address
+        );
+        assert_eq!(json["flags"]["Carry"], true);
+        assert_eq!(json["flags"]["Zero"], false);
+        assert_eq!(json["flags"]["DivideByZero"], false);
+        assert_eq!(json["flags"]["InterruptsEnabled"], false);
+        assert_eq!(json["flags"]["Overflow"], false);
+        assert_eq!(json["flags"]["Negative"], true);
+        assert_eq!(json["registers"][1], 5);
+        assert_eq!(json["registers"][2], Word::MAX);
+    }
+
+    #[test]
+    fn trace_logs_executed_instructions() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: 42,
+            },
+            Opcode::JumpImmediate {
+                immediate: instruction_address(3),
+            },
+            Opcode::NoOp {},
+            Opcode::AddTargetSourceImmediate {
+                target: 2.into(),
+                source: 1.into(),
+                immediate: 1,
+            },
+            Opcode::HaltAndCatchFire {},
+        ]);
+        let buffer = SharedBuffer::default();
+        machine.set_tracer(Some(Tracer::new(Box::new(buffer.clone()))));
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+
+        let trace = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let expected = [
+            format!(
+                "{:#010x}: MoveRegisterImmediate r1, 0x0000002a | r1 = 0x0000002a",
+                instruction_address(0)
+            ),
+            format!(
+                "{:#010x}: JumpImmediate {:#010x}",
+                instruction_address(1),
+                instruction_address(3)
+            ),
+            format!(
+                "{:#010x}: AddTargetSourceImmediate r2, r1, 0x00000001 | r2 = 0x0000002b",
+                instruction_address(3)
+            ),
+            format!("{:#010x}: HaltAndCatchFire", instruction_address(4)),
+        ];
+        assert_eq!(trace.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn profile_counts_loop_iterations() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: 10,
+            },
+            // loop start
+            Opcode::SubtractTargetSourceImmediate {
+                target: 1.into(),
+                source: 1.into(),
+                immediate: 1,
+            },
+            Opcode::JumpImmediateIfNotZero {
+                immediate: instruction_address(1),
+            },
+            Opcode::HaltAndCatchFire {},
+        ]);
+        machine.processor.enable_profiling();
+        assert_eq!(machine.run_cycles(100), ExecutionResult::Halted);
+
+        let profile = machine.processor.profile().unwrap();
+        let count = |mnemonic| profile.get(mnemonic).unwrap().count;
+        assert_eq!(count("MoveRegisterImmediate"), 1);
+        assert_eq!(count("SubtractTargetSourceImmediate"), 10);
+        assert_eq!(count("JumpImmediateIfNotZero"), 10);
+        assert_eq!(count("HaltAndCatchFire"), 1);
+        assert_eq!(
+            profile.get("JumpImmediateIfNotZero").unwrap().cycles,
+            10 * Opcode::JumpImmediateIfNotZero { immediate: 0 }.get_num_cycles() as u64
+        );
+        assert_eq!(profile.sorted_entries().len(), 4);
+    }
+
+    #[test]
+    fn failed_assertion_halts_with_reason() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: 42,
+            },
+            Opcode::AssertRegisterImmediate {
+                actual: 1.into(),
+                immediate: 42,
+            },
+            Opcode::Checkpoint { immediate: 0 },
+            Opcode::Checkpoint { immediate: 2 },
+            Opcode::NoOp {},
+        ]);
+        assert_eq!(
+            machine.run_cycles(10),
+            ExecutionResult::AssertionFailed {
+                expected: 1,
+                actual: 2,
+                address: instruction_address(3),
+            }
+        );
+        assert_eq!(machine.halt_reason(), Some(HaltReason::AssertionFailed));
+        assert!(machine.is_faulted());
+        assert_eq!(
+            machine.processor.get_instruction_pointer(),
+            instruction_address(3)
+        );
+        assert_eq!(machine.assertion_failures().len(), 1);
+    }
+
+    #[test]
+    fn matching_checkpoints_advance_the_counter() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::Checkpoint { immediate: 0 },
+            Opcode::Checkpoint { immediate: 1 },
+            Opcode::Checkpoint { immediate: 2 },
+            Opcode::HaltAndCatchFire {},
+        ]);
+        assert_eq!(machine.processor.checkpoint_counter(), 0);
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert_eq!(machine.processor.checkpoint_counter(), 3);
+        assert!(machine.assertion_failures().is_empty());
+    }
+
+    #[test]
+    fn checkpoint_mismatch_is_a_fault() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::Checkpoint { immediate: 0 },
+            Opcode::Checkpoint { immediate: 5 },
+            Opcode::HaltAndCatchFire {},
+        ]);
+        assert_eq!(
+            machine.run_cycles(10),
+            ExecutionResult::AssertionFailed {
+                expected: 1,
+                actual: 5,
+                address: instruction_address(1),
+            }
+        );
+        assert!(machine.is_faulted());
+        assert_eq!(machine.processor.checkpoint_counter(), 6);
+    }
+
+    #[test]
+    fn reset_checkpoints_restarts_counting_at_zero() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::Checkpoint { immediate: 0 },
+            Opcode::Checkpoint { immediate: 1 },
+            // e.g. the start of another test
+            Opcode::Checkpoint { immediate: 0 },
+            Opcode::HaltAndCatchFire {},
+        ]);
+        assert_eq!(machine.run_cycles(2), ExecutionResult::Normal);
+        assert_eq!(machine.processor.checkpoint_counter(), 2);
+
+        machine.processor.reset_checkpoints();
+        assert_eq!(machine.processor.checkpoint_counter(), 0);
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert!(machine.assertion_failures().is_empty());
+    }
+
+    #[test]
+    fn failed_assertions_are_recorded_without_halting() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: 0x100,
+            },
+            Opcode::AssertRegisterImmediate {
+                actual: 1.into(),
+                immediate: 42,
+            },
+            Opcode::AssertPointerImmediate {
+                pointer: 1.into(),
+                immediate: 7,
+            },
+            Opcode::AssertRegisterRegister {
+                expected: 1.into(),
+                actual: 1.into(),
+            },
+            Opcode::Checkpoint { immediate: 1 },
+            Opcode::Checkpoint { immediate: 2 },
+            Opcode::HaltAndCatchFire {},
+        ]);
+        machine.set_halt_on_assertion_failure(false);
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert_eq!(
+            machine.assertion_failures(),
+            [
+                AssertionFailure {
+                    expected: 42,
+                    actual: 0x100,
+                    address: instruction_address(1),
+                },
+                AssertionFailure {
+                    expected: 7,
+                    actual: 0,
+                    address: instruction_address(2),
+                },
+                AssertionFailure {
+                    expected: 0,
+                    actual: 1,
+                    address: instruction_address(4),
+                },
+            ]
+        );
+        // failed assertions take their cycles like passed ones
+        assert_eq!(machine.processor.get_cycle_count(), 8);
+    }
+
+    #[test]
+    fn halt_and_catch_fire_halts_with_reason() {
+        let mut machine = create_machine_with_opcodes(&[Opcode::HaltAndCatchFire {}]);
+        assert_eq!(machine.halt_reason(), None);
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert_eq!(machine.halt_reason(), Some(HaltReason::CaughtFire));
+        assert!(!machine.is_faulted());
+    }
+
+    fn step_opcodes() -> Vec<Opcode> {
+        vec![
+            Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: 1,
+            },
+            Opcode::MoveRegisterImmediate {
+                register: 2.into(),
+                immediate: 2,
+            },
+            Opcode::MoveRegisterImmediate {
+                register: 3.into(),
+                immediate: 3,
+            },
+            Opcode::HaltAndCatchFire {},
+        ]
+    }
+
+    #[test]
+    fn stepper_executes_one_instruction_per_line() {
+        use crate::stepper::{run_stepper, StepperExit};
+
+        let mut machine = create_machine_with_opcodes(&step_opcodes());
+        let mut output = Vec::new();
+        let exit = run_stepper(&mut machine, "\nr\n\nq\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(exit, StepperExit::Quit);
+        assert_eq!(machine.processor.get_cycle_count(), 2);
+        assert_eq!(machine.processor.registers[2.into()], 2);
+        assert_eq!(machine.processor.registers[3.into()], 0);
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(&format!(
+            "{:#010x}: MoveRegisterImmediate r3, 0x00000003 > ",
+            instruction_address(2)
+        )));
+        assert!(output.contains("r1 = 0x00000001"));
+    }
+
+    #[test]
+    fn stepper_stops_at_halt_or_continue() {
+        use crate::stepper::{run_stepper, StepperExit};
+
+        let mut machine = create_machine_with_opcodes(&step_opcodes());
+        let exit = run_stepper(&mut machine, "\n\n\n\n\n".as_bytes(), io::sink()).unwrap();
+        assert_eq!(exit, StepperExit::Halted);
+        assert_eq!(machine.halt_reason(), Some(HaltReason::CaughtFire));
+
+        let mut machine = create_machine_with_opcodes(&step_opcodes());
+        let exit = run_stepper(&mut machine, "\nc\n".as_bytes(), io::sink()).unwrap();
+        assert_eq!(exit, StepperExit::Continue);
+        assert_eq!(machine.processor.get_cycle_count(), 1);
+
+        // closed input quits
+        let exit = run_stepper(&mut machine, "".as_bytes(), io::sink()).unwrap();
+        assert_eq!(exit, StepperExit::Quit);
+    }
+
+    #[test]
+    fn load_rom_at_custom_address_and_entry_point() {
+        const LOAD_ADDRESS: Address = address_constants::ENTRY_POINT + 0x100;
+        let mut rom = vec![0xAB; 2 * Instruction::SIZE]; // some data in front of the code
+        for opcode in [
+            Opcode::MoveRegisterAddress {
+                register: 1.into(),
+                source_address: LOAD_ADDRESS,
+            },
+            Opcode::HaltAndCatchFire {},
+        ] {
+            rom.extend(opcode.as_instruction().to_be_bytes());
+        }
+        let entry_point = LOAD_ADDRESS + 2 * Instruction::SIZE as Address;
+
+        let mut machine = Machine::new(create_mock_periphery());
+        machine.load_rom_at(&rom, LOAD_ADDRESS).unwrap();
+        machine.set_entry_point(entry_point).unwrap();
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert_eq!(machine.processor.registers[1.into()], 0xABABABAB);
+        assert_eq!(
+            machine.processor.get_instruction_pointer(),
+            entry_point + Instruction::SIZE as Address
+        );
+    }
+
+    fn rom_body() -> Vec<u8> {
+        [
+            Opcode::NoOp {},
+            Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: 42,
+            },
+            Opcode::HaltAndCatchFire {},
+        ]
+        .iter()
+        .flat_map(|opcode| opcode.as_instruction().to_be_bytes())
+        .collect()
+    }
+
+    #[test]
+    fn load_headered_rom() {
+        let body = rom_body();
+        let entry_point = instruction_address(1);
+        let mut rom = rom::RomHeader::new(entry_point, &body).encode().to_vec();
+        rom.extend(&body);
+
+        let mut machine = Machine::new(create_mock_periphery());
+        machine.load_rom(&rom).unwrap();
+        assert_eq!(machine.processor.get_instruction_pointer(), entry_point);
+        assert_eq!(
+            machine.memory.read_opcode(instruction_address(0)),
+            Ok(Opcode::NoOp {})
+        );
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert_eq!(machine.processor.registers[1.into()], 42);
+    }
+
+    #[test]
+    fn load_headerless_rom() {
+        let mut machine = Machine::new(create_mock_periphery());
+        machine.load_rom(&rom_body()).unwrap();
+        assert_eq!(
+            machine.processor.get_instruction_pointer(),
+            address_constants::ENTRY_POINT
+        );
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert_eq!(machine.processor.registers[1.into()], 42);
+    }
+
+    #[test]
+    fn load_rom_rejects_version_mismatch() {
+        let body = rom_body();
+        let header = rom::RomHeader {
+            version: rom::VERSION + 1,
+            ..rom::RomHeader::new(address_constants::ENTRY_POINT, &body)
+        };
+        let mut rom = header.encode().to_vec();
+        rom.extend(&body);
+
+        let mut machine = Machine::new(create_mock_periphery());
+        let error = machine.load_rom(&rom).unwrap_err();
+        assert!(matches!(
+            error,
+            EmulatorError::Rom(rom::RomError::UnsupportedVersion(version))
+                if version == rom::VERSION + 1
+        ));
+        assert_eq!(machine.memory.read_data(address_constants::ENTRY_POINT), 0);
+    }
+
+    #[test]
+    fn invalid_load_address_and_entry_point_are_rejected() {
+        let mut machine = Machine::new(create_mock_periphery());
+        let rom = [0; Instruction::SIZE];
+        assert!(machine.load_rom_at(&rom, 0x1002).is_err());
+        assert!(machine
+            .load_rom_at(&rom, (Memory::DEFAULT_SIZE - Word::SIZE) as Address)
+            .is_err());
+        assert!(machine.load_rom_at(&rom, 0x1004).is_ok());
+        let entry_point = address_constants::ENTRY_POINT;
+        assert!(machine.set_entry_point(entry_point + 4).is_err());
+        assert!(machine.set_entry_point(entry_point - 8).is_ok());
+        assert!(machine
+            .set_entry_point(Memory::DEFAULT_SIZE as Address)
+            .is_err());
+        assert!(machine.set_entry_point(entry_point + 8).is_ok());
+    }
+
+    #[test]
+    fn load_errors_can_be_distinguished() {
+        let mut machine = Machine::new(create_mock_periphery());
+        let error = machine
+            .load_rom(&vec![0; Memory::DEFAULT_SIZE])
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            EmulatorError::TooBig { size, available }
+                if size == Memory::DEFAULT_SIZE
+                    && available == Memory::DEFAULT_SIZE - address_constants::ENTRY_POINT as usize
+        ));
+        let error = machine.load_rom(&[0; 6]).unwrap_err();
+        assert!(matches!(error, EmulatorError::MisalignedSize(6)));
+        let error = machine.load_rom_at(&[0; 4], 0x1002).unwrap_err();
+        assert!(matches!(
+            error,
+            EmulatorError::MisalignedAddress {
+                address: 0x1002,
+                alignment: 4
+            }
+        ));
+        let end = Memory::DEFAULT_SIZE as Address;
+        let error = machine.set_entry_point(end).unwrap_err();
+        assert!(matches!(error, EmulatorError::EntryPointOutOfRange(address) if address == end));
+    }
+
+    #[test]
+    fn padded_rom_is_filled_up_with_zeros() {
+        let mut machine = Machine::new(create_mock_periphery());
+        let entry_point = address_constants::ENTRY_POINT;
+        machine.memory.write_data(entry_point + 4, 0xFFFF_FFFF);
+        machine.set_pad_rom(true);
+        machine.load_rom(&[1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(machine.memory.read_data(entry_point), 0x0102_0304);
+        assert_eq!(machine.memory.read_data(entry_point + 4), 0x0506_0000);
+        // aligned ROMs are loaded unchanged
+        machine.load_rom(&[7, 8, 9, 10]).unwrap();
+        assert_eq!(machine.memory.read_data(entry_point), 0x0708_090A);
+        assert_eq!(machine.memory.read_data(entry_point + 4), 0x0506_0000);
+    }
+
+    fn timer_interrupt_opcodes(enable_interrupts: bool) -> Vec<Opcode> {
+        let increment = Opcode::AddTargetSourceImmediate {
+            target: 1.into(),
+            source: 1.into(),
+            immediate: 1,
+        };
+        vec![
+            match enable_interrupts {
+                true => Opcode::EnableInterrupts {},
+                false => Opcode::NoOp {},
+            },
+            increment,
+            increment,
+            increment,
+            Opcode::HaltAndCatchFire {},
+            // interrupt handler
+            Opcode::MoveRegisterImmediate {
+                register: 2.into(),
+                immediate: 42,
+            },
+            Opcode::ReturnFromInterrupt {},
+        ]
+    }
+
+    fn setup_timer_interrupt(machine: &mut Machine<MockDisplay>, interval: Word) {
+        machine.memory.write_data(
+            address_constants::INTERRUPT_VECTOR_TABLE_START,
+            instruction_address(5),
+        );
+        machine
+            .memory
+            .write_data(address_constants::TIMER_INTERRUPT_INTERVAL, interval);
+    }
+
+    #[test]
+    fn timer_interrupt_transfers_control_to_handler() {
+        let mut machine = create_machine_with_opcodes(&timer_interrupt_opcodes(true));
+        setup_timer_interrupt(&mut machine, 4);
+
+        assert_eq!(machine.run_cycles(4), ExecutionResult::Normal);
+        assert_eq!(
+            machine.processor.get_instruction_pointer(),
+            instruction_address(5)
+        );
+        assert!(!machine.processor.get_flag(Flag::InterruptsEnabled));
+        let stack_pointer = machine.processor.get_stack_pointer();
+        assert_eq!(
+            machine
+                .memory
+                .read_data(stack_pointer - Word::SIZE as Address),
+            instruction_address(4)
+        );
+        assert_eq!(
+            machine
+                .memory
+                .read_data(stack_pointer - 2 * Word::SIZE as Address),
+            Flag::InterruptsEnabled.bits()
+        );
+
+        // the handler returns to the interrupted program
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert_eq!(machine.processor.registers[1.into()], 3);
+        assert_eq!(machine.processor.registers[2.into()], 42);
+        assert!(machine.processor.get_flag(Flag::InterruptsEnabled));
+        assert_eq!(
+            machine.processor.get_stack_pointer(),
+            address_constants::STACK_START
+        );
+    }
+
+    #[test]
+    fn timer_interrupt_preserves_flags() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::EnableInterrupts {},
+            Opcode::NoOp {},
+            Opcode::NoOp {},
+            Opcode::NoOp {},
+            Opcode::HaltAndCatchFire {},
+            // interrupt handler
+            Opcode::ClearFlags {},
+            Opcode::ReturnFromInterrupt {},
+        ]);
+        setup_timer_interrupt(&mut machine, 4);
+        machine.processor.set_flag(Flag::Carry, true);
+        machine.processor.set_flag(Flag::Zero, true);
+
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert!(machine.processor.get_flag(Flag::Carry));
+        assert!(machine.processor.get_flag(Flag::Zero));
+        assert!(machine.processor.get_flag(Flag::InterruptsEnabled));
+        assert_eq!(
+            machine.processor.get_stack_pointer(),
+            address_constants::STACK_START
+        );
+    }
+
+    #[test]
+    fn timer_interrupt_while_disabled_fires_once_enabled() {
+        let increment = Opcode::AddTargetSourceImmediate {
+            target: 1.into(),
+            source: 1.into(),
+            immediate: 1,
+        };
+        let mut machine = create_machine_with_opcodes(&[
+            increment,
+            increment,
+            increment,
+            increment,
+            increment,
+            Opcode::EnableInterrupts {},
+            Opcode::HaltAndCatchFire {},
+            // interrupt handler
+            Opcode::AddTargetSourceImmediate {
+                target: 2.into(),
+                source: 2.into(),
+                immediate: 1,
+            },
+            Opcode::ReturnFromInterrupt {},
+        ]);
+        setup_timer_interrupt(&mut machine, 5);
+        machine.memory.write_data(
+            address_constants::INTERRUPT_VECTOR_TABLE_START,
+            instruction_address(7),
+        );
+
+        // the interval elapses while interrupts are still disabled
+        assert_eq!(machine.run_cycles(5), ExecutionResult::Normal);
+        assert_eq!(
+            machine.processor.get_instruction_pointer(),
+            instruction_address(5)
+        );
+        assert_eq!(machine.processor.registers[2.into()], 0);
+
+        // the latched interrupt is taken right after enabling interrupts
+        assert_eq!(machine.run_cycles(1), ExecutionResult::Normal);
+        assert_eq!(
+            machine.processor.get_instruction_pointer(),
+            instruction_address(7)
+        );
+
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert_eq!(machine.processor.registers[1.into()], 5);
+        assert_eq!(machine.processor.registers[2.into()], 1);
+    }
+
+    #[test]
+    fn timer_interrupt_outside_of_memory_is_an_error() {
+        // too small to contain the interrupt configuration
+        let size = address_constants::INTERRUPT_VECTOR_TABLE_START as usize / Instruction::SIZE
+            * Instruction::SIZE;
+        let mut machine =
+            Machine::with_memory(create_mock_periphery(), Memory::with_unchecked_size(size));
+        machine.memory.write_opcode(0, Opcode::NoOp {});
+        machine.generate_instruction_cache();
+        machine.set_entry_point(0).unwrap();
+        machine.processor.set_flag(Flag::InterruptsEnabled, true);
+        assert_eq!(machine.run_cycles(1), ExecutionResult::Error);
+    }
+
+    #[test]
+    fn timer_interrupt_requires_enabled_interrupts() {
+        let mut machine = create_machine_with_opcodes(&timer_interrupt_opcodes(false));
+        setup_timer_interrupt(&mut machine, 4);
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert_eq!(machine.processor.registers[1.into()], 3);
+        assert_eq!(machine.processor.registers[2.into()], 0);
+
+        let mut machine = create_machine_with_opcodes(&timer_interrupt_opcodes(true));
+        setup_timer_interrupt(&mut machine, 0);
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert_eq!(machine.processor.registers[2.into()], 0);
+    }
+
+    #[cfg(feature = "debugger")]
+    #[test]
+    fn in_process_debugger_breaks_and_steps() {
+        let opcodes: Vec<_> = (0..5)
+            .map(|i| Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: i,
+            })
+            .collect();
+        let mut machine = create_machine_with_opcodes(&opcodes);
+        let (commands, messages) = machine.start_in_process_debugger();
+        let reported_addresses = || -> Vec<_> {
+            messages
+                .try_iter()
+                .filter_map(|message| match message {
+                    DebugMessage::HitBreakpoint(address) => Some(("hit", address)),
+                    DebugMessage::Breaking(address) => Some(("breaking", address)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        commands
+            .send(DebugCommand::SetBreakpoints(vec![instruction_address(2)]))
+            .unwrap();
+        for _ in 0..3 {
+            machine.execute_next_instruction();
+        }
+        assert_eq!(reported_addresses(), [("hit", instruction_address(2))]);
+        assert_eq!(machine.processor.registers[1.into()], 1);
+
+        for _ in 0..2 {
+            commands.send(DebugCommand::StepOne).unwrap();
+            machine.execute_next_instruction();
+            machine.execute_next_instruction();
+        }
+        assert_eq!(
+            reported_addresses(),
+            [
+                ("breaking", instruction_address(3)),
+                ("breaking", instruction_address(4))
+            ]
+        );
+        assert_eq!(machine.processor.registers[1.into()], 3);
+    }
+
+    #[cfg(feature = "debugger")]
+    #[test]
+    fn stepping_after_setting_instruction_pointer_executes_new_instruction() {
+        let opcodes: Vec<_> = (0..5)
+            .map(|i| Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: i,
+            })
+            .collect();
+        let mut machine = create_machine_with_opcodes(&opcodes);
+        let (commands, messages) = machine.start_in_process_debugger();
+        commands.send(DebugCommand::Pause).unwrap();
+        machine.execute_next_instruction();
+
+        let pc = Processor::INSTRUCTION_POINTER.0;
+        commands
+            .send(DebugCommand::SetRegister(pc, instruction_address(3) + 2))
+            .unwrap();
+        commands
+            .send(DebugCommand::SetRegister(pc, instruction_address(3)))
+            .unwrap();
+        commands.send(DebugCommand::StepOne).unwrap();
+        // every call handles one command, the instruction is executed with the last one
+        for _ in 0..4 {
+            machine.execute_next_instruction();
+        }
+
+        assert_eq!(machine.processor.registers[1.into()], 3);
+        assert_eq!(
+            machine.processor.get_instruction_pointer(),
+            instruction_address(4)
+        );
+        let errors: Vec<_> = messages
+            .try_iter()
+            .filter_map(|message| match message {
+                DebugMessage::Error(message) => Some(message),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [format!(
+                "invalid instruction pointer {:#010x}",
+                instruction_address(3) + 2
+            )]
+        );
+    }
+
+    #[cfg(feature = "debugger")]
+    #[test]
+    fn break_state_reports_cycle_count_and_halt() {
+        let mut opcodes: Vec<_> = (0..3)
+            .map(|i| Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: i,
+            })
+            .collect();
+        opcodes.push(Opcode::HaltAndCatchFire {});
+        let mut machine = create_machine_with_opcodes(&opcodes);
+        let (commands, messages) = machine.start_in_process_debugger();
+        let break_states = || -> Vec<_> {
+            messages
+                .try_iter()
+                .filter_map(|message| match message {
+                    DebugMessage::BreakState {
+                        cycle_count,
+                        is_halted,
+                        ..
+                    } => Some((cycle_count, is_halted)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        commands
+            .send(DebugCommand::SetBreakpoints(vec![instruction_address(3)]))
+            .unwrap();
+        for _ in 0..4 {
+            machine.execute_next_instruction();
+        }
+        assert_eq!(break_states(), [(3, false)]);
+
+        commands.send(DebugCommand::Continue).unwrap();
+        machine.execute_next_instruction();
+        assert!(machine.is_halted());
+        assert_eq!(break_states(), [(4, true)]);
+    }
+
+    #[cfg(feature = "debugger")]
+    #[test]
+    fn break_state_reports_call_stack() {
+        use crate::debugger::StackFrame;
+
+        let call = |index| Opcode::CallImmediate {
+            immediate: instruction_address(index),
+        };
+        let mut machine = create_machine_with_opcodes(&[
+            call(3),
+            Opcode::HaltAndCatchFire {},
+            Opcode::NoOp {},
+            // 3: returns normally
+            call(6),
+            Opcode::NoOp {},
+            Opcode::Return {},
+            // 6: returns by popping the return address manually
+            call(9),
+            Opcode::PopRegister { register: 1.into() },
+            Opcode::JumpRegister { register: 1.into() },
+            // 9
+            Opcode::NoOp {},
+            Opcode::Return {},
+        ]);
+        let (commands, messages) = machine.start_in_process_debugger();
+        commands
+            .send(DebugCommand::SetBreakpoints(
+                [9, 7, 4, 1].map(instruction_address).to_vec(),
+            ))
+            .unwrap();
+
+        let mut call_stacks = Vec::new();
+        for _ in 0..100 {
+            if machine.is_halted() {
+                break;
+            }
+            machine.execute_next_instruction();
+            for message in messages.try_iter() {
+                if let DebugMessage::BreakState {
+                    call_stack,
+                    is_halted: false,
+                    ..
+                } = message
+                {
+                    call_stacks.push(call_stack);
+                    commands.send(DebugCommand::Continue).unwrap();
+                }
+            }
+        }
+        assert!(machine.is_halted());
+
+        let frame = |call_site: usize, depth: usize| StackFrame {
+            call_site: instruction_address(call_site),
+            return_address: instruction_address(call_site + 1),
+            stack_address: address_constants::STACK_START + (depth * Word::SIZE) as Address,
+        };
+        assert_eq!(
+            call_stacks,
+            [
+                vec![frame(0, 0), frame(3, 1), frame(6, 2)],
+                vec![frame(0, 0), frame(3, 1)],
+                vec![frame(0, 0)],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn fill_rom_draws_into_visible_framebuffer() {
+        let width = Resolution::DEFAULT.width;
+        let num_filled_pixels = 2 * width + 3;
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: address_constants::FIRST_FRAMEBUFFER_START,
+            },
+            Opcode::MoveRegisterImmediate {
+                register: 2.into(),
+                immediate: address_constants::FIRST_FRAMEBUFFER_START
+                    + (num_filled_pixels * Word::SIZE) as Address,
+            },
+            Opcode::MoveRegisterImmediate {
+                register: 3.into(),
+                immediate: 0x11223344,
+            },
+            Opcode::MovePointerSource {
+                pointer: 1.into(),
+                source: 3.into(),
+            },
+            Opcode::AddTargetSourceImmediate {
+                target: 1.into(),
+                source: 1.into(),
+                immediate: Word::SIZE as Word,
+            },
+            Opcode::CompareTargetLhsRhs {
+                target: 10.into(),
+                lhs: 1.into(),
+                rhs: 2.into(),
+            },
+            Opcode::JumpImmediateIfLessThan {
+                comparison: 10.into(),
+                immediate: instruction_address(3),
+            },
+            Opcode::HaltAndCatchFire {},
+        ]);
+        assert_eq!(machine.run_cycles(10_000), ExecutionResult::Halted);
+
+        let display = &machine.periphery.display;
+        let fill_color = [0x11, 0x22, 0x33, 0x44];
+        assert_eq!(display.pixel_at(&machine.memory, 0, 0), fill_color);
+        assert_eq!(display.pixel_at(&machine.memory, width - 1, 1), fill_color);
+        assert_eq!(display.pixel_at(&machine.memory, 2, 2), fill_color);
+        assert_eq!(display.pixel_at(&machine.memory, 3, 2), [0; 4]);
+        assert!(display
+            .capture_invisible(&machine.memory)
+            .iter()
+            .all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn system_call_writes_string() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::MoveRegisterImmediate {
+                register: 0.into(),
+                immediate: 0x100,
+            },
+            Opcode::SystemCall {
+                immediate: SYSTEM_CALL_WRITE_STRING,
+            },
+        ]);
+        for (address, &byte) in (0x100..).zip(b"Hello, World!\0junk") {
+            machine.memory.write_byte(address, byte);
+        }
+        let output = SharedBuffer::default();
+        machine.periphery.host_io = HostIo::new(Box::new(io::empty()), Box::new(output.clone()));
+        assert_eq!(machine.run_cycles(2), ExecutionResult::Normal);
+        assert_eq!(output.0.borrow().as_slice(), b"Hello, World!");
+    }
+
+    #[test]
+    fn buffered_system_call_output_is_flushed_after_every_string() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::MoveRegisterImmediate {
+                register: 0.into(),
+                immediate: 0x100,
+            },
+            Opcode::SystemCall {
+                immediate: SYSTEM_CALL_WRITE_STRING,
+            },
+            Opcode::HaltAndCatchFire {},
+        ]);
+        for (address, &byte) in (0x100..).zip(b"done\0") {
+            machine.memory.write_byte(address, byte);
+        }
+        let output = SharedBuffer::default();
+        machine.periphery.host_io = HostIo::new(
+            Box::new(io::empty()),
+            Box::new(io::BufWriter::new(output.clone())),
+        );
+        assert_eq!(machine.run_cycles(2), ExecutionResult::Normal);
+        assert_eq!(output.0.borrow().as_slice(), b"done");
+    }
+
+    #[test]
+    fn system_call_reads_lines() {
+        let read_line = Opcode::SystemCall {
+            immediate: SYSTEM_CALL_READ_LINE,
+        };
+        let set_arguments = [
+            Opcode::MoveRegisterImmediate {
+                register: 0.into(),
+                immediate: 0x100,
+            },
+            Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: 4,
+            },
+        ];
+        let mut opcodes = Vec::new();
+        for _ in 0..3 {
+            opcodes.extend(set_arguments);
+            opcodes.push(read_line);
+        }
+        let mut machine = create_machine_with_opcodes(&opcodes);
+        machine.periphery.host_io = HostIo::new(
+            Box::new(io::Cursor::new(b"ab\r\nlonger line\n".to_vec())),
+            Box::new(io::sink()),
+        );
+
+        assert_eq!(machine.run_cycles(3), ExecutionResult::Normal);
+        assert_eq!(machine.processor.registers[0.into()], 2);
+        assert_eq!(
+            machine.memory.read_data(0x100),
+            u32::from_be_bytes(*b"ab\0\0")
+        );
+
+        // truncated to fit into the buffer
+        assert_eq!(machine.run_cycles(3), ExecutionResult::Normal);
+        assert_eq!(machine.processor.registers[0.into()], 3);
+        assert_eq!(
+            machine.memory.read_data(0x100),
+            u32::from_be_bytes(*b"lon\0")
+        );
+
+        assert_eq!(machine.run_cycles(3), ExecutionResult::Normal);
+        assert_eq!(machine.processor.registers[0.into()], Word::MAX);
+    }
+
+    #[test]
+    fn debug_break_without_debugger_is_a_no_op() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::DebugBreak {},
+            Opcode::MoveRegisterImmediate {
+                register: 1.into(),
+                immediate: 42,
+            },
+        ]);
+        assert_eq!(machine.run_cycles(2), ExecutionResult::Normal);
+        assert_eq!(machine.processor.registers[1.into()], 42);
+    }
+
+    #[test]
+    fn unknown_system_call_is_an_error() {
+        let mut machine = create_machine_with_opcodes(&[Opcode::SystemCall { immediate: 42 }]);
+        assert_eq!(machine.run_cycles(1), ExecutionResult::Error);
+    }
+
+    #[test]
+    fn run_cycles_stops_at_halt() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::AddTargetSourceImmediate {
+                target: 0.into(),
+                source: 0.into(),
+                immediate: 1,
+            },
+            Opcode::HaltAndCatchFire {},
+            Opcode::AddTargetSourceImmediate {
+                target: 0.into(),
+                source: 0.into(),
+                immediate: 1,
+            },
+        ]);
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert_eq!(machine.processor.registers[0.into()], 1);
+        assert_eq!(
+            machine.processor.get_instruction_pointer(),
+            instruction_address(1)
+        );
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Halted);
+        assert_eq!(machine.processor.registers[0.into()], 1);
+    }
+
+    #[test]
+    fn run_cycles_stops_at_error() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::NoOp {},
+            Opcode::SetDisplayMode { immediate: 42 },
+            Opcode::NoOp {},
+        ]);
+        assert_eq!(machine.run_cycles(10), ExecutionResult::Error);
+        assert!(machine.is_faulted());
+        assert_eq!(
+            machine.processor.get_instruction_pointer(),
+            instruction_address(1)
+        );
+    }
+
+    #[test]
+    fn run_cycles_exhausts_budget() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::AddTargetSourceImmediate {
+                target: 0.into(),
+                source: 0.into(),
+                immediate: 1,
+            },
+            Opcode::JumpImmediate {
+                immediate: instruction_address(0),
+            },
+        ]);
+        assert_eq!(machine.run_cycles(5), ExecutionResult::Normal);
+        assert_eq!(machine.processor.registers[0.into()], 3);
+        assert!(!machine.is_halted());
+    }
+
+    #[test]
+    fn run_cycles_stops_at_cycle_limit() {
+        let mut machine = create_machine_with_opcodes(&[Opcode::JumpImmediate {
+            immediate: instruction_address(0),
+        }]);
+        machine.set_max_cycles(Some(100));
+        assert_eq!(machine.run_cycles(60), ExecutionResult::Normal);
+        assert_eq!(machine.halt_reason(), None);
+        assert_eq!(machine.run_cycles(1000), ExecutionResult::Error);
+        assert_eq!(machine.halt_reason(), Some(HaltReason::CycleLimit));
+        assert!(machine.is_faulted());
+        assert_eq!(machine.processor.get_cycle_count(), 100);
+        assert_eq!(machine.run_cycles(1000), ExecutionResult::Error);
+        assert_eq!(machine.processor.get_cycle_count(), 100);
+    }
+
+    #[test]
+    fn set_display_mode() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::SetDisplayMode { immediate: 1 },
+            Opcode::SetDisplayMode { immediate: 0 },
+            Opcode::SetDisplayMode { immediate: 2 },
+        ]);
+        machine.execute_next_instruction();
+        assert_eq!(machine.periphery.display.mode(), DisplayMode::Indexed);
+        machine.execute_next_instruction();
+        assert_eq!(machine.periphery.display.mode(), DisplayMode::Rgba);
+        assert!(matches!(
+            machine.processor.execute_next_instruction(
+                &mut machine.memory,
+                &mut machine.periphery,
+                &mut machine.instruction_cache
+            ),
+            ExecutionResult::Error
+        ));
+    }
+
+    #[test]
+    fn get_key_event_drains_queue_in_order() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::GetKeyEvent { target: 0.into() },
+            Opcode::GetKeyEvent { target: 0.into() },
+            Opcode::GetKeyEvent { target: 0.into() },
+        ]);
+        let keyboard = &mut machine.periphery.keyboard;
+        keyboard.push_event(KeyEvent {
+            keycode: 65,
+            state: KeyState::Down,
+        });
+        keyboard.push_event(KeyEvent {
+            keycode: 65,
+            state: KeyState::Up,
+        });
+        machine.execute_next_instruction();
+        assert_eq!(
+            machine.processor.registers[0.into()],
+            65 | KeyEvent::PRESSED_FLAG
+        );
+        assert!(!machine.processor.get_flag(Flag::Zero));
+        machine.execute_next_instruction();
+        assert_eq!(machine.processor.registers[0.into()], 65);
+        assert!(!machine.processor.get_flag(Flag::Zero));
+        machine.execute_next_instruction();
+        assert_eq!(machine.processor.registers[0.into()], 0);
+        assert!(machine.processor.get_flag(Flag::Zero));
+    }
+
+    #[test]
+    fn get_char_input_returns_typed_characters() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::GetCharInput { target: 0.into() },
+            Opcode::GetCharInput { target: 0.into() },
+            Opcode::GetCharInput { target: 0.into() },
+        ]);
+        machine.periphery.keyboard.push_char('h');
+        machine.periphery.keyboard.push_char('i');
+        machine.execute_next_instruction();
+        assert_eq!(machine.processor.registers[0.into()], 'h' as Word);
+        assert!(!machine.processor.get_flag(Flag::Zero));
+        machine.execute_next_instruction();
+        assert_eq!(machine.processor.registers[0.into()], 'i' as Word);
+        machine.execute_next_instruction();
+        assert_eq!(machine.processor.registers[0.into()], 0);
+        assert!(machine.processor.get_flag(Flag::Zero));
+    }
+
+    #[test]
+    fn get_mouse_position_and_buttons() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::GetMousePosition {
+                x_target: 0.into(),
+                y_target: 1.into(),
+            },
+            Opcode::GetMouseButtons { target: 2.into() },
+            Opcode::GetMouseButtons { target: 2.into() },
+            Opcode::GetMousePosition {
+                x_target: 0.into(),
+                y_target: 1.into(),
+            },
+        ]);
+        let pressed = mouse::BUTTON_LEFT | mouse::BUTTON_MIDDLE;
+        let mut mock_mouse = MockMouse::new([
+            Mouse::new(12, 34),
+            Mouse::new(12, 34),
+            Mouse::with_buttons(56, 78, pressed),
+            Mouse::with_buttons(56, 78, pressed),
+        ]);
+        let mut step = |machine: &mut Machine<MockDisplay>| {
+            assert!(mock_mouse.step(&mut machine.periphery.mouse));
+            machine.execute_next_instruction();
+        };
+
+        step(&mut machine);
+        assert_eq!(machine.processor.registers[0.into()], 12);
+        assert_eq!(machine.processor.registers[1.into()], 34);
+        step(&mut machine);
+        assert_eq!(machine.processor.registers[2.into()], 0);
+        assert!(machine.processor.get_flag(Flag::Zero));
+        step(&mut machine);
+        assert_eq!(machine.processor.registers[2.into()], pressed);
+        assert!(!machine.processor.get_flag(Flag::Zero));
+        step(&mut machine);
+        assert_eq!(machine.processor.registers[0.into()], 56);
+        assert_eq!(machine.processor.registers[1.into()], 78);
+    }
+
+    #[test]
+    fn terminal_write_byte() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::MoveRegisterImmediate {
+                register: 0.into(),
+                immediate: b'A' as Word,
+            },
+            Opcode::TerminalWriteByte { source: 0.into() },
+            Opcode::TerminalWriteByte { source: 0.into() },
+        ]);
+        for _ in 0..3 {
+            machine.execute_next_instruction();
+        }
+        assert_eq!(
+            machine
+                .memory
+                .read_halfword(address_constants::TERMINAL_BUFFER_START),
+            u16::from_be_bytes([b'A', b'A'])
+        );
+        assert_eq!(
+            machine
+                .memory
+                .read_data(address_constants::TERMINAL_CURSOR_POINTER),
+            address_constants::TERMINAL_BUFFER_START + 2
+        );
+    }
+
+    #[test]
+    fn writing_cursor_pointer_past_last_row_scrolls_the_terminal() {
+        let row_start = |row: usize| {
+            address_constants::TERMINAL_BUFFER_START + (row * terminal::WIDTH) as Address
+        };
+        let cursor_pointer = row_start(30);
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::MoveRegisterImmediate {
+                register: 0.into(),
+                immediate: cursor_pointer,
+            },
+            Opcode::MoveAddressRegister {
+                register: 0.into(),
+                target_address: address_constants::TERMINAL_CURSOR_POINTER,
+            },
+        ]);
+        // the text is written directly into the terminal buffer
+        machine.memory.write_byte(row_start(6), b'A');
+        assert_eq!(machine.run_cycles(3), ExecutionResult::Normal);
+        assert_eq!(
+            machine
+                .memory
+                .read_data(address_constants::TERMINAL_CURSOR_POINTER),
+            row_start(terminal::HEIGHT - 1)
+        );
+        assert_eq!(machine.memory.read_byte(row_start(0)), b'A');
+        assert_eq!(machine.memory.read_byte(row_start(6)), 0);
+    }
+
+    #[test]
+    fn swaps_within_a_frame_are_deferred_and_coalesced() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::BeginFrame {},
+            Opcode::SwapFramebuffers {},
+            Opcode::SwapFramebuffers {},
+            Opcode::SwapFramebuffers {},
+            Opcode::InvisibleFramebufferAddress { target: 0.into() },
+            Opcode::EndFrame {},
+            Opcode::InvisibleFramebufferAddress { target: 0.into() },
+        ]);
+        for _ in 0..5 {
+            machine.execute_next_instruction();
+        }
+        assert!(machine.periphery.display.is_frame_in_progress());
+        assert_eq!(
+            machine.processor.registers[0.into()],
+            Resolution::DEFAULT.second_framebuffer_start()
+        );
+        machine.execute_next_instruction();
+        machine.execute_next_instruction();
+        assert!(!machine.periphery.display.is_frame_in_progress());
+        assert_eq!(
+            machine.processor.registers[0.into()],
+            address_constants::FIRST_FRAMEBUFFER_START
+        );
+    }
+
+    #[test]
+    fn random_numbers_are_reproducible_with_the_same_seed() {
+        let opcodes = &[
+            Opcode::Random { target: 0.into() },
+            Opcode::Random { target: 1.into() },
+            Opcode::Random { target: 2.into() },
+        ];
+        let mut machines = [
+            create_machine_with_opcodes(opcodes),
+            create_machine_with_opcodes(opcodes),
+        ];
+        for machine in &mut machines {
+            machine.processor.seed_rng(42);
+            for _ in 0..opcodes.len() {
+                machine.execute_next_instruction();
+            }
+        }
+        let [first, second] = machines;
+        for register in 0..3 {
+            assert_eq!(
+                first.processor.registers[register.into()],
+                second.processor.registers[register.into()]
+            );
+        }
+        assert_ne!(
+            first.processor.registers[0.into()],
+            first.processor.registers[1.into()]
+        );
+    }
+
+    #[test]
+    fn random_numbers_differ_with_default_seeds() {
+        let mut first = create_machine_with_opcodes(&[Opcode::Random { target: 0.into() }]);
+        let mut second = create_machine_with_opcodes(&[Opcode::Random { target: 0.into() }]);
+        first.execute_next_instruction();
+        second.execute_next_instruction();
+        assert_ne!(
+            first.processor.registers[0.into()],
+            second.processor.registers[0.into()]
+        );
+    }
+
+    #[test]
+    fn applied_framebuffer_swaps_are_counted() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::SwapFramebuffers {},
+            Opcode::BeginFrame {},
+            Opcode::SwapFramebuffers {},
+            Opcode::SwapFramebuffers {},
+            Opcode::SwapFramebuffers {},
+            Opcode::EndFrame {},
+        ]);
+        for _ in 0..6 {
+            machine.execute_next_instruction();
+        }
+        assert_eq!(machine.periphery.display.num_swaps(), 2);
+    }
+
+    #[test]
+    fn distinct_instructions_are_counted() {
+        let mut machine = create_machine_with_opcodes(&[
+            Opcode::AddTargetSourceImmediate {
+                target: 0.into(),
+                source: 0.into(),
+                immediate: 1,
+            },
+            Opcode::JumpImmediate {
+                immediate: instruction_address(0),
+            },
+        ]);
+        assert_eq!(machine.num_distinct_instructions(), 0);
+        for _ in 0..20 {
+            machine.execute_next_instruction();
+        }
+        assert_eq!(machine.num_distinct_instructions(), 2);
+        // regenerating the cache doesn't count the instructions again
+        machine.generate_instruction_cache();
+        for _ in 0..20 {
+            machine.execute_next_instruction();
+        }
+        assert_eq!(machine.num_distinct_instructions(), 2);
+        // an instruction overwritten by self-modifying code is a new one
+        machine.memory.write_opcode(
+            address_constants::ENTRY_POINT,
+            Opcode::AddTargetSourceImmediate {
+                target: 0.into(),
+                source: 0.into(),
+                immediate: 2,
+            },
+        );
+        for _ in 0..20 {
+            machine.execute_next_instruction();
+        }
+        assert_eq!(machine.num_distinct_instructions(), 3);
+    }
+
+    create_test!(
+        is_headless_with_mock_display,
+        opcodes = &[Opcode::IsHeadless { target: 0.into() }],
+        registers_post = [(0.into(), 1)],
+    );
+
+    create_test!(
+        get_program_counter_offset,
+        opcodes = &[
+            Opcode::NoOp {},
+            Opcode::NoOp {},
+            Opcode::GetProgramCounterOffset { target: 0.into() },
+        ],
+        flags_pre = [true => Carry],
+        registers_post = [(0.into(), 2 * Instruction::SIZE as Word)],
+        flags_post = [(Carry, false)],
+    );
+
+    #[test]
+    fn get_program_counter_offset_below_entry_point_sets_carry() {
+        let mut machine = Machine::new(create_mock_periphery());
+        let address = address_constants::ENTRY_POINT - 2 * Instruction::SIZE as Address;
+        machine.memory.write_opcode(
+            address,
+            Opcode::GetProgramCounterOffset { target: 0.into() },
+        );
+        machine.generate_instruction_cache();
+        machine.set_entry_point(address).unwrap();
+        assert_eq!(machine.run_cycles(1), ExecutionResult::Normal);
+        assert_eq!(
+            machine.processor.registers[0.into()],
+            (2 * Instruction::SIZE as Word).wrapping_neg()
+        );
+        assert!(machine.processor.get_flag(Flag::Carry));
+    }
+
+    #[test]
+    fn cycle_count_advances_by_declared_number_of_cycles() {
+        let multiply = Opcode::MultiplyHighLowLhsRhs {
+            high: 0.into(),
+            low: 1.into(),
+            lhs: 2.into(),
+            rhs: 3.into(),
+        };
+        let mut machine = create_machine_with_opcodes(&[Opcode::NoOp {}, multiply]);
+        machine.execute_next_instruction();
+        assert_eq!(machine.processor.get_cycle_count(), 1);
+        machine.execute_next_instruction();
+        assert_eq!(multiply.get_num_cycles(), 4);
+        assert_eq!(
+            machine.processor.get_cycle_count(),
+            1 + multiply.get_num_cycles() as u64
+        );
+    }
+
     create_test!(
         poll_cycle_count,
         opcodes = &[Opcode::PollCycleCountHighLow {
@@ -1,125 +1,70 @@
-mod address_constants;
-mod cursor;
-#[cfg(feature = "debugger")]
-mod debugger;
-mod display;
-mod dumper;
-mod keyboard;
-mod machine;
-mod memory;
-mod opcodes;
-mod periphery;
-mod processor;
-mod terminal;
-mod timer;
-
 use std::{
     cell::RefCell,
     collections::HashMap,
-    error::Error,
     fmt::Debug,
-    io::{self, Read},
+    io::{self, Read, Write},
     path::{Path, PathBuf},
     rc::Rc,
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use address_constants::ENTRY_POINT;
+use backseat_safe_system_2k::{
+    address_constants::{self, ENTRY_POINT},
+    assembler,
+    cursor::{Cursor, CursorMode},
+    disassembler,
+    display::{self, Display, DisplayImplementation, DisplayMode, Resolution},
+    dumper::{self, MemoryDumpFormat, RegisterDumpFormat},
+    host_io::{self, HostIo},
+    keyboard::{KeyEvent, KeyState, Keyboard},
+    machine::{AssertionFailure, HaltReason},
+    mouse::{self, Mouse},
+    opcodes::OpcodeDescription,
+    periphery::PeripheryImplementation,
+    processor::{Flag, NUM_REGISTERS},
+    replay::{InputLog, Recording},
+    rom, samples,
+    snapshot::MachineSnapshot,
+    stepper::{self, StepperExit},
+    terminal::{self, TerminalWriter},
+    timer::Timer,
+    tracer::Tracer,
+    validator, Address, EmulatorError, Instruction, Machine, Memory, Opcode, Processor, Register,
+    Size, Word, SCREEN_SIZE,
+};
 use clap::StructOpt;
-use cursor::Cursor;
-use display::{Display, DisplayImplementation};
-use keyboard::{KeyState, Keyboard};
-use machine::Machine;
-use memory::Memory;
 use num_format::{CustomFormat, ToFormattedString};
-use opcodes::Opcode;
-use periphery::PeripheryImplementation;
-use processor::Processor;
-use serde::{Deserialize, Serialize};
-use timer::Timer;
+use serde::Serialize;
 
+#[cfg(feature = "debugger")]
+use backseat_safe_system_2k::debugger;
 #[cfg(feature = "graphics")]
 use raylib::prelude::*;
 
 #[cfg(not(feature = "graphics"))]
 use display::MockDisplay;
 
-use crate::{
-    cursor::CursorMode,
-    opcodes::OpcodeDescription,
-    processor::{Flag, NUM_REGISTERS},
-};
-
-pub struct Size2D {
-    width: i32,
-    height: i32,
-}
-
-pub const SCREEN_SIZE: Size2D = Size2D {
-    width: 1280,
-    height: 720,
-};
-
-pub const OPCODE_LENGTH: usize = 16;
-
-pub const fn static_assert(condition: bool) {
-    assert!(condition);
-}
-
 pub const TARGET_FPS: u64 = 60;
 
-pub type Instruction = u64;
-pub type Word = u32;
-pub type Halfword = u16;
-pub type Byte = u8;
-pub type Address = u32;
+/// Exit code used when the execution has been stopped because it exceeded the maximum runtime.
+pub const MAX_RUNTIME_EXCEEDED_EXIT_CODE: i32 = 3;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct Register(pub u8);
+/// Exit code used when the execution has been stopped because of an error (e.g. an invalid
+/// instruction).
+pub const EXECUTION_ERROR_EXIT_CODE: i32 = 4;
 
-impl From<u8> for Register {
-    fn from(value: u8) -> Self {
-        Self(value)
-    }
-}
+/// Exit code used when the execution has been stopped because an `Assert*` instruction or a
+/// `Checkpoint` failed.
+pub const ASSERTION_FAILED_EXIT_CODE: i32 = 5;
 
-const _: () = static_assert(Halfword::SIZE * 2 == Word::SIZE);
-
-pub trait AsHalfwords {
-    fn as_halfwords(&self) -> (Halfword, Halfword);
-}
-
-impl AsHalfwords for Word {
-    fn as_halfwords(&self) -> (Halfword, Halfword) {
-        (
-            (self >> (8 * Halfword::SIZE)) as Halfword,
-            *self as Halfword,
-        )
-    }
-}
-
-pub trait AsWords {
-    fn as_words(&self) -> (Word, Word);
-}
-
-impl AsWords for Instruction {
-    fn as_words(&self) -> (Word, Word) {
-        ((self >> (Word::SIZE * 8)) as Word, *self as Word)
-    }
-}
-
-pub trait Size: Sized {
-    const SIZE: usize = std::mem::size_of::<Self>();
-}
-
-impl Size for Instruction {}
-impl Size for Word {}
-impl Size for Halfword {}
-impl Size for Byte {}
+/// Exit code used when the execution has been stopped because it exceeded the maximum number of
+/// cycles.
+pub const CYCLE_LIMIT_EXCEEDED_EXIT_CODE: i32 = 6;
 
 const DEFAULT_FONT_PATH: &str = "./resources/CozetteVector.ttf";
 
 #[derive(clap::Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)] // only ever constructed once
 enum Action {
     /// Execute a ROM file (typically *.backseat)
     Run {
@@ -130,53 +75,271 @@ enum Action {
         /// instruction.
         #[clap(short, long, action)]
         exit_on_halt: bool,
+
+        /// Stop execution after the given number of milliseconds of wall-clock time.
+        #[clap(long)]
+        max_runtime_ms: Option<u64>,
+
+        /// Stop execution once the given number of cycles has been executed (unlimited if
+        /// omitted).
+        #[clap(long)]
+        max_cycles: Option<u64>,
+
+        /// Print execution statistics (cycles, runtime, clock frequency etc.) to stderr when the
+        /// execution ends.
+        #[clap(long, action)]
+        stats: bool,
+
+        /// Seed for the pseudo-random number generator (random if omitted).
+        #[clap(long)]
+        seed: Option<Word>,
+
+        /// Size of the memory in bytes (defaults to 16 MiB).
+        #[clap(long)]
+        memory_size: Option<usize>,
+
+        /// Save the visible framebuffer as PNG image to the given path when the 'halt and catch
+        /// fire'-instruction is executed.
+        #[clap(long)]
+        screenshot_on_halt: Option<PathBuf>,
+
+        /// Display resolution in the form WIDTHxHEIGHT (defaults to 480x360).
+        #[clap(long)]
+        resolution: Option<Resolution>,
+
+        /// Write the complete machine state to the given path when the execution stops.
+        #[clap(long)]
+        save_state: Option<PathBuf>,
+
+        /// Restore the machine state from the given save state after loading the ROM.
+        #[clap(long)]
+        load_state: Option<PathBuf>,
+
+        /// Record all timer and keyboard queries to the given file (written when the execution
+        /// stops), so that the run can be reproduced with --replay.
+        #[clap(long, conflicts_with = "replay")]
+        record: Option<PathBuf>,
+
+        /// Replay the timer and keyboard queries from a file written with --record instead of
+        /// using the live values.
+        #[clap(long)]
+        replay: Option<PathBuf>,
+
+        /// Log every executed instruction together with the registers it modified to stderr.
+        #[clap(long, action)]
+        trace: bool,
+
+        /// Like --trace, but write the log to the given file instead of stderr.
+        #[clap(long)]
+        trace_file: Option<PathBuf>,
+
+        /// Count the executed instructions per opcode and print a summary to stdout when the
+        /// execution stops.
+        #[clap(long, action)]
+        profile: bool,
+
+        /// Like --profile, but write the summary to the given file instead of stdout.
+        #[clap(long)]
+        profile_file: Option<PathBuf>,
+
+        /// Keep executing after a failed `Assert*` instruction or `Checkpoint` instead of
+        /// halting. The exit code still reports the failure.
+        #[clap(long, action)]
+        continue_after_failed_assertion: bool,
+
+        /// Limit the execution speed to the given number of cycles per second (0 means
+        /// unthrottled, which is the default).
+        #[clap(long)]
+        clock_hz: Option<u64>,
+
+        /// Execute the ROM step by step, controlled via stdin (enter = step, r = print registers,
+        /// c = continue without stepping, q = quit).
+        #[clap(long, action)]
+        step: bool,
+
+        /// Address the ROM is loaded to, in decimal or hexadecimal (`0x...`) notation (defaults
+        /// to the entry point).
+        #[clap(long, value_parser = parse_address)]
+        load_address: Option<Address>,
+
+        /// Zero-pad ROMs whose size isn't a multiple of the word size instead of rejecting them.
+        #[clap(long, action)]
+        pad: bool,
+
+        /// Make `SwapFramebuffers` wait for the next rendered frame (60 per second), so that ROMs
+        /// are paced by the display.
+        #[clap(long, action)]
+        vsync: bool,
+
+        /// Initial value of the instruction pointer, in decimal or hexadecimal (`0x...`)
+        /// notation (defaults to the entry point).
+        #[clap(long, value_parser = parse_address)]
+        entry: Option<Address>,
+
+        /// File format of the `DumpRegisters` instruction: `binary` (the raw register values,
+        /// default) or `json` (with named special registers and decoded flags).
+        #[clap(long)]
+        register_dump_format: Option<RegisterDumpFormat>,
+
+        /// File format of the `DumpMemory` instruction: `raw` (the whole memory, default) or
+        /// `compressed` (run-length encoded, loadable with --load-memory).
+        #[clap(long)]
+        memory_dump_format: Option<MemoryDumpFormat>,
+
+        /// Replace the memory contents after loading the ROM with a dump written by the
+        /// `DumpMemory` instruction (in any format).
+        #[clap(long)]
+        load_memory: Option<PathBuf>,
+
+        /// Directory the `DumpRegisters` and `DumpMemory` instructions write their files to
+        /// (created if needed, defaults to ./dumps).
+        #[clap(long)]
+        dump_dir: Option<PathBuf>,
     },
     /// Emit a sample program as machine code
     Emit {
         /// Output path of the machine code to be written
         path: Option<PathBuf>,
+
+        /// The sample program to be emitted.
+        #[clap(
+            long,
+            default_value = samples::DEFAULT,
+            value_parser = clap::builder::PossibleValuesParser::new(samples::NAMES)
+        )]
+        sample: String,
+    },
+    /// Assemble a text file with one instruction per line into machine code
+    Assemble {
+        /// The path to the assembly source file
+        input: PathBuf,
+        /// Output path of the machine code to be written
+        output: Option<PathBuf>,
+    },
+    /// Print a disassembly listing of a ROM file (typically *.backseat)
+    Disassemble {
+        /// The path to the ROM file to be disassembled
+        path: Option<PathBuf>,
+    },
+    /// Check that every instruction of a ROM file (typically *.backseat) can be decoded, only
+    /// references addresses inside the memory and only jumps to instructions of the ROM
+    Validate {
+        /// The path to the ROM file to be validated
+        path: Option<PathBuf>,
+
+        /// Size of the memory in bytes the ROM is checked against (defaults to 16 MiB).
+        #[clap(long)]
+        memory_size: Option<usize>,
+
+        /// Address the ROM is loaded to, in decimal or hexadecimal (`0x...`) notation (defaults
+        /// to the entry point).
+        #[clap(long, value_parser = parse_address)]
+        load_address: Option<Address>,
     },
     /// Write the available opcodes and other information such as constants in JSON format
     Json {
         /// Output path of the JSON file to be written
         path: Option<PathBuf>,
+
+        /// Display resolution in the form WIDTHxHEIGHT the emitted constants are based on
+        /// (defaults to 480x360).
+        #[clap(long)]
+        resolution: Option<Resolution>,
     },
     #[cfg(feature = "debugger")]
     /// Debugs a ROM file (typically *.backseat)
     Debug {
-        /// The path to the ROM file to be debugged
+        /// The path to the ROM file to be debugged (symbols are loaded from `<path>.sym` if it
+        /// exists)
         path: Option<PathBuf>,
         /// The path to the font file
         #[clap(long)]
         font_path: Option<String>,
+        /// The host name or IP address the debugger listens on.
+        #[clap(long, default_value = debugger::DEFAULT_HOST)]
+        debug_host: String,
+        /// The TCP port the debugger listens on (0 lets the OS choose a free port).
+        #[clap(long, default_value_t = debugger::DEFAULT_PORT)]
+        debug_port: u16,
     },
 }
 
 struct RunOptions {
     exit_on_halt: bool,
+    max_runtime_ms: Option<u64>,
+    max_cycles: Option<u64>,
+    stats: bool,
+    seed: Option<Word>,
+    memory_size: Option<usize>,
+    screenshot_on_halt: Option<PathBuf>,
+    resolution: Resolution,
+    save_state: Option<PathBuf>,
+    load_state: Option<PathBuf>,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    trace: bool,
+    trace_file: Option<PathBuf>,
+    profile: bool,
+    profile_file: Option<PathBuf>,
+    continue_after_failed_assertion: bool,
+    clock_hz: u64,
+    step: bool,
+    load_address: Address,
+    pad: bool,
+    vsync: bool,
+    entry_point: Option<Address>,
+    register_dump_format: RegisterDumpFormat,
+    memory_dump_format: MemoryDumpFormat,
+    load_memory: Option<PathBuf>,
+    dump_dir: PathBuf,
     #[cfg(feature = "debugger")]
     debug: bool,
+    #[cfg(feature = "debugger")]
+    debug_host: String,
+    #[cfg(feature = "debugger")]
+    debug_port: u16,
     font_path: String,
 }
 
-impl RunOptions {
-    fn new(exit_on_halt: bool) -> Self {
+impl Default for RunOptions {
+    fn default() -> Self {
         Self {
-            exit_on_halt,
+            exit_on_halt: false,
+            max_runtime_ms: None,
+            max_cycles: None,
+            stats: false,
+            seed: None,
+            memory_size: None,
+            screenshot_on_halt: None,
+            resolution: Resolution::DEFAULT,
+            save_state: None,
+            load_state: None,
+            record: None,
+            replay: None,
+            trace: false,
+            trace_file: None,
+            profile: false,
+            profile_file: None,
+            continue_after_failed_assertion: false,
+            clock_hz: 0,
+            step: false,
+            load_address: ENTRY_POINT,
+            pad: false,
+            vsync: false,
+            entry_point: None,
+            register_dump_format: RegisterDumpFormat::default(),
+            memory_dump_format: MemoryDumpFormat::default(),
+            load_memory: None,
+            dump_dir: dumper::DEFAULT_DIRECTORY.into(),
             #[cfg(feature = "debugger")]
             debug: false,
+            #[cfg(feature = "debugger")]
+            debug_host: debugger::DEFAULT_HOST.into(),
+            #[cfg(feature = "debugger")]
+            debug_port: debugger::DEFAULT_PORT,
             font_path: DEFAULT_FONT_PATH.into(),
         }
     }
-
-    #[cfg(feature = "debugger")]
-    fn new_debug(font_path: Option<String>) -> Self {
-        Self {
-            exit_on_halt: true,
-            debug: true,
-            font_path: font_path.unwrap_or(DEFAULT_FONT_PATH.into()),
-        }
-    }
 }
 
 /// The reference implementation of the backseat-safe-system-2k
@@ -186,23 +349,115 @@ struct Args {
     action: Action,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> Result<(), EmulatorError> {
     let args = Args::parse();
     match args.action {
-        Action::Run { path, exit_on_halt } => run(path.as_deref(), RunOptions::new(exit_on_halt)),
-        Action::Emit { path } => emit(path.as_deref()),
-        Action::Json { path } => print_json(path.as_deref()),
+        Action::Run {
+            path,
+            exit_on_halt,
+            max_runtime_ms,
+            max_cycles,
+            stats,
+            seed,
+            memory_size,
+            screenshot_on_halt,
+            resolution,
+            save_state,
+            load_state,
+            record,
+            replay,
+            trace,
+            trace_file,
+            profile,
+            profile_file,
+            continue_after_failed_assertion,
+            clock_hz,
+            step,
+            load_address,
+            pad,
+            vsync,
+            entry,
+            register_dump_format,
+            memory_dump_format,
+            load_memory,
+            dump_dir,
+        } => run(
+            path.as_deref(),
+            RunOptions {
+                exit_on_halt,
+                max_runtime_ms,
+                max_cycles,
+                stats,
+                seed,
+                memory_size,
+                screenshot_on_halt,
+                resolution: resolution.unwrap_or(Resolution::DEFAULT),
+                save_state,
+                load_state,
+                record,
+                replay,
+                trace,
+                trace_file,
+                profile,
+                profile_file,
+                continue_after_failed_assertion,
+                clock_hz: clock_hz.unwrap_or(0),
+                step,
+                load_address: load_address.unwrap_or(ENTRY_POINT),
+                pad,
+                vsync,
+                entry_point: entry,
+                register_dump_format: register_dump_format.unwrap_or_default(),
+                memory_dump_format: memory_dump_format.unwrap_or_default(),
+                load_memory,
+                dump_dir: dump_dir.unwrap_or_else(|| dumper::DEFAULT_DIRECTORY.into()),
+                ..Default::default()
+            },
+        ),
+        Action::Emit { path, sample } => emit(path.as_deref(), &sample),
+        Action::Assemble { input, output } => assemble(&input, output.as_deref()),
+        Action::Disassemble { path } => print_disassembly(path.as_deref()),
+        Action::Validate {
+            path,
+            memory_size,
+            load_address,
+        } => validate(
+            path.as_deref(),
+            memory_size,
+            load_address.unwrap_or(ENTRY_POINT),
+        ),
+        Action::Json { path, resolution } => {
+            print_json(path.as_deref(), resolution.unwrap_or(Resolution::DEFAULT))
+        }
         #[cfg(feature = "debugger")]
-        Action::Debug { path, font_path } => run(path.as_deref(), RunOptions::new_debug(font_path)),
+        Action::Debug {
+            path,
+            font_path,
+            debug_host,
+            debug_port,
+        } => run(
+            path.as_deref(),
+            RunOptions {
+                exit_on_halt: true,
+                debug: true,
+                debug_host,
+                debug_port,
+                font_path: font_path.unwrap_or(DEFAULT_FONT_PATH.into()),
+                ..Default::default()
+            },
+        ),
     }
 }
 
-fn load_from_stdin(machine: &mut Machine<impl display::Display>) -> Result<(), Box<dyn Error>> {
+fn load_from_stdin(
+    machine: &mut Machine<impl display::Display + 'static>,
+    load_address: Address,
+) -> Result<(), EmulatorError> {
     let instructions = read_machine_code_from_stdin()?;
-    write_buffer(&instructions, machine)
+    machine.load_rom_at(&instructions, load_address)
 }
 
-fn read_machine_code_from_stdin() -> Result<Vec<u8>, Box<dyn Error>> {
+fn read_machine_code_from_stdin() -> Result<Vec<u8>, EmulatorError> {
     let mut instructions = Vec::new();
     std::io::stdin().read_to_end(&mut instructions)?;
     Ok(instructions)
@@ -215,16 +470,19 @@ enum Constant {
     UnsignedInteger(u64),
 }
 
-fn print_json(output_filename: Option<&Path>) -> Result<(), Box<dyn Error>> {
+fn print_json(output_filename: Option<&Path>, resolution: Resolution) -> Result<(), EmulatorError> {
     #[derive(Serialize)]
     struct JsonInfo {
         opcodes: HashMap<&'static str, OpcodeDescription>,
+        /// Mnemonics of the `opcodes` by their numeric opcode.
+        by_code: HashMap<u16, &'static str>,
         constants: HashMap<&'static str, Constant>,
         flags: HashMap<&'static str, usize>,
     }
 
     let json_info = JsonInfo {
         opcodes: Opcode::as_hashmap(),
+        by_code: Opcode::mnemonics_by_code(),
         constants: HashMap::from([
             (
                 "ENTRY_POINT",
@@ -257,11 +515,11 @@ fn print_json(output_filename: Option<&Path>) -> Result<(), Box<dyn Error>> {
             ),
             (
                 "SECOND_FRAMEBUFFER_START",
-                Constant::Address(address_constants::SECOND_FRAMEBUFFER_START),
+                Constant::Address(resolution.second_framebuffer_start()),
             ),
             (
                 "FRAMEBUFFER_SIZE",
-                Constant::UnsignedInteger(address_constants::FRAMEBUFFER_SIZE as _),
+                Constant::UnsignedInteger(resolution.framebuffer_size() as _),
             ),
             (
                 "TERMINAL_WIDTH",
@@ -303,13 +561,81 @@ fn print_json(output_filename: Option<&Path>) -> Result<(), Box<dyn Error>> {
                 "TERMINAL_CURSOR_MODE_INVISIBLE",
                 Constant::UnsignedInteger(CursorMode::Invisible as _),
             ),
+            (
+                "KEY_EVENT_PRESSED_FLAG",
+                Constant::UnsignedInteger(KeyEvent::PRESSED_FLAG as _),
+            ),
+            (
+                "KEY_EVENT_KEYCODE_MASK",
+                Constant::UnsignedInteger(KeyEvent::KEYCODE_MASK as _),
+            ),
+            (
+                "MOUSE_BUTTON_LEFT",
+                Constant::UnsignedInteger(mouse::BUTTON_LEFT as _),
+            ),
+            (
+                "MOUSE_BUTTON_RIGHT",
+                Constant::UnsignedInteger(mouse::BUTTON_RIGHT as _),
+            ),
+            (
+                "MOUSE_BUTTON_MIDDLE",
+                Constant::UnsignedInteger(mouse::BUTTON_MIDDLE as _),
+            ),
+            (
+                "TERMINAL_ATTRIBUTES_START",
+                Constant::Address(address_constants::TERMINAL_ATTRIBUTES_START),
+            ),
+            (
+                "TERMINAL_ATTRIBUTES_SIZE",
+                Constant::UnsignedInteger(address_constants::TERMINAL_ATTRIBUTES_SIZE as _),
+            ),
+            (
+                "SYSTEM_CALL_WRITE_STRING",
+                Constant::UnsignedInteger(host_io::SYSTEM_CALL_WRITE_STRING as _),
+            ),
+            (
+                "SYSTEM_CALL_READ_LINE",
+                Constant::UnsignedInteger(host_io::SYSTEM_CALL_READ_LINE as _),
+            ),
+            (
+                "INTERRUPT_VECTOR_TABLE_START",
+                Constant::Address(address_constants::INTERRUPT_VECTOR_TABLE_START),
+            ),
+            (
+                "NUM_INTERRUPTS",
+                Constant::UnsignedInteger(address_constants::NUM_INTERRUPTS as _),
+            ),
+            (
+                "TIMER_INTERRUPT",
+                Constant::UnsignedInteger(address_constants::TIMER_INTERRUPT as _),
+            ),
+            (
+                "TIMER_INTERRUPT_INTERVAL",
+                Constant::Address(address_constants::TIMER_INTERRUPT_INTERVAL),
+            ),
+            (
+                "PALETTE_START",
+                Constant::Address(address_constants::PALETTE_START),
+            ),
+            (
+                "PALETTE_SIZE",
+                Constant::UnsignedInteger(address_constants::PALETTE_SIZE as _),
+            ),
+            (
+                "DISPLAY_MODE_RGBA",
+                Constant::UnsignedInteger(DisplayMode::Rgba as _),
+            ),
+            (
+                "DISPLAY_MODE_INDEXED",
+                Constant::UnsignedInteger(DisplayMode::Indexed as _),
+            ),
             (
                 "DISPLAY_WIDTH",
-                Constant::UnsignedInteger(display::WIDTH as _),
+                Constant::UnsignedInteger(resolution.width as _),
             ),
             (
                 "DISPLAY_HEIGHT",
-                Constant::UnsignedInteger(display::HEIGHT as _),
+                Constant::UnsignedInteger(resolution.height as _),
             ),
         ]),
         flags: Flag::as_hashmap(),
@@ -323,72 +649,70 @@ fn print_json(output_filename: Option<&Path>) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn emit(output_filename: Option<&Path>) -> Result<(), Box<dyn Error>> {
-    let opcodes = &[
-        Opcode::MoveRegisterImmediate {
-            // starting color
-            register: 0.into(),
-            immediate: 0xFF,
-        },
-        Opcode::MoveRegisterImmediate {
-            // num iterations
-            register: 42.into(),
-            immediate: (display::WIDTH * display::HEIGHT) as Word,
-        },
-        // outer loop start
-        Opcode::MoveRegisterImmediate {
-            // current loop counter
-            register: 1.into(),
-            immediate: 0,
-        },
-        Opcode::AddTargetSourceImmediate {
-            // current color
-            target: 0.into(),
-            source: 0.into(),
-            immediate: 0x200,
-        },
-        Opcode::MoveRegisterImmediate {
-            register: 2.into(),
-            immediate: address_constants::FIRST_FRAMEBUFFER_START,
-        },
-        // inner loop start
-        Opcode::MovePointerSource {
-            pointer: 2.into(),
-            source: 0.into(),
-        },
-        Opcode::AddTargetSourceImmediate {
-            target: 2.into(),
-            source: 2.into(),
-            immediate: Word::SIZE as Word,
-        },
-        Opcode::AddTargetSourceImmediate {
-            target: 1.into(),
-            source: 1.into(),
-            immediate: 1,
-        },
-        Opcode::CompareTargetLhsRhs {
-            target: 10.into(),
-            lhs: 1.into(),
-            rhs: 42.into(),
-        },
-        Opcode::JumpImmediateIfLessThan {
-            comparison: 10.into(),
-            immediate: address_constants::ENTRY_POINT + 5 * Instruction::SIZE as Word,
-        },
-        Opcode::JumpImmediate {
-            immediate: address_constants::ENTRY_POINT + 2 * Instruction::SIZE as Word,
-        },
-    ];
-    let machine_code = opcodes_to_machine_code(opcodes);
+fn emit(output_filename: Option<&Path>, sample: &str) -> Result<(), EmulatorError> {
+    let opcodes =
+        samples::by_name(sample).ok_or_else(|| EmulatorError::UnknownSample(sample.into()))?;
+    let rom = rom::with_header(&opcodes_to_machine_code(&opcodes));
     match output_filename {
-        Some(filename) => save_opcodes_as_machine_code(opcodes, filename)?,
-        None => io::Write::write_all(&mut std::io::stdout(), &machine_code)?,
+        Some(filename) => std::fs::write(filename, rom)?,
+        None => io::Write::write_all(&mut std::io::stdout(), &rom)?,
     }
 
     Ok(())
 }
 
-fn run(rom_filename: Option<&Path>, options: RunOptions) -> Result<(), Box<dyn Error>> {
+fn assemble(input_filename: &Path, output_filename: Option<&Path>) -> Result<(), EmulatorError> {
+    let opcodes = assembler::assemble(&std::fs::read_to_string(input_filename)?)?;
+    match output_filename {
+        Some(filename) => save_opcodes_as_machine_code(&opcodes, filename)?,
+        None => io::Write::write_all(&mut std::io::stdout(), &opcodes_to_machine_code(&opcodes))?,
+    }
+    Ok(())
+}
+
+fn print_disassembly(rom_filename: Option<&Path>) -> Result<(), EmulatorError> {
+    let machine_code = match rom_filename {
+        Some(filename) => std::fs::read(filename)?,
+        None => read_machine_code_from_stdin()?,
+    };
+    let (_header, machine_code) = rom::split_header(&machine_code)?;
+    for line in disassembler::disassemble(machine_code, ENTRY_POINT) {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+fn validate(
+    rom_filename: Option<&Path>,
+    memory_size: Option<usize>,
+    load_address: Address,
+) -> Result<(), EmulatorError> {
+    let machine_code = match rom_filename {
+        Some(filename) => std::fs::read(filename)?,
+        None => read_machine_code_from_stdin()?,
+    };
+    let (_header, machine_code) = rom::split_header(&machine_code)?;
+    let issues = validator::validate(
+        machine_code,
+        load_address,
+        memory_size.unwrap_or(Memory::DEFAULT_SIZE),
+    );
+    for issue in &issues {
+        println!("{issue}");
+    }
+    match issues.len() {
+        0 => Ok(()),
+        count => Err(EmulatorError::InvalidInstructions(count)),
+    }
+}
+
+fn run(rom_filename: Option<&Path>, options: RunOptions) -> Result<(), EmulatorError> {
+    if let Some(memory_size) = options.memory_size {
+        if !Memory::is_valid_size(memory_size) {
+            return Err(EmulatorError::InvalidMemorySize(memory_size));
+        }
+    }
+
     #[cfg(feature = "graphics")]
     let (raylib_handle, raylib_thread) = raylib::init()
         .size(SCREEN_SIZE.width, SCREEN_SIZE.height)
@@ -405,8 +729,8 @@ fn run(rom_filename: Option<&Path>, options: RunOptions) -> Result<(), Box<dyn E
         keyboard: Keyboard::new(Box::new(move |key| {
             #[cfg(feature = "graphics")]
             match raylib_handle_copy.borrow().is_key_down(
-                raylib::input::key_from_i32(key.try_into().expect("keycode out of range"))
-                    .expect("invalid keycode"),
+                raylib::input::key_from_i32(key as i32)
+                    .expect("KeyCode values match the raylib keycodes"),
             ) {
                 true => KeyState::Down,
                 false => KeyState::Up,
@@ -416,34 +740,107 @@ fn run(rom_filename: Option<&Path>, options: RunOptions) -> Result<(), Box<dyn E
             KeyState::Up
         })),
         #[cfg(feature = "graphics")]
-        display: DisplayImplementation::new(&mut raylib_handle.borrow_mut(), &raylib_thread),
+        display: DisplayImplementation::new(
+            &mut raylib_handle.borrow_mut(),
+            &raylib_thread,
+            options.resolution,
+        ),
 
         #[cfg(not(feature = "graphics"))]
-        display: MockDisplay::new(&mut (), &mut ()),
+        display: MockDisplay::new(&mut (), &mut (), options.resolution),
 
         cursor: Cursor {
             visible: true,
             time_of_next_toggle: Instant::now() + Cursor::TOGGLE_INTERVAL,
         },
+        terminal_writer: TerminalWriter::default(),
+        mouse: Mouse::new(0, 0),
+        input_log: InputLog::Off,
+        host_io: HostIo::stdio(),
     };
 
-    let mut machine = Machine::new(periphery, options.exit_on_halt);
+    let mut machine = match options.memory_size {
+        Some(memory_size) => Machine::with_memory_size(periphery, memory_size),
+        None => Machine::new(periphery),
+    };
+    if let Some(seed) = options.seed {
+        machine.processor.seed_rng(seed);
+    }
+    if let Some(path) = &options.replay {
+        let recording: Recording = serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|error| EmulatorError::Decode(format!("invalid recording: {error}")))?;
+        machine.processor.seed_rng(recording.rng_seed);
+        machine.periphery.input_log = InputLog::replay(recording);
+    } else if options.record.is_some() {
+        // the current state is a valid seed that reproduces the same random numbers
+        let rng_seed = machine.processor.snapshot().rng_state;
+        machine.periphery.input_log = InputLog::record(rng_seed);
+    }
+    if let Some(path) = &options.trace_file {
+        let file = io::LineWriter::new(std::fs::File::create(path)?);
+        machine.set_tracer(Some(Tracer::new(Box::new(file))));
+    } else if options.trace {
+        machine.set_tracer(Some(Tracer::new(Box::new(io::stderr()))));
+    }
+    machine.set_halt_on_assertion_failure(!options.continue_after_failed_assertion);
+    machine.set_max_cycles(options.max_cycles);
+    machine
+        .processor
+        .set_register_dump_format(options.register_dump_format);
+    machine
+        .processor
+        .set_memory_dump_format(options.memory_dump_format);
+    machine.processor.set_dump_directory(&options.dump_dir);
+    machine.set_vsync(options.vsync);
+    if options.profile || options.profile_file.is_some() {
+        machine.processor.enable_profiling();
+    }
+
+    // without a window, sleeping only advances the clock
+    machine.set_real_time_sleep(cfg!(feature = "graphics"));
 
     #[cfg(feature = "debugger")]
     if options.debug {
-        machine.start_debugger();
+        // don't block the debugger while the ROM sleeps
+        machine.set_real_time_sleep(false);
+        let symbols = match rom_filename.map(debugger::SymbolMap::sidecar_path) {
+            Some(path) if path.exists() => debugger::SymbolMap::load(&path)?,
+            _ => debugger::SymbolMap::default(),
+        };
+        machine.start_debugger(&options.debug_host, options.debug_port, symbols)?;
     }
 
+    machine.set_pad_rom(options.pad);
     match rom_filename {
-        Some(filename) => load_rom(&mut machine, filename)?,
-        None => load_from_stdin(&mut machine)?,
+        Some(filename) => load_rom(&mut machine, filename, options.load_address)?,
+        None => load_from_stdin(&mut machine, options.load_address)?,
     };
-    machine.generate_instruction_cache();
+    if let Some(path) = &options.load_memory {
+        let memory =
+            dumper::decode_memory_dump(&std::fs::read(path)?).map_err(EmulatorError::Decode)?;
+        machine.load_memory(&memory)?;
+    }
+    if let Some(entry_point) = options.entry_point {
+        machine.set_entry_point(entry_point)?;
+    }
+    if let Some(path) = &options.load_state {
+        let snapshot = MachineSnapshot::decode(&std::fs::read(path)?)?;
+        machine.restore(&snapshot)?;
+    }
+
+    if options.step {
+        let exit = stepper::run_stepper(&mut machine, io::stdin().lock(), io::stdout())?;
+        if exit == StepperExit::Quit {
+            save_on_stop(&machine, &options);
+            return Ok(());
+        }
+    }
 
     #[cfg(feature = "graphics")]
     let font = raylib_handle
         .borrow_mut()
-        .load_font(&raylib_thread, &options.font_path)?;
+        .load_font(&raylib_thread, &options.font_path)
+        .map_err(EmulatorError::Font)?;
 
     let mut time_measurements = TimeMeasurements {
         next_render_time: ms_since_epoch(),
@@ -453,9 +850,20 @@ fn run(rom_filename: Option<&Path>, options: RunOptions) -> Result<(), Box<dyn E
         next_clock_frequency_render: ms_since_epoch() + 1000,
         num_clock_frequency_accumulations: 0,
         clock_frequency_average: 0,
+        target_clock_frequency: options.clock_hz,
+        throttle_start_time: ms_since_epoch(),
+        throttle_start_cycle_count: machine.processor.get_cycle_count(),
     };
 
-    let custom_number_format = CustomFormat::builder().separator(" ").build()?;
+    let custom_number_format = CustomFormat::builder()
+        .separator(" ")
+        .build()
+        .expect("a single space is a valid separator");
+
+    let start_time = ms_since_epoch();
+    let mut is_halt_handled = false;
+    #[cfg(feature = "graphics")]
+    let mut held_keys = Vec::new();
 
     while {
         #[cfg(feature = "graphics")]
@@ -468,6 +876,27 @@ fn run(rom_filename: Option<&Path>, options: RunOptions) -> Result<(), Box<dyn E
         }
     } {
         let current_time = ms_since_epoch();
+
+        if let Some(max_runtime_ms) = options.max_runtime_ms {
+            let elapsed_ms = current_time - start_time;
+            if elapsed_ms >= max_runtime_ms {
+                eprintln!(
+                    "maximum runtime of {max_runtime_ms} ms exceeded: executed {} cycles in {elapsed_ms} ms",
+                    machine.processor.get_cycle_count()
+                );
+                save_on_stop(&machine, &options);
+                if options.stats {
+                    print_statistics(
+                        &machine,
+                        StopReason::MaxRuntimeExceeded,
+                        elapsed_ms,
+                        &custom_number_format,
+                    );
+                }
+                exit(&mut machine, MAX_RUNTIME_EXCEEDED_EXIT_CODE);
+            }
+        }
+
         #[cfg(feature = "graphics")]
         render_if_needed(
             current_time,
@@ -479,6 +908,24 @@ fn run(rom_filename: Option<&Path>, options: RunOptions) -> Result<(), Box<dyn E
             &custom_number_format,
         );
 
+        // without a window, there's nothing to render, but the frame count and vsync still need
+        // the frame ticks
+        #[cfg(not(feature = "graphics"))]
+        if current_time >= time_measurements.next_render_time {
+            time_measurements.next_render_time = current_time + 1000 / TARGET_FPS;
+            machine.render_tick();
+        }
+
+        #[cfg(feature = "graphics")]
+        feed_keyboard_input(
+            &mut raylib_handle.borrow_mut(),
+            &mut machine.periphery.keyboard,
+            &mut held_keys,
+        );
+
+        #[cfg(feature = "graphics")]
+        feed_mouse_input(&raylib_handle.borrow(), &mut machine.periphery);
+
         let num_cycles = match (
             time_measurements.clock_frequency_average,
             current_time > time_measurements.next_render_time,
@@ -500,8 +947,69 @@ fn run(rom_filename: Option<&Path>, options: RunOptions) -> Result<(), Box<dyn E
         #[cfg(feature = "debugger")]
         let num_cycles = if options.debug { 1 } else { num_cycles };
 
-        for _ in 0..num_cycles {
-            execute_next_instruction(&mut machine);
+        let throttled_num_cycles = time_measurements.throttle(
+            num_cycles,
+            current_time,
+            machine.processor.get_cycle_count(),
+        );
+        if throttled_num_cycles < num_cycles {
+            // ahead of the target clock frequency
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let num_cycles = throttled_num_cycles;
+
+        machine.run_cycles(num_cycles);
+
+        let remaining_sleep_ms = match machine.is_waiting_for_frame() {
+            true => u64::MAX,
+            false => machine.remaining_sleep_ms(),
+        };
+        if remaining_sleep_ms > 0 {
+            // don't spin while the ROM sleeps or waits for vsync, but keep rendering and polling
+            // the input
+            let ms_until_render = time_measurements
+                .next_render_time
+                .saturating_sub(ms_since_epoch());
+            std::thread::sleep(Duration::from_millis(
+                remaining_sleep_ms.min(ms_until_render).max(1),
+            ));
+        }
+
+        if machine.is_faulted() {
+            let halt_reason = machine.halt_reason().unwrap();
+            save_on_stop(&machine, &options);
+            if options.stats {
+                print_statistics(
+                    &machine,
+                    StopReason::Halted(halt_reason),
+                    ms_since_epoch() - start_time,
+                    &custom_number_format,
+                );
+            }
+            let exit_code = exit_code(halt_reason, machine.assertion_failures());
+            exit(&mut machine, exit_code);
+        }
+
+        if machine.is_halted() && !is_halt_handled {
+            is_halt_handled = true;
+            if let Some(path) = &options.screenshot_on_halt {
+                save_screenshot(&machine, path);
+            }
+            save_on_stop(&machine, &options);
+        }
+
+        if options.exit_on_halt && machine.is_halted() {
+            let halt_reason = machine.halt_reason().unwrap();
+            if options.stats {
+                print_statistics(
+                    &machine,
+                    StopReason::Halted(halt_reason),
+                    ms_since_epoch() - start_time,
+                    &custom_number_format,
+                );
+            }
+            let exit_code = exit_code(halt_reason, machine.assertion_failures());
+            exit(&mut machine, exit_code);
         }
     }
 
@@ -510,29 +1018,161 @@ fn run(rom_filename: Option<&Path>, options: RunOptions) -> Result<(), Box<dyn E
         machine.stop_debugger();
     }
 
+    if !is_halt_handled {
+        save_on_stop(&machine, &options);
+    }
+
+    if options.stats {
+        let stop_reason = match machine.halt_reason() {
+            Some(halt_reason) => StopReason::Halted(halt_reason),
+            None => StopReason::WindowClosed,
+        };
+        print_statistics(
+            &machine,
+            stop_reason,
+            ms_since_epoch() - start_time,
+            &custom_number_format,
+        );
+    }
+
     Ok(())
 }
 
+fn save_screenshot(machine: &Machine<impl display::Display>, path: &Path) {
+    let display = &machine.periphery.display;
+    let image = display.capture(&machine.memory);
+    let resolution = display.resolution();
+    if let Err(error) = dumper::save_png(path, resolution.width, resolution.height, &image) {
+        eprintln!("Error saving screenshot: {}", error);
+    }
+}
+
+/// Exits the process without losing output that the ROM (or the emulator) has buffered.
+fn exit(machine: &mut Machine<impl display::Display + 'static>, code: i32) -> ! {
+    machine.periphery.host_io.flush();
+    let _ = io::stdout().flush();
+    std::process::exit(code);
+}
+
+fn save_on_stop(machine: &Machine<impl display::Display + 'static>, options: &RunOptions) {
+    if let Some(path) = &options.save_state {
+        if let Err(error) = std::fs::write(path, machine.snapshot().encode()) {
+            eprintln!("Error saving state: {}", error);
+        }
+    }
+    if let (Some(path), Some(recording)) =
+        (&options.record, machine.periphery.input_log.recording())
+    {
+        let result = serde_json::to_vec(recording)
+            .map_err(|error| error.to_string())
+            .and_then(|json| std::fs::write(path, json).map_err(|error| error.to_string()));
+        if let Err(error) = result {
+            eprintln!("Error saving recording: {}", error);
+        }
+    }
+    if let Some(profile) = machine.processor.profile() {
+        match &options.profile_file {
+            Some(path) => {
+                if let Err(error) = std::fs::write(path, profile.summary()) {
+                    eprintln!("Error saving profile: {}", error);
+                }
+            }
+            None => print!("{}", profile.summary()),
+        }
+    }
+    if machine.periphery.input_log.has_diverged() {
+        eprintln!("Warning: the execution diverged from the replayed recording");
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum StopReason {
+    Halted(HaltReason),
+    MaxRuntimeExceeded,
+    WindowClosed,
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::Halted(HaltReason::CaughtFire) => write!(f, "halted"),
+            StopReason::Halted(HaltReason::AssertionFailed) => write!(f, "assertion failed"),
+            StopReason::Halted(HaltReason::Error) => write!(f, "execution error"),
+            StopReason::Halted(HaltReason::CycleLimit) => write!(f, "cycle limit reached"),
+            StopReason::MaxRuntimeExceeded => write!(f, "maximum runtime exceeded"),
+            StopReason::WindowClosed => write!(f, "window closed"),
+        }
+    }
+}
+
+/// Returns the exit code for a halted machine. Assertion failures that didn't halt the machine
+/// (see `--continue-after-failed-assertion`) still result in a non-zero exit code.
+fn exit_code(halt_reason: HaltReason, assertion_failures: &[AssertionFailure]) -> i32 {
+    match halt_reason {
+        HaltReason::CaughtFire if assertion_failures.is_empty() => 0,
+        HaltReason::CaughtFire | HaltReason::AssertionFailed => ASSERTION_FAILED_EXIT_CODE,
+        HaltReason::Error => EXECUTION_ERROR_EXIT_CODE,
+        HaltReason::CycleLimit => CYCLE_LIMIT_EXCEEDED_EXIT_CODE,
+    }
+}
+
+fn print_statistics<Display>(
+    machine: &Machine<Display>,
+    stop_reason: StopReason,
+    elapsed_ms: u64,
+    custom_number_format: &CustomFormat,
+) where
+    Display: display::Display + 'static,
+{
+    let cycle_count = machine.processor.get_cycle_count();
+    // cycles per millisecond equals kHz
+    let average_frequency_khz = cycle_count / elapsed_ms.max(1);
+    eprintln!("execution statistics:");
+    eprintln!("  stop reason: {stop_reason}");
+    eprintln!(
+        "  cycles: {}",
+        cycle_count.to_formatted_string(custom_number_format)
+    );
+    eprintln!(
+        "  wall time: {} ms",
+        elapsed_ms.to_formatted_string(custom_number_format)
+    );
+    eprintln!(
+        "  average clock frequency: {} kHz",
+        average_frequency_khz.to_formatted_string(custom_number_format)
+    );
+    eprintln!(
+        "  framebuffer swaps: {}",
+        machine
+            .periphery
+            .display
+            .num_swaps()
+            .to_formatted_string(custom_number_format)
+    );
+    eprintln!(
+        "  distinct instructions compiled: {}",
+        machine
+            .num_distinct_instructions()
+            .to_formatted_string(custom_number_format)
+    );
+}
+
 fn load_rom<Display: display::Display + 'static>(
     machine: &mut Machine<Display>,
     filename: impl AsRef<Path>,
-) -> Result<(), Box<dyn Error>> {
+    load_address: Address,
+) -> Result<(), EmulatorError> {
     let buffer = std::fs::read(filename)?;
-    write_buffer(&buffer, machine)
+    machine.load_rom_at(&buffer, load_address)
 }
 
-fn write_buffer(
-    buffer: &[u8],
-    machine: &mut Machine<impl display::Display>,
-) -> Result<(), Box<dyn Error>> {
-    if (Memory::SIZE - ENTRY_POINT as usize) < buffer.len() {
-        return Err(format!("Buffer size {} too big", buffer.len()).into());
-    }
-    if buffer.len() % Word::SIZE != 0 {
-        return Err(format!("Filesize must be divisible by {}", Word::SIZE).into());
-    }
-    machine.memory.data_mut()[ENTRY_POINT as usize..][..buffer.len()].copy_from_slice(buffer);
-    Ok(())
+/// Parses addresses given either in hexadecimal (`0x...`) or in decimal notation.
+fn parse_address(string: &str) -> Result<Address, String> {
+    let result = match string.strip_prefix("0x") {
+        Some(hex) => Address::from_str_radix(hex, 16),
+        None => string.parse(),
+    };
+    result.map_err(|error| format!("invalid address '{string}': {error}"))
 }
 
 fn duration_since_epoch() -> Duration {
@@ -546,15 +1186,6 @@ fn ms_since_epoch() -> u64 {
     since_the_epoch.as_secs() * 1000 + since_the_epoch.subsec_nanos() as u64 / 1_000_000
 }
 
-fn execute_next_instruction<Display>(machine: &mut Machine<Display>)
-where
-    Display: crate::Display + 'static,
-{
-    if !machine.is_halted() {
-        machine.execute_next_instruction();
-    }
-}
-
 fn opcodes_to_machine_code(instructions: &[Opcode]) -> Vec<u8> {
     instructions
         .iter()
@@ -576,6 +1207,79 @@ struct TimeMeasurements {
     next_clock_frequency_render: u64,
     num_clock_frequency_accumulations: u64,
     clock_frequency_average: u64,
+    /// Cycles per second the execution is limited to, 0 means unthrottled.
+    target_clock_frequency: u64,
+    throttle_start_time: u64,
+    throttle_start_cycle_count: u64,
+}
+
+impl TimeMeasurements {
+    /// Limits the number of cycles to execute so that the cycles executed since the start don't
+    /// exceed the target clock frequency.
+    fn throttle(&self, num_cycles: u64, current_time: u64, cycle_count: u64) -> u64 {
+        if self.target_clock_frequency == 0 {
+            return num_cycles;
+        }
+        let elapsed_ms = current_time.saturating_sub(self.throttle_start_time);
+        let budget = (elapsed_ms as u128 * self.target_clock_frequency as u128 / 1000) as u64;
+        let executed = cycle_count - self.throttle_start_cycle_count;
+        num_cycles.min(budget.saturating_sub(executed))
+    }
+}
+
+/// Translates the key presses and releases since the last call into keyboard events and
+/// enqueues the characters that have been typed.
+#[cfg(feature = "graphics")]
+fn feed_keyboard_input(
+    raylib_handle: &mut RaylibHandle,
+    keyboard: &mut Keyboard,
+    held_keys: &mut Vec<Word>,
+) {
+    held_keys.retain(|&keycode| {
+        let key = raylib::input::key_from_i32(keycode as i32).expect("invalid keycode");
+        if raylib_handle.is_key_down(key) {
+            return true;
+        }
+        keyboard.push_event(KeyEvent {
+            keycode,
+            state: KeyState::Up,
+        });
+        false
+    });
+    while let Some(key) = raylib_handle.get_key_pressed() {
+        let keycode = key as Word;
+        keyboard.push_event(KeyEvent {
+            keycode,
+            state: KeyState::Down,
+        });
+        if !held_keys.contains(&keycode) {
+            held_keys.push(keycode);
+        }
+    }
+    while let Some(character) = raylib_handle.get_char_pressed() {
+        keyboard.push_char(character);
+    }
+}
+
+#[cfg(feature = "graphics")]
+fn feed_mouse_input(
+    raylib_handle: &RaylibHandle,
+    periphery: &mut PeripheryImplementation<DisplayImplementation>,
+) {
+    let position = raylib_handle.get_mouse_position();
+    let resolution = periphery.display.resolution();
+    periphery
+        .mouse
+        .set_window_position(position.x, position.y, resolution);
+    let buttons = [
+        (MouseButton::MOUSE_LEFT_BUTTON, mouse::BUTTON_LEFT),
+        (MouseButton::MOUSE_RIGHT_BUTTON, mouse::BUTTON_RIGHT),
+        (MouseButton::MOUSE_MIDDLE_BUTTON, mouse::BUTTON_MIDDLE),
+    ]
+    .into_iter()
+    .filter(|&(button, _)| raylib_handle.is_mouse_button_down(button))
+    .fold(0, |mask, (_, bit)| mask | bit);
+    periphery.mouse.set_buttons(buttons);
 }
 
 #[cfg(feature = "graphics")]
@@ -591,6 +1295,7 @@ fn render_if_needed(
     if current_time >= time_measurements.next_render_time {
         time_measurements.next_render_time += 1000 / TARGET_FPS;
 
+        machine.render_tick();
         let mut draw_handle = raylib_handle.begin_drawing(thread);
         render(&mut draw_handle, machine, font);
 
@@ -659,3 +1364,109 @@ fn draw_clock_frequency(
         Color::WHITE,
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_code_depends_on_halt_reason() {
+        assert_eq!(exit_code(HaltReason::CaughtFire, &[]), 0);
+        assert_eq!(
+            exit_code(HaltReason::AssertionFailed, &[]),
+            ASSERTION_FAILED_EXIT_CODE
+        );
+        assert_eq!(exit_code(HaltReason::Error, &[]), EXECUTION_ERROR_EXIT_CODE);
+        assert_eq!(
+            exit_code(HaltReason::CycleLimit, &[]),
+            CYCLE_LIMIT_EXCEEDED_EXIT_CODE
+        );
+        assert_ne!(ASSERTION_FAILED_EXIT_CODE, EXECUTION_ERROR_EXIT_CODE);
+        assert_ne!(ASSERTION_FAILED_EXIT_CODE, MAX_RUNTIME_EXCEEDED_EXIT_CODE);
+        assert_ne!(
+            CYCLE_LIMIT_EXCEEDED_EXIT_CODE,
+            MAX_RUNTIME_EXCEEDED_EXIT_CODE
+        );
+    }
+
+    #[test]
+    fn recorded_assertion_failures_fail_a_halted_run() {
+        let failure = AssertionFailure {
+            expected: 1,
+            actual: 2,
+            address: ENTRY_POINT,
+        };
+        assert_eq!(
+            exit_code(HaltReason::CaughtFire, &[failure]),
+            ASSERTION_FAILED_EXIT_CODE
+        );
+    }
+
+    fn throttled_time_measurements(target_clock_frequency: u64) -> TimeMeasurements {
+        TimeMeasurements {
+            next_render_time: 0,
+            last_cycle_count: 0,
+            last_render_time: 0,
+            clock_frequency_accumulator: 0,
+            next_clock_frequency_render: 0,
+            num_clock_frequency_accumulations: 0,
+            clock_frequency_average: 0,
+            target_clock_frequency,
+            throttle_start_time: 1000,
+            throttle_start_cycle_count: 0,
+        }
+    }
+
+    #[test]
+    fn throttle_limits_cycles_per_second() {
+        const TARGET: u64 = 5_000;
+        let time_measurements = throttled_time_measurements(TARGET);
+        let mut cycle_count = 0;
+        // simulated second, the loop requests way more cycles than allowed
+        for current_time in (1000..=2000).step_by(3) {
+            cycle_count += time_measurements.throttle(10_000, current_time, cycle_count);
+            assert!(cycle_count <= (current_time - 1000) * TARGET / 1000);
+        }
+        assert!(cycle_count <= TARGET);
+        assert!(cycle_count >= TARGET - 3 * TARGET / 1000);
+    }
+
+    #[test]
+    fn zero_target_frequency_is_unthrottled() {
+        let time_measurements = throttled_time_measurements(0);
+        assert_eq!(time_measurements.throttle(10_000, 1000, 0), 10_000);
+    }
+
+    #[test]
+    fn parse_hexadecimal_and_decimal_addresses() {
+        assert_eq!(parse_address("0x1000"), Ok(0x1000));
+        assert_eq!(parse_address("4096"), Ok(0x1000));
+        assert!(parse_address("0xZZ").is_err());
+        assert!(parse_address("0x100000000").is_err());
+    }
+
+    #[test]
+    fn disassemble_sample_program() {
+        let machine_code = opcodes_to_machine_code(&samples::color_fill());
+        let mnemonics: Vec<_> = disassembler::disassemble(&machine_code, ENTRY_POINT)
+            .iter()
+            .map(|line| line.split_whitespace().nth(1).unwrap().to_string())
+            .collect();
+        assert_eq!(
+            mnemonics,
+            [
+                "MoveRegisterImmediate",
+                "MoveRegisterImmediate",
+                "MoveRegisterImmediate",
+                "AddTargetSourceImmediate",
+                "MoveRegisterImmediate",
+                "MovePointerSource",
+                "AddTargetSourceImmediate",
+                "AddTargetSourceImmediate",
+                "CompareTargetLhsRhs",
+                "JumpImmediateIfLessThan",
+                "JumpImmediate",
+            ]
+        );
+    }
+}
@@ -1,18 +1,86 @@
-use crate::{opcodes::Opcode, Address, Byte, Halfword, Instruction, Size, Word};
+use std::ops::Range;
+
+use crate::{address_constants, opcodes::Opcode, Address, Byte, Halfword, Instruction, Size, Word};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryError {
+    Misaligned { address: Address },
+    OutOfBounds { address: Address },
+}
+
+/// The parts of the memory shown by the display. Writes to each of them are tracked separately, so
+/// that drawing into the invisible framebuffer doesn't cause uploads of the visible one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayArea {
+    FirstFramebuffer,
+    SecondFramebuffer,
+    Palette,
+}
+
+impl DisplayArea {
+    const ALL: [Self; 3] = [
+        Self::FirstFramebuffer,
+        Self::SecondFramebuffer,
+        Self::Palette,
+    ];
+}
 
 pub struct Memory {
     data: Vec<u8>,
+    /// One flag per instruction slot, set when the slot gets written to (used to
+    /// invalidate the instruction cache).
+    dirty_instructions: Vec<bool>,
+    /// Size of a framebuffer in bytes, which determines where the second framebuffer starts.
+    framebuffer_size: usize,
+    /// First and last byte address written to within each `DisplayArea` (used to only upload the
+    /// changed part of the visible framebuffer).
+    display_dirty_ranges: [Option<(Address, Address)>; DisplayArea::ALL.len()],
+    /// Set when the instruction slot holding the terminal cursor pointer gets written to (used to
+    /// scroll the terminal once a program moved the cursor past the last row).
+    terminal_cursor_dirty: bool,
 }
 
+/// The instruction slot containing `address_constants::TERMINAL_CURSOR_POINTER`.
+const TERMINAL_CURSOR_SLOT: usize =
+    address_constants::TERMINAL_CURSOR_POINTER as usize / Instruction::SIZE;
+
 impl Memory {
-    pub const SIZE: usize = 16 * 1024 * 1024;
+    pub const DEFAULT_SIZE: usize = 16 * 1024 * 1024;
+    /// The size of the whole address space, more memory can't be addressed.
+    pub const MAX_SIZE: usize = Address::MAX as usize + 1;
 
     pub fn new() -> Self {
+        Self::with_size(Self::DEFAULT_SIZE)
+    }
+
+    /// Creates a memory of the given size in bytes. The size has to be a multiple of the
+    /// instruction size and there has to be room for at least one instruction after the
+    /// entry point.
+    pub fn with_size(size: usize) -> Self {
+        assert!(Self::is_valid_size(size), "invalid memory size {size}");
+        Self::with_unchecked_size(size)
+    }
+
+    /// Like `with_size`, but also accepts memories that don't contain all of the fixed address
+    /// ranges (e.g. to check that accesses to them fail gracefully).
+    pub(crate) fn with_unchecked_size(size: usize) -> Self {
         Self {
-            data: vec![0; Self::SIZE],
+            data: vec![0; size],
+            dirty_instructions: vec![false; size / Instruction::SIZE],
+            framebuffer_size: address_constants::MAX_FRAMEBUFFER_SIZE,
+            display_dirty_ranges: [None; DisplayArea::ALL.len()],
+            terminal_cursor_dirty: false,
         }
     }
 
+    pub fn is_valid_size(size: usize) -> bool {
+        size % Instruction::SIZE == 0 && size > address_constants::ENTRY_POINT as usize
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
     pub fn data(&self) -> &[u8] {
         &self.data
     }
@@ -25,12 +93,36 @@ impl Memory {
         &self,
         address: Address,
     ) -> Result<Opcode, <Opcode as TryFrom<Instruction>>::Error> {
-        debug_assert_eq!(address as usize % Instruction::SIZE, 0);
+        if address as usize % Instruction::SIZE != 0 {
+            return Err("Misaligned address");
+        }
+        if address as usize + Instruction::SIZE > self.size() {
+            return Err("Address out of bounds");
+        }
         let slice = &self.data[address as usize..][..Instruction::SIZE];
         let instruction = Instruction::from_be_bytes(slice.try_into().unwrap());
         instruction.try_into()
     }
 
+    /// Decodes every instruction slot within the given address range, i.e. every address that is
+    /// a multiple of the instruction size and followed by a whole instruction inside the range
+    /// and the memory.
+    pub fn instructions(
+        &self,
+        range: Range<Address>,
+    ) -> impl Iterator<
+        Item = (
+            Address,
+            Result<Opcode, <Opcode as TryFrom<Instruction>>::Error>,
+        ),
+    > + '_ {
+        let start = (range.start as usize).next_multiple_of(Instruction::SIZE);
+        let end = (range.end as usize).min(self.size());
+        (start..end.saturating_sub(Instruction::SIZE - 1))
+            .step_by(Instruction::SIZE)
+            .map(|address| (address as Address, self.read_opcode(address as Address)))
+    }
+
     pub fn read_data(&self, address: Address) -> Word {
         debug_assert_eq!(address as usize % Word::SIZE, 0);
         let slice = &self.data[address as usize..][..Word::SIZE];
@@ -53,20 +145,186 @@ impl Memory {
 
         self.data[address as usize..][..Instruction::SIZE]
             .copy_from_slice(&instruction.to_be_bytes());
+        self.mark_instruction_dirty(address);
+        self.mark_framebuffer_dirty(address, Instruction::SIZE);
     }
 
     pub fn write_data(&mut self, address: Address, data: Word) {
         debug_assert_eq!(address as usize % Word::SIZE, 0);
         self.data[address as usize..][..Word::SIZE].copy_from_slice(&data.to_be_bytes());
+        self.mark_instruction_dirty(address);
+        self.mark_framebuffer_dirty(address, Word::SIZE);
     }
 
     pub fn write_halfword(&mut self, address: Address, data: Halfword) {
         debug_assert_eq!(address as usize % Halfword::SIZE, 0);
         self.data[address as usize..][..Halfword::SIZE].copy_from_slice(&data.to_be_bytes());
+        self.mark_instruction_dirty(address);
+        self.mark_framebuffer_dirty(address, Halfword::SIZE);
     }
 
     pub fn write_byte(&mut self, address: Address, data: Byte) {
         self.data[address as usize] = data;
+        self.mark_instruction_dirty(address);
+        self.mark_framebuffer_dirty(address, Byte::SIZE);
+    }
+
+    /// Copies the bytes of `source` to `destination` (the ranges may overlap). Like the other
+    /// writes, this marks the overwritten bytes as changed.
+    pub fn copy_within(&mut self, source: Range<Address>, destination: Address) {
+        let size = source.len();
+        self.data.copy_within(
+            source.start as usize..source.end as usize,
+            destination as usize,
+        );
+        self.mark_range_dirty(destination..destination + size as Address);
+    }
+
+    /// Sets all bytes of the range to the given value (see `copy_within`).
+    pub fn fill(&mut self, range: Range<Address>, value: Byte) {
+        self.data[range.start as usize..range.end as usize].fill(value);
+        self.mark_range_dirty(range);
+    }
+
+    pub fn try_read_data(&self, address: Address) -> Result<Word, MemoryError> {
+        self.check_access(address, Word::SIZE)?;
+        Ok(self.read_data(address))
+    }
+
+    pub fn try_read_halfword(&self, address: Address) -> Result<Halfword, MemoryError> {
+        self.check_access(address, Halfword::SIZE)?;
+        Ok(self.read_halfword(address))
+    }
+
+    pub fn try_read_byte(&self, address: Address) -> Result<Byte, MemoryError> {
+        self.check_access(address, Byte::SIZE)?;
+        Ok(self.read_byte(address))
+    }
+
+    pub fn try_write_data(&mut self, address: Address, data: Word) -> Result<(), MemoryError> {
+        self.check_access(address, Word::SIZE)?;
+        self.write_data(address, data);
+        Ok(())
+    }
+
+    pub fn try_write_halfword(
+        &mut self,
+        address: Address,
+        data: Halfword,
+    ) -> Result<(), MemoryError> {
+        self.check_access(address, Halfword::SIZE)?;
+        self.write_halfword(address, data);
+        Ok(())
+    }
+
+    pub fn try_write_byte(&mut self, address: Address, data: Byte) -> Result<(), MemoryError> {
+        self.check_access(address, Byte::SIZE)?;
+        self.write_byte(address, data);
+        Ok(())
+    }
+
+    fn check_access(&self, address: Address, size: usize) -> Result<(), MemoryError> {
+        if address as usize % size != 0 {
+            return Err(MemoryError::Misaligned { address });
+        }
+        if address as usize + size > self.size() {
+            return Err(MemoryError::OutOfBounds { address });
+        }
+        Ok(())
+    }
+
+    /// Returns whether the instruction slot containing the given address has been written to
+    /// since the last call and resets the flag.
+    pub fn take_instruction_dirty(&mut self, address: Address) -> bool {
+        std::mem::take(&mut self.dirty_instructions[address as usize / Instruction::SIZE])
+    }
+
+    pub fn clear_dirty_instructions(&mut self) {
+        self.dirty_instructions.fill(false);
+    }
+
+    /// Returns whether the terminal cursor pointer may have been written to since the last call
+    /// and resets the flag.
+    pub fn take_terminal_cursor_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.terminal_cursor_dirty)
+    }
+
+    fn mark_range_dirty(&mut self, range: Range<Address>) {
+        if range.is_empty() {
+            return;
+        }
+        let slots =
+            range.start as usize / Instruction::SIZE..=(range.end as usize - 1) / Instruction::SIZE;
+        self.terminal_cursor_dirty |= slots.contains(&TERMINAL_CURSOR_SLOT);
+        self.dirty_instructions[slots].fill(true);
+        self.mark_framebuffer_dirty(range.start, range.len());
+    }
+
+    fn mark_instruction_dirty(&mut self, address: Address) {
+        // aligned writes never span more than one instruction slot
+        let slot = address as usize / Instruction::SIZE;
+        self.dirty_instructions[slot] = true;
+        self.terminal_cursor_dirty |= slot == TERMINAL_CURSOR_SLOT;
+    }
+
+    /// Sets the size of a framebuffer in bytes (see `Resolution::framebuffer_size`), which
+    /// determines where the second framebuffer starts. Resets the tracked writes.
+    pub fn set_framebuffer_size(&mut self, size: usize) {
+        self.framebuffer_size = size;
+        self.display_dirty_ranges = [None; DisplayArea::ALL.len()];
+    }
+
+    fn display_area(&self, area: DisplayArea) -> Range<Address> {
+        let second_framebuffer_start =
+            address_constants::FIRST_FRAMEBUFFER_START + self.framebuffer_size as Address;
+        match area {
+            DisplayArea::FirstFramebuffer => {
+                address_constants::FIRST_FRAMEBUFFER_START..second_framebuffer_start
+            }
+            DisplayArea::SecondFramebuffer => {
+                second_framebuffer_start
+                    ..second_framebuffer_start + self.framebuffer_size as Address
+            }
+            DisplayArea::Palette => {
+                address_constants::PALETTE_START
+                    ..address_constants::PALETTE_START + address_constants::PALETTE_SIZE as Address
+            }
+        }
+    }
+
+    /// Returns the first and last byte address written to within the given area since the last
+    /// call and resets the range of that area.
+    pub fn take_dirty_range(&mut self, area: DisplayArea) -> Option<(Address, Address)> {
+        self.display_dirty_ranges[area as usize].take()
+    }
+
+    fn mark_framebuffer_dirty(&mut self, address: Address, size: usize) {
+        let last = address + size as Address - 1;
+        if last < address_constants::FIRST_FRAMEBUFFER_START
+            || address >= address_constants::TERMINAL_ATTRIBUTES_START
+        {
+            return;
+        }
+        for area in DisplayArea::ALL {
+            let range = self.display_area(area);
+            let (first, last) = (address.max(range.start), last.min(range.end - 1));
+            if first > last {
+                continue;
+            }
+            let dirty_range = &mut self.display_dirty_ranges[area as usize];
+            *dirty_range = Some(match *dirty_range {
+                Some((previous_first, previous_last)) => {
+                    (previous_first.min(first), previous_last.max(last))
+                }
+                None => (first, last),
+            });
+        }
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -88,6 +346,72 @@ mod tests {
         assert_eq!(memory.read_opcode(address), Ok(opcode));
     }
 
+    #[test]
+    fn instructions_decodes_aligned_slots_in_range() {
+        let mut memory = Memory::new();
+        let opcodes = [
+            Opcode::NoOp {},
+            Opcode::MoveRegisterImmediate {
+                register: Register(1),
+                immediate: 42,
+            },
+            Opcode::HaltAndCatchFire {},
+        ];
+        for (address, opcode) in (0x100..).step_by(Instruction::SIZE).zip(opcodes) {
+            memory.write_opcode(address, opcode);
+        }
+        memory.write_data(0x118, 0xEEEE_0000);
+
+        let decoded: Vec<_> = memory.instructions(0xFC..0x124).collect();
+        assert_eq!(
+            decoded,
+            [
+                (0x100, Ok(opcodes[0])),
+                (0x108, Ok(opcodes[1])),
+                (0x110, Ok(opcodes[2])),
+                (0x118, Err("Invalid opcode")),
+            ]
+        );
+        // the range is clamped to the memory
+        let end = Memory::DEFAULT_SIZE as Address;
+        assert_eq!(memory.instructions(end - 0x10..Address::MAX).count(), 2);
+        assert_eq!(memory.instructions(0x104..0x108).count(), 0);
+    }
+
+    #[test]
+    fn read_instruction_out_of_bounds() {
+        let memory = Memory::new();
+        assert!(memory.read_opcode(Memory::DEFAULT_SIZE as Address).is_err());
+    }
+
+    #[test]
+    fn misaligned_access_is_an_error() {
+        let mut memory = Memory::new();
+        assert_eq!(
+            memory.try_read_data(0x1001),
+            Err(MemoryError::Misaligned { address: 0x1001 })
+        );
+        assert_eq!(
+            memory.try_write_halfword(0x1001, 42),
+            Err(MemoryError::Misaligned { address: 0x1001 })
+        );
+        assert_eq!(memory.try_read_byte(0x1001), Ok(0));
+    }
+
+    #[test]
+    fn out_of_bounds_access_is_an_error() {
+        let mut memory = Memory::new();
+        let address = (Memory::DEFAULT_SIZE - Word::SIZE) as Address;
+        assert_eq!(memory.try_write_data(address, 42), Ok(()));
+        assert_eq!(memory.try_read_data(address), Ok(42));
+        assert_eq!(
+            memory.try_read_byte(Memory::DEFAULT_SIZE as Address),
+            Err(MemoryError::OutOfBounds {
+                address: Memory::DEFAULT_SIZE as Address
+            })
+        );
+    }
+
     #[test]
     fn write_data_read_back() {
         let mut memory = Memory::new();
@@ -97,6 +421,105 @@ mod tests {
         assert_eq!(memory.read_data(address), data);
     }
 
+    #[test]
+    fn write_halfword_read_back() {
+        let mut memory = Memory::new();
+        let data = 0xABCD;
+        let address = 0x2;
+        memory.write_halfword(address, data);
+        assert_eq!(memory.read_halfword(address), data);
+        assert_eq!(memory.read_data(0x0), 0x0000ABCD);
+    }
+
+    #[test]
+    fn write_byte_read_back() {
+        let mut memory = Memory::new();
+        let data = 0xAB;
+        let address = 0x3;
+        memory.write_byte(address, data);
+        assert_eq!(memory.read_byte(address), data);
+        assert_eq!(memory.read_halfword(0x2), 0x00AB);
+        assert_eq!(memory.read_data(0x0), 0x000000AB);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn write_misaligned_halfword_panics() {
+        let mut memory = Memory::new();
+        memory.write_halfword(0x1, 0xABCD);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn read_misaligned_halfword_panics() {
+        let memory = Memory::new();
+        memory.read_halfword(0x3);
+    }
+
+    #[test]
+    fn display_areas_are_tracked_separately() {
+        let mut memory = Memory::new();
+        memory.set_framebuffer_size(16);
+        let first = address_constants::FIRST_FRAMEBUFFER_START;
+        memory.write_data(first + 4, 1);
+        memory.write_halfword(first + 20, 1);
+        memory.write_byte(first + 30, 1);
+        memory.write_byte(first + 40, 1);
+        memory.write_byte(address_constants::PALETTE_START + 7, 1);
+
+        assert_eq!(
+            memory.take_dirty_range(DisplayArea::FirstFramebuffer),
+            Some((first + 4, first + 7))
+        );
+        assert_eq!(
+            memory.take_dirty_range(DisplayArea::SecondFramebuffer),
+            Some((first + 20, first + 30))
+        );
+        assert_eq!(
+            memory.take_dirty_range(DisplayArea::Palette),
+            Some((
+                address_constants::PALETTE_START + 7,
+                address_constants::PALETTE_START + 7
+            ))
+        );
+        assert_eq!(memory.take_dirty_range(DisplayArea::FirstFramebuffer), None);
+    }
+
+    #[test]
+    fn bulk_writes_mark_written_range_dirty() {
+        let mut memory = Memory::new();
+        let first = address_constants::FIRST_FRAMEBUFFER_START;
+        memory.write_data(16, 0x1234_5678);
+        memory.clear_dirty_instructions();
+        memory.copy_within(16..20, 40);
+        assert_eq!(memory.read_data(40), 0x1234_5678);
+        assert!(memory.take_instruction_dirty(40));
+        assert!(!memory.take_instruction_dirty(16));
+
+        memory.fill(first + 8..first + 24, 0xFF);
+        assert_eq!(memory.read_data(first + 20), 0xFFFF_FFFF);
+        assert_eq!(
+            memory.take_dirty_range(DisplayArea::FirstFramebuffer),
+            Some((first + 8, first + 23))
+        );
+        for address in [first + 8, first + 16] {
+            assert!(memory.take_instruction_dirty(address / 8 * 8));
+        }
+    }
+
+    #[test]
+    fn writes_mark_instructions_dirty() {
+        let mut memory = Memory::new();
+        let address = Instruction::SIZE as Address;
+        assert!(!memory.take_instruction_dirty(address));
+        memory.write_byte(address + 7, 0xAB);
+        assert!(!memory.take_instruction_dirty(0));
+        assert!(memory.take_instruction_dirty(address));
+        assert!(!memory.take_instruction_dirty(address));
+    }
+
     #[test]
     fn fill_memory_with_instructions_read_back() {
         let mut memory = Memory::new();
@@ -106,11 +529,11 @@ mod tests {
             register: Register(0),
             immediate: 42,
         };
-        for address in (0..Memory::SIZE).step_by(Instruction::SIZE) {
+        for address in (0..Memory::DEFAULT_SIZE).step_by(Instruction::SIZE) {
             memory.write_opcode(address as Address, opcode);
         }
 
-        for address in (0..Memory::SIZE).step_by(Instruction::SIZE) {
+        for address in (0..Memory::DEFAULT_SIZE).step_by(Instruction::SIZE) {
             assert_eq!(memory.read_opcode(address as Address), Ok(opcode));
         }
     }
@@ -121,14 +544,14 @@ mod tests {
 
         // fill memory
         let mut data = 0x0;
-        for address in (0..Memory::SIZE).step_by(Word::SIZE) {
+        for address in (0..Memory::DEFAULT_SIZE).step_by(Word::SIZE) {
             memory.write_data(address as Address, data);
             data = data.wrapping_add(1);
         }
 
         // read back memory
         data = 0x0;
-        for address in (0..Memory::SIZE).step_by(Word::SIZE) {
+        for address in (0..Memory::DEFAULT_SIZE).step_by(Word::SIZE) {
             assert_eq!(memory.read_data(address as Address), data);
             data = data.wrapping_add(1);
         }
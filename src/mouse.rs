@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+
+use crate::{display::Resolution, Word, SCREEN_SIZE};
+
+/// Bits of the button mask reported by the `GetMouseButtons` opcode.
+pub const BUTTON_LEFT: Word = 1 << 0;
+pub const BUTTON_RIGHT: Word = 1 << 1;
+pub const BUTTON_MIDDLE: Word = 1 << 2;
+
+/// The most recently observed state of the mouse. The position is given in display pixels, i.e.
+/// in framebuffer coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Mouse {
+    x: Word,
+    y: Word,
+    buttons: Word,
+}
+
+impl Mouse {
+    pub fn new(x: Word, y: Word) -> Self {
+        Self { x, y, buttons: 0 }
+    }
+
+    pub fn with_buttons(x: Word, y: Word, buttons: Word) -> Self {
+        Self { x, y, buttons }
+    }
+
+    pub fn position(&self) -> (Word, Word) {
+        (self.x, self.y)
+    }
+
+    pub fn buttons(&self) -> Word {
+        self.buttons
+    }
+
+    pub fn set_buttons(&mut self, buttons: Word) {
+        self.buttons = buttons;
+    }
+
+    /// Sets the position from window coordinates. The display is scaled to fill the height of the
+    /// window, so the position is scaled back and clamped to the framebuffer.
+    pub fn set_window_position(&mut self, x: f32, y: f32, resolution: Resolution) {
+        let scale = SCREEN_SIZE.height as f32 / resolution.height as f32;
+        let to_pixel = |coordinate: f32, size: usize| {
+            ((coordinate / scale).max(0.0) as Word).min(size as Word - 1)
+        };
+        self.x = to_pixel(x, resolution.width);
+        self.y = to_pixel(y, resolution.height);
+    }
+}
+
+/// Replays a scripted sequence of mouse states, e.g. to move the mouse and press its buttons
+/// between the instructions of a test.
+#[derive(Debug, Clone, Default)]
+pub struct MockMouse {
+    states: VecDeque<Mouse>,
+}
+
+impl MockMouse {
+    pub fn new(states: impl IntoIterator<Item = Mouse>) -> Self {
+        Self {
+            states: states.into_iter().collect(),
+        }
+    }
+
+    /// Applies the next scripted state to `mouse`. Returns `false` (and leaves `mouse` as it is)
+    /// if the script has been exhausted.
+    pub fn step(&mut self, mouse: &mut Mouse) -> bool {
+        match self.states.pop_front() {
+            Some(state) => {
+                *mouse = state;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_mouse_applies_scripted_states_in_order() {
+        let mut mock = MockMouse::new([
+            Mouse::new(1, 2),
+            Mouse::with_buttons(3, 4, BUTTON_LEFT | BUTTON_RIGHT),
+        ]);
+        let mut mouse = Mouse::default();
+        assert!(mock.step(&mut mouse));
+        assert_eq!((mouse.position(), mouse.buttons()), ((1, 2), 0));
+        assert!(mock.step(&mut mouse));
+        assert_eq!(
+            (mouse.position(), mouse.buttons()),
+            ((3, 4), BUTTON_LEFT | BUTTON_RIGHT)
+        );
+        assert!(!mock.step(&mut mouse));
+        assert_eq!(mouse.position(), (3, 4));
+    }
+
+    #[test]
+    fn window_position_is_scaled_to_framebuffer() {
+        let resolution = Resolution {
+            width: 240,
+            height: 180,
+        };
+        let scale = SCREEN_SIZE.height as f32 / resolution.height as f32;
+        let mut mouse = Mouse::default();
+        mouse.set_window_position(10.0 * scale, 20.0 * scale, resolution);
+        assert_eq!(mouse.position(), (10, 20));
+    }
+
+    #[test]
+    fn window_position_is_clamped() {
+        let resolution = Resolution::DEFAULT;
+        let mut mouse = Mouse::default();
+        mouse.set_window_position(-5.0, 1_000_000.0, resolution);
+        assert_eq!(mouse.position(), (0, resolution.height as Word - 1));
+    }
+}
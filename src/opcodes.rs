@@ -1,4 +1,4 @@
-use crate::{Address, AsHalfwords, AsWords, Instruction, Register, Word};
+use crate::{Address, AsHalfwords, AsWords, Instruction, Register, Size, Word};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -158,6 +158,72 @@ macro_rules! type_to_opcode_type {
     };
 }
 
+macro_rules! target_address_operand {
+    (target_address, $value:ident) => {
+        Some(Operand::Value($value))
+    };
+    ($type:ident, $value:ident) => {
+        None::<Operand>
+    };
+}
+
+macro_rules! source_operand {
+    (target_address, $value:ident) => {
+        None::<Operand>
+    };
+    ($type:ident, $value:ident) => {
+        Some(Operand::Value($value))
+    };
+}
+
+macro_rules! take_target_address {
+    (target_address, $operands:ident) => {
+        Some(next_value(&mut $operands)?)
+    };
+    ($type:ident, $operands:ident) => {
+        None
+    };
+}
+
+macro_rules! address_operand {
+    (immediate, $value:ident) => {{
+        let _ = $value;
+        None
+    }};
+    ($type:ident, $value:ident) => {
+        Some($value)
+    };
+}
+
+/// Byte offset of the first register operand within an instruction. Further register operands
+/// follow in consecutive bytes.
+const REGISTERS_BYTE_OFFSET: usize = 2;
+/// Byte offset of the immediate or address operand (the lower word) within an instruction.
+const IMMEDIATE_BYTE_OFFSET: usize = 4;
+
+/// An operand of an instruction, in the order given by the `arguments` of its
+/// `OpcodeDescription`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Operand {
+    Register(Register),
+    /// An immediate or an address.
+    Value(Word),
+}
+
+fn next_register(operands: &mut impl Iterator<Item = Operand>) -> Option<Register> {
+    match operands.next()? {
+        Operand::Register(register) => Some(register),
+        Operand::Value(_) => None,
+    }
+}
+
+fn next_value(operands: &mut impl Iterator<Item = Operand>) -> Option<Word> {
+    match operands.next()? {
+        Operand::Value(value) => Some(value),
+        Operand::Register(_) => None,
+    }
+}
+
 macro_rules! opcodes {
     ( $({
         $identifier:ident,
@@ -200,10 +266,26 @@ macro_rules! opcodes {
             Immediate,
         }
 
+        /// Position of an operand within the instruction (in big endian byte order).
+        #[derive(Serialize)]
+        pub struct OperandEncoding {
+            byte_offset: usize,
+            byte_size: usize,
+        }
+
+        impl OperandEncoding {
+            const IMMEDIATE: Self = Self {
+                byte_offset: IMMEDIATE_BYTE_OFFSET,
+                byte_size: Word::SIZE,
+            };
+        }
+
         #[derive(Serialize)]
         pub struct OpcodeDescription {
             opcode: u16,
             arguments: Vec<Argument>,
+            /// The encoding of each of the `arguments` (in the same order).
+            operand_encodings: Vec<OperandEncoding>,
             opcode_type: Option<&'static str>,
             cycles: usize,
             should_increment: bool,
@@ -217,11 +299,14 @@ macro_rules! opcodes {
                     {
                         #[allow(unused_mut)]
                         let mut arguments = Vec::<Argument>::new();
+                        #[allow(unused_mut)]
+                        let mut operand_encodings = Vec::<OperandEncoding>::new();
 
                         macro_rules! push_target {
                             () => {};
                             (target_address) => {
                                 arguments.push(Argument::Address);
+                                operand_encodings.push(OperandEncoding::IMMEDIATE);
                             };
                             (source_address) => {};
                             (immediate) => {};
@@ -229,6 +314,13 @@ macro_rules! opcodes {
                         push_target!($($type)?);
 
                         $(
+                            operand_encodings.push(OperandEncoding {
+                                byte_offset: REGISTERS_BYTE_OFFSET + arguments
+                                    .iter()
+                                    .filter(|argument| matches!(argument, Argument::Register(..)))
+                                    .count(),
+                                byte_size: 1,
+                            });
                             arguments.push(Argument::Register(
                                 RegisterUsage::$register_usage,
                                 stringify!($register_letter),
@@ -240,9 +332,11 @@ macro_rules! opcodes {
                             () => {};
                             (source_address) => {
                                 arguments.push(Argument::Address);
+                                operand_encodings.push(OperandEncoding::IMMEDIATE);
                             };
                             (immediate) => {
                                 arguments.push(Argument::Immediate);
+                                operand_encodings.push(OperandEncoding::IMMEDIATE);
                             };
                             (target_address) => {};
                         }
@@ -251,6 +345,7 @@ macro_rules! opcodes {
                         result.insert(stringify!($identifier), OpcodeDescription{
                             opcode: $code,
                             arguments,
+                            operand_encodings,
                             opcode_type: type_to_opcode_type!($($type)?),
                             cycles: $num_cycles,
                             should_increment: matches!(Increment::$should_increment, Increment::Yes),
@@ -261,6 +356,19 @@ macro_rules! opcodes {
                 result
             }
 
+            /// Maps the numeric opcodes (as used in the instruction encoding) to the mnemonics.
+            pub fn mnemonics_by_code() -> HashMap<u16, &'static str> {
+                HashMap::from([
+                    $( ($code, stringify!($identifier)), )+
+                ])
+            }
+
+            /// Returns the mnemonic and the description of the opcode with the given number.
+            pub fn from_code(code: u16) -> Option<(&'static str, OpcodeDescription)> {
+                let mnemonic = *Self::mnemonics_by_code().get(&code)?;
+                Self::as_hashmap().remove_entry(mnemonic)
+            }
+
             pub fn as_instruction(self) -> Instruction {
                 match self {
                     $(
@@ -277,6 +385,71 @@ macro_rules! opcodes {
                 }
             }
 
+            pub fn get_mnemonic(self) -> &'static str {
+                match self {
+                    $(
+                        Self::$identifier{ .. } => stringify!($identifier),
+                    )+
+                }
+            }
+
+            /// Returns the operands in the same order as the `arguments` of the corresponding
+            /// `OpcodeDescription` (target address, registers, source address or immediate).
+            #[allow(clippy::vec_init_then_push)]
+            pub fn get_operands(self) -> Vec<Operand> {
+                match self {
+                    $(
+                        Self::$identifier{ $( $register_name, )* $($type)? } => {
+                            #[allow(unused_mut)]
+                            let mut operands = Vec::new();
+
+                            $( operands.extend(target_address_operand!($type, $type)); )?
+                            $( operands.push(Operand::Register($register_name)); )*
+                            $( operands.extend(source_operand!($type, $type)); )?
+                            operands
+                        }
+                    )+
+                }
+            }
+
+            /// The inverse of `get_operands`: builds the opcode with the given mnemonic from its
+            /// operands.
+            pub fn from_operands(mnemonic: &str, operands: &[Operand]) -> Result<Self, &'static str> {
+                match mnemonic {
+                    $(
+                        stringify!($identifier) => {
+                            let build = || {
+                                let mut operands = operands.iter().copied();
+                                $( let target_address_value = take_target_address!($type, operands); )?
+                                $( let $register_name = next_register(&mut operands)?; )*
+                                $(
+                                    let $type = match target_address_value {
+                                        Some(value) => value,
+                                        None => next_value(&mut operands)?,
+                                    };
+                                )?
+                                match operands.next() {
+                                    Some(_) => None,
+                                    None => Some(Self::$identifier{ $( $register_name, )* $($type)? }),
+                                }
+                            };
+                            build().ok_or("invalid operands")
+                        }
+                    )+
+                    _ => Err("unknown mnemonic"),
+                }
+            }
+
+            /// Returns the memory address the instruction reads from or writes to (if it has an
+            /// address operand).
+            pub fn get_address(self) -> Option<Address> {
+                match self {
+                    $(
+                        Self::$identifier{ $( $type, )? .. } => None $( .or(address_operand!($type, $type)) )?,
+                    )+
+                }
+            }
+
             pub fn get_num_cycles(self) -> u8 {
                 match self {
                     $(
@@ -330,52 +503,73 @@ macro_rules! opcodes {
 opcodes!(
     // move instructions
     { MoveRegisterImmediate, 0x0000, registers(Target R register), immediate; cycles = 1, Increment::Yes, "move the value C into register R" },
-    { MoveRegisterAddress, 0x0001, registers(Target R register), source_address; cycles = 1, Increment::Yes, "move the value at address A into register R" },
+    { MoveRegisterAddress, 0x0001, registers(Target R register), source_address; cycles = 2, Increment::Yes, "move the value at address A into register R" },
     { MoveTargetSource, 0x0002, registers(Target T target, Source S source); cycles = 1, Increment::Yes, "move the contents of register S into register T" },
-    { MoveAddressRegister, 0x0003, registers(Source R register), target_address; cycles = 1, Increment::Yes, "move the contents of register R into memory at address A" },
-    { MoveTargetPointer, 0x0004, registers(Target T target, Source P pointer); cycles = 1, Increment::Yes, "move the contents addressed by the value of register P into register T" },
-    { MovePointerSource, 0x0005, registers(Target P pointer, Source S source); cycles = 1, Increment::Yes, "move the contents of register S into memory at address specified by register P" },
+    { MoveAddressRegister, 0x0003, registers(Source R register), target_address; cycles = 2, Increment::Yes, "move the contents of register R into memory at address A" },
+    { MoveTargetPointer, 0x0004, registers(Target T target, Source P pointer); cycles = 2, Increment::Yes, "move the contents addressed by the value of register P into register T" },
+    { MovePointerSource, 0x0005, registers(Target P pointer, Source S source); cycles = 2, Increment::Yes, "move the contents of register S into memory at address specified by register P" },
     // move instructions for byte-sized access
-    { MoveByteRegisterAddress, 0x0041, registers(Target R register), source_address; cycles = 1, Increment::Yes, "move the value at address A into register R (1 byte)"},
-    { MoveByteAddressRegister, 0x0042, registers(Source R register), target_address; cycles = 1, Increment::Yes, "move the contents of register R into memory at address A (1 byte)" },
-    { MoveByteTargetPointer, 0x0043, registers(Target T target, Source P pointer); cycles = 1, Increment::Yes, "move the contents addressed by the value of register P into register T (1 byte)" },
-    { MoveBytePointerSource, 0x0044, registers(Target P pointer, Source S source); cycles = 1, Increment::Yes, "move the contents of register S into memory at address specified by register P (1 byte)" },
+    { MoveByteRegisterAddress, 0x0041, registers(Target R register), source_address; cycles = 2, Increment::Yes, "move the value at address A into register R (1 byte)"},
+    { MoveByteAddressRegister, 0x0042, registers(Source R register), target_address; cycles = 2, Increment::Yes, "move the contents of register R into memory at address A (1 byte)" },
+    { MoveByteTargetPointer, 0x0043, registers(Target T target, Source P pointer); cycles = 2, Increment::Yes, "move the contents addressed by the value of register P into register T (1 byte)" },
+    { MoveBytePointerSource, 0x0044, registers(Target P pointer, Source S source); cycles = 2, Increment::Yes, "move the contents of register S into memory at address specified by register P (1 byte)" },
     // move instructions for halfword-sized access
-    { MoveHalfwordRegisterAddress, 0x0045, registers(Target R register), source_address; cycles = 1, Increment::Yes, "move the value at address A into register R (2 bytes)"},
-    { MoveHalfwordAddressRegister, 0x0046, registers(Source R register), target_address; cycles = 1, Increment::Yes, "move the contents of register R into memory at address A (2 bytes)" },
-    { MoveHalfwordTargetPointer, 0x0047, registers(Target T target, Source P pointer); cycles = 1, Increment::Yes, "move the contents addressed by the value of register P into register T (2 bytes)" },
-    { MoveHalfwordPointerSource, 0x0048, registers(Target P pointer, Source S source); cycles = 1, Increment::Yes, "move the contents of register S into memory at address specified by register P (2 bytes)" },
+    { MoveHalfwordRegisterAddress, 0x0045, registers(Target R register), source_address; cycles = 2, Increment::Yes, "move the value at address A into register R (2 bytes)"},
+    { MoveHalfwordAddressRegister, 0x0046, registers(Source R register), target_address; cycles = 2, Increment::Yes, "move the contents of register R into memory at address A (2 bytes)" },
+    { MoveHalfwordTargetPointer, 0x0047, registers(Target T target, Source P pointer); cycles = 2, Increment::Yes, "move the contents addressed by the value of register P into register T (2 bytes)" },
+    { MoveHalfwordPointerSource, 0x0048, registers(Target P pointer, Source S source); cycles = 2, Increment::Yes, "move the contents of register S into memory at address specified by register P (2 bytes)" },
     // offset move-instructions
-    { MovePointerSourceOffset, 0x0049, registers(Target P pointer, Source S source), immediate; cycles = 1, Increment::Yes, "move the value in register S into memory at address pointer + immediate" },
-    { MoveBytePointerSourceOffset, 0x004A, registers(Target P pointer, Source S source), immediate; cycles = 1, Increment::Yes, "move the value in register S into memory at address pointer + immediate (1 byte)" },
-    { MoveHalfwordPointerSourceOffset, 0x004B, registers(Target P pointer, Source S source), immediate; cycles = 1, Increment::Yes, "move the value in register S into memory at address pointer + immediate (2 bytes)" },
-    { MoveTargetPointerOffset, 0x004C, registers(Target T target, Source P pointer), immediate; cycles = 1, Increment::Yes, "move the contents addressed by the sum of the pointer and the immediate into the register T" },
-    { MoveByteTargetPointerOffset, 0x004D, registers(Target T target, Source P pointer), immediate; cycles = 1, Increment::Yes, "move the contents addressed by the sum of the pointer and the immediate into the register T" },
-    { MoveHalfwordTargetPointerOffset, 0x004E, registers(Target T target, Source P pointer), immediate; cycles = 1, Increment::Yes, "move the contents addressed by the sum of the pointer and the immediate into the register T" },
+    { MovePointerSourceOffset, 0x0049, registers(Target P pointer, Source S source), immediate; cycles = 2, Increment::Yes, "move the value in register S into memory at address pointer + immediate" },
+    { MoveBytePointerSourceOffset, 0x004A, registers(Target P pointer, Source S source), immediate; cycles = 2, Increment::Yes, "move the value in register S into memory at address pointer + immediate (1 byte)" },
+    { MoveHalfwordPointerSourceOffset, 0x004B, registers(Target P pointer, Source S source), immediate; cycles = 2, Increment::Yes, "move the value in register S into memory at address pointer + immediate (2 bytes)" },
+    { MoveTargetPointerOffset, 0x004C, registers(Target T target, Source P pointer), immediate; cycles = 2, Increment::Yes, "move the contents addressed by the sum of the pointer and the immediate into the register T" },
+    { MoveByteTargetPointerOffset, 0x004D, registers(Target T target, Source P pointer), immediate; cycles = 2, Increment::Yes, "move the contents addressed by the sum of the pointer and the immediate into the register T" },
+    { MoveHalfwordTargetPointerOffset, 0x004E, registers(Target T target, Source P pointer), immediate; cycles = 2, Increment::Yes, "move the contents addressed by the sum of the pointer and the immediate into the register T" },
+    // atomic instructions
+    { CompareExchange, 0x0075, registers(Source P pointer, Source E expected, Source N new, Target R result); cycles = 3, Increment::Yes, "read the value addressed by the value of register P, replace it with the value of register N if it equals the value of register E, store the read value into register R, set the zero flag if the value has been replaced" },
+    // block move instructions
+    { StoreRegisterRange, 0x0055, registers(Source P pointer, Source F first_register), immediate; cycles = 2, Increment::Yes, "store the contents of CC consecutive registers (starting at register F) into memory as consecutive words, starting at the address specified by register P" },
+    { LoadRegisterRange, 0x0056, registers(Source P pointer, Target F first_register), immediate; cycles = 2, Increment::Yes, "load CC consecutive words from memory (starting at the address specified by register P) into consecutive registers, starting at register F" },
 
     // halt and catch fire
     { HaltAndCatchFire, 0x0006, registers(); cycles = 1, Increment::No, "halt and catch fire" },
 
     // artimetic (sic!) instructions
-    { AddTargetLhsRhs, 0x0007, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "add the values in registers L and R, store the result in T, set zero and carry flags appropriately" },
-    { AddWithCarryTargetLhsRhs, 0x0034, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "add (with carry) the values in registers L and R, store the result in T, set zero and carry flags appropriately" },
-    { SubtractTargetLhsRhs, 0x0008, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "subtract (without carry) the values in registers L and R, store the result in T, set zero and carry flags appropriately" },
-    { SubtractWithCarryTargetLhsRhs, 0x0009, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "subtract (with carry) the values in registers L and R, store the result in T, set zero and carry flags appropriately" },
-    { MultiplyHighLowLhsRhs, 0x000A, registers(Target H high, Target T low, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "multiply the values in registers L and R, store the low part of the result in T, the high part in H, set zero and carry flags appropriately" },
-    { DivmodTargetModLhsRhs, 0x000B, registers(Target D result, Target M remainder, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "divmod the values in registers L and R, store the result in D and the remainder in M set zero and divide-by-zero flags appropriately" },
+    { AddTargetLhsRhs, 0x0007, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "add the values in registers L and R, store the result in T, set zero, negative, carry and overflow flags appropriately" },
+    { AddWithCarryTargetLhsRhs, 0x0034, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "add (with carry) the values in registers L and R, store the result in T, set zero, negative, carry and overflow flags appropriately" },
+    { SubtractTargetLhsRhs, 0x0008, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "subtract (without carry) the value in register R from the value in register L, store the result in T, set the zero and negative flags appropriately and the carry flag if a borrow occurred (i.e. if L < R) and the overflow flag if the signed result doesn't fit" },
+    { SubtractWithCarryTargetLhsRhs, 0x0009, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "subtract (with carry) the value in register R and the carry flag from the value in register L, store the result in T, set the zero and negative flags appropriately and the carry flag if a borrow occurred (i.e. if L < R + carry) and the overflow flag if the signed result doesn't fit" },
+    { MultiplyHighLowLhsRhs, 0x000A, registers(Target H high, Target T low, Source L lhs, Source R rhs); cycles = 4, Increment::Yes, "multiply the values in registers L and R, store the low part of the result in T, the high part in H, set zero, negative and carry flags appropriately" },
+    { WideMultiplyAdd, 0x005F, registers(Target H high, Target T low, Source L lhs, Source R rhs, Source A addend); cycles = 4, Increment::Yes, "multiply the values in registers L and R and add the value in register A (without any loss), store the low part of the result in T, the high part in H, set zero, negative and carry flags appropriately" },
+    { DivmodTargetModLhsRhs, 0x000B, registers(Target D result, Target M remainder, Source L lhs, Source R rhs); cycles = 4, Increment::Yes, "divmod the values in registers L and R, store the result in D and the remainder in M set zero, negative and divide-by-zero flags appropriately" },
+    { SignedDivmodTargetModLhsRhs, 0x005A, registers(Target D result, Target M remainder, Source L lhs, Source R rhs); cycles = 4, Increment::Yes, "divmod the values in registers L and R as signed values (truncating, the remainder has the sign of L), store the result in D and the remainder in M set zero, negative and divide-by-zero flags appropriately (the division of the smallest value by -1 wraps around)" },
+    { NegateTargetSource, 0x005D, registers(Target T target, Source S source); cycles = 1, Increment::Yes, "negate the value in register S (two's complement, the smallest value wraps around to itself), store the result in T, set zero and negative flags appropriately" },
+    { AbsoluteValueTargetSource, 0x005E, registers(Target T target, Source S source); cycles = 1, Increment::Yes, "calculate the absolute value of the value in register S interpreted as signed value (the smallest value wraps around to itself), store the result in T, set zero and negative flags appropriately" },
 
     // bitwise instructions
-    { AndTargetLhsRhs, 0x000C, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "and the values in registers LL and RR, store the result in TT, set zero flag appropriately" },
-    { OrTargetLhsRhs, 0x000D, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "or the values in registers LL and RR, store the result in TT, set zero flag appropriately" },
-    { XorTargetLhsRhs, 0x000E, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "xor the values in registers LL and RR, store the result in TT, set zero flag appropriately" },
-    { NotTargetSource, 0x000F, registers(Target T target, Source S source); cycles = 1, Increment::Yes, "not the value in register SS, store the result in TT, set zero flag appropriately" },
-    { LeftShiftTargetLhsRhs, 0x0010, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "left shift the value in register LL by RR bits, store the result in TT, set zero and carry flags appropriately" },
-    { RightShiftTargetLhsRhs, 0x0011, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "right shift the value in register LL by RR bits, store the result in TT, set zero and carry flags appropriately" },
-    { AddTargetSourceImmediate, 0x0012, registers(Target T target, Source S source), immediate; cycles = 1, Increment::Yes, "add the constant CC to the value in register SS and store the result in TT, set zero and carry flags appropriately" },
-    { SubtractTargetSourceImmediate, 0x0013, registers(Target T target, Source S source), immediate; cycles = 1, Increment::Yes, "subtract the constant CC from the value in register SS and store the result in TT, set zero and carry flags appropriately" },
+    { AndTargetLhsRhs, 0x000C, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "and the values in registers LL and RR, store the result in TT, set zero and negative flags appropriately" },
+    { OrTargetLhsRhs, 0x000D, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "or the values in registers LL and RR, store the result in TT, set zero and negative flags appropriately" },
+    { XorTargetLhsRhs, 0x000E, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "xor the values in registers LL and RR, store the result in TT, set zero and negative flags appropriately" },
+    { BitTestTargetSourceIndex, 0x0062, registers(Target T target, Source S source, Source I index); cycles = 1, Increment::Yes, "test the bit of the value in register SS whose index is given by register II (modulo 32), store the result as boolean (0 or 1) in TT, set zero and negative flags appropriately" },
+    { BitSetTargetSourceIndex, 0x0063, registers(Target T target, Source S source, Source I index); cycles = 1, Increment::Yes, "set the bit of the value in register SS whose index is given by register II (modulo 32), store the result in TT, set zero and negative flags appropriately" },
+    { BitClearTargetSourceIndex, 0x0064, registers(Target T target, Source S source, Source I index); cycles = 1, Increment::Yes, "clear the bit of the value in register SS whose index is given by register II (modulo 32), store the result in TT, set zero and negative flags appropriately" },
+    { NotTargetSource, 0x000F, registers(Target T target, Source S source); cycles = 1, Increment::Yes, "not the value in register SS, store the result in TT, set zero and negative flags appropriately" },
+    { CountLeadingZerosTargetSource, 0x0057, registers(Target T target, Source S source); cycles = 1, Increment::Yes, "count the leading zero bits of the value in register SS (32 for a value of 0), store the result in TT, set zero and negative flags appropriately" },
+    { CountTrailingZerosTargetSource, 0x0058, registers(Target T target, Source S source); cycles = 1, Increment::Yes, "count the trailing zero bits of the value in register SS (32 for a value of 0), store the result in TT, set zero and negative flags appropriately" },
+    { SignExtendByteTargetSource, 0x0066, registers(Target T target, Source S source); cycles = 1, Increment::Yes, "interpret the lowest byte of the value in register SS as signed value and sign-extend it to a word, store the result in TT, set zero and negative flags appropriately" },
+    { SignExtendHalfwordTargetSource, 0x0067, registers(Target T target, Source S source); cycles = 1, Increment::Yes, "interpret the lower halfword of the value in register SS as signed value and sign-extend it to a word, store the result in TT, set zero and negative flags appropriately" },
+    { LeftShiftTargetLhsRhs, 0x0010, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "left shift the value in register LL by RR bits, store the result in TT, set the zero and negative flags appropriately and the carry flag if any set bit has been shifted out" },
+    { RightShiftTargetLhsRhs, 0x0011, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "right shift the value in register LL by RR bits, store the result in TT, set the zero and negative flags appropriately and the carry flag if any set bit has been shifted out" },
+    { ArithmeticRightShiftTargetLhsRhs, 0x0050, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "arithmetic (sign-preserving) right shift the value in register LL by RR bits, store the result in TT, set zero, negative and carry flags appropriately" },
+    { RotateLeftTargetLhsRhs, 0x0053, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "rotate the value in register LL to the left by RR bits (modulo 32), store the result in TT, set zero and negative flags appropriately" },
+    { RotateRightTargetLhsRhs, 0x0054, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "rotate the value in register LL to the right by RR bits (modulo 32), store the result in TT, set zero and negative flags appropriately" },
+    { AddTargetSourceImmediate, 0x0012, registers(Target T target, Source S source), immediate; cycles = 1, Increment::Yes, "add the constant CC to the value in register SS and store the result in TT, set zero, negative, carry and overflow flags appropriately" },
+    { SubtractTargetSourceImmediate, 0x0013, registers(Target T target, Source S source), immediate; cycles = 1, Increment::Yes, "subtract the constant CC from the value in register SS and store the result in TT, set the zero and negative flags appropriately and the carry flag if a borrow occurred (i.e. if SS < CC) and the overflow flag if the signed result doesn't fit" },
+    { IncrementRegister, 0x0060, registers(Target R register); cycles = 1, Increment::Yes, "increment the value in register R by one, set zero, negative, carry and overflow flags appropriately" },
+    { DecrementRegister, 0x0061, registers(Target R register); cycles = 1, Increment::Yes, "decrement the value in register R by one, set zero, negative, carry and overflow flags appropriately" },
 
     // comparison
-    { CompareTargetLhsRhs, 0x0014, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "compare the values in registers LL and RR, store the result (Word::MAX, 0, 1) in TT, set zero flag appropriately" },
+    { CompareTargetLhsRhs, 0x0014, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "compare the values in registers LL and RR, store the result (Word::MAX, 0, 1) in TT, set zero and negative flags appropriately" },
     { BoolCompareEquals, 0x003A, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "checks whether the values in registers L and R are equal and stores the result as boolean (0 or 1) in T" },
     { BoolCompareNotEquals, 0x003B, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "checks whether the values in registers L and R are not equal and stores the result as boolean (0 or 1) in T" },
     { BoolCompareGreater, 0x003C, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "checks whether the value in registers L is greater than the value in register R and stores the result as boolean (0 or 1) in T" },
@@ -384,18 +578,21 @@ opcodes!(
     { BoolCompareLessOrEquals, 0x003F, registers(Target T target, Source L lhs, Source R rhs); cycles = 1, Increment::Yes, "checks whether the value in registers L is less than or equals the value in register R and stores the result as boolean (0 or 1) in T" },
 
     // stack instructions
-    { PushRegister, 0x0015, registers(Source R register); cycles = 1, Increment::Yes, "pushes the value of register RR onto the stack" },
-    { PushImmediate, 0x004F, registers(), immediate; cycles = 1, Increment::Yes, "pushes the immediate value onto the stack" },
-    { PopRegister, 0x0016, registers(Target R register); cycles = 1, Increment::Yes, "pops from the stack and stores the value in register RR" },
-    { Pop, 0x0040, registers(); cycles = 1, Increment::Yes, "pops from the stack and discards the value" },
-    { CallImmediate, 0x0017, registers(), immediate; cycles = 1, Increment::No, "push the current instruction pointer onto the stack and jump to the specified address" },
-    { CallRegister, 0x0036, registers(Source R register); cycles = 1, Increment::No, "push the current instruction pointer onto the stack and jump to the address stored in register R" },
-    { CallPointer, 0x0037, registers(Source P pointer); cycles = 1, Increment::No, "push the current instruction pointer onto the stack and jump to the address stored in memory at the location specified by the value in register P" },
-    { Return, 0x0018, registers(); cycles = 1, Increment::No, "pop the return address from the stack and jump to it" },
+    { PushRegister, 0x0015, registers(Source R register); cycles = 2, Increment::Yes, "pushes the value of register RR onto the stack" },
+    { PushImmediate, 0x004F, registers(), immediate; cycles = 2, Increment::Yes, "pushes the immediate value onto the stack" },
+    { PopRegister, 0x0016, registers(Target R register); cycles = 2, Increment::Yes, "pops from the stack and stores the value in register RR" },
+    { Pop, 0x0040, registers(); cycles = 2, Increment::Yes, "pops from the stack and discards the value" },
+    { PushFlags, 0x0076, registers(); cycles = 2, Increment::Yes, "pushes the value of the flags register onto the stack" },
+    { PopFlags, 0x0077, registers(); cycles = 2, Increment::Yes, "pops from the stack and stores the value in the flags register (including the interrupt enable flag, bits that don't belong to a flag are ignored)" },
+    { CallImmediate, 0x0017, registers(), immediate; cycles = 2, Increment::No, "push the current instruction pointer onto the stack and jump to the specified address" },
+    { CallRegister, 0x0036, registers(Source R register); cycles = 2, Increment::No, "push the current instruction pointer onto the stack and jump to the address stored in register R" },
+    { CallPointer, 0x0037, registers(Source P pointer); cycles = 3, Increment::No, "push the current instruction pointer onto the stack and jump to the address stored in memory at the location specified by the value in register P" },
+    { Return, 0x0018, registers(); cycles = 2, Increment::No, "pop the return address from the stack and jump to it" },
 
     // unconditional jumps
     { JumpImmediate, 0x0019, registers(), immediate; cycles = 1, Increment::No, "jump to the given address" },
     { JumpRegister, 0x001A, registers(Source R register); cycles = 1, Increment::No, "jump to the address stored in register R" },
+    { JumpRelativeImmediate, 0x005C, registers(), immediate; cycles = 1, Increment::No, "jump by the given offset (interpreted as signed 32 bit value) relative to the address of this instruction" },
 
     // conditional jumps, address given as immediate
     { JumpImmediateIfEqual, 0x001B, registers(Source C comparison), immediate; cycles = 1, Increment::No, "jump to the specified address if the comparison result in register C corresponds to \"equality\"" },
@@ -409,6 +606,8 @@ opcodes!(
     { JumpImmediateIfNotCarry, 0x0023, registers(), immediate; cycles = 1, Increment::No, "jump to the specified address if the carry flag is not set" },
     { JumpImmediateIfDivideByZero, 0x0024, registers(), immediate; cycles = 1, Increment::No, "jump to the specified address if the divide by zero flag is set" },
     { JumpImmediateIfNotDivideByZero, 0x0025, registers(), immediate; cycles = 1, Increment::No, "jump to the specified address if the divide by zero flag is not set" },
+    { JumpImmediateIfNegative, 0x0079, registers(), immediate; cycles = 1, Increment::No, "jump to the specified address if the negative flag is set" },
+    { JumpImmediateIfNotNegative, 0x007A, registers(), immediate; cycles = 1, Increment::No, "jump to the specified address if the negative flag is not set" },
 
     // conditional jumps, address given as register
     { JumpRegisterIfEqual, 0x0026, registers(Source P pointer, Source C comparison); cycles = 1, Increment::No, "jump to the address specified in register P if the comparison result in register C corresponds to \"equality\"" },
@@ -428,22 +627,130 @@ opcodes!(
 
     // input
     { GetKeyState, 0x0032, registers(Target T target, Source K keycode); cycles = 1, Increment::Yes, "store the keystate (1 = held down, 0 = not held down) of the key specified by register K into register T and set the zero flag appropriately" },
+    { GetKeyEvent, 0x006B, registers(Target T target); cycles = 1, Increment::Yes, "pop the oldest keyboard event from the event queue and store it into register T (lower 16 bits: keycode, bit 16: set if the key was pressed, cleared if it was released), store 0 and set the zero flag if the queue is empty" },
+    { GetCharInput, 0x006C, registers(Target T target); cycles = 1, Increment::Yes, "pop the oldest typed character from the input queue and store its Unicode code point into register T, store 0 and set the zero flag if no character is pending" },
+    { GetMousePosition, 0x006D, registers(Target X x_target, Target Y y_target); cycles = 1, Increment::Yes, "store the mouse position in display pixels into registers X and Y" },
+    { GetMouseButtons, 0x006E, registers(Target T target); cycles = 1, Increment::Yes, "store the mask of currently pressed mouse buttons (bit 0: left, bit 1: right, bit 2: middle) into register T and set the zero flag appropriately" },
+
+    // interrupts
+    { EnableInterrupts, 0x006F, registers(); cycles = 1, Increment::Yes, "set the interrupt enable flag, so that interrupt sources can transfer control to their handlers from the interrupt vector table" },
+    { DisableInterrupts, 0x0070, registers(); cycles = 1, Increment::Yes, "clear the interrupt enable flag" },
+    { ClearFlags, 0x0078, registers(); cycles = 1, Increment::Yes, "clear all flags (including the interrupt enable flag)" },
+    { ReturnFromInterrupt, 0x0071, registers(); cycles = 2, Increment::No, "pop the return address and the flags from the stack, jump to the return address and restore the flags (including the interrupt enable flag)" },
+
+    // host services
+    { SystemCall, 0x0072, registers(), immediate; cycles = 1, Increment::Yes, "call the host service with number C, arguments are passed in registers 0 and 1 and the result is stored in register 0 (0: write the zero-terminated string at the address in register 0 to stdout, 1: read a line from stdin into the buffer at the address in register 0 with the size in register 1, store the length or Word::MAX at the end of the input)" },
+
+    // terminal output
+    { TerminalWriteByte, 0x006A, registers(Source S source); cycles = 1, Increment::Yes, "write the lowest byte of register S to the terminal at the cursor position and advance the cursor (carriage return, line feed and ANSI escape sequences for moving the cursor and clearing the screen are interpreted)" },
 
     // Timing
     { PollTime, 0x0033, registers(Target H high, Target L low); cycles = 1, Increment::Yes, "store the number of milliseconds since the UNIX epoch into registers high and low" },
+    { PollMonotonic, 0x0073, registers(Target H high, Target L low); cycles = 1, Increment::Yes, "store the number of milliseconds since an arbitrary point in time (e.g. the start of the emulator) into registers high and low, unlike PollTime the value never decreases (use it to measure durations)" },
+    { SleepMilliseconds, 0x0074, registers(Source S source); cycles = 1, Increment::Yes, "pause the execution (without advancing the cycle count) until the number of milliseconds in register S has passed on the clock of PollMonotonic" },
+
+    // random numbers
+    { Random, 0x0065, registers(Target T target); cycles = 1, Increment::Yes, "store a pseudo-random number into register T" },
 
     // Rendering
-    { SwapFramebuffers, 0x0035, registers(); cycles = 1, Increment::Yes, "swap the display buffers" },
+    { SwapFramebuffers, 0x0035, registers(); cycles = 1, Increment::Yes, "swap the display buffers (with vsync enabled, the execution stalls until the swap has been applied with the next rendered frame)" },
     { InvisibleFramebufferAddress, 0x0038, registers(Target T target); cycles = 1, Increment::Yes, "get the start address of the framebuffer that's currently invisible (use the address to draw without tearing)" },
+    { VisibleFramebufferAddress, 0x007B, registers(Target T target); cycles = 1, Increment::Yes, "get the start address of the framebuffer that's currently visible (use the address to read back what's on the screen)" },
+    { BeginFrame, 0x0051, registers(); cycles = 1, Increment::Yes, "start a frame: swaps requested before the matching EndFrame are coalesced and the display does not pick up framebuffer contents until the frame is committed" },
+    { EndFrame, 0x0052, registers(); cycles = 1, Increment::Yes, "commit the current frame, applying a pending swap of the display buffers (if any)" },
+    { PollFrameCount, 0x007C, registers(Target T target); cycles = 1, Increment::Yes, "store the number of frames that have been rendered so far (the lower 32 bits) into register T" },
+    { WaitForVSync, 0x007D, registers(); cycles = 1, Increment::Yes, "pause the execution (without advancing the cycle count) until the next frame has been rendered" },
+    { IsHeadless, 0x005B, registers(Target T target); cycles = 1, Increment::Yes, "store 1 into register T if there is no display (e.g. when running without graphics), otherwise store 0" },
+    { SetDisplayMode, 0x0069, registers(), immediate; cycles = 1, Increment::Yes, "set the display mode to C (0: four bytes RGBA per pixel, 1: one byte per pixel indexing into the RGBA palette at PALETTE_START)" },
 
     // Debugging and profiling
     { PollCycleCountHighLow, 0x0039, registers(Target H high, Target L low); cycles = 1, Increment::Yes, "store the current cycle (64 bit value) count into registers H and L (H: most significant bytes, L: least significant bytes)" },
-    { DumpRegisters, 0xFFFF, registers(); cycles = 1, Increment::Yes, "dump the contents of all registers into the file 'registers_YYYY-MM-DD_X.bin' where YYYY-MM-DD is the current date and X is an increasing number" },
-    { DumpMemory, 0xFFFE, registers(); cycles = 1, Increment::Yes, "dump the contents of the whole memory into the file 'memory_YYYY-MM-DD_X.bin' where YYYY-MM-DD is the current date and X is an increasing number" },
+    { GetProgramCounterOffset, 0x0059, registers(Target T target); cycles = 1, Increment::Yes, "store the offset of the current instruction pointer relative to the entry point into register T, set the carry flag if the instruction pointer is below the entry point" },
+    { DumpRegisters, 0xFFFF, registers(); cycles = 1, Increment::Yes, "dump the contents of all registers into the file 'registers_YYYY-MM-DD_X.bin' (or '.json', depending on the emulator's register dump format) where YYYY-MM-DD is the current date and X is an increasing number" },
+    { SaveScreenshot, 0x0068, registers(Source P pointer); cycles = 1, Increment::Yes, "save the currently visible framebuffer as PNG image into the file in the dump directory whose zero-terminated name (without path separators or '..') is stored in memory at the location specified by the value in register P" },
+    { DumpMemory, 0xFFFE, registers(); cycles = 1, Increment::Yes, "dump the contents of the whole memory into the file 'memory_YYYY-MM-DD_X.bin' (or the compressed '.mem', depending on the emulator's memory dump format) where YYYY-MM-DD is the current date and X is an increasing number" },
     { AssertRegisterRegister, 0xFFFD, registers(Source E expected, Source A actual); cycles = 1, Increment::Yes, "assert that the expected register value equals the actual register value (behavior of the VM on a failed assertion is implementation defined)" },
     { AssertRegisterImmediate, 0xFFFC, registers(Source A actual), immediate; cycles = 1, Increment::Yes, "assert that the actual register value equals the immediate (behavior of the VM on a failed assertion is implementation defined)"},
-    { AssertPointerImmediate, 0xFFFB, registers(Source P pointer), immediate; cycles = 1, Increment::Yes, "assert that the value in memory pointed at by P equals the immediate (behavior of the VM on a failed assertion is implementation defined)"},
-    { DebugBreak, 0xFFFA, registers(); cycles = 1, Increment::Yes, "behavior is implementation defined" },
+    { AssertPointerImmediate, 0xFFFB, registers(Source P pointer), immediate; cycles = 2, Increment::Yes, "assert that the value in memory pointed at by P equals the immediate (behavior of the VM on a failed assertion is implementation defined)"},
+    { DebugBreak, 0xFFFA, registers(); cycles = 1, Increment::Yes, "break into the attached debugger (as if a breakpoint was set at this instruction), do nothing if no debugger is attached" },
     { PrintRegister, 0xFFF9, registers(Source R register); cycles = 1, Increment::Yes, "prints the value of the register as debug output"},
     { Checkpoint, 0xFFF8, registers(), immediate; cycles = 1, Increment::Yes, "makes the emulator check the value of the internal checkpoint counter, fails on mismatch" },
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds the opcode with the given mnemonic with a distinct value for every register slot, so
+    /// that mixed-up register positions are detected.
+    fn build_opcode(mnemonic: &str, description: &OpcodeDescription, value: Word) -> Opcode {
+        let operands: Vec<_> = (0..)
+            .zip(&description.arguments)
+            .map(|(i, argument)| match argument {
+                Argument::Register(..) => {
+                    Operand::Register(Register((value as u8).wrapping_add(i)))
+                }
+                Argument::Address | Argument::Immediate => Operand::Value(value),
+            })
+            .collect();
+        Opcode::from_operands(mnemonic, &operands).unwrap()
+    }
+
+    #[test]
+    fn every_opcode_survives_encode_decode_round_trip() {
+        let descriptions = Opcode::as_hashmap();
+        for (mnemonic, description) in &descriptions {
+            for value in [0, 1, 0x7F, 0xDEAD_BEEF, Word::MAX] {
+                let opcode = build_opcode(mnemonic, description, value);
+                let instruction = opcode.as_instruction();
+                assert_eq!(
+                    (instruction >> (Instruction::BITS - u16::BITS)) as u16,
+                    description.opcode,
+                    "{mnemonic}"
+                );
+                assert_eq!(Opcode::try_from(instruction), Ok(opcode), "{mnemonic}");
+            }
+        }
+    }
+
+    #[test]
+    fn codes_are_unique_and_map_back_to_their_opcode() {
+        let descriptions = Opcode::as_hashmap();
+        let by_code = Opcode::mnemonics_by_code();
+        assert_eq!(by_code.len(), descriptions.len());
+        for (mnemonic, description) in &descriptions {
+            assert_eq!(by_code.get(&description.opcode), Some(mnemonic));
+            let (found_mnemonic, found_description) =
+                Opcode::from_code(description.opcode).unwrap();
+            assert_eq!(found_mnemonic, *mnemonic);
+            assert_eq!(found_description.opcode, description.opcode);
+        }
+        assert!(Opcode::from_code(0xEEEE).is_none());
+    }
+
+    #[test]
+    fn json_contains_operand_encodings() {
+        let json = serde_json::to_value(Opcode::as_hashmap()).unwrap();
+        let encodings = |mnemonic: &str| -> Vec<(u64, u64)> {
+            json[mnemonic]["operand_encodings"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|encoding| {
+                    (
+                        encoding["byte_offset"].as_u64().unwrap(),
+                        encoding["byte_size"].as_u64().unwrap(),
+                    )
+                })
+                .collect()
+        };
+        // target address, source register
+        assert_eq!(encodings("MoveAddressRegister"), [(4, 4), (2, 1)]);
+        assert_eq!(
+            encodings("AddTargetSourceImmediate"),
+            [(2, 1), (3, 1), (4, 4)]
+        );
+        assert_eq!(encodings("AddTargetLhsRhs"), [(2, 1), (3, 1), (4, 1)]);
+        assert_eq!(encodings("NoOp"), []);
+    }
+}
@@ -1,4 +1,16 @@
-use crate::{cursor::Cursor, display, keyboard::Keyboard, timer::Timer};
+use std::time::Instant;
+
+use crate::{
+    cursor::Cursor,
+    display::{self, MockDisplay, Resolution},
+    host_io::HostIo,
+    keyboard::{KeyEvent, KeyState, Keyboard},
+    mouse::Mouse,
+    replay::InputLog,
+    terminal::TerminalWriter,
+    timer::Timer,
+    Word,
+};
 
 pub trait Periphery {
     type Handle;
@@ -10,6 +22,27 @@ pub trait Periphery {
         &mut self,
     ) -> &mut dyn display::Display<Handle = Self::Handle, Thread = Self::Thread>;
     fn cursor(&mut self) -> &mut Cursor;
+    fn terminal_writer(&mut self) -> &mut TerminalWriter;
+    fn mouse(&mut self) -> &mut Mouse;
+    fn host_io(&mut self) -> &mut HostIo;
+    /// Queries the timer through the input log, i.e. the value is recorded or replayed if
+    /// requested.
+    fn poll_time(&mut self, cycle: u64) -> u64;
+    /// Queries the monotonic clock of the timer through the input log (see `poll_time`).
+    fn poll_monotonic_time(&mut self, cycle: u64) -> u64;
+    /// Queries the keyboard through the input log (see `poll_time`).
+    fn poll_keystate(&mut self, key: Word, cycle: u64) -> KeyState;
+    /// Removes the oldest event from the keyboard's event queue through the input log (see
+    /// `poll_time`).
+    fn poll_key_event(&mut self, cycle: u64) -> Option<KeyEvent>;
+    /// Removes the oldest typed character through the input log (see `poll_time`).
+    fn poll_char(&mut self, cycle: u64) -> Option<char>;
+    /// Queries the mouse position through the input log (see `poll_time`).
+    fn poll_mouse_position(&mut self, cycle: u64) -> (Word, Word);
+    /// Queries the mouse buttons through the input log (see `poll_time`).
+    fn poll_mouse_buttons(&mut self, cycle: u64) -> Word;
+    /// Reads a line from the host's stdin through the input log (see `poll_time`).
+    fn read_line(&mut self, cycle: u64) -> Option<Vec<u8>>;
 }
 
 pub struct PeripheryImplementation<Display: display::Display> {
@@ -17,6 +50,31 @@ pub struct PeripheryImplementation<Display: display::Display> {
     pub keyboard: Keyboard,
     pub display: Display,
     pub cursor: Cursor,
+    pub terminal_writer: TerminalWriter,
+    pub mouse: Mouse,
+    pub input_log: InputLog,
+    pub host_io: HostIo,
+}
+
+impl PeripheryImplementation<MockDisplay> {
+    /// Creates a periphery without a window or host I/O (e.g. for tests or when embedding the
+    /// emulator): the timer always reports 0, no key is ever pressed and the output of
+    /// `SystemCall` is discarded.
+    pub fn headless(resolution: Resolution) -> Self {
+        Self {
+            timer: Timer::new(|| 0),
+            keyboard: Keyboard::new(Box::new(|_| KeyState::Up)),
+            display: MockDisplay::new(&mut (), &(), resolution),
+            cursor: Cursor {
+                visible: false,
+                time_of_next_toggle: Instant::now() + Cursor::TOGGLE_INTERVAL,
+            },
+            terminal_writer: TerminalWriter::default(),
+            mouse: Mouse::new(0, 0),
+            input_log: InputLog::Off,
+            host_io: HostIo::null(),
+        }
+    }
 }
 
 impl<Display: display::Display> Periphery for PeripheryImplementation<Display> {
@@ -40,4 +98,58 @@ impl<Display: display::Display> Periphery for PeripheryImplementation<Display> {
     fn cursor(&mut self) -> &mut Cursor {
         &mut self.cursor
     }
+
+    fn terminal_writer(&mut self) -> &mut TerminalWriter {
+        &mut self.terminal_writer
+    }
+
+    fn mouse(&mut self) -> &mut Mouse {
+        &mut self.mouse
+    }
+
+    fn host_io(&mut self) -> &mut HostIo {
+        &mut self.host_io
+    }
+
+    fn poll_time(&mut self, cycle: u64) -> u64 {
+        let timer = &mut self.timer;
+        self.input_log.time(cycle, || timer.get_ms_since_epoch())
+    }
+
+    fn poll_monotonic_time(&mut self, cycle: u64) -> u64 {
+        let timer = &mut self.timer;
+        self.input_log
+            .monotonic_time(cycle, || timer.get_monotonic_ms())
+    }
+
+    fn poll_keystate(&mut self, key: Word, cycle: u64) -> KeyState {
+        let keyboard = &mut self.keyboard;
+        self.input_log
+            .key_state(cycle, key, || keyboard.get_keystate(key))
+    }
+
+    fn poll_key_event(&mut self, cycle: u64) -> Option<KeyEvent> {
+        let keyboard = &mut self.keyboard;
+        self.input_log.key_event(cycle, || keyboard.poll_event())
+    }
+
+    fn poll_char(&mut self, cycle: u64) -> Option<char> {
+        let keyboard = &mut self.keyboard;
+        self.input_log.character(cycle, || keyboard.poll_char())
+    }
+
+    fn poll_mouse_position(&mut self, cycle: u64) -> (Word, Word) {
+        let mouse = &self.mouse;
+        self.input_log.mouse_position(cycle, || mouse.position())
+    }
+
+    fn poll_mouse_buttons(&mut self, cycle: u64) -> Word {
+        let mouse = &self.mouse;
+        self.input_log.mouse_buttons(cycle, || mouse.buttons())
+    }
+
+    fn read_line(&mut self, cycle: u64) -> Option<Vec<u8>> {
+        let host_io = &mut self.host_io;
+        self.input_log.line(cycle, || host_io.read_line())
+    }
 }
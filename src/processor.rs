@@ -1,28 +1,70 @@
 #![allow(non_upper_case_globals)]
 
 use std::ops::{Index, IndexMut};
+use std::path::PathBuf;
 
+use crate::display::DisplayMode;
+use crate::dumper::{self, MemoryDumpFormat, RegisterDumpFormat};
+use crate::host_io;
 use crate::keyboard::KeyState;
 use crate::opcodes::Opcode;
 use crate::periphery::Periphery;
+use crate::profiler::Profile;
+use crate::static_assert;
 use crate::{address_constants, Byte, Halfword};
-use crate::{dumper, static_assert};
-use crate::{memory::Memory, Address, Instruction, Word};
+use crate::{
+    memory::{Memory, MemoryError},
+    Address, Instruction, Word,
+};
 use crate::{Register, Size};
 use bitflags::bitflags;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hasher};
 
 const _: () = static_assert(address_constants::ENTRY_POINT as usize % Instruction::SIZE == 0);
 
+/// Unwraps the result of a checked memory (or stack) access or makes the current instruction fail.
+macro_rules! try_memory_access {
+    ($access:expr) => {
+        match $access {
+            Ok(value) => value,
+            Err(_) => return ExecutionResult::Error,
+        }
+    };
+}
+
 pub enum Direction {
     Forwards,
     Backwards,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ExecutionResult {
     Error,
     Normal,
     Halted,
+    /// An `Assert*` instruction or a `Checkpoint` at `address` failed. The instruction pointer
+    /// still points to the failed instruction.
+    AssertionFailed {
+        expected: Word,
+        actual: Word,
+        address: Address,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackError {
+    Overflow,
+    Underflow,
+    Memory(MemoryError),
+}
+
+impl From<MemoryError> for StackError {
+    fn from(error: MemoryError) -> Self {
+        Self::Memory(error)
+    }
 }
 
 macro_rules! define_flags {
@@ -50,7 +92,10 @@ macro_rules! define_flags {
 define_flags![
     (Zero, shift = 0),
     (Carry, shift = 1),
-    (DivideByZero, shift = 2)
+    (DivideByZero, shift = 2),
+    (InterruptsEnabled, shift = 3),
+    (Overflow, shift = 4),
+    (Negative, shift = 5)
 ];
 
 pub struct Registers<const SIZE: usize>([Word; SIZE]);
@@ -58,7 +103,6 @@ pub struct Registers<const SIZE: usize>([Word; SIZE]);
 impl<const SIZE: usize> Registers<SIZE> {
     const _ASSERT_VALID_REGISTER_COUNT: () = assert!(SIZE - 1 < u8::MAX as usize);
 
-    #[cfg(feature = "debugger")]
     pub fn contents(&self) -> &[Word; SIZE] {
         &self.0
     }
@@ -84,14 +128,57 @@ pub type CachedInstruction<ConcretePeriphery> =
     Box<dyn Fn(&mut Processor, &mut Memory, &mut ConcretePeriphery) -> ExecutionResult>;
 
 pub struct InstructionCache<ConcretePeriphery: Periphery> {
-    pub cache: Box<[CachedInstruction<ConcretePeriphery>; Memory::SIZE / Instruction::SIZE]>,
+    pub cache: Box<[CachedInstruction<ConcretePeriphery>]>,
+    /// Whether the instruction of each slot has been executed since it was last compiled.
+    pub executed: Box<[bool]>,
+    /// Number of distinct instructions that have been executed, counting each slot once per
+    /// compilation (i.e. again after self-modifying code overwrote it).
+    pub num_distinct: u64,
+}
+
+/// The complete state of a `Processor`, see `Processor::snapshot`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessorSnapshot {
+    pub registers: Vec<Word>,
+    pub cycle_count: u64,
+    pub checkpoint_counter: Word,
+    pub rng_state: Word,
+    pub sleep_until: Option<u64>,
+    pub frame_count: u64,
+    pub waiting_for_vsync: bool,
+    pub timer_interrupt_pending: bool,
+}
+
+/// The registers as written by `DumpRegisters` in the `RegisterDumpFormat::Json` format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisterDump {
+    pub instruction_pointer: Address,
+    pub stack_pointer: Address,
+    /// The state of every flag by the name used in the `flags` of the `json` command.
+    pub flags: BTreeMap<String, bool>,
+    /// All registers including the special ones above.
+    pub registers: Vec<Word>,
 }
 
 pub struct Processor {
     pub registers: Registers<{ NUM_REGISTERS }>,
     cycle_count: u64,
-    exit_on_halt: bool,
     checkpoint_counter: Word,
+    rng_state: Word,
+    profile: Option<Profile>,
+    register_dump_format: RegisterDumpFormat,
+    memory_dump_format: MemoryDumpFormat,
+    dump_directory: PathBuf,
+    /// The monotonic time (see `Timer::get_monotonic_ms`) until which `SleepMilliseconds` pauses
+    /// the execution.
+    sleep_until: Option<u64>,
+    /// Number of frames rendered so far (see `Machine::render_tick`).
+    frame_count: u64,
+    /// Set while a `WaitForVSync` waits for the next rendered frame.
+    waiting_for_vsync: bool,
+    /// Set when the timer interrupt interval elapses while interrupts are disabled, so that the
+    /// interrupt is taken as soon as they are enabled again.
+    timer_interrupt_pending: bool,
 }
 
 impl Processor {
@@ -99,18 +186,121 @@ impl Processor {
     pub const INSTRUCTION_POINTER: Register = Register((NUM_REGISTERS - 2) as _);
     pub const STACK_POINTER: Register = Register((NUM_REGISTERS - 1) as _);
 
-    pub fn new(exit_on_halt: bool) -> Self {
+    pub fn new() -> Self {
         let mut result = Self {
             registers: Registers([0; NUM_REGISTERS]),
             cycle_count: 0,
-            exit_on_halt,
             checkpoint_counter: 0,
+            rng_state: 0,
+            profile: None,
+            register_dump_format: RegisterDumpFormat::default(),
+            memory_dump_format: MemoryDumpFormat::default(),
+            dump_directory: dumper::DEFAULT_DIRECTORY.into(),
+            sleep_until: None,
+            frame_count: 0,
+            waiting_for_vsync: false,
+            timer_interrupt_pending: false,
         };
         result.registers[Self::INSTRUCTION_POINTER] = address_constants::ENTRY_POINT;
         result.registers[Self::STACK_POINTER] = address_constants::STACK_START;
+        // every `RandomState` is initialized with different keys
+        result.seed_rng(RandomState::new().build_hasher().finish() as Word);
         result
     }
 
+    pub fn snapshot(&self) -> ProcessorSnapshot {
+        ProcessorSnapshot {
+            registers: self.registers.0.to_vec(),
+            cycle_count: self.cycle_count,
+            checkpoint_counter: self.checkpoint_counter,
+            rng_state: self.rng_state,
+            sleep_until: self.sleep_until,
+            frame_count: self.frame_count,
+            waiting_for_vsync: self.waiting_for_vsync,
+            timer_interrupt_pending: self.timer_interrupt_pending,
+        }
+    }
+
+    /// Restores a snapshot taken with `snapshot`. Fails if the snapshot doesn't contain
+    /// exactly `NUM_REGISTERS` registers or if undefined flags are set.
+    pub fn restore(&mut self, snapshot: &ProcessorSnapshot) -> Result<(), String> {
+        if snapshot.registers.len() != NUM_REGISTERS {
+            return Err(format!(
+                "expected {NUM_REGISTERS} registers, got {}",
+                snapshot.registers.len()
+            ));
+        }
+        let flags = snapshot.registers[Self::FLAGS.0 as usize];
+        if Flag::from_bits(flags).is_none() {
+            return Err(format!("invalid flags value {flags:#010x}"));
+        }
+        self.registers.0.copy_from_slice(&snapshot.registers);
+        self.cycle_count = snapshot.cycle_count;
+        self.checkpoint_counter = snapshot.checkpoint_counter;
+        self.rng_state = snapshot.rng_state;
+        self.sleep_until = snapshot.sleep_until;
+        self.frame_count = snapshot.frame_count;
+        self.waiting_for_vsync = snapshot.waiting_for_vsync;
+        self.timer_interrupt_pending = snapshot.timer_interrupt_pending;
+        Ok(())
+    }
+
+    pub fn sleep_until(&self) -> Option<u64> {
+        self.sleep_until
+    }
+
+    /// Ends a pause started by `SleepMilliseconds`.
+    pub fn wake_up(&mut self) {
+        self.sleep_until = None;
+    }
+
+    /// The number of frames rendered so far (as returned by `PollFrameCount`).
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Returns whether the execution is paused by `WaitForVSync` until the next rendered frame.
+    pub fn is_waiting_for_vsync(&self) -> bool {
+        self.waiting_for_vsync
+    }
+
+    /// Counts a rendered frame, which ends a pause started by `WaitForVSync`.
+    pub fn advance_frame_count(&mut self) {
+        self.frame_count += 1;
+        self.waiting_for_vsync = false;
+    }
+
+    /// The value the next `Checkpoint` instruction expects.
+    pub fn checkpoint_counter(&self) -> Word {
+        self.checkpoint_counter
+    }
+
+    /// Makes the next `Checkpoint` instruction expect 0 again, e.g. before running another
+    /// checkpoint-instrumented ROM on the same processor.
+    pub fn reset_checkpoints(&mut self) {
+        self.checkpoint_counter = 0;
+    }
+
+    /// Seeds the pseudo-random number generator used by the `Random` instruction. Using
+    /// the same seed always results in the same sequence of random numbers.
+    pub fn seed_rng(&mut self, seed: Word) {
+        // xorshift gets stuck on a state of zero
+        self.rng_state = match seed {
+            0 => 0x9E37_79B9,
+            seed => seed,
+        };
+    }
+
+    fn next_random_number(&mut self) -> Word {
+        // xorshift32
+        let mut state = self.rng_state;
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        self.rng_state = state;
+        state
+    }
+
     pub fn get_flag(&self, flag: Flag) -> bool {
         self.registers[Self::FLAGS] & flag.bits == flag.bits
     }
@@ -121,6 +311,13 @@ impl Processor {
         self.registers[Self::FLAGS] = flags.bits;
     }
 
+    /// Sets the zero and negative flags according to the result of an arithmetic or logic
+    /// instruction.
+    fn set_result_flags(&mut self, result: Word) {
+        self.set_flag(Flag::Zero, result == 0);
+        self.set_flag(Flag::Negative, result >> (Word::BITS - 1) == 1);
+    }
+
     pub fn get_stack_pointer(&self) -> Address {
         self.registers[Self::STACK_POINTER]
     }
@@ -143,14 +340,30 @@ impl Processor {
         }
     }
 
-    pub fn stack_push(&mut self, memory: &mut Memory, value: Word) {
-        memory.write_data(self.get_stack_pointer(), value);
+    pub fn stack_push(&mut self, memory: &mut Memory, value: Word) -> Result<(), StackError> {
+        let stack_pointer = self.get_stack_pointer() as usize;
+        if stack_pointer < address_constants::STACK_START as usize {
+            return Err(StackError::Underflow);
+        }
+        if stack_pointer + Word::SIZE > address_constants::STACK_END as usize {
+            return Err(StackError::Overflow);
+        }
+        memory.try_write_data(stack_pointer as Address, value)?;
         self.advance_stack_pointer(Word::SIZE, Direction::Forwards);
+        Ok(())
     }
 
-    pub fn stack_pop(&mut self, memory: &mut Memory) -> Word {
+    pub fn stack_pop(&mut self, memory: &mut Memory) -> Result<Word, StackError> {
+        let stack_pointer = self.get_stack_pointer() as usize;
+        if stack_pointer < address_constants::STACK_START as usize + Word::SIZE {
+            return Err(StackError::Underflow);
+        }
+        if stack_pointer > address_constants::STACK_END as usize {
+            return Err(StackError::Overflow);
+        }
+        let value = memory.try_read_data((stack_pointer - Word::SIZE) as Address)?;
         self.advance_stack_pointer(Word::SIZE, Direction::Backwards);
-        memory.read_data(self.get_stack_pointer())
+        Ok(value)
     }
 
     pub fn set_instruction_pointer(&mut self, address: Address) {
@@ -173,6 +386,44 @@ impl Processor {
         }
     }
 
+    /// Sets the file format the `DumpRegisters` instruction writes.
+    pub fn set_register_dump_format(&mut self, format: RegisterDumpFormat) {
+        self.register_dump_format = format;
+    }
+
+    /// Sets the file format the `DumpMemory` instruction writes.
+    pub fn set_memory_dump_format(&mut self, format: MemoryDumpFormat) {
+        self.memory_dump_format = format;
+    }
+
+    /// Sets the directory `DumpRegisters` and `DumpMemory` write to.
+    pub fn set_dump_directory(&mut self, directory: impl Into<PathBuf>) {
+        self.dump_directory = directory.into();
+    }
+
+    pub fn register_dump(&self) -> RegisterDump {
+        let flags = self.registers[Self::FLAGS];
+        RegisterDump {
+            instruction_pointer: self.get_instruction_pointer(),
+            stack_pointer: self.get_stack_pointer(),
+            flags: Flag::as_hashmap()
+                .into_iter()
+                .map(|(name, shift)| (name.to_string(), (flags >> shift) & 1 == 1))
+                .collect(),
+            registers: self.registers.0.to_vec(),
+        }
+    }
+
+    /// Starts counting the executed instructions per opcode (see `profile`).
+    pub fn enable_profiling(&mut self) {
+        self.profile.get_or_insert_with(Profile::default);
+    }
+
+    /// Returns the profile if profiling has been enabled.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
     pub fn get_cycle_count(&self) -> u64 {
         self.cycle_count
     }
@@ -181,16 +432,37 @@ impl Processor {
         self.cycle_count += amount;
     }
 
+    /// Accounts for the cycles of `opcode` and moves on to the next instruction unless the opcode
+    /// sets the instruction pointer by itself.
+    pub fn complete_instruction(&mut self, opcode: Opcode) {
+        self.increase_cycle_count(opcode.get_num_cycles().into());
+        if opcode.should_increment_instruction_pointer() {
+            self.advance_instruction_pointer(Direction::Forwards);
+        }
+    }
+
+    /// Generates the cached instruction for the (aligned) address. Invalid opcodes result in an
+    /// error when being executed.
+    pub fn generate_cached_instruction_at<ConcretePeriphery: Periphery>(
+        memory: &Memory,
+        address: Address,
+    ) -> CachedInstruction<ConcretePeriphery> {
+        match memory.read_opcode(address) {
+            Ok(opcode) => Self::generate_cached_instruction(opcode),
+            Err(_) => Box::new(
+                |_: &mut Processor, _: &mut Memory, _: &mut ConcretePeriphery| {
+                    ExecutionResult::Error
+                },
+            ),
+        }
+    }
+
     pub fn generate_cached_instruction<ConcretePeriphery: Periphery>(
         opcode: Opcode,
     ) -> CachedInstruction<ConcretePeriphery> {
         use crate::processor::Opcode::*;
-        let handle_cycle_count_and_instruction_pointer = move |processor: &mut Processor| {
-            processor.increase_cycle_count(opcode.get_num_cycles().into());
-            if opcode.should_increment_instruction_pointer() {
-                processor.advance_instruction_pointer(Direction::Forwards);
-            }
-        };
+        let handle_cycle_count_and_instruction_pointer =
+            move |processor: &mut Processor| processor.complete_instruction(opcode);
 
         match opcode {
             MoveRegisterImmediate {
@@ -212,7 +484,8 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    processor.registers[register] = memory.read_data(address);
+                    processor.registers[register] =
+                        try_memory_access!(memory.try_read_data(address));
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -234,7 +507,9 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    memory.write_data(address, processor.registers[register]);
+                    try_memory_access!(
+                        memory.try_write_data(address, processor.registers[register])
+                    );
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -243,7 +518,8 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    processor.registers[target] = memory.read_data(processor.registers[pointer]);
+                    processor.registers[target] =
+                        try_memory_access!(memory.try_read_data(processor.registers[pointer]));
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -253,12 +529,34 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    memory.write_data(processor.registers[pointer], processor.registers[source]);
+                    try_memory_access!(memory
+                        .try_write_data(processor.registers[pointer], processor.registers[source]));
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
             )
                 as CachedInstruction<ConcretePeriphery>,
+            CompareExchange {
+                pointer,
+                expected,
+                new,
+                result,
+            } => Box::new(
+                move |processor: &mut Processor,
+                      memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let address = processor.registers[pointer];
+                    let old_value = try_memory_access!(memory.try_read_data(address));
+                    let swap = old_value == processor.registers[expected];
+                    if swap {
+                        try_memory_access!(memory.try_write_data(address, processor.registers[new]));
+                    }
+                    processor.registers[result] = old_value;
+                    processor.set_flag(Flag::Zero, swap);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
             MoveByteRegisterAddress {
                 register,
                 source_address,
@@ -266,7 +564,8 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    processor.registers[register] = memory.read_byte(source_address) as Word;
+                    processor.registers[register] =
+                        try_memory_access!(memory.try_read_byte(source_address)) as Word;
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -278,7 +577,9 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    memory.write_byte(target_address, processor.registers[register] as u8);
+                    try_memory_access!(
+                        memory.try_write_byte(target_address, processor.registers[register] as u8)
+                    );
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -288,7 +589,8 @@ impl Processor {
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
                     processor.registers[target] =
-                        memory.read_byte(processor.registers[pointer]) as Word;
+                        try_memory_access!(memory.try_read_byte(processor.registers[pointer]))
+                            as Word;
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -298,10 +600,10 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    memory.write_byte(
+                    try_memory_access!(memory.try_write_byte(
                         processor.registers[pointer],
                         processor.registers[source] as u8,
-                    );
+                    ));
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -314,7 +616,8 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    processor.registers[register] = memory.read_halfword(source_address).into();
+                    processor.registers[register] =
+                        try_memory_access!(memory.try_read_halfword(source_address)).into();
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -326,7 +629,8 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    memory.write_halfword(target_address, processor.registers[register] as u16);
+                    try_memory_access!(memory
+                        .try_write_halfword(target_address, processor.registers[register] as u16));
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -336,7 +640,8 @@ impl Processor {
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
                     processor.registers[target] =
-                        memory.read_halfword(processor.registers[pointer]).into();
+                        try_memory_access!(memory.try_read_halfword(processor.registers[pointer]))
+                            .into();
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -346,10 +651,10 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    memory.write_halfword(
+                    try_memory_access!(memory.try_write_halfword(
                         processor.registers[pointer],
                         processor.registers[source] as u16,
-                    );
+                    ));
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -363,10 +668,10 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    memory.write_data(
+                    try_memory_access!(memory.try_write_data(
                         processor.registers[pointer].wrapping_add(immediate),
                         processor.registers[source],
-                    );
+                    ));
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -379,10 +684,10 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    memory.write_byte(
+                    try_memory_access!(memory.try_write_byte(
                         processor.registers[pointer].wrapping_add(immediate),
                         processor.registers[source] as Byte,
-                    );
+                    ));
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -395,10 +700,10 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    memory.write_halfword(
+                    try_memory_access!(memory.try_write_halfword(
                         processor.registers[pointer].wrapping_add(immediate),
                         processor.registers[source] as Halfword,
-                    );
+                    ));
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -412,7 +717,8 @@ impl Processor {
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
                     processor.registers[target] =
-                        memory.read_data(processor.registers[pointer].wrapping_add(immediate));
+                        try_memory_access!(memory
+                            .try_read_data(processor.registers[pointer].wrapping_add(immediate)));
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -425,8 +731,9 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    processor.registers[target] = memory
-                        .read_byte(processor.registers[pointer].wrapping_add(immediate))
+                    processor.registers[target] =
+                        try_memory_access!(memory
+                            .try_read_byte(processor.registers[pointer].wrapping_add(immediate)))
                         .into();
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
@@ -440,22 +747,67 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    processor.registers[target] = memory
-                        .read_halfword(processor.registers[pointer].wrapping_add(immediate))
-                        .into();
+                    processor.registers[target] = try_memory_access!(memory
+                        .try_read_halfword(processor.registers[pointer].wrapping_add(immediate)))
+                    .into();
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
             ) as CachedInstruction<ConcretePeriphery>,
-            HaltAndCatchFire {} => Box::new(
+            StoreRegisterRange {
+                pointer,
+                first_register,
+                immediate: count,
+            } => Box::new(
                 move |processor: &mut Processor,
-                      _memory: &mut Memory,
+                      memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    println!("HALT AND CATCH FIRE!");
-                    if processor.exit_on_halt {
-                        std::process::exit(0);
+                    let start = processor.registers[pointer] as usize;
+                    let first = first_register.0 as usize;
+                    let count = count as usize;
+                    if first + count > NUM_REGISTERS || start + count * Word::SIZE > memory.size() {
+                        return ExecutionResult::Error;
+                    }
+                    for i in 0..count {
+                        try_memory_access!(memory.try_write_data(
+                            (start + i * Word::SIZE) as Address,
+                            processor.registers[Register((first + i) as u8)],
+                        ));
                     }
                     handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            LoadRegisterRange {
+                pointer,
+                first_register,
+                immediate: count,
+            } => Box::new(
+                move |processor: &mut Processor,
+                      memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let start = processor.registers[pointer] as usize;
+                    let first = first_register.0 as usize;
+                    let count = count as usize;
+                    if first + count > NUM_REGISTERS || start + count * Word::SIZE > memory.size() {
+                        return ExecutionResult::Error;
+                    }
+                    for i in 0..count {
+                        processor.registers[Register((first + i) as u8)] = try_memory_access!(
+                            memory.try_read_data((start + i * Word::SIZE) as Address)
+                        );
+                    }
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            HaltAndCatchFire {} => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    periphery.host_io().flush();
+                    eprintln!("HALT AND CATCH FIRE!");
+                    handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Halted
                 },
             ) as CachedInstruction<ConcretePeriphery>,
@@ -463,12 +815,12 @@ impl Processor {
                 move |processor: &mut Processor,
                       _memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    let lhs = processor.registers[lhs];
-                    let rhs = processor.registers[rhs];
-                    let did_overflow;
-                    (processor.registers[target], did_overflow) = lhs.overflowing_add(rhs);
-                    processor.set_flag(Flag::Zero, processor.registers[target] == 0);
-                    processor.set_flag(Flag::Carry, did_overflow);
+                    let (result, carry, overflow) =
+                        add_with_carry(processor.registers[lhs], processor.registers[rhs], false);
+                    processor.registers[target] = result;
+                    processor.set_result_flags(result);
+                    processor.set_flag(Flag::Carry, carry);
+                    processor.set_flag(Flag::Overflow, overflow);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -478,12 +830,15 @@ impl Processor {
                 move |processor: &mut Processor,
                       _memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    let lhs = processor.registers[lhs];
-                    let rhs = processor.registers[rhs];
-                    let did_overflow;
-                    (processor.registers[target], did_overflow) = lhs.overflowing_sub(rhs);
-                    processor.set_flag(Flag::Zero, processor.registers[target] == 0);
-                    processor.set_flag(Flag::Carry, did_overflow);
+                    let (result, borrow, overflow) = subtract_with_borrow(
+                        processor.registers[lhs],
+                        processor.registers[rhs],
+                        false,
+                    );
+                    processor.registers[target] = result;
+                    processor.set_result_flags(result);
+                    processor.set_flag(Flag::Carry, borrow);
+                    processor.set_flag(Flag::Overflow, overflow);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -493,21 +848,15 @@ impl Processor {
                 move |processor: &mut Processor,
                       _memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    let lhs = processor.registers[lhs];
-                    let rhs = processor.registers[rhs];
-                    let carry_flag_set = processor.get_flag(Flag::Carry);
-                    let did_overflow;
-                    (processor.registers[target], did_overflow) = lhs.overflowing_sub(rhs);
-                    let did_overflow_after_subtracting_carry;
-                    (
-                        processor.registers[target],
-                        did_overflow_after_subtracting_carry,
-                    ) = processor.registers[target].overflowing_sub(carry_flag_set as _);
-                    processor.set_flag(Flag::Zero, processor.registers[target] == 0);
-                    processor.set_flag(
-                        Flag::Carry,
-                        did_overflow || did_overflow_after_subtracting_carry,
+                    let (result, borrow, overflow) = subtract_with_borrow(
+                        processor.registers[lhs],
+                        processor.registers[rhs],
+                        processor.get_flag(Flag::Carry),
                     );
+                    processor.registers[target] = result;
+                    processor.set_result_flags(result);
+                    processor.set_flag(Flag::Carry, borrow);
+                    processor.set_flag(Flag::Overflow, overflow);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -527,7 +876,30 @@ impl Processor {
                     let result = lhs as u64 * rhs as u64;
                     processor.registers[high] = (result >> 32) as u32;
                     processor.registers[low] = result as u32;
-                    processor.set_flag(Flag::Zero, processor.registers[low] == 0);
+                    processor.set_result_flags(processor.registers[low]);
+                    processor.set_flag(Flag::Carry, processor.registers[high] > 0);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            WideMultiplyAdd {
+                high,
+                low,
+                lhs,
+                rhs,
+                addend,
+            } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let lhs = processor.registers[lhs];
+                    let rhs = processor.registers[rhs];
+                    let addend = processor.registers[addend];
+                    // cannot overflow: (2^32 - 1)^2 + (2^32 - 1) = 2^64 - 2^32
+                    let result = lhs as u64 * rhs as u64 + addend as u64;
+                    processor.registers[high] = (result >> 32) as u32;
+                    processor.registers[low] = result as u32;
+                    processor.set_result_flags(processor.registers[low]);
                     processor.set_flag(Flag::Carry, processor.registers[high] > 0);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
@@ -547,18 +919,68 @@ impl Processor {
                     if rhs == 0 {
                         processor.registers[result] = 0;
                         processor.registers[remainder] = lhs;
-                        processor.set_flag(Flag::Zero, true);
+                        processor.set_result_flags(0);
                         processor.set_flag(Flag::DivideByZero, true);
                     } else {
                         (processor.registers[result], processor.registers[remainder]) =
                             (lhs / rhs, lhs % rhs);
-                        processor.set_flag(Flag::Zero, processor.registers[result] == 0);
+                        processor.set_result_flags(processor.registers[result]);
+                        processor.set_flag(Flag::DivideByZero, false);
+                    }
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            SignedDivmodTargetModLhsRhs {
+                result,
+                remainder,
+                lhs,
+                rhs,
+            } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let lhs = processor.registers[lhs] as i32;
+                    let rhs = processor.registers[rhs] as i32;
+                    if rhs == 0 {
+                        processor.registers[result] = 0;
+                        processor.registers[remainder] = lhs as Word;
+                        processor.set_result_flags(0);
+                        processor.set_flag(Flag::DivideByZero, true);
+                    } else {
+                        (processor.registers[result], processor.registers[remainder]) =
+                            (lhs.wrapping_div(rhs) as Word, lhs.wrapping_rem(rhs) as Word);
+                        processor.set_result_flags(processor.registers[result]);
                         processor.set_flag(Flag::DivideByZero, false);
                     }
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
             ) as CachedInstruction<ConcretePeriphery>,
+            NegateTargetSource { target, source } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    processor.registers[target] =
+                        0i32.wrapping_sub(processor.registers[source] as i32) as Word;
+                    processor.set_result_flags(processor.registers[target]);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
+            AbsoluteValueTargetSource { target, source } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    processor.registers[target] =
+                        (processor.registers[source] as i32).wrapping_abs() as Word;
+                    processor.set_result_flags(processor.registers[target]);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
             AndTargetLhsRhs { target, lhs, rhs } => Box::new(
                 move |processor: &mut Processor,
                       _memory: &mut Memory,
@@ -566,7 +988,7 @@ impl Processor {
                     let lhs = processor.registers[lhs];
                     let rhs = processor.registers[rhs];
                     processor.registers[target] = lhs & rhs;
-                    processor.set_flag(Flag::Zero, processor.registers[target] == 0);
+                    processor.set_result_flags(processor.registers[target]);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -579,7 +1001,7 @@ impl Processor {
                     let lhs = processor.registers[lhs];
                     let rhs = processor.registers[rhs];
                     processor.registers[target] = lhs | rhs;
-                    processor.set_flag(Flag::Zero, processor.registers[target] == 0);
+                    processor.set_result_flags(processor.registers[target]);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -592,18 +1014,110 @@ impl Processor {
                     let lhs = processor.registers[lhs];
                     let rhs = processor.registers[rhs];
                     processor.registers[target] = lhs ^ rhs;
-                    processor.set_flag(Flag::Zero, processor.registers[target] == 0);
+                    processor.set_result_flags(processor.registers[target]);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
             )
                 as CachedInstruction<ConcretePeriphery>,
+            BitTestTargetSourceIndex {
+                target,
+                source,
+                index,
+            } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let source = processor.registers[source];
+                    let index = processor.registers[index];
+                    processor.registers[target] = (source >> (index % Word::BITS)) & 1;
+                    processor.set_result_flags(processor.registers[target]);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            BitSetTargetSourceIndex {
+                target,
+                source,
+                index,
+            } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let source = processor.registers[source];
+                    let index = processor.registers[index];
+                    processor.registers[target] = source | (1 << (index % Word::BITS));
+                    processor.set_result_flags(processor.registers[target]);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            BitClearTargetSourceIndex {
+                target,
+                source,
+                index,
+            } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let source = processor.registers[source];
+                    let index = processor.registers[index];
+                    processor.registers[target] = source & !(1 << (index % Word::BITS));
+                    processor.set_result_flags(processor.registers[target]);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
             NotTargetSource { target, source } => Box::new(
                 move |processor: &mut Processor,
                       _memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
                     processor.registers[target] = !processor.registers[source];
-                    processor.set_flag(Flag::Zero, processor.registers[target] == 0);
+                    processor.set_result_flags(processor.registers[target]);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
+            CountLeadingZerosTargetSource { target, source } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    processor.registers[target] = processor.registers[source].leading_zeros();
+                    processor.set_result_flags(processor.registers[target]);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
+            CountTrailingZerosTargetSource { target, source } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    processor.registers[target] = processor.registers[source].trailing_zeros();
+                    processor.set_result_flags(processor.registers[target]);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
+            SignExtendByteTargetSource { target, source } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    processor.registers[target] = processor.registers[source] as i8 as Word;
+                    processor.set_result_flags(processor.registers[target]);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
+            SignExtendHalfwordTargetSource { target, source } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    processor.registers[target] = processor.registers[source] as i16 as Word;
+                    processor.set_result_flags(processor.registers[target]);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -615,14 +1129,15 @@ impl Processor {
                       _periphery: &mut ConcretePeriphery| {
                     let lhs = processor.registers[lhs];
                     let rhs = processor.registers[rhs];
-                    if rhs > Word::BITS {
+                    // the carry flag is set if at least one set bit is shifted out
+                    if rhs >= Word::BITS {
                         processor.registers[target] = 0;
-                        processor.set_flag(Flag::Zero, true);
-                        processor.set_flag(Flag::Carry, lhs > 0);
+                        processor.set_result_flags(0);
+                        processor.set_flag(Flag::Carry, lhs != 0);
                     } else {
                         let result = lhs << rhs;
                         processor.registers[target] = result;
-                        processor.set_flag(Flag::Zero, result == 0);
+                        processor.set_result_flags(result);
                         processor.set_flag(Flag::Carry, rhs > lhs.leading_zeros());
                     }
                     handle_cycle_count_and_instruction_pointer(processor);
@@ -636,14 +1151,15 @@ impl Processor {
                       _periphery: &mut ConcretePeriphery| {
                     let lhs = processor.registers[lhs];
                     let rhs = processor.registers[rhs];
-                    if rhs > Word::BITS {
+                    // the carry flag is set if at least one set bit is shifted out
+                    if rhs >= Word::BITS {
                         processor.registers[target] = 0;
-                        processor.set_flag(Flag::Zero, true);
-                        processor.set_flag(Flag::Carry, lhs > 0);
+                        processor.set_result_flags(0);
+                        processor.set_flag(Flag::Carry, lhs != 0);
                     } else {
                         let result = lhs >> rhs;
                         processor.registers[target] = result;
-                        processor.set_flag(Flag::Zero, result == 0);
+                        processor.set_result_flags(result);
                         processor.set_flag(Flag::Carry, rhs > lhs.trailing_zeros());
                     }
                     handle_cycle_count_and_instruction_pointer(processor);
@@ -651,6 +1167,54 @@ impl Processor {
                 },
             )
                 as CachedInstruction<ConcretePeriphery>,
+            ArithmeticRightShiftTargetLhsRhs { target, lhs, rhs } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let lhs = processor.registers[lhs];
+                    let rhs = processor.registers[rhs];
+                    if rhs >= Word::BITS {
+                        let result = ((lhs as i32) >> (Word::BITS - 1)) as Word;
+                        processor.registers[target] = result;
+                        processor.set_result_flags(result);
+                        processor.set_flag(Flag::Carry, lhs > 0);
+                    } else {
+                        let result = ((lhs as i32) >> rhs) as Word;
+                        processor.registers[target] = result;
+                        processor.set_result_flags(result);
+                        processor.set_flag(Flag::Carry, rhs > lhs.trailing_zeros());
+                    }
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
+            RotateLeftTargetLhsRhs { target, lhs, rhs } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let lhs = processor.registers[lhs];
+                    let rhs = processor.registers[rhs];
+                    processor.registers[target] = lhs.rotate_left(rhs % Word::BITS);
+                    processor.set_result_flags(processor.registers[target]);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
+            RotateRightTargetLhsRhs { target, lhs, rhs } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let lhs = processor.registers[lhs];
+                    let rhs = processor.registers[rhs];
+                    processor.registers[target] = lhs.rotate_right(rhs % Word::BITS);
+                    processor.set_result_flags(processor.registers[target]);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
             AddTargetSourceImmediate {
                 target,
                 source,
@@ -659,100 +1223,216 @@ impl Processor {
                 move |processor: &mut Processor,
                       _memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    let carry;
-                    (processor.registers[target], carry) =
-                        processor.registers[source].overflowing_add(immediate);
-                    processor.set_flag(Flag::Zero, processor.registers[target] == 0);
-                    processor.set_flag(Flag::Carry, carry);
+                    let (result, carry, overflow) =
+                        add_with_carry(processor.registers[source], immediate, false);
+                    processor.registers[target] = result;
+                    processor.set_result_flags(result);
+                    processor.set_flag(Flag::Carry, carry);
+                    processor.set_flag(Flag::Overflow, overflow);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            SubtractTargetSourceImmediate {
+                target,
+                source,
+                immediate,
+            } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let (result, borrow, overflow) =
+                        subtract_with_borrow(processor.registers[source], immediate, false);
+                    processor.registers[target] = result;
+                    processor.set_result_flags(result);
+                    processor.set_flag(Flag::Carry, borrow);
+                    processor.set_flag(Flag::Overflow, overflow);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            IncrementRegister { register } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let (result, carry, overflow) =
+                        add_with_carry(processor.registers[register], 1, false);
+                    processor.registers[register] = result;
+                    processor.set_result_flags(result);
+                    processor.set_flag(Flag::Carry, carry);
+                    processor.set_flag(Flag::Overflow, overflow);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            DecrementRegister { register } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let (result, carry, overflow) =
+                        subtract_with_borrow(processor.registers[register], 1, false);
+                    processor.registers[register] = result;
+                    processor.set_result_flags(result);
+                    processor.set_flag(Flag::Carry, carry);
+                    processor.set_flag(Flag::Overflow, overflow);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            CompareTargetLhsRhs { target, lhs, rhs } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let lhs = processor.registers[lhs];
+                    let rhs = processor.registers[rhs];
+                    processor.registers[target] = match lhs.cmp(&rhs) {
+                        std::cmp::Ordering::Less => Word::MAX,
+                        std::cmp::Ordering::Equal => 0,
+                        std::cmp::Ordering::Greater => 1,
+                    };
+                    processor.set_result_flags(processor.registers[target]);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
+            PushRegister { register } => Box::new(
+                move |processor: &mut Processor,
+                      memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    try_memory_access!(processor.stack_push(memory, processor.registers[register]));
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            PushImmediate { immediate } => Box::new(
+                move |processor: &mut Processor,
+                      memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    try_memory_access!(processor.stack_push(memory, immediate));
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            PopRegister { register } => Box::new(
+                move |processor: &mut Processor,
+                      memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    processor.registers[register] = try_memory_access!(processor.stack_pop(memory));
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            PushFlags {} => Box::new(
+                move |processor: &mut Processor,
+                      memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    try_memory_access!(
+                        processor.stack_push(memory, processor.registers[Self::FLAGS])
+                    );
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
             ) as CachedInstruction<ConcretePeriphery>,
-            SubtractTargetSourceImmediate {
-                target,
-                source,
-                immediate,
-            } => Box::new(
+            PopFlags {} => Box::new(
                 move |processor: &mut Processor,
-                      _memory: &mut Memory,
+                      memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    processor.registers[target] =
-                        processor.registers[source].wrapping_sub(immediate);
-                    processor.set_flag(Flag::Zero, processor.registers[target] == 0);
-                    processor.set_flag(Flag::Carry, immediate > processor.registers[source]);
+                    let flags = try_memory_access!(processor.stack_pop(memory));
+                    processor.registers[Self::FLAGS] = Flag::from_bits_truncate(flags).bits;
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
             ) as CachedInstruction<ConcretePeriphery>,
-            CompareTargetLhsRhs { target, lhs, rhs } => Box::new(
+            ClearFlags {} => Box::new(
                 move |processor: &mut Processor,
                       _memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    let lhs = processor.registers[lhs];
-                    let rhs = processor.registers[rhs];
-                    processor.registers[target] = match lhs.cmp(&rhs) {
-                        std::cmp::Ordering::Less => Word::MAX,
-                        std::cmp::Ordering::Equal => 0,
-                        std::cmp::Ordering::Greater => 1,
-                    };
-                    processor.set_flag(Flag::Zero, processor.registers[target] == 0);
+                    processor.registers[Self::FLAGS] = 0;
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
-            )
-                as CachedInstruction<ConcretePeriphery>,
-            PushRegister { register } => Box::new(
+            ) as CachedInstruction<ConcretePeriphery>,
+            Pop {} => Box::new(
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    processor.stack_push(memory, processor.registers[register]);
+                    try_memory_access!(processor.stack_pop(memory));
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
             ) as CachedInstruction<ConcretePeriphery>,
-            PushImmediate { immediate } => Box::new(
+            CallImmediate { immediate: address } => Box::new(
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    processor.stack_push(memory, immediate);
+                    try_memory_access!(processor.push_instruction_pointer(memory));
+                    processor.set_instruction_pointer(address);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
-            ) as CachedInstruction<ConcretePeriphery>,
-            PopRegister { register } => Box::new(
+            )
+                as CachedInstruction<ConcretePeriphery>,
+            SystemCall { immediate } => Box::new(
                 move |processor: &mut Processor,
                       memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    let argument0 = processor.registers[Register(0)];
+                    let argument1 = processor.registers[Register(1)];
+                    match immediate {
+                        host_io::SYSTEM_CALL_WRITE_STRING => {
+                            try_memory_access!(periphery.host_io().write_string(memory, argument0));
+                        }
+                        host_io::SYSTEM_CALL_READ_LINE => {
+                            processor.registers[Register(0)] =
+                                match periphery.read_line(processor.get_cycle_count()) {
+                                    Some(line) => try_memory_access!(host_io::store_line(
+                                        memory, argument0, argument1, line
+                                    )),
+                                    None => Word::MAX,
+                                };
+                        }
+                        _ => return ExecutionResult::Error,
+                    }
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            EnableInterrupts {} => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    processor.registers[register] = processor.stack_pop(memory);
+                    processor.set_flag(Flag::InterruptsEnabled, true);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
             ) as CachedInstruction<ConcretePeriphery>,
-            Pop {} => Box::new(
+            DisableInterrupts {} => Box::new(
                 move |processor: &mut Processor,
-                      memory: &mut Memory,
+                      _memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    processor.stack_pop(memory);
+                    processor.set_flag(Flag::InterruptsEnabled, false);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
             ) as CachedInstruction<ConcretePeriphery>,
-            CallImmediate { immediate: address } => Box::new(
+            ReturnFromInterrupt {} => Box::new(
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    processor.push_instruction_pointer(memory);
-                    processor.set_instruction_pointer(address);
+                    let return_address = try_memory_access!(processor.stack_pop(memory));
+                    let flags = try_memory_access!(processor.stack_pop(memory));
+                    processor.set_instruction_pointer(return_address);
+                    processor.registers[Self::FLAGS] = Flag::from_bits_truncate(flags).bits;
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
-            )
-                as CachedInstruction<ConcretePeriphery>,
+            ) as CachedInstruction<ConcretePeriphery>,
             Return {} => Box::new(
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    let return_address = processor.stack_pop(memory);
+                    let return_address = try_memory_access!(processor.stack_pop(memory));
                     processor.set_instruction_pointer(return_address);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
@@ -768,6 +1448,28 @@ impl Processor {
                 },
             )
                 as CachedInstruction<ConcretePeriphery>,
+            JumpRelativeImmediate { immediate: offset } => Box::new(
+                move |processor: &mut Processor,
+                      memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let target = match processor
+                        .get_instruction_pointer()
+                        .checked_add_signed(offset as i32)
+                    {
+                        Some(target)
+                            if (target as usize) < memory.size()
+                                && target.is_multiple_of(Instruction::SIZE as Address) =>
+                        {
+                            target
+                        }
+                        _ => return ExecutionResult::Error,
+                    };
+                    processor.set_instruction_pointer(target);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
             JumpRegister { register } => Box::new(
                 move |processor: &mut Processor,
                       _memory: &mut Memory,
@@ -930,6 +1632,32 @@ impl Processor {
                 },
             )
                 as CachedInstruction<ConcretePeriphery>,
+            JumpImmediateIfNegative { immediate: address } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    match processor.get_flag(Flag::Negative) {
+                        true => processor.set_instruction_pointer(address),
+                        false => processor.advance_instruction_pointer(Direction::Forwards),
+                    }
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
+            JumpImmediateIfNotNegative { immediate: address } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    match processor.get_flag(Flag::Negative) {
+                        false => processor.set_instruction_pointer(address),
+                        true => processor.advance_instruction_pointer(Direction::Forwards),
+                    }
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
             JumpRegisterIfEqual {
                 pointer,
                 comparison,
@@ -1099,9 +1827,10 @@ impl Processor {
                       _memory: &mut Memory,
                       periphery: &mut ConcretePeriphery| {
                     processor.registers[target] = matches!(
-                        periphery
-                            .keyboard()
-                            .get_keystate(processor.registers[keycode] as _),
+                        periphery.poll_keystate(
+                            processor.registers[keycode] as _,
+                            processor.get_cycle_count()
+                        ),
                         KeyState::Down
                     )
                     .into();
@@ -1111,31 +1840,118 @@ impl Processor {
                 },
             )
                 as CachedInstruction<ConcretePeriphery>,
+            GetKeyEvent { target } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    processor.registers[target] = periphery
+                        .poll_key_event(processor.get_cycle_count())
+                        .map_or(0, |event| event.encode());
+                    processor.set_flag(Flag::Zero, processor.registers[target] == 0);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            GetCharInput { target } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    processor.registers[target] = periphery
+                        .poll_char(processor.get_cycle_count())
+                        .map_or(0, |character| character as Word);
+                    processor.set_flag(Flag::Zero, processor.registers[target] == 0);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            GetMousePosition { x_target, y_target } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    let (x, y) = periphery.poll_mouse_position(processor.get_cycle_count());
+                    processor.registers[x_target] = x;
+                    processor.registers[y_target] = y;
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
+            GetMouseButtons { target } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    processor.registers[target] =
+                        periphery.poll_mouse_buttons(processor.get_cycle_count());
+                    processor.set_flag(Flag::Zero, processor.registers[target] == 0);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            TerminalWriteByte { source } => Box::new(
+                move |processor: &mut Processor,
+                      memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    periphery
+                        .terminal_writer()
+                        .write_byte(memory, processor.registers[source] as Byte);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
             PollTime { high, low } => Box::new(
                 move |processor: &mut Processor,
                       _memory: &mut Memory,
                       periphery: &mut ConcretePeriphery| {
-                    let time = periphery.timer().get_ms_since_epoch();
+                    let time = periphery.poll_time(processor.get_cycle_count());
+                    processor.registers[low] = time as Word;
+                    processor.registers[high] = (time >> Word::BITS) as Word;
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            PollMonotonic { high, low } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    let time = periphery.poll_monotonic_time(processor.get_cycle_count());
                     processor.registers[low] = time as Word;
                     processor.registers[high] = (time >> Word::BITS) as Word;
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
             ) as CachedInstruction<ConcretePeriphery>,
+            SleepMilliseconds { source } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    let now = periphery.poll_monotonic_time(processor.get_cycle_count());
+                    processor.sleep_until = Some(now + processor.registers[source] as u64);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            Random { target } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    processor.registers[target] = processor.next_random_number();
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
             AddWithCarryTargetLhsRhs { target, lhs, rhs } => Box::new(
                 move |processor: &mut Processor,
                       _memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    let result = processor.registers[lhs]
-                        .wrapping_add(processor.registers[rhs])
-                        .wrapping_add(processor.get_flag(Flag::Carry).into());
-                    let overflow_happened = (processor.registers[lhs] as u64
-                        + processor.registers[rhs] as u64
-                        + processor.get_flag(Flag::Carry) as u64)
-                        > Word::MAX as u64;
+                    let (result, carry, overflow) = add_with_carry(
+                        processor.registers[lhs],
+                        processor.registers[rhs],
+                        processor.get_flag(Flag::Carry),
+                    );
                     processor.registers[target] = result;
-                    processor.set_flag(Flag::Zero, processor.registers[target] == 0);
-                    processor.set_flag(Flag::Carry, overflow_happened);
+                    processor.set_result_flags(result);
+                    processor.set_flag(Flag::Carry, carry);
+                    processor.set_flag(Flag::Overflow, overflow);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -1145,7 +1961,7 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    processor.push_instruction_pointer(memory);
+                    try_memory_access!(processor.push_instruction_pointer(memory));
                     processor.set_instruction_pointer(processor.registers[register]);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
@@ -1155,9 +1971,10 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    processor.push_instruction_pointer(memory);
-                    processor
-                        .set_instruction_pointer(memory.read_data(processor.registers[pointer]));
+                    let target_address =
+                        try_memory_access!(memory.try_read_data(processor.registers[pointer]));
+                    try_memory_access!(processor.push_instruction_pointer(memory));
+                    processor.set_instruction_pointer(target_address);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -1182,6 +1999,74 @@ impl Processor {
                 },
             )
                 as CachedInstruction<ConcretePeriphery>,
+            VisibleFramebufferAddress { target } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    processor.registers[target] = periphery.display().visible_framebuffer_address();
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
+            PollFrameCount { target } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    processor.registers[target] = processor.frame_count as Word;
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            WaitForVSync {} => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    processor.waiting_for_vsync = true;
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            IsHeadless { target } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    processor.registers[target] = periphery.display().is_headless() as Word;
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            SetDisplayMode { immediate } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    let mode = match DisplayMode::try_from(immediate) {
+                        Ok(mode) => mode,
+                        Err(_) => return ExecutionResult::Error,
+                    };
+                    periphery.display().set_mode(mode);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            BeginFrame {} => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    periphery.display().begin_frame();
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
+            EndFrame {} => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    periphery.display().end_frame();
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
             PollCycleCountHighLow { high, low } => Box::new(
                 move |processor: &mut Processor,
                       _memory: &mut Memory,
@@ -1193,17 +2078,42 @@ impl Processor {
                 },
             )
                 as CachedInstruction<ConcretePeriphery>,
+            GetProgramCounterOffset { target } => Box::new(
+                move |processor: &mut Processor,
+                      _memory: &mut Memory,
+                      _periphery: &mut ConcretePeriphery| {
+                    let borrow;
+                    (processor.registers[target], borrow) = processor
+                        .get_instruction_pointer()
+                        .overflowing_sub(address_constants::ENTRY_POINT);
+                    processor.set_flag(Flag::Carry, borrow);
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            )
+                as CachedInstruction<ConcretePeriphery>,
             DumpRegisters {} => Box::new(
                 move |processor: &mut Processor,
                       _memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    let data: Vec<_> = processor
-                        .registers
-                        .0
-                        .iter()
-                        .flat_map(|word| word.to_be_bytes())
-                        .collect();
-                    if let Err(error) = dumper::dump("registers", &data) {
+                    let format = processor.register_dump_format;
+                    let data: Vec<_> = match format {
+                        RegisterDumpFormat::Binary => processor
+                            .registers
+                            .0
+                            .iter()
+                            .flat_map(|word| word.to_be_bytes())
+                            .collect(),
+                        RegisterDumpFormat::Json => {
+                            serde_json::to_vec_pretty(&processor.register_dump()).unwrap()
+                        }
+                    };
+                    if let Err(error) = dumper::dump(
+                        &processor.dump_directory,
+                        "registers",
+                        format.extension(),
+                        &data,
+                    ) {
                         eprintln!("Error dumping registers: {}", error);
                     }
                     handle_cycle_count_and_instruction_pointer(processor);
@@ -1214,18 +2124,58 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    if let Err(error) = dumper::dump("memory", memory.data()) {
+                    let format = processor.memory_dump_format;
+                    if let Err(error) = dumper::dump(
+                        &processor.dump_directory,
+                        "memory",
+                        format.extension(),
+                        &format.encode(memory.data()),
+                    ) {
                         eprintln!("Error dumping memory: {}", error);
                     }
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
             ) as CachedInstruction<ConcretePeriphery>,
+            SaveScreenshot { pointer } => Box::new(
+                move |processor: &mut Processor,
+                      memory: &mut Memory,
+                      periphery: &mut ConcretePeriphery| {
+                    let mut filename = Vec::new();
+                    let mut address = processor.registers[pointer];
+                    loop {
+                        let byte = try_memory_access!(memory.try_read_byte(address));
+                        if byte == 0 {
+                            break;
+                        }
+                        filename.push(byte);
+                        address = address.wrapping_add(1);
+                    }
+                    let filename = String::from_utf8_lossy(&filename);
+                    let image = periphery.display().capture(memory);
+                    let resolution = periphery.display().resolution();
+                    if let Err(error) = dumper::save_screenshot(
+                        &processor.dump_directory,
+                        &filename,
+                        resolution.width,
+                        resolution.height,
+                        &image,
+                    ) {
+                        eprintln!("Error saving screenshot: {}", error);
+                    }
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
+                },
+            ) as CachedInstruction<ConcretePeriphery>,
             AssertRegisterRegister { expected, actual } => Box::new(
                 move |processor: &mut Processor,
                       _memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    assert_eq!(processor.registers[actual], processor.registers[expected]);
+                    let (expected, actual) =
+                        (processor.registers[expected], processor.registers[actual]);
+                    if actual != expected {
+                        return processor.assertion_failed(expected, actual);
+                    }
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -1235,7 +2185,10 @@ impl Processor {
                 move |processor: &mut Processor,
                       _memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    assert_eq!(processor.registers[actual], immediate);
+                    let actual = processor.registers[actual];
+                    if actual != immediate {
+                        return processor.assertion_failed(immediate, actual);
+                    }
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -1245,18 +2198,23 @@ impl Processor {
                 move |processor: &mut Processor,
                       memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
-                    assert_eq!(memory.read_data(processor.registers[pointer]), immediate);
+                    let actual =
+                        try_memory_access!(memory.try_read_data(processor.registers[pointer]));
+                    if actual != immediate {
+                        return processor.assertion_failed(immediate, actual);
+                    }
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
             )
                 as CachedInstruction<ConcretePeriphery>,
+            // an attached debugger breaks before executing this instruction
             DebugBreak {} => Box::new(
-                move |_processor: &mut Processor,
+                move |processor: &mut Processor,
                       _memory: &mut Memory,
-                      _periphery: &mut ConcretePeriphery|
-                      -> ExecutionResult {
-                    panic!();
+                      _periphery: &mut ConcretePeriphery| {
+                    handle_cycle_count_and_instruction_pointer(processor);
+                    ExecutionResult::Normal
                 },
             ) as CachedInstruction<ConcretePeriphery>,
             PrintRegister { register } => Box::new(
@@ -1366,12 +2324,12 @@ impl Processor {
                       _memory: &mut Memory,
                       _periphery: &mut ConcretePeriphery| {
                     if immediate != processor.checkpoint_counter {
-                        panic!(
-                            "checkpoint counter mismatch: expected {}, got {}",
-                            processor.checkpoint_counter, immediate
-                        );
+                        let expected = processor.checkpoint_counter;
+                        // continue counting from here if the execution is resumed
+                        processor.checkpoint_counter = immediate.wrapping_add(1);
+                        return processor.assertion_failed(expected, immediate);
                     }
-                    processor.checkpoint_counter += 1;
+                    processor.checkpoint_counter = processor.checkpoint_counter.wrapping_add(1);
                     handle_cycle_count_and_instruction_pointer(processor);
                     ExecutionResult::Normal
                 },
@@ -1387,13 +2345,128 @@ impl Processor {
     ) -> ExecutionResult {
         let instruction_address = self.get_instruction_pointer();
         let cache_index = instruction_address / Instruction::SIZE as Address;
-        instruction_cache.cache[cache_index as usize](self, memory, periphery)
+        if !instruction_address.is_multiple_of(Instruction::SIZE as Address)
+            || instruction_address as usize + Instruction::SIZE > memory.size()
+        {
+            return ExecutionResult::Error;
+        }
+        // self-modifying code: recompile instructions that have been overwritten
+        if memory.take_instruction_dirty(instruction_address) {
+            instruction_cache.cache[cache_index as usize] = Self::generate_cached_instruction_at(
+                memory,
+                cache_index * Instruction::SIZE as Address,
+            );
+            instruction_cache.executed[cache_index as usize] = false;
+        }
+        if !instruction_cache.executed[cache_index as usize] {
+            instruction_cache.executed[cache_index as usize] = true;
+            instruction_cache.num_distinct += 1;
+        }
+        let cycle_count_before = self.cycle_count;
+        let result = match self.profile.is_none() {
+            true => instruction_cache.cache[cache_index as usize](self, memory, periphery),
+            false => {
+                let opcode = memory.read_opcode(instruction_address);
+                let result = instruction_cache.cache[cache_index as usize](self, memory, periphery);
+                if let (Some(profile), Ok(opcode)) = (&mut self.profile, opcode) {
+                    profile.record(opcode.get_mnemonic(), self.cycle_count - cycle_count_before);
+                }
+                result
+            }
+        };
+
+        if result == ExecutionResult::Normal {
+            return self.handle_timer_interrupt(memory, cycle_count_before);
+        }
+        result
+    }
+
+    /// Latches the timer interrupt if the last instruction has crossed a multiple of the timer
+    /// interrupt interval and enters the handler once interrupts are enabled: the flags and the
+    /// instruction pointer are pushed onto the stack, interrupts are disabled until
+    /// `ReturnFromInterrupt` and execution continues at the handler address from the interrupt
+    /// vector table.
+    fn handle_timer_interrupt(
+        &mut self,
+        memory: &mut Memory,
+        cycle_count_before: u64,
+    ) -> ExecutionResult {
+        let interrupts_enabled = self.get_flag(Flag::InterruptsEnabled);
+        match memory.try_read_data(address_constants::TIMER_INTERRUPT_INTERVAL) {
+            Ok(interval) => {
+                let interval = interval as u64;
+                if interval != 0 && cycle_count_before / interval != self.cycle_count / interval {
+                    self.timer_interrupt_pending = true;
+                }
+            }
+            Err(_) if interrupts_enabled => return ExecutionResult::Error,
+            // without the interrupt configuration there is no timer to latch
+            Err(_) => {}
+        }
+        if !interrupts_enabled || !self.timer_interrupt_pending {
+            return ExecutionResult::Normal;
+        }
+        self.timer_interrupt_pending = false;
+        if self
+            .stack_push(memory, self.registers[Self::FLAGS])
+            .and_then(|_| self.stack_push(memory, self.get_instruction_pointer()))
+            .is_err()
+        {
+            return ExecutionResult::Error;
+        }
+        self.set_flag(Flag::InterruptsEnabled, false);
+        let handler = try_memory_access!(memory.try_read_data(
+            address_constants::INTERRUPT_VECTOR_TABLE_START
+                + (address_constants::TIMER_INTERRUPT * Word::SIZE) as Address,
+        ));
+        self.set_instruction_pointer(handler);
+        ExecutionResult::Normal
+    }
+
+    fn assertion_failed(&self, expected: Word, actual: Word) -> ExecutionResult {
+        ExecutionResult::AssertionFailed {
+            expected,
+            actual,
+            address: self.get_instruction_pointer(),
+        }
     }
 
-    fn push_instruction_pointer(&mut self, memory: &mut Memory) {
+    fn push_instruction_pointer(&mut self, memory: &mut Memory) -> Result<(), StackError> {
         self.stack_push(
             memory,
             self.get_instruction_pointer() + Instruction::SIZE as Address,
-        );
+        )
+    }
+}
+
+impl Default for Processor {
+    fn default() -> Self {
+        Self::new()
     }
 }
+
+/// Calculates `lhs + rhs + carry`, whether the unsigned result doesn't fit into a word (carry)
+/// and whether the signed result doesn't fit into a word (overflow).
+fn add_with_carry(lhs: Word, rhs: Word, carry: bool) -> (Word, bool, bool) {
+    let sum = lhs as u64 + rhs as u64 + carry as u64;
+    let signed_sum = lhs as i32 as i64 + rhs as i32 as i64 + carry as i64;
+    (
+        sum as Word,
+        sum > Word::MAX as u64,
+        i32::try_from(signed_sum).is_err(),
+    )
+}
+
+/// Calculates `lhs - rhs - borrow`, whether a borrow occurred, i.e. whether `lhs` is less than
+/// `rhs + borrow`, and whether the signed result doesn't fit into a word (overflow). All
+/// subtracting instructions report the borrow via the carry flag.
+fn subtract_with_borrow(lhs: Word, rhs: Word, borrow: bool) -> (Word, bool, bool) {
+    let (difference, first_borrow) = lhs.overflowing_sub(rhs);
+    let (difference, second_borrow) = difference.overflowing_sub(borrow.into());
+    let signed_difference = lhs as i32 as i64 - rhs as i32 as i64 - borrow as i64;
+    (
+        difference,
+        first_borrow || second_borrow,
+        i32::try_from(signed_difference).is_err(),
+    )
+}
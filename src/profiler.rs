@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+/// Execution counts of a single opcode variant.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProfileEntry {
+    pub count: u64,
+    pub cycles: u64,
+}
+
+/// Counts the executed instructions per opcode variant (keyed by the mnemonic, see
+/// `Opcode::get_mnemonic`).
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    entries: HashMap<&'static str, ProfileEntry>,
+}
+
+impl Profile {
+    pub fn record(&mut self, mnemonic: &'static str, cycles: u64) {
+        let entry = self.entries.entry(mnemonic).or_default();
+        entry.count += 1;
+        entry.cycles += cycles;
+    }
+
+    pub fn get(&self, mnemonic: &str) -> Option<ProfileEntry> {
+        self.entries.get(mnemonic).copied()
+    }
+
+    /// Returns all entries, the most frequently executed opcodes first.
+    pub fn sorted_entries(&self) -> Vec<(&'static str, ProfileEntry)> {
+        let mut result: Vec<_> = self
+            .entries
+            .iter()
+            .map(|(&mnemonic, &entry)| (mnemonic, entry))
+            .collect();
+        result.sort_by(|(lhs_mnemonic, lhs), (rhs_mnemonic, rhs)| {
+            rhs.count
+                .cmp(&lhs.count)
+                .then_with(|| lhs_mnemonic.cmp(rhs_mnemonic))
+        });
+        result
+    }
+
+    /// Formats the profile as a table with one line per opcode.
+    pub fn summary(&self) -> String {
+        let entries = self.sorted_entries();
+        let width = entries
+            .iter()
+            .map(|(mnemonic, _)| mnemonic.len())
+            .chain(["opcode".len()])
+            .max()
+            .unwrap();
+        let mut result = format!("{:width$} {:>12} {:>12}\n", "opcode", "count", "cycles");
+        for (mnemonic, entry) in entries {
+            result.push_str(&format!(
+                "{:width$} {:>12} {:>12}\n",
+                mnemonic, entry.count, entry.cycles
+            ));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_is_sorted_by_count() {
+        let mut profile = Profile::default();
+        profile.record("NoOp", 1);
+        profile.record("Jump", 1);
+        profile.record("Jump", 1);
+        profile.record("Add", 2);
+        assert_eq!(
+            profile.summary(),
+            "opcode        count       cycles\n\
+             Jump              2            2\n\
+             Add               1            2\n\
+             NoOp              1            1\n"
+        );
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    keyboard::{KeyEvent, KeyState},
+    Word,
+};
+
+/// A single value that has been returned by the timer, the keyboard, the mouse or the host's
+/// stdin, together with the cycle count at the time of the query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputRecord {
+    Time {
+        cycle: u64,
+        ms: u64,
+    },
+    MonotonicTime {
+        cycle: u64,
+        ms: u64,
+    },
+    KeyState {
+        cycle: u64,
+        key: Word,
+        down: bool,
+    },
+    KeyEvent {
+        cycle: u64,
+        event: Option<KeyEvent>,
+    },
+    Char {
+        cycle: u64,
+        character: Option<char>,
+    },
+    MousePosition {
+        cycle: u64,
+        x: Word,
+        y: Word,
+    },
+    MouseButtons {
+        cycle: u64,
+        buttons: Word,
+    },
+    Line {
+        cycle: u64,
+        line: Option<Vec<u8>>,
+    },
+    /// A frame has been rendered before executing the instruction at the given cycle count.
+    RenderTick {
+        cycle: u64,
+    },
+}
+
+/// Everything needed to replay a run: the initial state of the random number generator and all
+/// recorded input queries in the order they happened.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recording {
+    pub rng_seed: Word,
+    pub records: Vec<InputRecord>,
+}
+
+/// Sits between the processor and the input devices to either record the values they return or
+/// to replace them with previously recorded ones.
+#[derive(Debug, Default)]
+pub enum InputLog {
+    /// The live values are used and not recorded.
+    #[default]
+    Off,
+    Recording(Recording),
+    Replaying {
+        records: VecDeque<InputRecord>,
+        /// Set as soon as a query doesn't match the next recorded value (or there are no more
+        /// recorded values). From then on, the live values are used.
+        diverged: bool,
+    },
+}
+
+impl InputLog {
+    pub fn record(rng_seed: Word) -> Self {
+        InputLog::Recording(Recording {
+            rng_seed,
+            records: Vec::new(),
+        })
+    }
+
+    pub fn replay(recording: Recording) -> Self {
+        InputLog::Replaying {
+            records: recording.records.into(),
+            diverged: false,
+        }
+    }
+
+    /// Returns the recorded time if replaying, otherwise calls `live` (and records the result if
+    /// recording).
+    pub fn time(&mut self, cycle: u64, live: impl FnOnce() -> u64) -> u64 {
+        let replayed = self.next_replayed(|record| match record {
+            InputRecord::Time {
+                cycle: recorded_cycle,
+                ms,
+            } if recorded_cycle == cycle => Some(ms),
+            _ => None,
+        });
+        match replayed {
+            Some(ms) => ms,
+            None => {
+                let ms = live();
+                self.push(InputRecord::Time { cycle, ms });
+                ms
+            }
+        }
+    }
+
+    /// Like `time`, but for the monotonic clock.
+    pub fn monotonic_time(&mut self, cycle: u64, live: impl FnOnce() -> u64) -> u64 {
+        let replayed = self.next_replayed(|record| match record {
+            InputRecord::MonotonicTime {
+                cycle: recorded_cycle,
+                ms,
+            } if recorded_cycle == cycle => Some(ms),
+            _ => None,
+        });
+        match replayed {
+            Some(ms) => ms,
+            None => {
+                let ms = live();
+                self.push(InputRecord::MonotonicTime { cycle, ms });
+                ms
+            }
+        }
+    }
+
+    /// Like `time`, but for the state of the given key.
+    pub fn key_state(
+        &mut self,
+        cycle: u64,
+        key: Word,
+        live: impl FnOnce() -> KeyState,
+    ) -> KeyState {
+        let replayed = self.next_replayed(|record| match record {
+            InputRecord::KeyState {
+                cycle: recorded_cycle,
+                key: recorded_key,
+                down,
+            } if recorded_cycle == cycle && recorded_key == key => Some(down),
+            _ => None,
+        });
+        let down = match replayed {
+            Some(down) => down,
+            None => {
+                let down = matches!(live(), KeyState::Down);
+                self.push(InputRecord::KeyState { cycle, key, down });
+                down
+            }
+        };
+        match down {
+            true => KeyState::Down,
+            false => KeyState::Up,
+        }
+    }
+
+    /// Like `time`, but for the next event of the keyboard's event queue.
+    pub fn key_event(
+        &mut self,
+        cycle: u64,
+        live: impl FnOnce() -> Option<KeyEvent>,
+    ) -> Option<KeyEvent> {
+        self.replay_or_record(
+            |record| match record {
+                InputRecord::KeyEvent {
+                    cycle: recorded_cycle,
+                    event,
+                } if recorded_cycle == cycle => Some(event),
+                _ => None,
+            },
+            live,
+            |&event| InputRecord::KeyEvent { cycle, event },
+        )
+    }
+
+    /// Like `time`, but for the next typed character.
+    pub fn character(&mut self, cycle: u64, live: impl FnOnce() -> Option<char>) -> Option<char> {
+        self.replay_or_record(
+            |record| match record {
+                InputRecord::Char {
+                    cycle: recorded_cycle,
+                    character,
+                } if recorded_cycle == cycle => Some(character),
+                _ => None,
+            },
+            live,
+            |&character| InputRecord::Char { cycle, character },
+        )
+    }
+
+    /// Like `time`, but for the mouse position.
+    pub fn mouse_position(
+        &mut self,
+        cycle: u64,
+        live: impl FnOnce() -> (Word, Word),
+    ) -> (Word, Word) {
+        self.replay_or_record(
+            |record| match record {
+                InputRecord::MousePosition {
+                    cycle: recorded_cycle,
+                    x,
+                    y,
+                } if recorded_cycle == cycle => Some((x, y)),
+                _ => None,
+            },
+            live,
+            |&(x, y)| InputRecord::MousePosition { cycle, x, y },
+        )
+    }
+
+    /// Like `time`, but for the mouse buttons.
+    pub fn mouse_buttons(&mut self, cycle: u64, live: impl FnOnce() -> Word) -> Word {
+        self.replay_or_record(
+            |record| match record {
+                InputRecord::MouseButtons {
+                    cycle: recorded_cycle,
+                    buttons,
+                } if recorded_cycle == cycle => Some(buttons),
+                _ => None,
+            },
+            live,
+            |&buttons| InputRecord::MouseButtons { cycle, buttons },
+        )
+    }
+
+    /// Like `time`, but for a line read from the host's stdin (`None` at the end of the input).
+    pub fn line(&mut self, cycle: u64, live: impl FnOnce() -> Option<Vec<u8>>) -> Option<Vec<u8>> {
+        self.replay_or_record(
+            |record| match record {
+                InputRecord::Line {
+                    cycle: recorded_cycle,
+                    line,
+                } if recorded_cycle == cycle => Some(line),
+                _ => None,
+            },
+            live,
+            |line| InputRecord::Line {
+                cycle,
+                line: line.clone(),
+            },
+        )
+    }
+
+    /// Records a rendered frame. Returns whether the frame should be applied to the machine,
+    /// which isn't the case while replaying: the recorded frames are applied instead (see
+    /// `replayed_render_tick`).
+    pub fn render_tick(&mut self, cycle: u64) -> bool {
+        self.push(InputRecord::RenderTick { cycle });
+        !self.is_replaying()
+    }
+
+    /// Returns whether a frame has been rendered at the given cycle count during the recorded run
+    /// (and consumes that frame from the recording).
+    pub fn replayed_render_tick(&mut self, cycle: u64) -> bool {
+        let InputLog::Replaying {
+            records,
+            diverged: false,
+        } = self
+        else {
+            return false;
+        };
+        let is_render_tick = records.front() == Some(&InputRecord::RenderTick { cycle });
+        if is_render_tick {
+            records.pop_front();
+        }
+        is_render_tick
+    }
+
+    /// Returns whether the recorded values are used, i.e. a replay that hasn't diverged yet.
+    pub fn is_replaying(&self) -> bool {
+        matches!(
+            self,
+            InputLog::Replaying {
+                diverged: false,
+                ..
+            }
+        )
+    }
+
+    /// Returns whether a replay ran into a query that isn't covered by the recording.
+    pub fn has_diverged(&self) -> bool {
+        matches!(self, InputLog::Replaying { diverged: true, .. })
+    }
+
+    pub fn recording(&self) -> Option<&Recording> {
+        match self {
+            InputLog::Recording(recording) => Some(recording),
+            _ => None,
+        }
+    }
+
+    fn next_replayed<T>(&mut self, matches: impl FnOnce(InputRecord) -> Option<T>) -> Option<T> {
+        let InputLog::Replaying { records, diverged } = self else {
+            return None;
+        };
+        if *diverged {
+            return None;
+        }
+        let result = records.pop_front().and_then(matches);
+        *diverged = result.is_none();
+        result
+    }
+
+    fn replay_or_record<T>(
+        &mut self,
+        matches: impl FnOnce(InputRecord) -> Option<T>,
+        live: impl FnOnce() -> T,
+        to_record: impl FnOnce(&T) -> InputRecord,
+    ) -> T {
+        if let Some(value) = self.next_replayed(matches) {
+            return value;
+        }
+        let value = live();
+        if let InputLog::Recording(_) = self {
+            self.push(to_record(&value));
+        }
+        value
+    }
+
+    fn push(&mut self, record: InputRecord) {
+        if let InputLog::Recording(recording) = self {
+            recording.records.push(record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_returns_recorded_values() {
+        let mut log = InputLog::record(1);
+        assert_eq!(log.time(3, || 100), 100);
+        assert_eq!(log.key_state(5, 32, || KeyState::Down), KeyState::Down);
+        let recording = log.recording().unwrap().clone();
+        assert_eq!(recording.records.len(), 2);
+
+        let mut log = InputLog::replay(recording);
+        assert_eq!(log.time(3, || panic!("live timer used")), 100);
+        assert_eq!(
+            log.key_state(5, 32, || panic!("live keyboard used")),
+            KeyState::Down
+        );
+        assert!(!log.has_diverged());
+        // the recording is exhausted, so the live values are used again
+        assert_eq!(log.time(7, || 200), 200);
+        assert!(log.has_diverged());
+    }
+
+    #[test]
+    fn mismatching_query_diverges() {
+        let mut log = InputLog::replay(Recording {
+            rng_seed: 1,
+            records: vec![
+                InputRecord::Time { cycle: 3, ms: 100 },
+                InputRecord::Time { cycle: 4, ms: 101 },
+            ],
+        });
+        assert_eq!(log.time(2, || 42), 42);
+        assert!(log.has_diverged());
+        assert_eq!(log.time(4, || 43), 43);
+    }
+
+    #[test]
+    fn replay_returns_recorded_events_and_lines() {
+        let event = KeyEvent {
+            keycode: 65,
+            state: KeyState::Down,
+        };
+        let mut log = InputLog::record(1);
+        assert_eq!(log.key_event(2, || Some(event)), Some(event));
+        assert_eq!(log.character(3, || Some('x')), Some('x'));
+        assert_eq!(log.mouse_position(4, || (5, 6)), (5, 6));
+        assert_eq!(log.mouse_buttons(5, || 3), 3);
+        assert_eq!(log.line(6, || Some(b"hi".to_vec())), Some(b"hi".to_vec()));
+        assert_eq!(log.line(7, || None), None);
+        let recording = log.recording().unwrap().clone();
+
+        let mut log = InputLog::replay(recording);
+        assert_eq!(log.key_event(2, || None), Some(event));
+        assert_eq!(log.character(3, || None), Some('x'));
+        assert_eq!(log.mouse_position(4, || (0, 0)), (5, 6));
+        assert_eq!(log.mouse_buttons(5, || 0), 3);
+        assert_eq!(log.line(6, || None), Some(b"hi".to_vec()));
+        assert_eq!(log.line(7, || Some(Vec::new())), None);
+        assert!(!log.has_diverged());
+    }
+
+    #[test]
+    fn render_ticks_are_replayed_at_recorded_cycle() {
+        let mut log = InputLog::record(1);
+        assert!(log.render_tick(10));
+        assert!(log.render_tick(10));
+        let recording = log.recording().unwrap().clone();
+
+        let mut log = InputLog::replay(recording);
+        assert!(!log.render_tick(3));
+        assert!(!log.replayed_render_tick(9));
+        assert!(log.replayed_render_tick(10));
+        assert!(log.replayed_render_tick(10));
+        assert!(!log.replayed_render_tick(10));
+        assert!(!log.has_diverged());
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+
+use crate::{address_constants::ENTRY_POINT, dumper::crc32, Address};
+
+const MAGIC: &[u8; 4] = b"B2K\0";
+pub const VERSION: u32 = 1;
+/// Magic, version, entry point and checksum (all numbers in big endian byte order).
+pub const HEADER_SIZE: usize = 16;
+
+/// Optional header in front of a ROM. ROMs without a header (i.e. that don't start with the
+/// magic bytes) are loaded to `ENTRY_POINT` and start executing there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RomHeader {
+    pub version: u32,
+    pub entry_point: Address,
+    /// CRC-32 of the ROM body (everything after the header).
+    pub checksum: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomError {
+    Truncated,
+    UnsupportedVersion(u32),
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::Truncated => write!(f, "ROM header is truncated"),
+            RomError::UnsupportedVersion(version) => {
+                write!(
+                    f,
+                    "unsupported ROM format version {version} (expected {VERSION})"
+                )
+            }
+            RomError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "ROM checksum mismatch: expected {expected:#010x}, got {actual:#010x}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+impl RomHeader {
+    pub fn new(entry_point: Address, body: &[u8]) -> Self {
+        Self {
+            version: VERSION,
+            entry_point,
+            checksum: crc32(body.iter()),
+        }
+    }
+
+    pub fn encode(&self) -> [u8; HEADER_SIZE] {
+        let mut result = [0; HEADER_SIZE];
+        result[..4].copy_from_slice(MAGIC);
+        result[4..8].copy_from_slice(&self.version.to_be_bytes());
+        result[8..12].copy_from_slice(&self.entry_point.to_be_bytes());
+        result[12..].copy_from_slice(&self.checksum.to_be_bytes());
+        result
+    }
+}
+
+/// Prepends a header to the ROM body, with execution starting at `ENTRY_POINT`.
+pub fn with_header(body: &[u8]) -> Vec<u8> {
+    let mut result = RomHeader::new(ENTRY_POINT, body).encode().to_vec();
+    result.extend_from_slice(body);
+    result
+}
+
+/// Splits the ROM into its header (if there is one) and its body. Fails if the header is
+/// incomplete, has an unsupported version or doesn't match the body.
+pub fn split_header(rom: &[u8]) -> Result<(Option<RomHeader>, &[u8]), RomError> {
+    if !rom.starts_with(MAGIC) {
+        return Ok((None, rom));
+    }
+    if rom.len() < HEADER_SIZE {
+        return Err(RomError::Truncated);
+    }
+    let (header, body) = rom.split_at(HEADER_SIZE);
+    let read_u32 = |offset: usize| u32::from_be_bytes(header[offset..][..4].try_into().unwrap());
+    let header = RomHeader {
+        version: read_u32(4),
+        entry_point: read_u32(8),
+        checksum: read_u32(12),
+    };
+    if header.version != VERSION {
+        return Err(RomError::UnsupportedVersion(header.version));
+    }
+    let actual = crc32(body.iter());
+    if actual != header.checksum {
+        return Err(RomError::ChecksumMismatch {
+            expected: header.checksum,
+            actual,
+        });
+    }
+    Ok((Some(header), body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_headered_and_headerless_roms() {
+        let body = [1, 2, 3, 4, 5, 6, 7, 8];
+        let rom = with_header(&body);
+        assert_eq!(rom.len(), HEADER_SIZE + body.len());
+        let (header, split_body) = split_header(&rom).unwrap();
+        assert_eq!(header, Some(RomHeader::new(ENTRY_POINT, &body)));
+        assert_eq!(split_body, body);
+
+        assert_eq!(split_header(&body), Ok((None, &body[..])));
+    }
+
+    #[test]
+    fn invalid_headers_are_rejected() {
+        let mut rom = with_header(&[0; 8]);
+        rom[7] = 2;
+        assert_eq!(split_header(&rom), Err(RomError::UnsupportedVersion(2)));
+
+        let mut rom = with_header(&[0; 8]);
+        rom[HEADER_SIZE] = 1;
+        assert!(matches!(
+            split_header(&rom),
+            Err(RomError::ChecksumMismatch { .. })
+        ));
+
+        assert_eq!(split_header(b"B2K\0\0\0"), Err(RomError::Truncated));
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{
+    address_constants, display::Resolution, instruction_address, opcodes::Opcode, Size, Word,
+};
+
+/// Names of the built-in sample programs.
+pub const NAMES: [&str; 4] = ["color-fill", "hello-world", "fibonacci", "keyboard-echo"];
+/// The sample program that is emitted if no name is given.
+pub const DEFAULT: &str = NAMES[0];
+
+/// Returns the sample program with the given name (see `NAMES`).
+pub fn by_name(name: &str) -> Option<Vec<Opcode>> {
+    match name {
+        "color-fill" => Some(color_fill()),
+        "hello-world" => Some(hello_world()),
+        "fibonacci" => Some(fibonacci()),
+        "keyboard-echo" => Some(keyboard_echo()),
+        _ => None,
+    }
+}
+
+/// Endlessly fills the first framebuffer with changing colors.
+pub fn color_fill() -> Vec<Opcode> {
+    vec![
+        Opcode::MoveRegisterImmediate {
+            // starting color
+            register: 0.into(),
+            immediate: 0xFF,
+        },
+        Opcode::MoveRegisterImmediate {
+            // num iterations
+            register: 42.into(),
+            immediate: (Resolution::DEFAULT.width * Resolution::DEFAULT.height) as Word,
+        },
+        // outer loop start
+        Opcode::MoveRegisterImmediate {
+            // current loop counter
+            register: 1.into(),
+            immediate: 0,
+        },
+        Opcode::AddTargetSourceImmediate {
+            // current color
+            target: 0.into(),
+            source: 0.into(),
+            immediate: 0x200,
+        },
+        Opcode::MoveRegisterImmediate {
+            register: 2.into(),
+            immediate: address_constants::FIRST_FRAMEBUFFER_START,
+        },
+        // inner loop start
+        Opcode::MovePointerSource {
+            pointer: 2.into(),
+            source: 0.into(),
+        },
+        Opcode::AddTargetSourceImmediate {
+            target: 2.into(),
+            source: 2.into(),
+            immediate: Word::SIZE as Word,
+        },
+        Opcode::AddTargetSourceImmediate {
+            target: 1.into(),
+            source: 1.into(),
+            immediate: 1,
+        },
+        Opcode::CompareTargetLhsRhs {
+            target: 10.into(),
+            lhs: 1.into(),
+            rhs: 42.into(),
+        },
+        Opcode::JumpImmediateIfLessThan {
+            comparison: 10.into(),
+            immediate: instruction_address(5),
+        },
+        Opcode::JumpImmediate {
+            immediate: instruction_address(2),
+        },
+    ]
+}
+
+/// Writes "Hello, World!" to the terminal and halts.
+pub fn hello_world() -> Vec<Opcode> {
+    let mut result: Vec<_> = b"Hello, World!\n"
+        .iter()
+        .flat_map(|&byte| {
+            [
+                Opcode::MoveRegisterImmediate {
+                    register: 0.into(),
+                    immediate: byte as Word,
+                },
+                Opcode::TerminalWriteByte { source: 0.into() },
+            ]
+        })
+        .collect();
+    result.push(Opcode::HaltAndCatchFire {});
+    result
+}
+
+/// Prints the Fibonacci numbers up to the first one that doesn't fit into a word (as debug
+/// output), followed by the number of cycles this took, and halts.
+pub fn fibonacci() -> Vec<Opcode> {
+    vec![
+        Opcode::PollCycleCountHighLow {
+            high: 10.into(),
+            low: 11.into(),
+        },
+        Opcode::MoveRegisterImmediate {
+            register: 1.into(),
+            immediate: 0,
+        },
+        Opcode::MoveRegisterImmediate {
+            register: 2.into(),
+            immediate: 1,
+        },
+        // loop start
+        Opcode::AddTargetLhsRhs {
+            target: 3.into(),
+            lhs: 1.into(),
+            rhs: 2.into(),
+        },
+        Opcode::JumpImmediateIfCarry {
+            immediate: instruction_address(9),
+        },
+        Opcode::MoveTargetSource {
+            target: 1.into(),
+            source: 2.into(),
+        },
+        Opcode::MoveTargetSource {
+            target: 2.into(),
+            source: 3.into(),
+        },
+        Opcode::PrintRegister { register: 2.into() },
+        Opcode::JumpImmediate {
+            immediate: instruction_address(3),
+        },
+        // overflow
+        Opcode::PollCycleCountHighLow {
+            high: 12.into(),
+            low: 13.into(),
+        },
+        Opcode::SubtractTargetLhsRhs {
+            target: 14.into(),
+            lhs: 13.into(),
+            rhs: 11.into(),
+        },
+        Opcode::PrintRegister {
+            register: 14.into(),
+        },
+        Opcode::HaltAndCatchFire {},
+    ]
+}
+
+/// Writes the keycode of every pressed key to the terminal (which corresponds to the ASCII code
+/// for letters, digits and space).
+pub fn keyboard_echo() -> Vec<Opcode> {
+    vec![
+        // loop start
+        Opcode::GetKeyEvent { target: 1.into() },
+        Opcode::JumpImmediateIfZero {
+            immediate: instruction_address(0),
+        },
+        Opcode::MoveRegisterImmediate {
+            // "pressed" bit of the event
+            register: 2.into(),
+            immediate: 1 << 16,
+        },
+        Opcode::AndTargetLhsRhs {
+            target: 3.into(),
+            lhs: 1.into(),
+            rhs: 2.into(),
+        },
+        Opcode::JumpImmediateIfZero {
+            immediate: instruction_address(0),
+        },
+        Opcode::TerminalWriteByte { source: 1.into() },
+        Opcode::JumpImmediate {
+            immediate: instruction_address(0),
+        },
+    ]
+}
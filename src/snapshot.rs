@@ -0,0 +1,339 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    display::SwapState,
+    machine::{AssertionFailure, HaltReason},
+    memory::Memory,
+    processor::ProcessorSnapshot,
+    terminal::TerminalWriter,
+    Word,
+};
+
+const MAGIC: &[u8; 8] = b"BSS2KSAV";
+const VERSION: u32 = 1;
+
+/// The complete state of a machine (see `Machine::snapshot`). The instruction cache is not part
+/// of the snapshot since it can be regenerated from the memory contents.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MachineSnapshot {
+    #[serde(with = "packed_memory")]
+    pub memory: Vec<u8>,
+    pub processor: ProcessorSnapshot,
+    pub halt_reason: Option<HaltReason>,
+    pub assertion_failures: Vec<AssertionFailure>,
+    pub swap_state: SwapState,
+    pub display_mode: Word,
+    pub cursor_visible: bool,
+    pub terminal_writer: TerminalWriter,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    InvalidHeader,
+    UnsupportedVersion(u32),
+    Truncated,
+    Corrupt,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::InvalidHeader => write!(f, "not a save state"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {version}")
+            }
+            SnapshotError::Truncated => write!(f, "save state is truncated"),
+            SnapshotError::Corrupt => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl MachineSnapshot {
+    /// Encodes the snapshot into the binary save state format: a header (magic bytes and the
+    /// version as big endian number) followed by the snapshot serialized with `bincode`. The
+    /// memory contents are compressed using PackBits (the memory mostly consists of long runs of
+    /// zeros).
+    pub fn encode(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(MAGIC);
+        result.extend_from_slice(&VERSION.to_be_bytes());
+        bincode::serialize_into(&mut result, self).expect("snapshots can always be serialized");
+        result
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let Some(body) = bytes.strip_prefix(MAGIC) else {
+            return Err(SnapshotError::InvalidHeader);
+        };
+        if body.len() < 4 {
+            return Err(SnapshotError::Truncated);
+        }
+        let (version, body) = body.split_at(4);
+        let version = u32::from_be_bytes(version.try_into().unwrap());
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        bincode::deserialize(body).map_err(|error| match *error {
+            bincode::ErrorKind::Io(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+                SnapshotError::Truncated
+            }
+            _ => SnapshotError::Corrupt,
+        })
+    }
+}
+
+/// Stores the memory contents PackBits compressed together with the uncompressed size.
+mod packed_memory {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct PackedMemory {
+        size: u64,
+        data: Vec<u8>,
+    }
+
+    pub fn serialize<S: Serializer>(memory: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut data = Vec::new();
+        super::pack_bits(memory, &mut data);
+        PackedMemory {
+            size: memory.len() as u64,
+            data,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let PackedMemory { size, data } = PackedMemory::deserialize(deserializer)?;
+        let size = usize::try_from(size).map_err(D::Error::custom)?;
+        super::unpack_bits(&data, size).map_err(D::Error::custom)
+    }
+}
+
+const MAX_RUN_LENGTH: usize = 128;
+
+/// Compresses the data using PackBits: a header byte `n` in `0..=127` is followed by `n + 1`
+/// literal bytes, a header byte `n` in `129..=255` is followed by a single byte that is repeated
+/// `257 - n` times.
+pub(crate) fn pack_bits(data: &[u8], output: &mut Vec<u8>) {
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i < data.len() {
+        let run_length = data[i..]
+            .iter()
+            .take(MAX_RUN_LENGTH)
+            .take_while(|&&byte| byte == data[i])
+            .count();
+        if run_length >= 3 {
+            flush_literals(&data[literal_start..i], output);
+            output.push((257 - run_length) as u8);
+            output.push(data[i]);
+            i += run_length;
+            literal_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    flush_literals(&data[literal_start..], output);
+}
+
+fn flush_literals(literals: &[u8], output: &mut Vec<u8>) {
+    for chunk in literals.chunks(MAX_RUN_LENGTH) {
+        output.push((chunk.len() - 1) as u8);
+        output.extend_from_slice(chunk);
+    }
+}
+
+/// Decompresses data compressed with `pack_bits`. The size is checked against the largest
+/// possible memory size before allocating, since it usually comes from an untrusted file.
+pub(crate) fn unpack_bits(mut data: &[u8], size: usize) -> Result<Vec<u8>, SnapshotError> {
+    if size > Memory::MAX_SIZE {
+        return Err(SnapshotError::Corrupt);
+    }
+    let mut result = Vec::with_capacity(size.min(data.len() * MAX_RUN_LENGTH));
+    while let Some((&header, rest)) = data.split_first() {
+        match header {
+            0..=127 => {
+                let count = header as usize + 1;
+                if rest.len() < count {
+                    return Err(SnapshotError::Truncated);
+                }
+                result.extend_from_slice(&rest[..count]);
+                data = &rest[count..];
+            }
+            128 => return Err(SnapshotError::Corrupt),
+            _ => {
+                let (&byte, rest) = rest.split_first().ok_or(SnapshotError::Truncated)?;
+                result.resize(result.len() + 257 - header as usize, byte);
+                data = rest;
+            }
+        }
+        if result.len() > size {
+            return Err(SnapshotError::Corrupt);
+        }
+    }
+    if result.len() != size {
+        return Err(SnapshotError::Truncated);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip_pack_bits(data: &[u8]) -> Vec<u8> {
+        let mut packed = Vec::new();
+        pack_bits(data, &mut packed);
+        unpack_bits(&packed, data.len()).unwrap()
+    }
+
+    #[test]
+    fn pack_bits_round_trip() {
+        let mut data = vec![0; 1000];
+        data.extend(0..=255);
+        data.extend([1, 1, 2, 2, 2, 3]);
+        data.extend(vec![0xAB; 300]);
+        assert_eq!(round_trip_pack_bits(&data), data);
+        assert_eq!(round_trip_pack_bits(&[]), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn pack_bits_compresses_runs() {
+        let mut packed = Vec::new();
+        pack_bits(&[0; 1024], &mut packed);
+        assert_eq!(packed.len(), 2 * 1024 / MAX_RUN_LENGTH);
+    }
+
+    fn create_snapshot() -> MachineSnapshot {
+        MachineSnapshot {
+            memory: vec![0; 16],
+            processor: ProcessorSnapshot {
+                registers: vec![0; 256],
+                cycle_count: 0,
+                checkpoint_counter: 0,
+                rng_state: 1,
+                sleep_until: None,
+                frame_count: 0,
+                waiting_for_vsync: false,
+                timer_interrupt_pending: false,
+            },
+            halt_reason: None,
+            assertion_failures: Vec::new(),
+            swap_state: SwapState::new(),
+            display_mode: 0,
+            cursor_visible: true,
+            terminal_writer: TerminalWriter::default(),
+        }
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let mut terminal_writer = TerminalWriter::default();
+        let mut memory = Memory::new();
+        for &byte in b"\x1b[12;3" {
+            terminal_writer.write_byte(&mut memory, byte);
+        }
+        let snapshot = MachineSnapshot {
+            memory: (0..64).map(|i| (i / 8) as u8).collect(),
+            processor: ProcessorSnapshot {
+                registers: (0..256).collect(),
+                cycle_count: 1 << 40,
+                checkpoint_counter: 7,
+                rng_state: 0xDEAD_BEEF,
+                sleep_until: Some(1234),
+                frame_count: 99,
+                waiting_for_vsync: true,
+                timer_interrupt_pending: true,
+            },
+            halt_reason: Some(HaltReason::AssertionFailed),
+            assertion_failures: vec![AssertionFailure {
+                expected: 1,
+                actual: 2,
+                address: 0x100,
+            }],
+            swap_state: SwapState {
+                first_framebuffer_visible: false,
+                frame_in_progress: true,
+                swap_pending: true,
+                vsync: false,
+                vsync_swap_pending: false,
+                num_swaps: 5,
+            },
+            display_mode: 1,
+            cursor_visible: false,
+            terminal_writer,
+        };
+        let encoded = snapshot.encode();
+        assert_eq!(MachineSnapshot::decode(&encoded), Ok(snapshot));
+        assert_eq!(
+            MachineSnapshot::decode(&encoded[..encoded.len() - 1]),
+            Err(SnapshotError::Truncated)
+        );
+        assert_eq!(
+            MachineSnapshot::decode(b"NOTASAVESTATE"),
+            Err(SnapshotError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        let mut encoded = create_snapshot().encode();
+        encoded[MAGIC.len()..][..4].copy_from_slice(&(VERSION + 1).to_be_bytes());
+        assert_eq!(
+            MachineSnapshot::decode(&encoded),
+            Err(SnapshotError::UnsupportedVersion(VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn huge_memory_size_is_rejected_without_allocating() {
+        let mut encoded = create_snapshot().encode();
+        // the memory size is the first field after the header
+        let size_offset = MAGIC.len() + 4;
+        encoded[size_offset..][..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(
+            MachineSnapshot::decode(&encoded),
+            Err(SnapshotError::Corrupt)
+        );
+        assert_eq!(
+            unpack_bits(&[0x81, 0], Memory::MAX_SIZE + 1),
+            Err(SnapshotError::Corrupt)
+        );
+    }
+
+    #[test]
+    fn memory_is_stored_packed() {
+        let snapshot = MachineSnapshot {
+            memory: vec![0; 1024],
+            ..create_snapshot()
+        };
+        let mut packed = Vec::new();
+        pack_bits(&snapshot.memory, &mut packed);
+        let encoded = snapshot.encode();
+        assert!(encoded
+            .windows(packed.len())
+            .any(|window| window == packed.as_slice()));
+    }
+
+    #[test]
+    fn all_halt_reasons_round_trip() {
+        for halt_reason in [
+            None,
+            Some(HaltReason::CaughtFire),
+            Some(HaltReason::AssertionFailed),
+            Some(HaltReason::Error),
+            Some(HaltReason::CycleLimit),
+        ] {
+            let snapshot = MachineSnapshot {
+                halt_reason,
+                ..create_snapshot()
+            };
+            let decoded = MachineSnapshot::decode(&snapshot.encode()).unwrap();
+            assert_eq!(decoded.halt_reason, halt_reason);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{disassembler::format_opcode, display, Machine};
+
+/// How an interactive stepping session (see `run_stepper`) has ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepperExit {
+    /// The user requested to continue without stepping.
+    Continue,
+    /// The user requested to quit (or the input has been closed).
+    Quit,
+    /// The machine has halted.
+    Halted,
+}
+
+const HELP: &str = "commands: <enter> = step, r = registers, c = continue, q = quit";
+
+/// Executes one instruction per empty input line, printing the next instruction before each
+/// step. This is a lightweight alternative to the TCP debugger.
+pub fn run_stepper<Display: display::Display + 'static>(
+    machine: &mut Machine<Display>,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<StepperExit> {
+    writeln!(output, "{HELP}")?;
+    let mut line = String::new();
+    loop {
+        if machine.is_halted() {
+            writeln!(output, "halted")?;
+            return Ok(StepperExit::Halted);
+        }
+
+        let instruction_pointer = machine.processor.get_instruction_pointer();
+        let instruction = match machine.memory.read_opcode(instruction_pointer) {
+            Ok(opcode) => format_opcode(opcode),
+            Err(error) => format!("<{error}>"),
+        };
+        write!(output, "{instruction_pointer:#010x}: {instruction} > ")?;
+        output.flush()?;
+
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            return Ok(StepperExit::Quit);
+        }
+        match line.trim() {
+            "" => machine.execute_next_instruction(),
+            "r" => print_registers(machine, &mut output)?,
+            "c" => return Ok(StepperExit::Continue),
+            "q" => return Ok(StepperExit::Quit),
+            _ => writeln!(output, "{HELP}")?,
+        }
+    }
+}
+
+/// Prints all registers that are not zero, eight per line.
+fn print_registers<Display: display::Display + 'static>(
+    machine: &Machine<Display>,
+    output: &mut impl Write,
+) -> io::Result<()> {
+    let registers = machine.processor.registers.contents();
+    let non_zero: Vec<_> = registers
+        .iter()
+        .enumerate()
+        .filter(|(_, &value)| value != 0)
+        .map(|(register, value)| format!("r{register} = {value:#010x}"))
+        .collect();
+    if non_zero.is_empty() {
+        writeln!(output, "all registers are zero")?;
+    }
+    for chunk in non_zero.chunks(8) {
+        writeln!(output, "{}", chunk.join(", "))?;
+    }
+    Ok(())
+}
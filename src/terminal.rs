@@ -1,6 +1,8 @@
 // featuring Tom Hanks
 
-use crate::{address_constants, cursor::Cursor, memory::Memory, Address, Size, Word};
+use serde::{Deserialize, Serialize};
+
+use crate::{address_constants, cursor::Cursor, memory::Memory, Address, Byte, Word};
 
 #[cfg(feature = "graphics")]
 use raylib::prelude::*;
@@ -8,63 +10,525 @@ use raylib::prelude::*;
 pub const WIDTH: usize = 80;
 pub const HEIGHT: usize = 25;
 
-#[cfg(feature = "graphics")]
-pub fn render(
-    memory: &Memory,
-    draw_handle: &mut RaylibDrawHandle,
-    position: Vector2,
-    font: &Font,
-    font_height: f32,
-    cursor: &Cursor,
-) {
+/// Scrolls the terminal contents (characters and attributes) up if the cursor pointer has moved
+/// past the last row. The topmost rows are discarded, the freed rows at the bottom are blanked
+/// and the cursor pointer is moved up by the same number of rows.
+pub fn scroll_if_needed(memory: &mut Memory) {
+    let cursor_pointer = memory.read_data(address_constants::TERMINAL_CURSOR_POINTER);
+    if cursor_pointer < address_constants::TERMINAL_BUFFER_END {
+        return;
+    }
+    let cursor_row = (cursor_pointer - address_constants::TERMINAL_BUFFER_START) as usize / WIDTH;
+    let num_rows_to_scroll = (cursor_row - (HEIGHT - 1)).min(HEIGHT);
+    let buffer_size = (WIDTH * HEIGHT) as Address;
+    let scrolled_size = (num_rows_to_scroll * WIDTH) as Address;
+    for start in [
+        address_constants::TERMINAL_BUFFER_START,
+        address_constants::TERMINAL_ATTRIBUTES_START,
+    ] {
+        memory.copy_within(start + scrolled_size..start + buffer_size, start);
+        memory.fill(start + buffer_size - scrolled_size..start + buffer_size, 0);
+    }
+    memory.write_data(
+        address_constants::TERMINAL_CURSOR_POINTER,
+        cursor_pointer.saturating_sub((num_rows_to_scroll * WIDTH) as Word),
+    );
+}
+
+const ESCAPE: Byte = 0x1B;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+enum WriterState {
+    #[default]
+    Text,
+    Escape,
+    ControlSequence {
+        parameters: Vec<usize>,
+    },
+    /// A control sequence with too many parameters, swallowed up to its final byte.
+    IgnoredControlSequence,
+}
+
+/// Writes bytes to the terminal buffer while interpreting a small subset of the ANSI escape
+/// sequences:
+///
+/// * `\r` moves the cursor to the start of the current row, `\n` moves it down by one row
+/// * `ESC [ row ; column H` (or `f`) moves the cursor to the (1-based) position, defaulting to
+///   the top left corner
+/// * `ESC [ n A/B/C/D` moves the cursor up/down/forward/back by `n` cells (default 1)
+/// * `ESC [ 2 J` clears the whole screen, `ESC [ J` clears from the cursor to the end
+///
+/// Unknown sequences and sequences with more than `MAX_PARAMETERS` parameters are swallowed.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TerminalWriter {
+    state: WriterState,
+}
+
+impl TerminalWriter {
+    /// Maximum number of parameters of a control sequence.
+    pub const MAX_PARAMETERS: usize = 16;
+
+    pub fn write_byte(&mut self, memory: &mut Memory, byte: Byte) {
+        self.state = match std::mem::take(&mut self.state) {
+            WriterState::Text => match byte {
+                ESCAPE => WriterState::Escape,
+                b'\r' => {
+                    let (row, _) = cursor_position(memory);
+                    set_cursor_position(memory, row, 0);
+                    WriterState::Text
+                }
+                b'\n' => {
+                    let index = cursor_index(memory) + WIDTH;
+                    set_cursor_index(memory, index);
+                    scroll_if_needed(memory);
+                    WriterState::Text
+                }
+                _ => {
+                    let index = cursor_index(memory);
+                    memory.write_byte(
+                        address_constants::TERMINAL_BUFFER_START + index as Address,
+                        byte,
+                    );
+                    set_cursor_index(memory, index + 1);
+                    scroll_if_needed(memory);
+                    WriterState::Text
+                }
+            },
+            WriterState::Escape => match byte {
+                b'[' => WriterState::ControlSequence { parameters: vec![] },
+                _ => WriterState::Text,
+            },
+            WriterState::ControlSequence { mut parameters } => match byte {
+                b'0'..=b'9' => {
+                    if parameters.is_empty() {
+                        parameters.push(0);
+                    }
+                    let parameter = parameters.last_mut().unwrap();
+                    *parameter = parameter
+                        .saturating_mul(10)
+                        .saturating_add((byte - b'0') as usize);
+                    WriterState::ControlSequence { parameters }
+                }
+                b';' => {
+                    if parameters.is_empty() {
+                        parameters.push(0);
+                    }
+                    if parameters.len() >= Self::MAX_PARAMETERS {
+                        WriterState::IgnoredControlSequence
+                    } else {
+                        parameters.push(0);
+                        WriterState::ControlSequence { parameters }
+                    }
+                }
+                _ => {
+                    execute_control_sequence(memory, byte, &parameters);
+                    WriterState::Text
+                }
+            },
+            WriterState::IgnoredControlSequence => match byte {
+                b'0'..=b'9' | b';' => WriterState::IgnoredControlSequence,
+                _ => WriterState::Text,
+            },
+        };
+    }
+}
+
+fn execute_control_sequence(memory: &mut Memory, command: Byte, parameters: &[usize]) {
+    // omitted parameters and parameters with the value 0 both mean "use the default"
+    let parameter = |index: usize, default: usize| match parameters.get(index) {
+        Some(&value) if value != 0 => value,
+        _ => default,
+    };
+    let (row, column) = cursor_position(memory);
+    match command {
+        b'H' | b'f' => set_cursor_position(memory, parameter(0, 1) - 1, parameter(1, 1) - 1),
+        b'A' => set_cursor_position(memory, row.saturating_sub(parameter(0, 1)), column),
+        b'B' => set_cursor_position(memory, row.saturating_add(parameter(0, 1)), column),
+        b'C' => set_cursor_position(memory, row, column.saturating_add(parameter(0, 1))),
+        b'D' => set_cursor_position(memory, row, column.saturating_sub(parameter(0, 1))),
+        b'J' => {
+            let start = match parameters.first() {
+                Some(2) => 0,
+                _ => cursor_index(memory),
+            };
+            for buffer_start in [
+                address_constants::TERMINAL_BUFFER_START,
+                address_constants::TERMINAL_ATTRIBUTES_START,
+            ] {
+                memory.fill(
+                    buffer_start + start as Address..buffer_start + (WIDTH * HEIGHT) as Address,
+                    0,
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns the cell index the cursor points to, clamped to the terminal buffer.
+fn cursor_index(memory: &Memory) -> usize {
+    let cursor_pointer = memory.read_data(address_constants::TERMINAL_CURSOR_POINTER);
+    (cursor_pointer.saturating_sub(address_constants::TERMINAL_BUFFER_START) as usize)
+        .min(WIDTH * HEIGHT - 1)
+}
+
+fn set_cursor_index(memory: &mut Memory, index: usize) {
+    memory.write_data(
+        address_constants::TERMINAL_CURSOR_POINTER,
+        address_constants::TERMINAL_BUFFER_START + index as Address,
+    );
+}
+
+fn cursor_position(memory: &Memory) -> (usize, usize) {
+    let index = cursor_index(memory);
+    (index / WIDTH, index % WIDTH)
+}
+
+fn set_cursor_position(memory: &mut Memory, row: usize, column: usize) {
+    set_cursor_index(memory, row.min(HEIGHT - 1) * WIDTH + column.min(WIDTH - 1));
+}
+
+/// Colors selectable by the terminal attributes, in the order of the classic 16 color text mode.
+pub const COLORS: [[u8; 4]; 16] = [
+    [0x00, 0x00, 0x00, 0xFF],
+    [0x00, 0x00, 0xAA, 0xFF],
+    [0x00, 0xAA, 0x00, 0xFF],
+    [0x00, 0xAA, 0xAA, 0xFF],
+    [0xAA, 0x00, 0x00, 0xFF],
+    [0xAA, 0x00, 0xAA, 0xFF],
+    [0xAA, 0x55, 0x00, 0xFF],
+    [0xAA, 0xAA, 0xAA, 0xFF],
+    [0x55, 0x55, 0x55, 0xFF],
+    [0x55, 0x55, 0xFF, 0xFF],
+    [0x55, 0xFF, 0x55, 0xFF],
+    [0x55, 0xFF, 0xFF, 0xFF],
+    [0xFF, 0x55, 0x55, 0xFF],
+    [0xFF, 0x55, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0x55, 0xFF],
+    [0xFF, 0xFF, 0xFF, 0xFF],
+];
+
+/// Returns the foreground and background color of a cell attribute. The lower nibble selects the
+/// foreground color and the upper nibble the background color from [`COLORS`]. The attribute 0
+/// (black on black) is used as the default and therefore results in white on black instead.
+pub fn attribute_colors(attribute: Byte) -> ([u8; 4], [u8; 4]) {
+    if attribute == 0 {
+        return (COLORS[15], COLORS[0]);
+    }
+    (
+        COLORS[(attribute & 0x0F) as usize],
+        COLORS[(attribute >> 4) as usize],
+    )
+}
+
+/// Something the terminal contents can be drawn onto.
+pub trait TerminalCanvas {
+    /// Draws consecutive cells of a row that share the same colors.
+    fn draw_cells(
+        &mut self,
+        row: usize,
+        column: usize,
+        text: &str,
+        foreground: [u8; 4],
+        background: [u8; 4],
+    );
+}
+
+fn cell_character(memory: &Memory, index: usize) -> char {
+    let byte = memory.read_byte(address_constants::TERMINAL_BUFFER_START + index as Address);
+    if !(32..=255).contains(&byte) {
+        ' '
+    } else {
+        byte as char
+    }
+}
+
+pub fn render_to(memory: &Memory, canvas: &mut impl TerminalCanvas, cursor: &Cursor) {
     let cursor_pointer = memory.read_data(address_constants::TERMINAL_CURSOR_POINTER) as usize;
     debug_assert_eq!(address_constants::TERMINAL_BUFFER_START, 0); // to assume we get no overflow
     let cursor_index = cursor_pointer - address_constants::TERMINAL_BUFFER_START as usize;
-    let cursor_row = cursor_index / WIDTH;
-    let cursor_column = cursor_index % WIDTH;
     for row in 0..HEIGHT {
-        // let words = &memory[row * WIDTH..][..WIDTH];
-        let mut string: String = (0..WIDTH)
-            .map(|i| {
-                memory.read_byte(
-                    address_constants::TERMINAL_BUFFER_START + (row * WIDTH + i) as Address,
-                )
-            })
-            .map(|byte| {
-                if !(32..=255).contains(&byte) {
-                    b' '
-                } else {
-                    byte as u8
-                }
-            })
-            .map(|c| c as char)
-            .collect();
-        if row == cursor_row && cursor.visible {
-            let bytes = unsafe { string.as_bytes_mut() };
-            debug_assert!(bytes[cursor_column].is_ascii());
-            bytes[cursor_column] = b'_';
+        let mut run_start = 0;
+        let mut run_text = String::with_capacity(WIDTH);
+        let mut run_attribute = None;
+        for column in 0..WIDTH {
+            let index = row * WIDTH + column;
+            let attribute =
+                memory.read_byte(address_constants::TERMINAL_ATTRIBUTES_START + index as Address);
+            if run_attribute.is_some_and(|run_attribute| run_attribute != attribute) {
+                let (foreground, background) = attribute_colors(run_attribute.unwrap());
+                canvas.draw_cells(row, run_start, &run_text, foreground, background);
+                run_start = column;
+                run_text.clear();
+            }
+            run_attribute = Some(attribute);
+            if index == cursor_index && cursor.visible {
+                run_text.push('_');
+            } else {
+                run_text.push(cell_character(memory, index));
+            }
+        }
+        if let Some(run_attribute) = run_attribute {
+            let (foreground, background) = attribute_colors(run_attribute);
+            canvas.draw_cells(row, run_start, &run_text, foreground, background);
         }
-        let text = string.as_str();
+    }
+}
+
+#[cfg(feature = "graphics")]
+const TEXT_SPACING: f32 = 5.0;
+
+#[cfg(feature = "graphics")]
+struct RaylibCanvas<'a, 'b> {
+    draw_handle: &'a mut RaylibDrawHandle<'b>,
+    position: Vector2,
+    font: &'a Font,
+    font_height: f32,
+    cell_width: f32,
+}
 
-        draw_handle.draw_text_ex(
-            font,
+#[cfg(feature = "graphics")]
+impl TerminalCanvas for RaylibCanvas<'_, '_> {
+    fn draw_cells(
+        &mut self,
+        row: usize,
+        column: usize,
+        text: &str,
+        foreground: [u8; 4],
+        background: [u8; 4],
+    ) {
+        let [r, g, b, a] = foreground;
+        let foreground = Color::new(r, g, b, a);
+        let [r, g, b, a] = background;
+        let background = Color::new(r, g, b, a);
+        let position = Vector2::new(
+            self.position.x + column as f32 * self.cell_width,
+            self.position.y + row as f32 * self.font_height,
+        );
+        if background != Color::BLACK {
+            self.draw_handle.draw_rectangle_v(
+                position,
+                Vector2::new(
+                    text.chars().count() as f32 * self.cell_width,
+                    self.font_height,
+                ),
+                background,
+            );
+        }
+        self.draw_handle.draw_text_ex(
+            self.font,
             text,
-            Vector2::new(position.x, position.y + row as f32 * font_height as f32),
-            font_height,
-            5.0,
-            Color::WHITE,
+            position,
+            self.font_height,
+            TEXT_SPACING,
+            foreground,
         );
     }
 }
 
+#[cfg(feature = "graphics")]
+pub fn render(
+    memory: &Memory,
+    draw_handle: &mut RaylibDrawHandle,
+    position: Vector2,
+    font: &Font,
+    font_height: f32,
+    cursor: &Cursor,
+) {
+    let cell_width = measure_text_ex(font, "M", font_height, TEXT_SPACING).x + TEXT_SPACING;
+    let mut canvas = RaylibCanvas {
+        draw_handle,
+        position,
+        font,
+        font_height,
+        cell_width,
+    };
+    render_to(memory, &mut canvas, cursor);
+}
+
 #[cfg(test)]
 mod tests {
+    use std::time::Instant;
+
     use crate::{Size, Word};
 
     use super::*;
 
+    type DrawCall = (usize, usize, String, [u8; 4], [u8; 4]);
+
+    #[derive(Default)]
+    struct CapturingCanvas {
+        calls: Vec<DrawCall>,
+    }
+
+    impl TerminalCanvas for CapturingCanvas {
+        fn draw_cells(
+            &mut self,
+            row: usize,
+            column: usize,
+            text: &str,
+            foreground: [u8; 4],
+            background: [u8; 4],
+        ) {
+            self.calls
+                .push((row, column, text.to_string(), foreground, background));
+        }
+    }
+
+    fn invisible_cursor() -> Cursor {
+        Cursor {
+            visible: false,
+            time_of_next_toggle: Instant::now(),
+        }
+    }
+
     #[test]
     fn terminal_character_width_divisible_by_word_size() {
         assert_eq!(WIDTH % Word::SIZE, 0);
     }
+
+    #[test]
+    fn writing_past_last_row_scrolls_up() {
+        let mut memory = Memory::new();
+        for line in 0..=HEIGHT {
+            if line > 0 {
+                let cursor_pointer = memory.read_data(address_constants::TERMINAL_CURSOR_POINTER);
+                memory.write_data(
+                    address_constants::TERMINAL_CURSOR_POINTER,
+                    cursor_pointer + WIDTH as Word,
+                );
+                scroll_if_needed(&mut memory);
+            }
+            let cursor_pointer = memory.read_data(address_constants::TERMINAL_CURSOR_POINTER);
+            memory.write_byte(cursor_pointer, b'a' + line as u8);
+        }
+        for row in 0..HEIGHT {
+            let address = address_constants::TERMINAL_BUFFER_START + (row * WIDTH) as Address;
+            assert_eq!(memory.read_byte(address), b'a' + (row + 1) as u8);
+        }
+        assert_eq!(
+            memory.read_data(address_constants::TERMINAL_CURSOR_POINTER),
+            address_constants::TERMINAL_BUFFER_START + ((HEIGHT - 1) * WIDTH) as Address
+        );
+    }
+
+    #[test]
+    fn default_attribute_renders_white_on_black() {
+        let memory = Memory::new();
+        let mut canvas = CapturingCanvas::default();
+        render_to(&memory, &mut canvas, &invisible_cursor());
+        assert_eq!(canvas.calls.len(), HEIGHT);
+        for (row, (call_row, column, text, foreground, background)) in
+            canvas.calls.into_iter().enumerate()
+        {
+            assert_eq!((call_row, column), (row, 0));
+            assert_eq!(text.len(), WIDTH);
+            assert_eq!(foreground, COLORS[15]);
+            assert_eq!(background, COLORS[0]);
+        }
+    }
+
+    #[test]
+    fn cell_attribute_selects_colors() {
+        let mut memory = Memory::new();
+        let row = 2;
+        let column = 5;
+        let index = (row * WIDTH + column) as Address;
+        memory.write_byte(address_constants::TERMINAL_BUFFER_START + index, b'X');
+        memory.write_byte(address_constants::TERMINAL_ATTRIBUTES_START + index, 0x1C);
+        let mut canvas = CapturingCanvas::default();
+        render_to(&memory, &mut canvas, &invisible_cursor());
+        let row_calls: Vec<_> = canvas
+            .calls
+            .into_iter()
+            .filter(|call| call.0 == row)
+            .collect();
+        assert_eq!(row_calls.len(), 3);
+        assert_eq!(row_calls[0].1, 0);
+        assert_eq!(row_calls[0].3, COLORS[15]);
+        assert_eq!(
+            row_calls[1],
+            (row, column, "X".to_string(), COLORS[0xC], COLORS[0x1])
+        );
+        assert_eq!(row_calls[2].1, column + 1);
+        assert_eq!(row_calls[2].4, COLORS[0]);
+    }
+
+    fn write_all(writer: &mut TerminalWriter, memory: &mut Memory, bytes: &[u8]) {
+        for &byte in bytes {
+            writer.write_byte(memory, byte);
+        }
+    }
+
+    fn cursor_pointer(memory: &Memory) -> Address {
+        memory.read_data(address_constants::TERMINAL_CURSOR_POINTER)
+    }
+
+    #[test]
+    fn carriage_return_and_line_feed_move_to_next_line() {
+        let mut memory = Memory::new();
+        let mut writer = TerminalWriter::default();
+        write_all(&mut writer, &mut memory, b"ab\r\ncd");
+        assert_eq!(memory.read_byte(0), b'a');
+        assert_eq!(memory.read_byte(1), b'b');
+        assert_eq!(memory.read_byte(WIDTH as Address), b'c');
+        assert_eq!(memory.read_byte(WIDTH as Address + 1), b'd');
+        assert_eq!(cursor_pointer(&memory), WIDTH as Address + 2);
+    }
+
+    #[test]
+    fn scrolling_goes_through_tracked_writes() {
+        let mut memory = Memory::new();
+        let mut writer = TerminalWriter::default();
+        write_all(&mut writer, &mut memory, &[b'\n'; HEIGHT - 1]);
+        memory.clear_dirty_instructions();
+        write_all(&mut writer, &mut memory, b"\n");
+        assert_eq!(cursor_pointer(&memory), ((HEIGHT - 1) * WIDTH) as Address);
+        assert!(memory.take_instruction_dirty(address_constants::TERMINAL_ATTRIBUTES_START / 8 * 8));
+    }
+
+    #[test]
+    fn clear_screen_sequence_clears_buffer() {
+        let mut memory = Memory::new();
+        let mut writer = TerminalWriter::default();
+        write_all(&mut writer, &mut memory, b"hello\r\nworld");
+        let cursor_before = cursor_pointer(&memory);
+        write_all(&mut writer, &mut memory, b"\x1b[2J");
+        assert!(memory.data()[..WIDTH * HEIGHT]
+            .iter()
+            .all(|&byte| byte == 0));
+        assert_eq!(cursor_pointer(&memory), cursor_before);
+    }
+
+    #[test]
+    fn cursor_home_and_position_sequences() {
+        let mut memory = Memory::new();
+        let mut writer = TerminalWriter::default();
+        write_all(&mut writer, &mut memory, b"abc\r\ndef\x1b[H");
+        assert_eq!(
+            cursor_pointer(&memory),
+            address_constants::TERMINAL_BUFFER_START
+        );
+        write_all(&mut writer, &mut memory, b"X\x1b[3;5HY");
+        assert_eq!(memory.read_byte(0), b'X');
+        assert_eq!(memory.read_byte((2 * WIDTH + 4) as Address), b'Y');
+        assert_eq!(cursor_pointer(&memory), (2 * WIDTH + 5) as Address);
+        write_all(&mut writer, &mut memory, b"\x1b[2A\x1b[D");
+        assert_eq!(cursor_pointer(&memory), 4);
+    }
+
+    #[test]
+    fn control_sequence_with_too_many_parameters_is_swallowed() {
+        let mut memory = Memory::new();
+        let mut writer = TerminalWriter::default();
+        let mut sequence = b"\x1b[".to_vec();
+        for _ in 0..10_000 {
+            sequence.extend_from_slice(b"1;");
+        }
+        sequence.extend_from_slice(b"3HX");
+        write_all(&mut writer, &mut memory, &sequence);
+        assert_eq!(writer.state, WriterState::Text);
+        assert_eq!(memory.read_byte(0), b'X');
+        assert_eq!(cursor_pointer(&memory), 1);
+    }
 }
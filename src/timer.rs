@@ -1,15 +1,50 @@
+use std::time::Instant;
+
 pub struct Timer {
     get_ms_callback: Box<dyn FnMut() -> u64>,
+    get_monotonic_ms_callback: Box<dyn FnMut() -> u64>,
+    last_monotonic_ms: u64,
+    /// Added to the values of the monotonic source, see `skip_monotonic`.
+    monotonic_offset_ms: u64,
 }
 
 impl<'a> Timer {
+    /// Creates a timer with the given wall clock. The monotonic clock counts the milliseconds
+    /// since the creation of the timer.
     pub fn new(get_ms_callback: impl FnMut() -> u64 + 'static) -> Self {
+        let start = Instant::now();
         Self {
             get_ms_callback: Box::new(get_ms_callback),
+            get_monotonic_ms_callback: Box::new(move || start.elapsed().as_millis() as u64),
+            last_monotonic_ms: 0,
+            monotonic_offset_ms: 0,
         }
     }
 
+    /// Replaces the source of the monotonic clock (e.g. with a mock).
+    pub fn with_monotonic_source(
+        mut self,
+        get_monotonic_ms_callback: impl FnMut() -> u64 + 'static,
+    ) -> Self {
+        self.get_monotonic_ms_callback = Box::new(get_monotonic_ms_callback);
+        self
+    }
+
     pub fn get_ms_since_epoch(&mut self) -> u64 {
         (self.get_ms_callback)()
     }
+
+    /// Returns the current value of the monotonic clock, which never decreases (even if the
+    /// source does).
+    pub fn get_monotonic_ms(&mut self) -> u64 {
+        self.last_monotonic_ms = self
+            .last_monotonic_ms
+            .max((self.get_monotonic_ms_callback)() + self.monotonic_offset_ms);
+        self.last_monotonic_ms
+    }
+
+    /// Advances the monotonic clock by the given number of milliseconds without waiting.
+    pub fn skip_monotonic(&mut self, ms: u64) {
+        self.monotonic_offset_ms += ms;
+    }
 }
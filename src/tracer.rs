@@ -0,0 +1,49 @@
+use std::io::Write;
+
+use crate::{
+    disassembler::format_opcode,
+    opcodes::Opcode,
+    processor::{Processor, NUM_REGISTERS},
+    Address, Word,
+};
+
+/// Writes one line per executed instruction, containing the instruction address, the decoded
+/// instruction and the registers it has modified (the instruction pointer is left out since it
+/// is the first column of the next line anyway).
+pub struct Tracer {
+    output: Box<dyn Write>,
+}
+
+impl Tracer {
+    pub fn new(output: Box<dyn Write>) -> Self {
+        Self { output }
+    }
+
+    pub fn trace(
+        &mut self,
+        instruction_address: Address,
+        opcode: Option<Opcode>,
+        registers_before: &[Word; NUM_REGISTERS],
+        registers_after: &[Word; NUM_REGISTERS],
+    ) {
+        let mut line = format!(
+            "{:#010x}: {}",
+            instruction_address,
+            opcode.map_or_else(|| "<invalid instruction>".to_string(), format_opcode)
+        );
+        let modified_registers = registers_before
+            .iter()
+            .zip(registers_after)
+            .enumerate()
+            .filter(|&(register, (before, after))| {
+                before != after && register != Processor::INSTRUCTION_POINTER.0 as usize
+            });
+        for (i, (register, (_, value))) in modified_registers.enumerate() {
+            line.push_str(if i == 0 { " | " } else { ", " });
+            line.push_str(&format!("r{register} = {value:#010x}"));
+        }
+        line.push('\n');
+        // a broken trace output must not stop the execution
+        let _ = self.output.write_all(line.as_bytes());
+    }
+}
@@ -0,0 +1,259 @@
+use std::{fmt, ops::Range};
+
+use crate::{opcodes::Opcode, Address, Instruction, Size};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// The opcode number is unknown.
+    InvalidOpcode,
+    /// The opcode is known, but the instruction has bits set in register slots or the immediate
+    /// that the opcode doesn't use (i.e. it doesn't match the canonical encoding).
+    UnusedBitsSet,
+    /// The instruction reads from or writes to an address outside of the memory.
+    AddressOutOfRange(Address),
+    /// The instruction jumps to an address outside of the loaded machine code.
+    JumpTargetOutOfRange(Address),
+    /// The instruction jumps to an address that isn't a multiple of the instruction size.
+    MisalignedJumpTarget(Address),
+    /// The machine code ends with an incomplete instruction.
+    Truncated,
+}
+
+/// An instruction slot of a ROM that would fail (or behave unexpectedly) when executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Issue {
+    pub address: Address,
+    /// The raw instruction word (the remaining bytes, zero-padded, for `Problem::Truncated`).
+    pub instruction: Instruction,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#010x}: {:#018x}: ", self.address, self.instruction)?;
+        match self.problem {
+            Problem::InvalidOpcode => write!(f, "invalid opcode"),
+            Problem::UnusedBitsSet => write!(f, "unused bits are set"),
+            Problem::AddressOutOfRange(address) => {
+                write!(f, "address {:#010x} is out of range", address)
+            }
+            Problem::JumpTargetOutOfRange(address) => {
+                write!(f, "jump target {:#010x} is out of range", address)
+            }
+            Problem::MisalignedJumpTarget(address) => {
+                write!(f, "jump target {:#010x} is misaligned", address)
+            }
+            Problem::Truncated => write!(f, "incomplete instruction"),
+        }
+    }
+}
+
+/// Decodes every instruction slot of machine code that is loaded at the given address into a
+/// memory of the given size and returns all slots that can't be executed as intended. Since
+/// ROMs may contain data between the instructions, the result can contain false positives.
+pub fn validate(machine_code: &[u8], start_address: Address, memory_size: usize) -> Vec<Issue> {
+    let mut result = Vec::new();
+    let loaded_range = start_address as usize..start_address as usize + machine_code.len();
+    let mut chunks = machine_code.chunks_exact(Instruction::SIZE);
+    for (i, chunk) in chunks.by_ref().enumerate() {
+        let address = start_address + (i * Instruction::SIZE) as Address;
+        let instruction = Instruction::from_be_bytes(chunk.try_into().unwrap());
+        if let Some(problem) = check_instruction(instruction, address, memory_size, &loaded_range) {
+            result.push(Issue {
+                address,
+                instruction,
+                problem,
+            });
+        }
+    }
+    let remainder = chunks.remainder();
+    if !remainder.is_empty() {
+        let mut bytes = [0; Instruction::SIZE];
+        bytes[..remainder.len()].copy_from_slice(remainder);
+        result.push(Issue {
+            address: start_address + (machine_code.len() - remainder.len()) as Address,
+            instruction: Instruction::from_be_bytes(bytes),
+            problem: Problem::Truncated,
+        });
+    }
+    result
+}
+
+fn check_instruction(
+    instruction: Instruction,
+    address: Address,
+    memory_size: usize,
+    loaded_range: &Range<usize>,
+) -> Option<Problem> {
+    let Ok(opcode) = Opcode::try_from(instruction) else {
+        return Some(Problem::InvalidOpcode);
+    };
+    if opcode.as_instruction() != instruction {
+        return Some(Problem::UnusedBitsSet);
+    }
+    if let Some(data_address) = opcode.get_address() {
+        if data_address as usize >= memory_size {
+            return Some(Problem::AddressOutOfRange(data_address));
+        }
+    }
+    match jump_target(opcode, address) {
+        Some(target)
+            if (target as usize) < loaded_range.start
+                || target as usize + Instruction::SIZE > loaded_range.end =>
+        {
+            Some(Problem::JumpTargetOutOfRange(target))
+        }
+        Some(target) if target as usize % Instruction::SIZE != 0 => {
+            Some(Problem::MisalignedJumpTarget(target))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the address an instruction with an immediate jump target may continue at.
+fn jump_target(opcode: Opcode, address: Address) -> Option<Address> {
+    use Opcode::*;
+    match opcode {
+        CallImmediate { immediate }
+        | JumpImmediate { immediate }
+        | JumpImmediateIfEqual { immediate, .. }
+        | JumpImmediateIfGreaterThan { immediate, .. }
+        | JumpImmediateIfLessThan { immediate, .. }
+        | JumpImmediateIfGreaterThanOrEqual { immediate, .. }
+        | JumpImmediateIfLessThanOrEqual { immediate, .. }
+        | JumpImmediateIfZero { immediate }
+        | JumpImmediateIfNotZero { immediate }
+        | JumpImmediateIfCarry { immediate }
+        | JumpImmediateIfNotCarry { immediate }
+        | JumpImmediateIfDivideByZero { immediate }
+        | JumpImmediateIfNotDivideByZero { immediate }
+        | JumpImmediateIfNegative { immediate }
+        | JumpImmediateIfNotNegative { immediate } => Some(immediate),
+        JumpRelativeImmediate { immediate } => Some(address.wrapping_add(immediate)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{address_constants::ENTRY_POINT, memory::Memory};
+
+    fn machine_code(opcodes: &[Opcode]) -> Vec<u8> {
+        opcodes
+            .iter()
+            .flat_map(|opcode| opcode.as_instruction().to_be_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn valid_program_has_no_issues() {
+        let machine_code = machine_code(&[
+            Opcode::MoveRegisterAddress {
+                register: 1.into(),
+                source_address: 0x1000,
+            },
+            Opcode::JumpImmediate {
+                immediate: ENTRY_POINT,
+            },
+            Opcode::JumpRelativeImmediate {
+                immediate: -(Instruction::SIZE as i32) as Address,
+            },
+        ]);
+        assert_eq!(
+            validate(&machine_code, ENTRY_POINT, Memory::DEFAULT_SIZE),
+            []
+        );
+    }
+
+    #[test]
+    fn corrupted_words_are_reported_at_their_offset() {
+        let mut machine_code = machine_code(&[
+            Opcode::NoOp {},
+            Opcode::NoOp {},
+            Opcode::MoveAddressRegister {
+                register: 1.into(),
+                target_address: Memory::DEFAULT_SIZE as Address,
+            },
+            Opcode::CallImmediate { immediate: 0x10 },
+            Opcode::NoOp {},
+        ]);
+        // corrupt the opcode number of the second instruction and a register slot of the last one
+        machine_code[Instruction::SIZE] = 0xEE;
+        machine_code[4 * Instruction::SIZE + 2] = 0x01;
+        machine_code.push(0xAB);
+
+        let issues = validate(&machine_code, ENTRY_POINT, Memory::DEFAULT_SIZE);
+        let at = |index: usize| ENTRY_POINT + (index * Instruction::SIZE) as Address;
+        assert_eq!(
+            issues,
+            [
+                Issue {
+                    address: at(1),
+                    instruction: 0xEE31_0000_0000_0000,
+                    problem: Problem::InvalidOpcode,
+                },
+                Issue {
+                    address: at(2),
+                    instruction: Opcode::MoveAddressRegister {
+                        register: 1.into(),
+                        target_address: Memory::DEFAULT_SIZE as Address,
+                    }
+                    .as_instruction(),
+                    problem: Problem::AddressOutOfRange(Memory::DEFAULT_SIZE as Address),
+                },
+                Issue {
+                    address: at(3),
+                    instruction: Opcode::CallImmediate { immediate: 0x10 }.as_instruction(),
+                    problem: Problem::JumpTargetOutOfRange(0x10),
+                },
+                Issue {
+                    address: at(4),
+                    instruction: Opcode::NoOp {}.as_instruction() | 0x0000_0100_0000_0000,
+                    problem: Problem::UnusedBitsSet,
+                },
+                Issue {
+                    address: at(5),
+                    instruction: 0xAB00_0000_0000_0000,
+                    problem: Problem::Truncated,
+                },
+            ]
+        );
+        assert_eq!(
+            issues[0].to_string(),
+            format!("{:#010x}: 0xee31000000000000: invalid opcode", at(1))
+        );
+    }
+
+    #[test]
+    fn jump_targets_are_checked_against_the_loaded_code() {
+        let load_address = 0x100;
+        let at = |index: usize| load_address + (index * Instruction::SIZE) as Address;
+        let opcodes = [
+            Opcode::JumpImmediate {
+                immediate: load_address,
+            },
+            Opcode::JumpImmediate { immediate: at(3) },
+            Opcode::JumpImmediate { immediate: at(4) },
+            Opcode::CallImmediate {
+                immediate: at(1) + 4,
+            },
+        ];
+        let issues = validate(&machine_code(&opcodes), load_address, Memory::DEFAULT_SIZE);
+        assert_eq!(
+            issues,
+            [
+                Issue {
+                    address: at(2),
+                    instruction: opcodes[2].as_instruction(),
+                    problem: Problem::JumpTargetOutOfRange(at(4)),
+                },
+                Issue {
+                    address: at(3),
+                    instruction: opcodes[3].as_instruction(),
+                    problem: Problem::MisalignedJumpTarget(at(1) + 4),
+                },
+            ]
+        );
+    }
+}
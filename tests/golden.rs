@@ -0,0 +1,70 @@
+//! Runs ROMs headlessly and compares the visible framebuffer with stored golden images (raw RGBA,
+//! row by row). Set `BLESS_GOLDEN_IMAGES=1` to (re)generate the golden images instead.
+
+use std::path::PathBuf;
+
+use backseat_safe_system_2k::{
+    display::{Display, MockDisplay, Resolution},
+    periphery::PeripheryImplementation,
+    ExecutionResult, Machine,
+};
+
+const MAX_CYCLES: u64 = 10_000_000;
+
+fn create_machine(resolution: Resolution) -> Machine<MockDisplay> {
+    Machine::new(PeripheryImplementation::headless(resolution))
+}
+
+/// Runs `tests/golden/<name>.backseat` until it halts and compares the result with
+/// `tests/golden/<name>.rgba`.
+fn check_golden_image(name: &str, resolution: Resolution) {
+    let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let rom = std::fs::read(directory.join(format!("{name}.backseat"))).unwrap();
+    let golden_path = directory.join(format!("{name}.rgba"));
+
+    let mut machine = create_machine(resolution);
+    machine.load_rom(&rom).unwrap();
+    assert_eq!(
+        machine.run_cycles(MAX_CYCLES),
+        ExecutionResult::Halted,
+        "{name} didn't halt"
+    );
+    let image = machine.periphery.display.capture(&machine.memory);
+
+    if std::env::var_os("BLESS_GOLDEN_IMAGES").is_some() {
+        std::fs::write(&golden_path, &image).unwrap();
+        return;
+    }
+    let golden = std::fs::read(&golden_path).unwrap_or_else(|error| {
+        panic!(
+            "cannot read {} ({error}), run with BLESS_GOLDEN_IMAGES=1 to create it",
+            golden_path.display()
+        )
+    });
+    assert_eq!(image.len(), golden.len(), "{name}: image size differs");
+    let mismatches: Vec<_> = image
+        .chunks_exact(4)
+        .zip(golden.chunks_exact(4))
+        .enumerate()
+        .filter(|(_, (actual, expected))| actual != expected)
+        .collect();
+    if let Some(&(index, (actual, expected))) = mismatches.first() {
+        panic!(
+            "{name}: {} pixels differ, first at ({}, {}): expected {expected:02x?}, got {actual:02x?}",
+            mismatches.len(),
+            index % resolution.width,
+            index / resolution.width,
+        );
+    }
+}
+
+#[test]
+fn gradient() {
+    check_golden_image(
+        "gradient",
+        Resolution {
+            width: 64,
+            height: 48,
+        },
+    );
+}
@@ -0,0 +1,25 @@
+; Fills a 64x48 framebuffer with a gradient (red increases to the right, green to the bottom)
+; and halts. Assemble with `backseat_safe_system_2k assemble gradient.asm gradient.backseat`.
+;
+; r1: current pixel address, r2: current row, r3: current column, r4: color of the first pixel
+; of the current row, r5: current color, r6: width, r7: height
+MoveRegisterImmediate r1, 0x7d8 ; FIRST_FRAMEBUFFER_START
+MoveRegisterImmediate r6, 0x40
+MoveRegisterImmediate r7, 0x30
+MoveRegisterImmediate r4, 0x000080ff
+MoveRegisterImmediate r2, 0x0
+; row loop
+MoveRegisterImmediate r3, 0x0
+MoveTargetSource r5, r4
+; pixel loop
+MovePointerSource r1, r5
+AddTargetSourceImmediate r1, r1, 0x4
+AddTargetSourceImmediate r5, r5, 0x04000000
+AddTargetSourceImmediate r3, r3, 0x1
+CompareTargetLhsRhs r10, r3, r6
+JumpImmediateIfLessThan r10, 0x1d2010 ; pixel loop
+AddTargetSourceImmediate r4, r4, 0x00050000
+AddTargetSourceImmediate r2, r2, 0x1
+CompareTargetLhsRhs r10, r2, r7
+JumpImmediateIfLessThan r10, 0x1d2000 ; row loop
+HaltAndCatchFire
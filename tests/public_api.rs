@@ -0,0 +1,74 @@
+use backseat_safe_system_2k::{
+    display::{MockDisplay, Resolution},
+    instruction_address,
+    periphery::PeripheryImplementation,
+    samples,
+    ExecutionResult, Instruction, Machine, Opcode, Size,
+};
+
+fn create_machine() -> Machine<MockDisplay> {
+    Machine::new(PeripheryImplementation::headless(Resolution::DEFAULT))
+}
+
+#[test]
+fn run_rom_through_public_api() {
+    let rom: Vec<u8> = [
+        Opcode::MoveRegisterImmediate {
+            register: 1.into(),
+            immediate: 40,
+        },
+        Opcode::MoveRegisterImmediate {
+            register: 2.into(),
+            immediate: 2,
+        },
+        Opcode::AddTargetLhsRhs {
+            target: 3.into(),
+            lhs: 1.into(),
+            rhs: 2.into(),
+        },
+        Opcode::HaltAndCatchFire {},
+    ]
+    .iter()
+    .flat_map(|opcode| opcode.as_instruction().to_be_bytes())
+    .collect();
+    assert_eq!(rom.len(), 4 * Instruction::SIZE);
+
+    let mut machine = create_machine();
+    machine.load_rom(&rom).unwrap();
+    assert_eq!(machine.run_cycles(100), ExecutionResult::Halted);
+    assert_eq!(machine.processor.registers[3.into()], 42);
+    assert_eq!(
+        machine.processor.get_instruction_pointer(),
+        instruction_address(3)
+    );
+}
+
+#[test]
+fn load_rom_rejects_unaligned_size() {
+    let mut machine = create_machine();
+    assert!(machine.load_rom(&[0; 3]).is_err());
+}
+
+#[test]
+fn every_sample_program_runs() {
+    for name in samples::NAMES {
+        let opcodes = samples::by_name(name).unwrap();
+        let rom: Vec<u8> = opcodes
+            .iter()
+            .flat_map(|opcode| opcode.as_instruction().to_be_bytes())
+            .collect();
+        for (chunk, &opcode) in rom.chunks_exact(Instruction::SIZE).zip(&opcodes) {
+            let instruction = Instruction::from_be_bytes(chunk.try_into().unwrap());
+            assert_eq!(Opcode::try_from(instruction), Ok(opcode), "{name}");
+        }
+
+        let mut machine = create_machine();
+        machine.load_rom(&rom).unwrap();
+        assert_eq!(machine.run_cycles(1), ExecutionResult::Normal, "{name}");
+        assert_ne!(
+            machine.processor.get_instruction_pointer(),
+            instruction_address(0),
+            "{name}"
+        );
+    }
+}